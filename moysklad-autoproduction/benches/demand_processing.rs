@@ -0,0 +1,136 @@
+//! Скорость разбора заказов и отчётов об остатках — основная работа CPU при обработке заказа.
+//!
+//! Замеряется только разбор: модели подключаются напрямую из исходников, ответы API
+//! генерируются в форме, которую возвращает МойСклад. Обработка заказа целиком (этапы,
+//! запросы, создание документов) сюда не входит — её время по записанным ответам
+//! показывает `play-fixtures` (`elapsed_ms`).
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Value};
+
+// Тесты модуля в бенчмарк не входят, их импорты не используются
+#[allow(dead_code, unused_imports)]
+#[path = "../src/models/moysklad.rs"]
+mod moysklad;
+
+use moysklad::{ApiResponse, CustomerOrder, QuantityBasis, StockRow};
+
+const API: &str = "https://api.moysklad.ru/api/remap/1.2";
+
+fn id(kind: u32, n: usize) -> String {
+    format!("{:08x}-0000-4000-8000-{:012x}", kind, n)
+}
+
+fn meta(entity: &str, id: &str) -> Value {
+    json!({
+        "href": format!("{}/entity/{}/{}", API, entity, id),
+        "type": entity,
+        "mediaType": "application/json"
+    })
+}
+
+/// Заказ покупателя с `positions` позициями, как при `expand=positions.assortment,...`
+fn order_json(positions: usize) -> String {
+    let order_id = id(1, positions);
+    let rows: Vec<Value> = (0..positions)
+        .map(|n| {
+            let product_id = id(2, n);
+            json!({
+                "id": id(3, n),
+                "meta": meta("customerorderposition", &id(3, n)),
+                "assortment": {
+                    "meta": meta("product", &product_id),
+                    "id": product_id,
+                    "name": format!("Товар {}", n),
+                    "code": format!("{:05}", n),
+                    "article": format!("ART-{}", n)
+                },
+                "quantity": (n % 7 + 1) as f64,
+                "price": 150000.0,
+                "discount": 0.0,
+                "vat": 20.0,
+                "reserve": (n % 5) as f64,
+                "shipped": (n % 3) as f64
+            })
+        })
+        .collect();
+
+    json!({
+        "meta": meta("customerorder", &order_id),
+        "id": order_id,
+        "name": "00042",
+        "moment": "2024-01-15 10:00:00.000",
+        "applicable": true,
+        "store": {"meta": meta("store", &id(4, 0)), "name": "Основной склад"},
+        "organization": {"meta": meta("organization", &id(5, 0)), "name": "ООО Ромашка"},
+        "agent": {"meta": meta("counterparty", &id(6, 0)), "name": "Покупатель"},
+        "positions": {
+            "meta": {
+                "href": format!("{}/entity/customerorder/{}/positions", API, order_id),
+                "type": "customerorderposition",
+                "size": positions
+            },
+            "rows": rows
+        }
+    })
+    .to_string()
+}
+
+/// Страница отчёта «Остатки по складам» из `rows` строк
+fn stock_page_json(rows: usize) -> String {
+    let rows: Vec<Value> = (0..rows)
+        .map(|n| {
+            json!({
+                "meta": meta("product", &id(2, n)),
+                "name": format!("Товар {}", n),
+                "code": format!("{:05}", n),
+                "stock": (n % 40) as f64,
+                "reserve": (n % 4) as f64,
+                "inTransit": 0.0
+            })
+        })
+        .collect();
+
+    json!({
+        "meta": {"href": format!("{}/report/stock/all", API), "size": rows.len(), "limit": 1000, "offset": 0},
+        "rows": rows
+    })
+    .to_string()
+}
+
+fn parse_orders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("customer_order");
+
+    for positions in [1, 10, 100] {
+        let body = order_json(positions);
+        group.throughput(Throughput::Elements(positions as u64));
+
+        group.bench_with_input(BenchmarkId::new("parse", positions), &body, |b, body| {
+            b.iter(|| serde_json::from_str::<CustomerOrder>(black_box(body)).unwrap())
+        });
+
+        let order: CustomerOrder = serde_json::from_str(&body).unwrap();
+        group.bench_with_input(BenchmarkId::new("reserve_basis", positions), &order, |b, order| {
+            b.iter(|| QuantityBasis::Reserve.apply(black_box(order.clone())))
+        });
+    }
+
+    group.finish();
+}
+
+fn parse_stock_pages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stock_report");
+
+    for rows in [100, 1000] {
+        let body = stock_page_json(rows);
+        group.throughput(Throughput::Elements(rows as u64));
+        group.bench_with_input(BenchmarkId::new("parse_page", rows), &body, |b, body| {
+            b.iter(|| serde_json::from_str::<ApiResponse<StockRow>>(black_box(body)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, parse_orders, parse_stock_pages);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+//! Способы авторизации в API МойСклад
+
+use serde::Deserialize;
+
+use crate::config::Settings;
+
+/// Способ авторизации
+#[derive(Debug, Clone)]
+pub enum AuthStrategy {
+    /// Постоянный токен доступа
+    Bearer(String),
+    /// Логин и пароль: токен получается через `/security/token`
+    /// и перевыпускается при ответе 401
+    Basic { login: String, password: String },
+}
+
+impl AuthStrategy {
+    /// Выбрать способ авторизации по настройкам: токен имеет приоритет
+    pub fn from_settings(settings: &Settings) -> Self {
+        match (&settings.moysklad_login, &settings.moysklad_password) {
+            (Some(login), Some(password)) if settings.moysklad_token.is_empty() => Self::Basic {
+                login: login.clone(),
+                password: password.clone(),
+            },
+            _ => Self::Bearer(settings.moysklad_token.clone()),
+        }
+    }
+}
+
+/// Ответ `/security/token`
+#[derive(Debug, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+}
@@ -0,0 +1,98 @@
+//! Автоматический выключатель (circuit breaker) для запросов к МойСклад
+
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Состояние выключателя
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Запросы проходят
+    Closed,
+    /// Запросы отклоняются без обращения к API
+    Open,
+    /// Пропускается пробный запрос
+    HalfOpen,
+}
+
+struct Inner {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+/// Размыкается после `threshold` подряд идущих сбоев; через `cooldown`
+/// пропускает один пробный запрос и замыкается при его успехе
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            inner: Mutex::new(Inner {
+                consecutive_failures: 0,
+                opened_at: None,
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    /// Текущее состояние
+    pub fn state(&self) -> CircuitState {
+        let inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        match inner.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() >= self.cooldown => CircuitState::HalfOpen,
+            Some(_) => CircuitState::Open,
+        }
+    }
+
+    /// Можно ли выполнить запрос. В полуоткрытом состоянии разрешается
+    /// только один пробный запрос одновременно.
+    pub fn allow_request(&self) -> bool {
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        match inner.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() >= self.cooldown && !inner.probe_in_flight => {
+                inner.probe_in_flight = true;
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// Зафиксировать успешный запрос
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        if inner.opened_at.is_some() {
+            info!("Moysklad API recovered, circuit closed");
+        }
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.probe_in_flight = false;
+    }
+
+    /// Зафиксировать сбой запроса
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().expect("circuit breaker lock poisoned");
+        inner.consecutive_failures += 1;
+
+        if inner.probe_in_flight || inner.consecutive_failures >= self.threshold {
+            if inner.opened_at.is_none() {
+                warn!(
+                    "Circuit opened after {} consecutive Moysklad failures",
+                    inner.consecutive_failures
+                );
+            }
+            inner.opened_at = Some(Instant::now());
+            inner.probe_in_flight = false;
+        }
+    }
+}
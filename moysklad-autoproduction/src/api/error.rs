@@ -0,0 +1,80 @@
+//! Ошибки API МойСклад
+
+use thiserror::Error;
+
+use super::redact::sanitize;
+
+/// Ошибка ответа API МойСклад
+#[derive(Debug, Error)]
+pub enum ApiError {
+    /// Сервер вернул неуспешный HTTP статус. Тело хранится целиком (для разбора кода
+    /// ошибки), в текст ошибки попадает без учётных данных и обрезанным.
+    #[error("API error {status}: {}", sanitize(.body))]
+    Status { status: u16, body: String },
+
+    /// Запрос отклонён: API недоступен, выключатель разомкнут
+    #[error("Moysklad API unavailable (circuit open)")]
+    CircuitOpen,
+
+    /// Этап обработки не уложился в таймаут
+    #[error("Moysklad request timed out after {secs} s")]
+    Timeout { secs: u64 },
+
+    /// Ответ больше MAX_RESPONSE_BYTES: чтение прервано
+    #[error("Moysklad response exceeds {limit} bytes")]
+    TooLarge { limit: usize },
+
+    /// Запрос на создание или проведение документа не дождался ответа: документ мог
+    /// быть создан, поэтому повтор (и повторная обработка заказа) создал бы дубликат
+    #[error("Moysklad write timed out, the document may have been created")]
+    OutcomeUnknown,
+}
+
+/// Является ли ошибка временной (сеть, таймаут, 5xx, 429), т.е. имеет ли смысл повтор
+pub fn is_transient_error(error: &anyhow::Error) -> bool {
+    if error
+        .chain()
+        .any(|cause| matches!(cause.downcast_ref::<ApiError>(), Some(ApiError::OutcomeUnknown)))
+    {
+        return false;
+    }
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.is_timeout() || e.is_connect() || e.is_request();
+        }
+        match cause.downcast_ref::<ApiError>() {
+            Some(ApiError::Status { status, .. }) => *status == 429 || *status >= 500,
+            Some(ApiError::CircuitOpen) | Some(ApiError::Timeout { .. }) => true,
+            Some(ApiError::TooLarge { .. }) | Some(ApiError::OutcomeUnknown) | None => false,
+        }
+    })
+}
+
+/// Запрос не дождался ответа (таймаут этапа или HTTP-клиента)
+pub fn is_timeout_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.is_timeout();
+        }
+        matches!(cause.downcast_ref::<ApiError>(), Some(ApiError::Timeout { .. }))
+    })
+}
+
+/// Таймаут записи — исход неизвестен: такую ошибку не повторяют
+pub fn mark_write_timeout(error: anyhow::Error) -> anyhow::Error {
+    if is_timeout_error(&error) {
+        error.context(ApiError::OutcomeUnknown)
+    } else {
+        error
+    }
+}
+
+/// Ответ 404: сущность удалена или не существует
+pub fn is_not_found(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<ApiError>(),
+            Some(ApiError::Status { status: 404, .. })
+        )
+    })
+}
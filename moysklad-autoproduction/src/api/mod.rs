@@ -1,3 +1,14 @@
+pub mod auth;
+pub mod circuit;
+pub mod error;
 pub mod moysklad;
+pub mod playback;
+pub mod redact;
+pub mod schema;
+pub mod usage;
 
+pub use circuit::*;
+pub use error::*;
 pub use moysklad::*;
+pub use playback::*;
+pub use usage::*;
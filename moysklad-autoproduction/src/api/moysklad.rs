@@ -1,59 +1,489 @@
 //! Клиент API МойСклад
 
+use super::auth::{AuthStrategy, TokenResponse};
+use super::circuit::CircuitBreaker;
+use super::error::ApiError;
+use super::playback::{Playback, RecordedWrite};
+use super::redact::{redact, sanitize};
+use super::schema::{is_unknown_variant, parse_tracked, unknown_variant_label, LOGGED_FIELDS_SAMPLE};
+use super::usage::{endpoint_label, ApiUsage};
+use crate::config::Settings;
+use crate::history::{payload_digest, AuditLog, AuditRecord};
 use crate::models::*;
-use anyhow::{anyhow, Context, Result};
-use reqwest::Client;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use reqwest::{Client, Proxy, RequestBuilder};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
-const MOYSKLAD_API_BASE: &str = "https://api.moysklad.ru/api/remap/1.2";
+/// Ответ на GET запрос, сохранённый при записи фикстур
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RecordedResponse {
+    /// Путь запроса относительно адреса API, с параметрами
+    pub endpoint: String,
+    pub body: serde_json::Value,
+}
 
 /// Клиент API МойСклад
 pub struct MoyskladClient {
     client: Client,
-    token: String,
+    /// Адрес API без завершающего `/`
+    base_url: String,
+    /// Путь API из адреса (отбрасывается в статистике эндпоинтов)
+    api_path: String,
+    /// Заменяется при ротации токена
+    auth: std::sync::RwLock<AuthStrategy>,
+    /// Токен, полученный по логину и паролю
+    session_token: RwLock<Option<String>>,
+    breaker: Arc<CircuitBreaker>,
+    /// Статистика обращений к API
+    usage: Arc<ApiUsage>,
+    /// С каким остатком сравнивается порог
+    stock_mode: StockMode,
+    /// Строк на страницу отчётов об остатках
+    page_size: u32,
+    /// Максимальный размер ответа, байт
+    max_response_bytes: Option<usize>,
+    /// Журнал изменений (POST/PUT/DELETE)
+    audit: Option<Arc<AuditLog>>,
+    /// Заказ, обрабатываемый в данный момент: (id, название)
+    audit_order: std::sync::Mutex<Option<(String, String)>>,
+    /// Ответы на GET запросы, пока включена запись фикстур
+    recording: std::sync::Mutex<Option<Vec<RecordedResponse>>>,
+    /// Воспроизведение: ответы из записи, изменения не отправляются
+    playback: Option<Playback>,
 }
 
 impl MoyskladClient {
     /// Создать новый клиент
-    pub fn new(token: String) -> Self {
-        let client = Client::builder()
+    pub fn new(
+        settings: &Settings,
+        breaker: Arc<CircuitBreaker>,
+        usage: Arc<ApiUsage>,
+        audit: Option<Arc<AuditLog>>,
+    ) -> Self {
+        let mut builder = Client::builder()
             .gzip(true)
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .expect("Failed to create HTTP client");
+            .connect_timeout(Duration::from_secs(settings.http_connect_timeout_secs))
+            .timeout(Duration::from_secs(settings.http_timeout_secs))
+            .pool_max_idle_per_host(settings.http_pool_max_idle_per_host)
+            .pool_idle_timeout(Duration::from_secs(settings.http_pool_idle_timeout_secs));
+
+        if let Some(secs) = settings.http_read_timeout_secs {
+            builder = builder.read_timeout(Duration::from_secs(secs));
+        }
+
+        if let Some(secs) = settings.http_tcp_keepalive_secs {
+            builder = builder.tcp_keepalive(Duration::from_secs(secs));
+        }
+
+        // Без явного прокси reqwest использует HTTP_PROXY/HTTPS_PROXY/NO_PROXY из окружения
+        if let Some(ref proxy_url) = settings.moysklad_proxy {
+            info!("Using proxy for Moysklad API: {}", redact(proxy_url));
+            let proxy = Proxy::all(proxy_url).expect("MOYSKLAD_PROXY is validated by Settings::from_env");
+            builder = builder.proxy(proxy);
+        }
+
+        let client = builder.build().expect("Failed to create HTTP client");
+
+        let base_url = settings.moysklad_api_url.trim_end_matches('/').to_string();
+        let api_path = reqwest::Url::parse(&base_url)
+            .map(|url| url.path().trim_end_matches('/').to_string())
+            .unwrap_or_default();
         
-        Self { client, token }
+        Self {
+            client,
+            base_url,
+            api_path,
+            auth: std::sync::RwLock::new(AuthStrategy::from_settings(settings)),
+            session_token: RwLock::new(None),
+            breaker,
+            usage,
+            stock_mode: StockMode::parse(&settings.stock_mode).unwrap_or_default(),
+            page_size: settings.report_page_size,
+            max_response_bytes: settings.max_response_bytes,
+            audit,
+            audit_order: std::sync::Mutex::new(None),
+            recording: std::sync::Mutex::new(None),
+            playback: None,
+        }
     }
 
-    /// Выполнить GET запрос к API
-    async fn get<T: serde::de::DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
-        let url = if endpoint.starts_with("http") {
-            endpoint.to_string()
-        } else {
-            format!("{}{}", MOYSKLAD_API_BASE, endpoint)
+    /// Клиент для воспроизведения с теми же адресом и авторизацией: GET запросы
+    /// обслуживает `playback` (или МойСклад, если записи нет и чтение разрешено),
+    /// изменения не отправляются. Записываются все ответы на GET запросы.
+    pub fn playback(
+        &self,
+        breaker: Arc<CircuitBreaker>,
+        usage: Arc<ApiUsage>,
+        playback: Playback,
+    ) -> Self {
+        Self {
+            client: self.client.clone(),
+            base_url: self.base_url.clone(),
+            api_path: self.api_path.clone(),
+            auth: std::sync::RwLock::new(self.auth.read().expect("auth lock poisoned").clone()),
+            session_token: RwLock::new(None),
+            breaker,
+            usage,
+            stock_mode: self.stock_mode,
+            page_size: self.page_size,
+            max_response_bytes: self.max_response_bytes,
+            audit: None,
+            audit_order: std::sync::Mutex::new(None),
+            recording: std::sync::Mutex::new(Some(Vec::new())),
+            playback: Some(playback),
+        }
+    }
+
+    /// Получить токен доступа (для входа по логину — из кэша или через `/security/token`)
+    async fn access_token(&self) -> Result<String> {
+        let (login, password) = match &*self.auth.read().expect("auth lock poisoned") {
+            AuthStrategy::Bearer(token) => return Ok(token.clone()),
+            AuthStrategy::Basic { login, password } => (login.clone(), password.clone()),
         };
-        
-        debug!("GET request to: {}", url);
-        
-        let response = self.client
-            .get(&url)
-            .bearer_auth(&self.token)
+
+        if let Some(ref token) = *self.session_token.read().await {
+            return Ok(token.clone());
+        }
+
+        let mut session_token = self.session_token.write().await;
+        if let Some(ref token) = *session_token {
+            return Ok(token.clone());
+        }
+
+        info!("Requesting Moysklad access token for {}", login);
+
+        let response = self
+            .client
+            .post(format!("{}/security/token", self.base_url))
+            .basic_auth(&login, Some(&password))
             .header("Accept-Encoding", "gzip")
             .send()
             .await
-            .context("Failed to send request")?;
+            .context("Failed to request access token")?;
+
+        let status = response.status();
+        let body = response.text().await.context("Failed to read token response")?;
+        if !status.is_success() {
+            return Err(ApiError::Status { status: status.as_u16(), body }.into());
+        }
+
+        let token: TokenResponse =
+            serde_json::from_str(&body).context("Failed to parse token response")?;
+        *session_token = Some(token.access_token.clone());
+
+        Ok(token.access_token)
+    }
+
+    /// Заменить токен доступа: следующие запросы идут с новым токеном
+    pub async fn set_token(&self, token: String) {
+        *self.auth.write().expect("auth lock poisoned") = AuthStrategy::Bearer(token);
+        *self.session_token.write().await = None;
+    }
+
+    /// Отправить запрос; при входе по логину и ответе 401 токен перевыпускается
+    /// и запрос повторяется один раз
+    async fn send(&self, request: RequestBuilder) -> Result<String> {
+        let retry = match *self.auth.read().expect("auth lock poisoned") {
+            AuthStrategy::Basic { .. } => request.try_clone(),
+            AuthStrategy::Bearer(_) => None,
+        };
+
+        match self.send_once(request).await {
+            Err(e) if retry.is_some() && is_unauthorized(&e) => {
+                warn!("Moysklad access token rejected, requesting a new one");
+                *self.session_token.write().await = None;
+                self.send_once(retry.expect("checked above")).await
+            }
+            result => result,
+        }
+    }
+
+    /// Отправить запрос через выключатель и вернуть тело успешного ответа
+    async fn send_once(&self, request: RequestBuilder) -> Result<String> {
+        if !self.breaker.allow_request() {
+            return Err(ApiError::CircuitOpen.into());
+        }
+
+        let token = self.access_token().await?;
+
+        let (client, request) = request.build_split();
+        let request = request.context("Failed to build request")?;
+        let path = request.url().path();
+        let endpoint = endpoint_label(
+            request.method().as_str(),
+            path.strip_prefix(self.api_path.as_str()).unwrap_or(path),
+        );
+        let started = Instant::now();
+
+        let response = match RequestBuilder::from_parts(client, request)
+            .bearer_auth(&token)
+            .header("Accept-Encoding", "gzip")
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                self.breaker.record_failure();
+                self.usage.record(endpoint, None, started.elapsed(), None, None);
+                return Err(anyhow::Error::new(e).context("Failed to send request"));
+            }
+        };
         
         let status = response.status();
-        let body = response.text().await.context("Failed to read response body")?;
+        let limit = header_u64(&response, "X-RateLimit-Limit");
+        let remaining = header_u64(&response, "X-RateLimit-Remaining");
+        let body = match self.read_body(response).await {
+            Ok(body) => body,
+            Err(e) => {
+                // Слишком большой ответ — не сбой МойСклад
+                if !matches!(e.downcast_ref::<ApiError>(), Some(ApiError::TooLarge { .. })) {
+                    self.breaker.record_failure();
+                }
+                self.usage.record(endpoint, None, started.elapsed(), limit, remaining);
+                return Err(e);
+            }
+        };
+        self.usage
+            .record(endpoint, Some(status.as_u16()), started.elapsed(), limit, remaining);
+
+        if status.is_server_error() || status.as_u16() == 429 {
+            self.breaker.record_failure();
+        } else {
+            self.breaker.record_success();
+        }
         
         if !status.is_success() {
-            warn!("API error response: {} - {}", status, body);
-            return Err(anyhow!("API error {}: {}", status, body));
+            warn!("API error response: {} - {}", status, sanitize(&body));
+            return Err(ApiError::Status { status: status.as_u16(), body }.into());
+        }
+
+        Ok(body)
+    }
+
+    /// Прочитать тело ответа по частям, прерывая чтение при превышении MAX_RESPONSE_BYTES
+    async fn read_body(&self, mut response: reqwest::Response) -> Result<String> {
+        let Some(limit) = self.max_response_bytes else {
+            return response.text().await.context("Failed to read response body");
+        };
+        if response.content_length().is_some_and(|len| len > limit as u64) {
+            return Err(ApiError::TooLarge { limit }.into());
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.context("Failed to read response body")? {
+            if body.len() + chunk.len() > limit {
+                return Err(ApiError::TooLarge { limit }.into());
+            }
+            body.extend_from_slice(&chunk);
         }
+
+        String::from_utf8(body).context("Response body is not valid UTF-8")
+    }
+
+    /// Обойти отчёт постранично (limit/offset): в памяти одновременно одна страница.
+    /// `visit` возвращает `false`, чтобы остановить обход.
+    async fn for_each_page<T: serde::de::DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        visit: impl FnMut(Vec<T>) -> bool,
+    ) -> Result<()> {
+        self.for_each_page_sized(endpoint, self.page_size, visit).await
+    }
+
+    /// Постраничный обход с заданным размером страницы (с `expand` МойСклад отдаёт не больше 100)
+    async fn for_each_page_sized<T: serde::de::DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        page_size: u32,
+        mut visit: impl FnMut(Vec<T>) -> bool,
+    ) -> Result<()> {
+        let separator = if endpoint.contains('?') { '&' } else { '?' };
+        let mut offset = 0;
+
+        loop {
+            let page: ApiResponse<T> = self
+                .get(&format!(
+                    "{}{}limit={}&offset={}",
+                    endpoint, separator, page_size, offset
+                ))
+                .await?;
+            let total = page.meta.and_then(|meta| meta.size);
+            let rows = page.rows.unwrap_or_default();
+            let fetched = rows.len() as u32;
+
+            if !visit(rows) {
+                return Ok(());
+            }
+
+            offset += fetched;
+            if fetched < page_size || total.is_some_and(|size| offset >= size) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Задать заказ, к которому относятся последующие изменения в журнале
+    pub fn set_audit_order(&self, order: Option<(String, String)>) {
+        *self.audit_order.lock().expect("audit lock poisoned") = order;
+    }
+
+    /// Записать запрос на изменение в журнал
+    fn audit<B: serde::Serialize>(
+        &self,
+        method: &str,
+        endpoint: &str,
+        body: Option<&B>,
+        result: &Result<String>,
+    ) {
+        let Some(ref audit) = self.audit else {
+            return;
+        };
+
+        let digest = body
+            .and_then(|b| serde_json::to_vec(b).ok())
+            .map(|payload| payload_digest(&payload));
+
+        // ID сущности: из ответа, для PUT/DELETE — из пути запроса
+        let entity_id = result
+            .as_ref()
+            .ok()
+            .and_then(|body| serde_json::from_str::<serde_json::Value>(body).ok())
+            .and_then(|v| v.get("id").and_then(|id| id.as_str()).map(str::to_string))
+            .or_else(|| match method {
+                "POST" => None,
+                _ => endpoint.rsplit('/').next().map(str::to_string),
+            });
+
+        let (order_id, order_name) = self
+            .audit_order
+            .lock()
+            .expect("audit lock poisoned")
+            .clone()
+            .unzip();
+
+        audit.append(AuditRecord {
+            timestamp: Utc::now(),
+            method: method.to_string(),
+            endpoint: endpoint.to_string(),
+            payload_digest: digest,
+            entity_id,
+            order_id,
+            order_name,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+    }
+
+    /// Остановить запись и вернуть записанные ответы
+    pub fn take_recording(&self) -> Vec<RecordedResponse> {
+        self.recording
+            .lock()
+            .expect("recording lock poisoned")
+            .take()
+            .unwrap_or_default()
+    }
+
+    /// Изменения, которые при воспроизведении не были отправлены в МойСклад
+    pub fn playback_writes(&self) -> Vec<RecordedWrite> {
+        self.playback.as_ref().map(Playback::writes).unwrap_or_default()
+    }
+
+    /// Выполнить GET запрос к API
+    async fn get<T: serde::de::DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
+        let url = if endpoint.starts_with("http") {
+            endpoint.to_string()
+        } else {
+            format!("{}{}", self.base_url, endpoint)
+        };
         
-        debug!("Response body (first 1000 chars): {}", &body[..body.len().min(1000)]);
+        debug!("GET request to: {}", redact(&url));
+
+        let relative = url.strip_prefix(self.base_url.as_str()).unwrap_or(&url);
+        let recorded = match self.playback {
+            Some(ref playback) => playback.get(relative)?,
+            None => None,
+        };
+        let body = match recorded {
+            Some(body) => body,
+            None => self.send(self.client.get(&url)).await?,
+        };
+        
+        debug!("Response body: {}", sanitize(&body));
+
+        if let Some(recording) = self.recording.lock().expect("recording lock poisoned").as_mut() {
+            recording.push(RecordedResponse {
+                endpoint: relative.to_string(),
+                body: serde_json::from_str(&body).unwrap_or_else(|_| body.clone().into()),
+            });
+        }
         
-        serde_json::from_str(&body).with_context(|| format!("Failed to parse response from {}: {}", url, &body[..body.len().min(500)]))
+        self.parse("GET", &url, &body).with_context(|| {
+            format!("Failed to parse response from {}: {}", redact(&url), sanitize(&body))
+        })
+    }
+
+    /// Разобрать ответ, отметив в статистике эндпоинта пропущенные поля
+    /// и неизвестные значения перечислений
+    fn parse<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        url: &str,
+        body: &str,
+    ) -> serde_json::Result<T> {
+        let path = reqwest::Url::parse(url)
+            .map(|url| url.path().to_string())
+            .unwrap_or_else(|_| url.to_string());
+        let endpoint = endpoint_label(
+            method,
+            path.strip_prefix(self.api_path.as_str()).unwrap_or(&path),
+        );
+
+        let (result, ignored, variants) = parse_tracked(body);
+        match &result {
+            Ok(_) => {
+                let (new, seen_before) = self.usage.record_unknown_fields(&endpoint, ignored);
+                if !new.is_empty() {
+                    let mut sample = new[..new.len().min(LOGGED_FIELDS_SAMPLE)].join(", ");
+                    if new.len() > LOGGED_FIELDS_SAMPLE {
+                        sample.push_str(&format!(" (+{} more)", new.len() - LOGGED_FIELDS_SAMPLE));
+                    }
+                    if seen_before {
+                        info!("New fields in Moysklad response {}: {}", endpoint, sample);
+                    } else {
+                        debug!("Fields of {} not used by the models: {}", endpoint, sample);
+                    }
+                }
+
+                // Ответ разобран, неизвестные значения заменены запасным вариантом модели
+                if !variants.is_empty() {
+                    let (new, count) = self.usage.record_unknown_variants(&endpoint, variants);
+                    if !new.is_empty() {
+                        warn!(
+                            "Moysklad response {} has values the models do not know ({} so far): {}",
+                            endpoint,
+                            count,
+                            new.join(", ")
+                        );
+                    }
+                }
+            }
+            Err(e) if is_unknown_variant(e) => {
+                // Каждый такой ответ — ошибка разбора; в лог попадает выборка
+                let (_, count) = self.usage.record_unknown_variants(&endpoint, vec![unknown_variant_label(e)]);
+                if count.is_power_of_two() {
+                    warn!(
+                        "Moysklad response {} has a value the models do not know ({} times): {}",
+                        endpoint, count, e
+                    );
+                }
+            }
+            Err(_) => {}
+        }
+        result
     }
 
     /// Выполнить POST запрос к API
@@ -62,29 +492,20 @@ impl MoyskladClient {
         endpoint: &str,
         body: &B,
     ) -> Result<T> {
-        let url = format!("{}{}", MOYSKLAD_API_BASE, endpoint);
+        let url = format!("{}{}", self.base_url, endpoint);
         
-        debug!("POST request to: {}", url);
+        debug!("POST request to: {}", redact(&url));
         
-        let response = self.client
-            .post(&url)
-            .bearer_auth(&self.token)
-            .header("Accept-Encoding", "gzip")
-            .header("Content-Type", "application/json")
-            .json(body)
-            .send()
-            .await
-            .context("Failed to send request")?;
-        
-        let status = response.status();
-        let response_body = response.text().await.context("Failed to read response body")?;
-        
-        if !status.is_success() {
-            warn!("API error response: {} - {}", status, response_body);
-            return Err(anyhow!("API error {}: {}", status, response_body));
-        }
+        let result = match self.playback {
+            Some(ref playback) => {
+                Ok(playback.write("POST", &self.base_url, endpoint, serde_json::to_value(body).ok()))
+            }
+            None => self.send(self.client.post(&url).json(body)).await,
+        };
+        self.audit("POST", endpoint, Some(body), &result);
+        let response_body = result?;
         
-        serde_json::from_str(&response_body).context("Failed to parse response")
+        self.parse("POST", &url, &response_body).context("Failed to parse response")
     }
 
     /// Выполнить PUT запрос к API
@@ -93,29 +514,34 @@ impl MoyskladClient {
         endpoint: &str,
         body: &B,
     ) -> Result<T> {
-        let url = format!("{}{}", MOYSKLAD_API_BASE, endpoint);
+        let url = format!("{}{}", self.base_url, endpoint);
         
-        debug!("PUT request to: {}", url);
+        debug!("PUT request to: {}", redact(&url));
         
-        let response = self.client
-            .put(&url)
-            .bearer_auth(&self.token)
-            .header("Accept-Encoding", "gzip")
-            .header("Content-Type", "application/json")
-            .json(body)
-            .send()
-            .await
-            .context("Failed to send request")?;
-        
-        let status = response.status();
-        let response_body = response.text().await.context("Failed to read response body")?;
-        
-        if !status.is_success() {
-            warn!("API error response: {} - {}", status, response_body);
-            return Err(anyhow!("API error {}: {}", status, response_body));
-        }
+        let result = match self.playback {
+            Some(ref playback) => {
+                Ok(playback.write("PUT", &self.base_url, endpoint, serde_json::to_value(body).ok()))
+            }
+            None => self.send(self.client.put(&url).json(body)).await,
+        };
+        self.audit("PUT", endpoint, Some(body), &result);
+        let response_body = result?;
         
-        serde_json::from_str(&response_body).context("Failed to parse response")
+        self.parse("PUT", &url, &response_body).context("Failed to parse response")
+    }
+
+    /// Выполнить DELETE запрос к API
+    async fn delete(&self, endpoint: &str) -> Result<()> {
+        let url = format!("{}{}", self.base_url, endpoint);
+
+        debug!("DELETE request to: {}", redact(&url));
+
+        let result = match self.playback {
+            Some(ref playback) => Ok(playback.write("DELETE", &self.base_url, endpoint, None)),
+            None => self.send(self.client.delete(&url)).await,
+        };
+        self.audit::<()>("DELETE", endpoint, None, &result);
+        result.map(|_| ())
     }
 
     /// Найти склад по названию
@@ -129,43 +555,158 @@ impl MoyskladClient {
         Ok(response.rows.and_then(|mut rows| rows.pop()))
     }
 
+    /// Найти единицу измерения по названию
+    pub async fn find_uom_by_name(&self, name: &str) -> Result<Option<EntityRef>> {
+        info!("Searching for unit of measure: {}", name);
+
+        let response: ApiResponse<EntityRef> = self
+            .get(&format!("/entity/uom?filter=name={}", urlencoding::encode(name)))
+            .await?;
+
+        Ok(response.rows.and_then(|mut rows| rows.pop()))
+    }
+
+    /// Получить склад по ID
+    pub async fn get_store_by_id(&self, id: &str) -> Result<EntityRef> {
+        debug!("Getting store: {}", id);
+
+        self.get(&format!("/entity/store/{}", id)).await
+    }
+
+    /// Найти проект по названию
+    pub async fn find_project_by_name(&self, name: &str) -> Result<Option<EntityRef>> {
+        info!("Searching for project: {}", name);
+
+        let response: ApiResponse<EntityRef> = self
+            .get(&format!("/entity/project?filter=name={}", urlencoding::encode(name)))
+            .await?;
+
+        Ok(response.rows.and_then(|mut rows| rows.pop()))
+    }
+
     /// Получить остаток конкретного товара на складе
-    pub async fn get_product_stock(&self, product_id: &str, store_id: &str) -> Result<f64> {
+    pub async fn get_product_stock(&self, product_id: &str, store_id: &str) -> Result<Decimal> {
+        // Остаток в режиме STOCK_MODE (по умолчанию доступный: stock - reserve)
+        Ok(self
+            .get_product_stock_info(product_id, store_id)
+            .await?
+            .map(|info| self.stock_mode.effective(info.stock, info.reserve, info.in_transit))
+            .unwrap_or_default())
+    }
+
+    /// Количество товара в непроведённых тех. операциях с выпуском на склад
+    pub async fn get_pending_production_quantity(&self, product_id: &str, store_href: &str) -> Result<Decimal> {
+        debug!("Getting pending productions of {} on {}", product_id, store_href);
+
+        Ok(self
+            .find_store_processings(store_href, "applicable=false")
+            .await?
+            .iter()
+            .map(|processing| processing.produced_quantity(product_id))
+            .sum())
+    }
+
+    /// Непроведённые тех. операции с выпуском на склад (с развёрнутыми продуктами)
+    pub async fn get_pending_productions(&self, store_href: &str) -> Result<Vec<Processing>> {
+        debug!("Getting pending productions on {}", store_href);
+
+        self.find_store_processings(store_href, "applicable=false").await
+    }
+
+    /// Непроведённые и сегодняшние тех. операции, выпускающие товар на склад
+    pub async fn find_recent_productions(&self, product_id: &str, store_href: &str) -> Result<Vec<Processing>> {
+        debug!("Searching for recent productions of {} on {}", product_id, store_href);
+
+        let today = chrono::Local::now().date_naive().format("%Y-%m-%d 00:00:00").to_string();
+        let mut found = self.find_store_processings(store_href, "applicable=false").await?;
+        for processing in self
+            .find_store_processings(store_href, &format!("moment>={}", urlencoding::encode(&today)))
+            .await?
+        {
+            if !found.iter().any(|p| p.id == processing.id) {
+                found.push(processing);
+            }
+        }
+
+        found.retain(|processing| processing.produced_quantity(product_id) > Decimal::ZERO);
+        Ok(found)
+    }
+
+    /// Тех. операции с выпуском на склад по дополнительному фильтру (с развёрнутыми продуктами)
+    async fn find_store_processings(&self, store_href: &str, filter: &str) -> Result<Vec<Processing>> {
+        let response: ApiResponse<Processing> = self
+            .get(&format!(
+                "/entity/processing?filter={};productsStore={}&expand=products&limit=100",
+                filter,
+                urlencoding::encode(store_href)
+            ))
+            .await?;
+
+        Ok(response.rows.unwrap_or_default())
+    }
+
+    /// Получить строку остатков (остаток, резерв, ожидание) товара на складе.
+    /// Отчёт фильтруется по товару и складу, а не просматривается целиком для каждого товара.
+    pub async fn get_product_stock_info(
+        &self,
+        product_id: &str,
+        store_id: &str,
+    ) -> Result<Option<StoreStockInfo>> {
         debug!("Getting stock for product {} on store {}", product_id, store_id);
-        
-        // Получаем все остатки и фильтруем по product_id и store_id
+
+        let product_href = format!("{}/entity/product/{}", self.base_url, product_id);
+        let store_href = format!("{}/entity/store/{}", self.base_url, store_id);
         let response: ApiResponse<StockByStoreRow> = self
-            .get("/report/stock/bystore?limit=1000")
+            .get(&format!(
+                "/report/stock/bystore?filter=product={};store={}",
+                urlencoding::encode(&product_href),
+                urlencoding::encode(&store_href)
+            ))
             .await?;
-        
-        if let Some(rows) = response.rows {
-            for row in rows {
-                // Извлекаем ID продукта из meta.href
-                let row_product_id = row.meta.href
-                    .rsplit('/')
-                    .next()
-                    .unwrap_or("");
-                
-                if row_product_id == product_id {
-                    // Нашли нужный продукт, ищем нужный склад
-                    if let Some(stocks) = &row.stock_by_store {
-                        for store_stock in stocks {
-                            let row_store_id = store_stock.meta.href
-                                .rsplit('/')
-                                .next()
-                                .unwrap_or("");
-                            
-                            if row_store_id == store_id {
-                                // Возвращаем доступный остаток (stock - reserve)
-                                return Ok(store_stock.stock - store_stock.reserve);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        
-        Ok(0.0)
+
+        // Товара без остатка в отчёте нет
+        Ok(response
+            .rows
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|row| row.meta.href.rsplit('/').next() == Some(product_id))
+            .flat_map(|row| row.stock_by_store.unwrap_or_default())
+            .find(|stock| stock.meta.href.rsplit('/').next() == Some(store_id)))
+    }
+
+    /// Получить остатки всех товаров на складе
+    pub async fn get_store_stock(&self, store_href: &str) -> Result<Vec<StockRow>> {
+        debug!("Getting stock report for store {}", store_href);
+
+        let endpoint = format!("/report/stock/all?filter=store={}", urlencoding::encode(store_href));
+        let mut rows = Vec::new();
+        self.for_each_page(&endpoint, |page: Vec<StockRow>| {
+            rows.extend(page);
+            true
+        })
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Продажи по товарам со склада за период (`from`, `to` — даты МойСклад)
+    pub async fn get_sales_by_product(&self, store_href: &str, from: &str, to: &str) -> Result<Vec<SalesRow>> {
+        debug!("Getting sales by product between {} and {}", from, to);
+
+        let endpoint = format!(
+            "/report/profit/byproduct?momentFrom={}&momentTo={}&filter=store={}",
+            urlencoding::encode(from),
+            urlencoding::encode(to),
+            urlencoding::encode(store_href)
+        );
+        let mut rows = Vec::new();
+        self.for_each_page(&endpoint, |page: Vec<SalesRow>| {
+            rows.extend(page);
+            true
+        })
+        .await?;
+
+        Ok(rows)
     }
 
     /// Получить товар с атрибутами
@@ -176,14 +717,96 @@ impl MoyskladClient {
             .await
     }
 
+    /// Получить группу товаров по ссылке
+    pub async fn get_product_folder(&self, href: &str) -> Result<ProductFolder> {
+        debug!("Getting product folder: {}", href);
+
+        self.get(href).await
+    }
+
+    /// Получить товары с заполненным дополнительным полем
+    pub async fn get_products_with_attribute(&self, attribute_href: &str) -> Result<Vec<Product>> {
+        debug!("Getting products with attribute {}", attribute_href);
+
+        let endpoint = format!("/entity/product?filter={}!=", urlencoding::encode(attribute_href));
+        let mut products = Vec::new();
+        self.for_each_page(&endpoint, |page: Vec<Product>| {
+            products.extend(page);
+            true
+        })
+        .await?;
+
+        Ok(products)
+    }
+
+    /// Получить все товары с дополнительными полями
+    pub async fn get_products(&self) -> Result<Vec<Product>> {
+        debug!("Getting all products");
+
+        let mut products = Vec::new();
+        self.for_each_page("/entity/product", |page: Vec<Product>| {
+            products.extend(page);
+            true
+        })
+        .await?;
+
+        Ok(products)
+    }
+
+    /// Получить описания дополнительных полей товаров
+    pub async fn get_product_attributes_metadata(&self) -> Result<Vec<AttributeMetadata>> {
+        debug!("Getting product attributes metadata");
+
+        let response: ApiResponse<AttributeMetadata> =
+            self.get("/entity/product/metadata/attributes").await?;
+
+        Ok(response.rows.unwrap_or_default())
+    }
+
     /// Найти тех. карту по названию
     pub async fn find_processing_plan_by_name(&self, name: &str) -> Result<Option<ProcessingPlan>> {
         info!("Searching for processing plan: {}", name);
         
+        self.find_processing_plan("name", name).await
+    }
+
+    /// Названия тех. карт, содержащие `pattern` (версии `ТК-001 v2`): постранично и без
+    /// `expand`, полные тех. карты запрашиваются по точному названию
+    pub async fn find_processing_plan_names(&self, pattern: &str) -> Result<Vec<String>> {
+        info!("Searching for processing plans containing: {}", pattern);
+
+        let endpoint = format!("/entity/processingplan?filter=name~={}", urlencoding::encode(pattern));
+        let mut names = Vec::new();
+        self.for_each_page(&endpoint, |page: Vec<ProcessingPlan>| {
+            names.extend(page.into_iter().map(|plan| plan.name));
+            true
+        })
+        .await?;
+
+        Ok(names)
+    }
+
+    /// Найти тех. карту по внешнему коду
+    pub async fn find_processing_plan_by_external_code(&self, external_code: &str) -> Result<Option<ProcessingPlan>> {
+        info!("Searching for processing plan by external code: {}", external_code);
+
+        self.find_processing_plan("externalCode", external_code).await
+    }
+
+    /// Найти тех. карту по коду
+    pub async fn find_processing_plan_by_code(&self, code: &str) -> Result<Option<ProcessingPlan>> {
+        info!("Searching for processing plan by code: {}", code);
+
+        self.find_processing_plan("code", code).await
+    }
+
+    /// Найти тех. карту по значению поля
+    async fn find_processing_plan(&self, field: &str, value: &str) -> Result<Option<ProcessingPlan>> {
         let response: ApiResponse<ProcessingPlan> = self
             .get(&format!(
-                "/entity/processingplan?filter=name={}&expand=materials,products",
-                urlencoding::encode(name)
+                "/entity/processingplan?filter={}={}&expand=materials,products",
+                field,
+                urlencoding::encode(value)
             ))
             .await?;
         
@@ -197,6 +820,109 @@ impl MoyskladClient {
         self.post("/entity/processing", request).await
     }
 
+    /// Создать оприходование
+    pub async fn create_enter(&self, request: &CreateEnterRequest) -> Result<Enter> {
+        info!("Creating enter document");
+
+        self.post("/entity/enter", request).await
+    }
+
+    /// Создать перемещение
+    pub async fn create_move(&self, request: &CreateMoveRequest) -> Result<Move> {
+        info!("Creating move document");
+
+        self.post("/entity/move", request).await
+    }
+
+    /// Найти контрагента по названию
+    pub async fn find_counterparty_by_name(&self, name: &str) -> Result<Option<EntityRef>> {
+        info!("Searching for counterparty: {}", name);
+
+        let response: ApiResponse<EntityRef> = self
+            .get(&format!("/entity/counterparty?filter=name={}", urlencoding::encode(name)))
+            .await?;
+
+        Ok(response.rows.and_then(|mut rows| rows.pop()))
+    }
+
+    /// Найти пользовательский справочник по названию, вернуть его ID
+    pub async fn find_custom_entity(&self, name: &str) -> Result<Option<String>> {
+        info!("Searching for custom entity: {}", name);
+
+        let metadata: CompanySettingsMetadata = self.get("/context/companysettings/metadata").await?;
+
+        Ok(metadata
+            .custom_entities
+            .iter()
+            .find(|e| e.name == name)
+            .and_then(|e| e.id())
+            .map(str::to_string))
+    }
+
+    /// Добавить элемент в пользовательский справочник
+    pub async fn create_custom_entity_element(
+        &self,
+        entity_id: &str,
+        request: &CreateCustomEntityElementRequest,
+    ) -> Result<CustomEntityElement> {
+        self.post(&format!("/entity/customentity/{}", entity_id), request).await
+    }
+
+    /// Создать заказ поставщику
+    pub async fn create_purchase_order(&self, request: &CreatePurchaseOrderRequest) -> Result<PurchaseOrder> {
+        info!("Creating purchase order");
+
+        self.post("/entity/purchaseorder", request).await
+    }
+
+    /// Найти сотрудника по email или ID
+    pub async fn find_employee(&self, email_or_id: &str) -> Result<Option<EntityRef>> {
+        info!("Searching for employee: {}", email_or_id);
+
+        if !email_or_id.contains('@') {
+            return self
+                .get(&format!("/entity/employee/{}", urlencoding::encode(email_or_id)))
+                .await
+                .map(Some);
+        }
+
+        let response: ApiResponse<EntityRef> = self
+            .get(&format!("/entity/employee?filter=email={}", urlencoding::encode(email_or_id)))
+            .await?;
+
+        Ok(response.rows.and_then(|mut rows| rows.pop()))
+    }
+
+    /// Создать задачу
+    pub async fn create_task(&self, request: &CreateTaskRequest) -> Result<Task> {
+        info!("Creating task");
+
+        self.post("/entity/task", request).await
+    }
+
+    /// Удалить тех. операцию
+    pub async fn delete_processing(&self, processing_id: &str) -> Result<()> {
+        self.delete_document("processing", processing_id).await
+    }
+
+    /// Распровести документ (тех. операцию, перемещение, оприходование, заказ поставщику)
+    pub async fn unapply_document(&self, entity_type: &str, id: &str) -> Result<serde_json::Value> {
+        info!("Unapplying {}: {}", entity_type, id);
+
+        self.put(
+            &format!("/entity/{}/{}", entity_type, id),
+            &serde_json::json!({ "applicable": false }),
+        )
+        .await
+    }
+
+    /// Удалить документ
+    pub async fn delete_document(&self, entity_type: &str, id: &str) -> Result<()> {
+        info!("Deleting {}: {}", entity_type, id);
+
+        self.delete(&format!("/entity/{}/{}", entity_type, id)).await
+    }
+
     /// Провести тех. операцию
     pub async fn apply_processing(&self, processing_id: &str) -> Result<Processing> {
         info!("Applying processing: {}", processing_id);
@@ -221,14 +947,233 @@ impl MoyskladClient {
         Ok(response.rows.and_then(|mut rows| rows.pop()))
     }
 
+    /// Получить организацию по ID
+    pub async fn get_organization_by_id(&self, id: &str) -> Result<EntityRef> {
+        debug!("Getting organization: {}", id);
+
+        self.get(&format!("/entity/organization/{}", id)).await
+    }
+
     /// Получить заказ покупателя по ID
     pub async fn get_customer_order(&self, order_id: &str) -> Result<CustomerOrder> {
         info!("Getting customer order: {}", order_id);
 
         self.get(&format!(
-            "/entity/customerorder/{}?expand=positions,store,organization,agent",
+            "/entity/customerorder/{}?expand=positions,positions.assortment,store,organization,agent,salesChannel",
+            order_id
+        ))
+        .await
+    }
+
+    /// Получить розничную продажу по ID
+    pub async fn get_retail_demand(&self, demand_id: &str) -> Result<RetailDemand> {
+        info!("Getting retail demand: {}", demand_id);
+
+        self.get(&format!(
+            "/entity/retaildemand/{}?expand=positions,positions.assortment,store,organization,agent,salesChannel",
+            demand_id
+        ))
+        .await
+    }
+
+    /// Получить внутренний заказ по ID
+    pub async fn get_internal_order(&self, order_id: &str) -> Result<InternalOrder> {
+        info!("Getting internal order: {}", order_id);
+
+        self.get(&format!(
+            "/entity/internalorder/{}?expand=positions,positions.assortment,store,organization",
             order_id
         ))
         .await
     }
+
+    /// Получить приёмку по ID
+    pub async fn get_supply(&self, supply_id: &str) -> Result<Supply> {
+        info!("Getting supply: {}", supply_id);
+
+        self.get(&format!("/entity/supply/{}?expand=store", supply_id))
+            .await
+    }
+
+    /// Получить корректировку остатка по ID (`entity_type` — inventory, enter или loss)
+    pub async fn get_stock_correction(&self, entity_type: &str, id: &str) -> Result<StockCorrection> {
+        info!("Getting {}: {}", entity_type, id);
+
+        self.get(&format!(
+            "/entity/{}/{}?expand=positions,positions.assortment,store,organization",
+            entity_type, id
+        ))
+        .await
+    }
+
+    /// Проведённые заказы покупателей со склада, содержащие товар, которые ещё могут быть
+    /// не отгружены: с плановой отгрузкой в периоде (`from` и `to` — даты МойСклад,
+    /// включительно) и без даты отгрузки
+    pub async fn get_open_customer_orders_with_product(
+        &self,
+        product_href: &str,
+        store_href: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<CustomerOrder>> {
+        debug!("Getting customer orders with product {} shipping between {} and {}", product_href, from, to);
+
+        let filter = format!(
+            "applicable=true;store={};assortment={}",
+            urlencoding::encode(store_href),
+            urlencoding::encode(product_href)
+        );
+        let periods = [
+            format!(
+                "deliveryPlannedMoment>={};deliveryPlannedMoment<={}",
+                urlencoding::encode(from),
+                urlencoding::encode(to)
+            ),
+            "deliveryPlannedMoment=".to_string(),
+        ];
+        let mut orders = Vec::new();
+        for period in &periods {
+            let endpoint = format!("/entity/customerorder?filter={};{}&expand=positions,store", filter, period);
+            self.for_each_page_sized(&endpoint, 100, |page: Vec<CustomerOrder>| {
+                orders.extend(page);
+                true
+            })
+            .await?;
+        }
+
+        Ok(orders)
+    }
+
+    /// Проведённые заказы покупателей со склада с плановой отгрузкой в периоде
+    /// (`from` и `to` — даты МойСклад, включительно)
+    pub async fn get_customer_orders_shipping_between(
+        &self,
+        store_href: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<CustomerOrder>> {
+        debug!("Getting customer orders shipping between {} and {}", from, to);
+
+        let endpoint = format!(
+            "/entity/customerorder?filter=applicable=true;store={};deliveryPlannedMoment>={};\
+             deliveryPlannedMoment<={}&expand=positions,positions.assortment",
+            urlencoding::encode(store_href),
+            urlencoding::encode(from),
+            urlencoding::encode(to)
+        );
+        let mut orders = Vec::new();
+        self.for_each_page_sized(&endpoint, 100, |page: Vec<CustomerOrder>| {
+            orders.extend(page);
+            true
+        })
+        .await?;
+
+        Ok(orders)
+    }
+
+    /// Получить комментарий (описание) документа
+    pub async fn get_document_description(&self, entity_type: &str, id: &str) -> Result<Option<String>> {
+        debug!("Getting description of {} {}", entity_type, id);
+
+        #[derive(serde::Deserialize)]
+        struct Document {
+            description: Option<String>,
+        }
+
+        let document: Document = self.get(&format!("/entity/{}/{}", entity_type, id)).await?;
+        Ok(document.description)
+    }
+
+    /// Заменить комментарий (описание) документа
+    pub async fn update_document_description(
+        &self,
+        entity_type: &str,
+        id: &str,
+        description: &str,
+    ) -> Result<()> {
+        info!("Updating description of {} {}", entity_type, id);
+
+        let _: serde_json::Value = self
+            .put(
+                &format!("/entity/{}/{}", entity_type, id),
+                &serde_json::json!({ "description": description }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Прикрепить файл к документу
+    pub async fn attach_file(
+        &self,
+        entity_type: &str,
+        id: &str,
+        filename: &str,
+        content: &[u8],
+    ) -> Result<()> {
+        use base64::Engine;
+
+        info!("Attaching file {} to {} {}", filename, entity_type, id);
+
+        let _: serde_json::Value = self
+            .post(
+                &format!("/entity/{}/{}/files", entity_type, id),
+                &serde_json::json!([{
+                    "filename": filename,
+                    "content": base64::engine::general_purpose::STANDARD.encode(content),
+                }]),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Имена файлов, прикреплённых к документу
+    pub async fn get_attached_files(&self, entity_type: &str, id: &str) -> Result<Vec<String>> {
+        debug!("Getting files of {} {}", entity_type, id);
+
+        #[derive(serde::Deserialize)]
+        struct AttachedFile {
+            filename: String,
+        }
+
+        let response: ApiResponse<AttachedFile> =
+            self.get(&format!("/entity/{}/{}/files?limit=100", entity_type, id)).await?;
+        Ok(response.rows.unwrap_or_default().into_iter().map(|f| f.filename).collect())
+    }
+
+    /// Получить подписки на webhook аккаунта
+    pub async fn get_webhooks(&self) -> Result<Vec<Webhook>> {
+        debug!("Getting webhook subscriptions");
+
+        let mut webhooks = Vec::new();
+        self.for_each_page("/entity/webhook", |page: Vec<Webhook>| {
+            webhooks.extend(page);
+            true
+        })
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    /// Проверить доступность API МойСклад
+    pub async fn ping(&self) -> Result<()> {
+        let _: ApiResponse<EntityRef> = self.get("/entity/organization?limit=1").await?;
+        Ok(())
+    }
+}
+
+/// Ответ 401 Unauthorized
+fn is_unauthorized(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<ApiError>(),
+        Some(ApiError::Status { status: 401, .. })
+    )
+}
+
+/// Числовое значение заголовка ответа
+fn header_u64(response: &reqwest::Response, name: &str) -> Option<u64> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
 }
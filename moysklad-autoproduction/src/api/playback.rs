@@ -0,0 +1,142 @@
+//! Воспроизведение записанных ответов API: обработка идёт без обращения к МойСклад
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use super::moysklad::RecordedResponse;
+
+/// Запрос на изменение, который при воспроизведении не отправляется в МойСклад
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RecordedWrite {
+    pub method: String,
+    /// Путь запроса относительно адреса API
+    pub endpoint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+    /// Id, выданный созданному документу
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+/// Преобразование пути запроса перед поиском записи (например, анонимизация фильтров)
+pub type EndpointMapper = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Записанные ответы и изменения, сделанные при воспроизведении
+pub struct Playback {
+    /// Ответы по пути запроса; повторяющиеся запросы получают ответы по порядку,
+    /// последний ответ отдаётся и дальше
+    responses: Mutex<HashMap<String, VecDeque<Value>>>,
+    /// Без записи запрос читается из МойСклад (`true`) или завершается ошибкой
+    live_reads: bool,
+    mapper: Option<EndpointMapper>,
+    writes: Mutex<Vec<RecordedWrite>>,
+    /// Номер для ID созданных документов
+    created: Mutex<u64>,
+}
+
+impl Playback {
+    pub fn new(responses: Vec<RecordedResponse>, live_reads: bool) -> Self {
+        let mut map: HashMap<String, VecDeque<Value>> = HashMap::new();
+        for response in responses {
+            map.entry(response.endpoint).or_default().push_back(response.body);
+        }
+
+        Self {
+            responses: Mutex::new(map),
+            live_reads,
+            mapper: None,
+            writes: Mutex::new(Vec::new()),
+            created: Mutex::new(0),
+        }
+    }
+
+    /// Искать записи также по пути после `mapper`, если точного совпадения нет
+    pub fn with_mapper(mut self, mapper: EndpointMapper) -> Self {
+        self.mapper = Some(mapper);
+        self
+    }
+
+    /// Ответ на GET запрос: точное совпадение пути, путь после преобразования, путь без
+    /// параметров (документ из события). `None` — записи нет, запрос читается из МойСклад.
+    pub fn get(&self, endpoint: &str) -> Result<Option<String>> {
+        let mut responses = self.responses.lock().expect("playback lock poisoned");
+        let path = endpoint.split('?').next().unwrap_or(endpoint).to_string();
+        let candidates = [
+            Some(endpoint.to_string()),
+            self.mapper.as_ref().map(|mapper| mapper(endpoint)),
+            Some(path),
+        ];
+
+        for key in candidates.into_iter().flatten() {
+            if let Some(queue) = responses.get_mut(&key) {
+                let body = if queue.len() > 1 { queue.pop_front() } else { queue.front().cloned() };
+                if let Some(body) = body {
+                    return Ok(Some(body.to_string()));
+                }
+            }
+        }
+
+        if self.live_reads {
+            Ok(None)
+        } else {
+            Err(anyhow!("No recorded response for GET {}", endpoint))
+        }
+    }
+
+    /// Сохранить изменение и вернуть ответ, как у МойСклад: тело запроса с `id` и `meta`.
+    /// ID созданного документа — UUID с порядковым номером, у изменения — ID из пути.
+    pub fn write(&self, method: &str, base_url: &str, endpoint: &str, body: Option<Value>) -> String {
+        let recorded = body.clone();
+        let path = endpoint.split('?').next().unwrap_or(endpoint);
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let echo = |mut value: Value, entity_type: &str, id: String| {
+            if let Value::Object(ref mut map) = value {
+                map.entry("id").or_insert_with(|| id.clone().into());
+                map.entry("meta").or_insert_with(|| {
+                    json!({
+                        "href": format!("{}/entity/{}/{}", base_url, entity_type, id),
+                        "type": entity_type,
+                        "mediaType": "application/json",
+                    })
+                });
+            }
+            value
+        };
+
+        let response = match (method, segments.as_slice(), body) {
+            ("POST", ["entity", entity_type], Some(Value::Array(items))) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|item| echo(item, entity_type, self.next_id()))
+                    .collect(),
+            ),
+            ("POST", ["entity", entity_type], Some(item)) => echo(item, entity_type, self.next_id()),
+            ("PUT", ["entity", entity_type, id], Some(item)) => echo(item, entity_type, id.to_string()),
+            (_, _, Some(item)) => item,
+            (_, _, None) => Value::Null,
+        };
+        self.writes.lock().expect("playback lock poisoned").push(RecordedWrite {
+            method: method.to_string(),
+            endpoint: endpoint.to_string(),
+            body: recorded,
+            id: match method {
+                "POST" => response.get("id").and_then(Value::as_str).map(str::to_string),
+                _ => None,
+            },
+        });
+        response.to_string()
+    }
+
+    fn next_id(&self) -> String {
+        let mut created = self.created.lock().expect("playback lock poisoned");
+        *created += 1;
+        format!("00000000-0000-4000-8000-{:012x}", *created)
+    }
+
+    /// Изменения, сделанные при воспроизведении
+    pub fn writes(&self) -> Vec<RecordedWrite> {
+        self.writes.lock().expect("playback lock poisoned").clone()
+    }
+}
@@ -0,0 +1,62 @@
+//! Очистка текста, попадающего в логи и ответы об ошибках: без учётных данных и длинных тел
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Максимальная длина тела ответа в логах и сообщениях об ошибках, символов
+pub const MAX_SURFACED_BODY: usize = 500;
+
+/// Шаблоны учётных данных и их замена
+static SECRET_PATTERNS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        // Заголовок Authorization в любом виде, включая JSON и дампы заголовков
+        (r#"(?i)("?authorization"?\s*[:=]\s*"?)(?:bearer\s+|basic\s+)?[^"\s,;}]+"#, "${1}***"),
+        (r"(?i)\b(bearer|basic)\s+[A-Za-z0-9\-._~+/]+=*", "${1} ***"),
+        // Параметры URL и форм
+        (
+            r"(?i)([?&](?:access_token|token|password|secret|api_key|apikey|key)=)[^&\s]+",
+            "${1}***",
+        ),
+        // Поля JSON
+        (
+            r#"(?i)("(?:access_token|token|password|secret|secret_key|api_key)"\s*:\s*")[^"]*""#,
+            r#"${1}***""#,
+        ),
+        // Логин и пароль в URL
+        (r"(?i)(https?://)[^/\s:@]+:[^/\s@]+@", "${1}***@"),
+        // Токен бота Telegram в пути запроса
+        (r"/bot\d+:[A-Za-z0-9_-]+", "/bot***"),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| {
+        (Regex::new(pattern).expect("valid redaction pattern"), replacement)
+    })
+    .collect()
+});
+
+/// Скрыть токены, пароли и заголовки авторизации
+pub fn redact(text: &str) -> String {
+    SECRET_PATTERNS
+        .iter()
+        .fold(text.to_string(), |text, (pattern, replacement)| {
+            pattern.replace_all(&text, *replacement).into_owned()
+        })
+}
+
+/// Обрезать текст до `max_chars` символов с пометкой об исходной длине
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}… ({} bytes total)", &text[..end], text.len()),
+        None => text.to_string(),
+    }
+}
+
+/// Текст для логов и ответов об ошибках: без учётных данных и не длиннее `MAX_SURFACED_BODY`
+pub fn sanitize(text: &str) -> String {
+    truncate(&redact(text), MAX_SURFACED_BODY)
+}
+
+/// Сообщение об ошибке для HTTP ответа
+pub fn error_message(error: &anyhow::Error) -> String {
+    sanitize(&error.to_string())
+}
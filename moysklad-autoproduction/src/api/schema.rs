@@ -0,0 +1,61 @@
+//! Поля и значения ответов МойСклад, которые модели не разбирают: признак изменений API
+//! до того, как разбор ответа начнёт падать
+
+use serde::de::DeserializeOwned;
+use serde_ignored::Path;
+use std::collections::BTreeSet;
+
+use crate::models::take_unknown_variants;
+
+/// Сколько новых полей показывать в одной строке лога
+pub const LOGGED_FIELDS_SAMPLE: usize = 5;
+
+/// Разобрать ответ, собрав пути пропущенных полей (`rows[].owner`) и неизвестные
+/// значения перечислений, которые модели разобрали без ошибки (`AttributeType::geo`).
+/// Индексы массивов не различаются, чтобы число путей не росло с размером ответа.
+pub fn parse_tracked<T: DeserializeOwned>(
+    body: &str,
+) -> (serde_json::Result<T>, BTreeSet<String>, Vec<String>) {
+    // Значения, оставшиеся от разбора вне клиента, к этому ответу не относятся
+    take_unknown_variants();
+
+    let mut ignored = BTreeSet::new();
+    let mut deserializer = serde_json::Deserializer::from_str(body);
+    let result = serde_ignored::deserialize(&mut deserializer, |path| {
+        ignored.insert(path_label(&path));
+    })
+    .and_then(|value| deserializer.end().map(|()| value));
+    (result, ignored, take_unknown_variants())
+}
+
+/// Ошибка разбора из-за значения перечисления, которого модель не знает
+pub fn is_unknown_variant(e: &serde_json::Error) -> bool {
+    let message = e.to_string();
+    message.contains("unknown variant") || message.contains("did not match any variant")
+}
+
+/// Неизвестное значение из ошибки разбора: `geo` из «unknown variant `geo`, expected ...»,
+/// иначе имя перечисления
+pub fn unknown_variant_label(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+    let quoted = message.split('`').nth(1);
+    let untagged = message
+        .split("any variant of untagged enum ")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next());
+    quoted.or(untagged).unwrap_or("unknown").to_string()
+}
+
+fn path_label(path: &Path) -> String {
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, .. } => format!("{}[]", path_label(parent)),
+        Path::Map { parent, key } => match path_label(parent) {
+            parent if parent.is_empty() => key.to_string(),
+            parent => format!("{}.{}", parent, key),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => path_label(parent),
+    }
+}
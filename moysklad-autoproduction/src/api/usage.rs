@@ -0,0 +1,204 @@
+//! Статистика обращений к API МойСклад: вызовы по эндпоинтам, задержка и остаток лимита
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Default)]
+struct EndpointCounters {
+    calls: u64,
+    errors: u64,
+    total_latency: Duration,
+    /// Разобранных ответов
+    parsed: u64,
+    /// Поля ответов, которые модели пропускают
+    unknown_fields: BTreeSet<String>,
+    /// Пропускаемые поля, которых не было в первом разобранном ответе: признак изменения API
+    new_fields: BTreeSet<String>,
+    /// Неизвестные значения перечислений (разобранные и вызвавшие ошибку разбора)
+    unknown_variants: u64,
+    unknown_variant_values: BTreeSet<String>,
+}
+
+#[derive(Default)]
+struct Inner {
+    endpoints: BTreeMap<String, EndpointCounters>,
+    rate_limit: RateLimitStatus,
+}
+
+/// Статистика по эндпоинту
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointUsage {
+    pub endpoint: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub avg_latency_ms: f64,
+    #[serde(skip)]
+    pub total_latency_secs: f64,
+    /// Поля ответов, которые модели пропускают
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_fields: Vec<String>,
+    /// Пропускаемые поля, появившиеся после первого разобранного ответа
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub new_fields: Vec<String>,
+    pub unknown_variants: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_variant_values: Vec<String>,
+}
+
+/// Лимит запросов по заголовкам последнего ответа
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RateLimitStatus {
+    /// `X-RateLimit-Limit`
+    pub limit: Option<u64>,
+    /// `X-RateLimit-Remaining`
+    pub remaining: Option<u64>,
+    /// Доля оставшегося лимита, %
+    pub headroom_percent: Option<f64>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Снимок статистики
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiUsageSnapshot {
+    pub total_calls: u64,
+    pub total_errors: u64,
+    pub endpoints: Vec<EndpointUsage>,
+    pub rate_limit: RateLimitStatus,
+}
+
+/// Счётчики обращений к API одного аккаунта
+#[derive(Default)]
+pub struct ApiUsage {
+    inner: Mutex<Inner>,
+}
+
+impl ApiUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Зафиксировать запрос. `status` — `None`, если ответ не получен.
+    pub fn record(
+        &self,
+        endpoint: String,
+        status: Option<u16>,
+        latency: Duration,
+        limit: Option<u64>,
+        remaining: Option<u64>,
+    ) {
+        let mut inner = self.inner.lock().expect("api usage lock poisoned");
+
+        let counters = inner.endpoints.entry(endpoint).or_default();
+        counters.calls += 1;
+        counters.total_latency += latency;
+        if !status.is_some_and(|s| (200..300).contains(&s)) {
+            counters.errors += 1;
+        }
+
+        if limit.is_some() || remaining.is_some() {
+            let limit = limit.or(inner.rate_limit.limit);
+            inner.rate_limit = RateLimitStatus {
+                limit,
+                remaining,
+                headroom_percent: match (limit, remaining) {
+                    (Some(limit), Some(remaining)) if limit > 0 => {
+                        Some(remaining as f64 / limit as f64 * 100.0)
+                    }
+                    _ => None,
+                },
+                updated_at: Some(Utc::now()),
+            };
+        }
+    }
+
+    /// Зафиксировать поля, пропущенные при разборе ответа. Возвращает поля, которых
+    /// эндпоинт раньше не присылал, и был ли ответ эндпоинта уже разобран до этого
+    /// (иначе новые поля — исходный набор, а не изменение API).
+    pub fn record_unknown_fields(&self, endpoint: &str, fields: BTreeSet<String>) -> (Vec<String>, bool) {
+        let mut inner = self.inner.lock().expect("api usage lock poisoned");
+
+        let counters = inner.endpoints.entry(endpoint.to_string()).or_default();
+        let seen_before = counters.parsed > 0;
+        counters.parsed += 1;
+        let new: Vec<String> = fields
+            .into_iter()
+            .filter(|field| counters.unknown_fields.insert(field.clone()))
+            .collect();
+        if seen_before {
+            counters.new_fields.extend(new.iter().cloned());
+        }
+        (new, seen_before)
+    }
+
+    /// Зафиксировать неизвестные значения перечислений в ответе. Возвращает значения,
+    /// которых эндпоинт раньше не присылал, и общее число встреченных значений.
+    pub fn record_unknown_variants(&self, endpoint: &str, values: Vec<String>) -> (Vec<String>, u64) {
+        let mut inner = self.inner.lock().expect("api usage lock poisoned");
+        let counters = inner.endpoints.entry(endpoint.to_string()).or_default();
+        counters.unknown_variants += values.len() as u64;
+        let new = values
+            .into_iter()
+            .filter(|value| counters.unknown_variant_values.insert(value.clone()))
+            .collect();
+        (new, counters.unknown_variants)
+    }
+
+    /// Текущая статистика
+    pub fn snapshot(&self) -> ApiUsageSnapshot {
+        let inner = self.inner.lock().expect("api usage lock poisoned");
+
+        let endpoints: Vec<EndpointUsage> = inner
+            .endpoints
+            .iter()
+            .map(|(endpoint, c)| {
+                let total_latency_secs = c.total_latency.as_secs_f64();
+                EndpointUsage {
+                    endpoint: endpoint.clone(),
+                    calls: c.calls,
+                    errors: c.errors,
+                    avg_latency_ms: if c.calls > 0 {
+                        total_latency_secs * 1000.0 / c.calls as f64
+                    } else {
+                        0.0
+                    },
+                    total_latency_secs,
+                    unknown_fields: c.unknown_fields.iter().cloned().collect(),
+                    new_fields: c.new_fields.iter().cloned().collect(),
+                    unknown_variants: c.unknown_variants,
+                    unknown_variant_values: c.unknown_variant_values.iter().cloned().collect(),
+                }
+            })
+            .collect();
+
+        ApiUsageSnapshot {
+            total_calls: endpoints.iter().map(|e| e.calls).sum(),
+            total_errors: endpoints.iter().map(|e| e.errors).sum(),
+            endpoints,
+            rate_limit: inner.rate_limit.clone(),
+        }
+    }
+}
+
+/// Метка эндпоинта: метод и путь, в котором идентификаторы заменены на `{id}`
+pub fn endpoint_label(method: &str, path: &str) -> String {
+    let path: Vec<&str> = path
+        .split('/')
+        .map(|segment| if is_id(segment) { "{id}" } else { segment })
+        .collect();
+    format!("{} {}", method, path.join("/"))
+}
+
+/// UUID сущности МойСклад
+fn is_id(segment: &str) -> bool {
+    segment.len() == 36
+        && segment
+            .chars()
+            .enumerate()
+            .all(|(i, c)| match i {
+                8 | 13 | 18 | 23 => c == '-',
+                _ => c.is_ascii_hexdigit(),
+            })
+}
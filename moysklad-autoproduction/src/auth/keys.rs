@@ -0,0 +1,126 @@
+//! API-ключи и роли доступа к служебным эндпоинтам
+
+use anyhow::{anyhow, Context, Result};
+use ring::hmac;
+use ring::rand::SystemRandom;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::config::Settings;
+
+/// Роль пользователя API (по возрастанию прав)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Чтение истории, отчётов и остатков
+    Viewer,
+    /// Ручная обработка заказов
+    Operator,
+    /// Конфигурация и администрирование
+    Admin,
+}
+
+impl Role {
+    /// Разобрать роль из строки
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "viewer" => Some(Self::Viewer),
+            "operator" => Some(Self::Operator),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    /// Строковое представление
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Operator => "operator",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+/// Пользователь API
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiUser {
+    pub name: String,
+    pub key: String,
+    pub role: Role,
+}
+
+/// Набор API-ключей. Пустой набор отключает проверку.
+///
+/// Ключи сравниваются по HMAC со случайным ключом процесса (`hmac::verify` —
+/// сравнение за постоянное время), чтобы время ответа не выдавало совпавший префикс.
+pub struct ApiKeys {
+    users: Vec<(ApiUser, hmac::Tag)>,
+    signer: hmac::Key,
+}
+
+impl ApiKeys {
+    /// Загрузить ключи из API_KEYS (`ключ:роль,...`) и файла API_USERS_FILE
+    pub fn from_settings(settings: &Settings) -> Result<Self> {
+        let mut users = Vec::new();
+
+        for (index, item) in settings.api_keys.iter().enumerate() {
+            let (key, role) = item
+                .split_once(':')
+                .ok_or_else(|| anyhow!("API_KEYS entry #{} must be 'key:role'", index + 1))?;
+            let role = Role::parse(role)
+                .ok_or_else(|| anyhow!("Unknown role '{}' in API_KEYS entry #{}", role, index + 1))?;
+
+            users.push(ApiUser {
+                name: format!("key-{}", index + 1),
+                key: key.trim().to_string(),
+                role,
+            });
+        }
+
+        if let Some(ref path) = settings.api_users_file {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read API users file {}", path))?;
+            let file_users: Vec<ApiUser> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse API users file {}", path))?;
+            users.extend(file_users);
+        }
+
+        if users.iter().any(|u| u.key.is_empty()) {
+            return Err(anyhow!("API keys must not be empty"));
+        }
+
+        if users.is_empty() {
+            warn!("No API keys configured, service endpoints are not protected");
+        } else {
+            info!("API authentication enabled for {} users", users.len());
+        }
+
+        let signer = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .map_err(|_| anyhow!("Failed to generate API key verification secret"))?;
+        let users = users
+            .into_iter()
+            .map(|user| {
+                let tag = hmac::sign(&signer, user.key.as_bytes());
+                (user, tag)
+            })
+            .collect();
+
+        Ok(Self { users, signer })
+    }
+
+    /// Включена ли проверка ключей
+    pub fn enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    /// Найти пользователя по ключу. Проверяются все ключи, без раннего выхода.
+    pub fn find(&self, key: &str) -> Option<&ApiUser> {
+        let mut found = None;
+        for (user, tag) in &self.users {
+            if hmac::verify(&self.signer, key.as_bytes(), tag.as_ref()).is_ok() && found.is_none() {
+                found = Some(user);
+            }
+        }
+        found
+    }
+}
@@ -0,0 +1,92 @@
+//! Role checks for service endpoints
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use std::sync::Arc;
+use tracing::warn;
+
+use super::keys::Role;
+use crate::handlers::AppState;
+
+/// Role required for a route; `None` for public routes
+/// Anything that is not a read (non-GET) needs at least an operator.
+/// (health checks, the Moysklad webhook and the Vendor API, which carries its own token)
+pub fn required_role(method: &Method, path: &str) -> Option<Role> {
+    match path {
+        "/health" | "/readyz" | "/webhook" => None,
+        p if p.starts_with("/api/moysklad/vendor/") || p.starts_with("/vendor/context/") => None,
+        "/config" | "/plan/execute" => Some(Role::Admin),
+        p if p.starts_with("/admin/") => Some(Role::Admin),
+        _ if method != Method::GET && method != Method::HEAD => Some(Role::Operator),
+        _ => Some(Role::Viewer),
+    }
+}
+
+/// API key from `X-API-Key` or `Authorization: Bearer`
+fn request_key(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+
+    if let Some(key) = headers.get("X-API-Key").and_then(|v| v.to_str().ok()) {
+        return Some(key.to_string());
+    }
+
+    headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string())
+}
+
+fn reject<B>(req: ServiceRequest, response: HttpResponse) -> ServiceResponse<EitherBody<B>> {
+    req.into_response(response).map_into_right_body()
+}
+
+/// Reject requests without a key of sufficient role (401 / 403)
+pub async fn require_role(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let state = req.app_data::<web::Data<Arc<AppState>>>().cloned();
+
+    let (required, state) = match (required_role(req.method(), req.path()), state) {
+        (Some(required), Some(state)) if state.api_keys.enabled() => (required, state),
+        _ => return next.call(req).await.map(ServiceResponse::map_into_left_body),
+    };
+
+    let Some(key) = request_key(&req) else {
+        let response = HttpResponse::Unauthorized().json(serde_json::json!({
+            "status": "error",
+            "message": "API key required"
+        }));
+        return Ok(reject(req, response));
+    };
+
+    let Some(user) = state.api_keys.find(&key) else {
+        warn!("Rejected request to {} with unknown API key", req.path());
+        let response = HttpResponse::Unauthorized().json(serde_json::json!({
+            "status": "error",
+            "message": "Invalid API key"
+        }));
+        return Ok(reject(req, response));
+    };
+
+    if user.role < required {
+        warn!(
+            "User {} ({}) denied access to {} (requires {})",
+            user.name,
+            user.role.as_str(),
+            req.path(),
+            required.as_str()
+        );
+        let response = HttpResponse::Forbidden().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Role '{}' required", required.as_str())
+        }));
+        return Ok(reject(req, response));
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
@@ -0,0 +1,5 @@
+pub mod keys;
+pub mod middleware;
+
+pub use keys::*;
+pub use middleware::*;
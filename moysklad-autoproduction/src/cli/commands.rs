@@ -0,0 +1,240 @@
+//! Подкоманды командной строки для разовых операций
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{export_fixtures, play_fixtures};
+use crate::config::{SecretsKey, Settings, HANDLED_ENTITY_TYPES};
+use crate::models::{ProcessingResult, WebhookEvent};
+use crate::notifications::NotificationRouter;
+use crate::processing::ReplayFile;
+use crate::tenants::{Tenant, TenantRegistry, DEFAULT_TENANT};
+
+/// Автоматическое создание тех. операций при низких остатках товара
+#[derive(Debug, Parser)]
+#[command(name = "autoproduction", version, about)]
+pub struct Cli {
+    /// Аккаунт (тенант) для разовых команд: имя или accountId
+    #[arg(long, global = true)]
+    pub tenant: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Запустить HTTP сервер (по умолчанию)
+    Serve,
+    /// Обработать заказ покупателя по ID
+    #[command(alias = "process-demand")]
+    ProcessOrder {
+        /// ID заказа покупателя
+        id: String,
+        /// Вывести только позиции с ошибкой
+        #[arg(long)]
+        failed: bool,
+    },
+    /// Показать товары с остатком ниже порога на отслеживаемом складе
+    ScanStock,
+    /// Проверить настройки и доступ к МойСклад
+    CheckConfig,
+    /// Повторно обработать webhook события из JSON файла
+    Replay {
+        /// Файл с событием или массивом событий `{"id": ..., "type": ..., "accountId": ...}`
+        /// в формате тела `/admin/replay`
+        file: PathBuf,
+        /// Обработать в песочнице, как `/admin/replay`: без записи в МойСклад
+        #[arg(long)]
+        dry_run: bool,
+        /// Вывести только позиции с ошибкой
+        #[arg(long)]
+        failed: bool,
+    },
+    /// Зашифровать токен или пароль мастер-ключом для MOYSKLAD_TOKEN и TENANTS_FILE
+    EncryptSecret {
+        /// Значение; если не задано, читается из stdin
+        value: Option<String>,
+        /// Тенант, для которого шифруется значение: имя из TENANTS_FILE или `default`
+        /// для MOYSKLAD_TOKEN и MOYSKLAD_PASSWORD
+        #[arg(long, default_value = DEFAULT_TENANT)]
+        tenant: String,
+    },
+    /// Сохранить ответы API для заказа (товары, тех. карты, остатки) как фикстуры для `play-fixtures`.
+    /// Названия и ID заменяются детерминированно, заказ обрабатывается без записи в МойСклад
+    ExportFixtures {
+        /// ID заказа покупателя
+        id: String,
+        /// Файл фикстур
+        #[arg(long, default_value = "fixtures.json")]
+        out: PathBuf,
+        /// Соль для замены названий и ID
+        #[arg(long, default_value = "")]
+        salt: String,
+    },
+    /// Обработать заказ из фикстур `export-fixtures` без обращения к МойСклад
+    PlayFixtures {
+        /// Файл фикстур
+        #[arg(default_value = "fixtures.json")]
+        file: PathBuf,
+        /// Соль, с которой выгружены фикстуры
+        #[arg(long, default_value = "")]
+        salt: String,
+    },
+}
+
+/// Выполнить разовую команду
+pub async fn run(command: Command, tenant: Option<&str>, settings: Settings) -> Result<()> {
+    // Шифрование не требует доступа к аккаунтам
+    if let Command::EncryptSecret { ref value, ref tenant } = command {
+        return encrypt_secret(&settings, value.as_deref(), tenant);
+    }
+
+    let notifier = Arc::new(NotificationRouter::from_settings(&settings));
+    let tenants = TenantRegistry::load(&settings, notifier)?;
+
+    match command {
+        Command::Serve => Err(anyhow!("serve is handled by main")),
+        Command::ProcessOrder { id, failed } => {
+            let tenant = select_tenant(&tenants, tenant)?;
+            let results = tenant
+                .processor
+                .lock()
+                .await
+                .process_now(&WebhookEvent::customer_order(&id))
+                .await?;
+            print_job(&tenant, &id, results, failed)
+        }
+        Command::ScanStock => {
+            let tenant = select_tenant(&tenants, tenant)?;
+            let items = tenant.processor.lock().await.scan_stock().await?;
+            print_json(&items)
+        }
+        Command::CheckConfig => check_config(&tenants).await,
+        Command::Replay { file, dry_run, failed } => replay(&tenants, &file, dry_run, failed).await,
+        Command::ExportFixtures { id, out, salt } => {
+            let tenant = select_tenant(&tenants, tenant)?;
+            export_fixtures(&tenant, &id, &out, &salt).await
+        }
+        Command::PlayFixtures { file, salt } => {
+            let tenant = select_tenant(&tenants, tenant)?;
+            play_fixtures(&tenant, &file, &salt).await
+        }
+        Command::EncryptSecret { .. } => Err(anyhow!("encrypt-secret is handled above")),
+    }
+}
+
+fn encrypt_secret(settings: &Settings, value: Option<&str>, tenant: &str) -> Result<()> {
+    let key = SecretsKey::from_settings(settings)?
+        .ok_or_else(|| anyhow!("SECRETS_KEY or SECRETS_KEY_FILE is required"))?;
+
+    let value = match value {
+        Some(value) => value.to_string(),
+        None => {
+            let mut value = String::new();
+            std::io::stdin().read_line(&mut value).context("Failed to read value from stdin")?;
+            value
+        }
+    };
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(anyhow!("Nothing to encrypt"));
+    }
+
+    println!("{}", key.encrypt(value, tenant)?);
+    Ok(())
+}
+
+fn select_tenant(tenants: &TenantRegistry, key: Option<&str>) -> Result<Arc<Tenant>> {
+    tenants
+        .resolve(key)
+        .ok_or_else(|| anyhow!("Unknown tenant '{}'", key.unwrap_or_default()))
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Записать запуск обработки как `/order/{id}/process` и вывести его сводку с результатами
+/// (с `failed` — только позиции с ошибкой)
+fn print_job(tenant: &Tenant, order_id: &str, results: Vec<ProcessingResult>, failed: bool) -> Result<()> {
+    let job = tenant.jobs.insert(order_id, results);
+    let (_, results) = job.page(failed.then_some(false), 0, usize::MAX);
+    print_json(&serde_json::json!({
+        "job": job.summary(),
+        "results": results,
+    }))
+}
+
+async fn check_config(tenants: &TenantRegistry) -> Result<()> {
+    let mut failed = false;
+
+    for tenant in tenants.all() {
+        let mut processor = tenant.processor.lock().await;
+        match processor.check_setup().await {
+            Ok(check) => println!(
+                "[{}] OK: API {}, store '{}', organization '{}', tech card field '{}' ({})",
+                tenant.name,
+                tenant.settings.moysklad_api_url,
+                check.store.name.unwrap_or_default(),
+                check.organization.name.unwrap_or_default(),
+                check.tech_card_attribute.name,
+                check.tech_card_attribute.id
+            ),
+            Err(e) => {
+                failed = true;
+                println!("[{}] ERROR (API {}): {:#}", tenant.name, tenant.settings.moysklad_api_url, e);
+            }
+        }
+    }
+
+    if failed {
+        Err(anyhow!("Configuration check failed"))
+    } else {
+        Ok(())
+    }
+}
+
+async fn replay(tenants: &TenantRegistry, file: &Path, dry_run: bool, failed: bool) -> Result<()> {
+    let data = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let events = serde_json::from_str::<ReplayFile>(&data)
+        .with_context(|| format!("Failed to parse {}", file.display()))?
+        .into_events();
+
+    for event in events {
+        if !HANDLED_ENTITY_TYPES.contains(&event.entity_type.to_lowercase().as_str()) {
+            println!("Skipping {} (type={})", event.id, event.entity_type);
+            continue;
+        }
+
+        let tenant = match event.account_id.as_deref() {
+            Some(account_id) => tenants
+                .by_account(account_id)
+                .ok_or_else(|| anyhow!("Unknown account {}", account_id))?,
+            None => tenants
+                .default_tenant()
+                .ok_or_else(|| anyhow!("No default account, accountId is required"))?,
+        };
+
+        if dry_run {
+            let sandbox = tenant.processor.lock().await.sandbox(event.playback())?;
+            let (results, trace) = sandbox.replay(&event.webhook()).await;
+            print_json(&serde_json::json!({
+                "id": event.id,
+                "results": results?,
+                "api_calls": trace.api_calls,
+                "writes": trace.writes,
+                "stages": trace.stages,
+            }))?;
+        } else {
+            let results = tenant.processor.lock().await.process_now(&event.webhook()).await?;
+            print_job(&tenant, &event.id, results, failed)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,239 @@
+//! Фикстуры для воспроизведения обработки (`play-fixtures`): ответы API реального аккаунта
+//! без персональных данных
+
+use anyhow::{Context, Result};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use crate::api::{Playback, RecordedResponse};
+use crate::models::WebhookEvent;
+use crate::tenants::Tenant;
+
+static UUID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}")
+        .expect("valid uuid pattern")
+});
+
+/// Фильтр по тексту в пути запроса: значение анонимизируется так же, как поле в ответе
+static TEXT_FILTER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(filter=(?:name|code|externalCode|article)=)([^&]*)").expect("valid filter pattern")
+});
+
+/// Текстовые поля сущностей, которые заменяются
+const TEXT_FIELDS: &[&str] = &[
+    "name",
+    "description",
+    "code",
+    "externalCode",
+    "article",
+    "comment",
+    "shortFio",
+    "fullName",
+    "firstName",
+    "middleName",
+    "lastName",
+    "email",
+    "phone",
+    "fax",
+    "legalTitle",
+    "legalAddress",
+    "actualAddress",
+    "address",
+    "inn",
+    "kpp",
+    "ogrn",
+    "ogrnip",
+    "okpo",
+];
+
+/// Детерминированная замена идентификаторов и названий.
+/// Одинаковые значения заменяются одинаково, поэтому ссылки между ответами сохраняются.
+pub struct Anonymizer {
+    salt: String,
+}
+
+impl Anonymizer {
+    pub fn new(salt: &str) -> Self {
+        Self { salt: salt.to_string() }
+    }
+
+    fn digest(&self, value: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Идентификатор в формате UUID
+    pub fn id(&self, id: &str) -> String {
+        let hex = self.digest(&id.to_lowercase());
+        format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+    }
+
+    /// Название, код или контактные данные
+    pub fn text(&self, value: &str) -> String {
+        if value.is_empty() {
+            return String::new();
+        }
+        format!("anon-{}", &self.digest(value)[..10])
+    }
+
+    /// Заменить все UUID в строке (ссылки, accountId, syncId)
+    fn ids_in(&self, text: &str) -> String {
+        UUID.replace_all(text, |caps: &Captures| self.id(&caps[0])).into_owned()
+    }
+
+    /// Путь запроса: UUID и текстовые фильтры
+    pub fn endpoint(&self, endpoint: &str) -> String {
+        let endpoint = self.ids_in(endpoint);
+        TEXT_FILTER
+            .replace_all(&endpoint, |caps: &Captures| {
+                let value = urlencoding::decode(&caps[2])
+                    .map(|v| v.into_owned())
+                    .unwrap_or_else(|_| caps[2].to_string());
+                format!("{}{}", &caps[1], urlencoding::encode(&self.text(&value)))
+            })
+            .into_owned()
+    }
+
+    pub fn value(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.ids_in(s),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.value(item)),
+            Value::Object(map) => self.object(map),
+            _ => {}
+        }
+    }
+
+    fn object(&self, map: &mut Map<String, Value>) {
+        // Названия доп. полей нужны для настроек (TECH_CARD_FIELD_NAME), заменяются только значения
+        let is_attribute = map
+            .get("meta")
+            .and_then(|meta| meta.get("type"))
+            .and_then(Value::as_str)
+            == Some("attributemetadata");
+
+        for (key, value) in map.iter_mut() {
+            let is_text = if is_attribute {
+                key == "value"
+            } else {
+                TEXT_FIELDS.contains(&key.as_str())
+            };
+
+            match value {
+                Value::String(s) if is_text => *s = self.text(s),
+                other => self.value(other),
+            }
+        }
+    }
+}
+
+fn is_report(response: &RecordedResponse) -> bool {
+    response.endpoint.starts_with("/report/")
+}
+
+/// Оставить в отчётах об остатках только строки товаров, встречающихся в остальных ответах
+fn trim_reports(responses: &mut [RecordedResponse]) {
+    let known: HashSet<String> = responses
+        .iter()
+        .filter(|r| !is_report(r))
+        .flat_map(|r| {
+            let body = r.body.to_string();
+            UUID.find_iter(&body).map(|m| m.as_str().to_lowercase()).collect::<Vec<_>>()
+        })
+        .collect();
+
+    for response in responses.iter_mut().filter(|r| is_report(r)) {
+        let Some(rows) = response.body.get_mut("rows").and_then(Value::as_array_mut) else {
+            continue;
+        };
+
+        rows.retain(|row| {
+            row.pointer("/meta/href")
+                .and_then(Value::as_str)
+                .and_then(|href| UUID.find(href))
+                .is_some_and(|id| known.contains(&id.as_str().to_lowercase()))
+        });
+
+        let size = rows.len();
+        if let Some(meta) = response.body.get_mut("meta").and_then(Value::as_object_mut) {
+            meta.insert("size".to_string(), size.into());
+        }
+    }
+}
+
+/// Записать ответы API для обработки заказа и сохранить их анонимизированными в `out`
+pub async fn export_fixtures(tenant: &Tenant, order_id: &str, out: &Path, salt: &str) -> Result<()> {
+    let mut responses = tenant
+        .processor
+        .lock()
+        .await
+        .record_order_responses(order_id)
+        .await?;
+
+    trim_reports(&mut responses);
+
+    let anonymizer = Anonymizer::new(salt);
+    for response in responses.iter_mut() {
+        response.endpoint = anonymizer.endpoint(&response.endpoint);
+        anonymizer.value(&mut response.body);
+    }
+
+    let fixtures = serde_json::json!({
+        "order_id": anonymizer.id(order_id),
+        "store_name": anonymizer.text(&tenant.settings.store_name),
+        "responses": responses,
+    });
+
+    std::fs::write(out, serde_json::to_string_pretty(&fixtures)?)
+        .with_context(|| format!("Failed to write {}", out.display()))?;
+
+    println!("Wrote {} responses to {}", responses.len(), out.display());
+    Ok(())
+}
+
+/// Файл `export-fixtures`
+#[derive(Debug, Deserialize)]
+struct Fixtures {
+    order_id: String,
+    responses: Vec<RecordedResponse>,
+}
+
+/// Обработать заказ из фикстур без обращения к МойСклад: ответы API берутся из файла,
+/// запрос без записанного ответа — ошибка. Названия и ID из настроек заменяются так же,
+/// как при выгрузке (`salt`), поэтому подходят настройки аккаунта, с которого сняты фикстуры.
+/// Выводит результаты по позициям, изменения, которые были бы отправлены, этапы
+/// и время обработки без сети.
+pub async fn play_fixtures(tenant: &Tenant, file: &Path, salt: &str) -> Result<()> {
+    let data = std::fs::read_to_string(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let fixtures: Fixtures = serde_json::from_str(&data)
+        .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+    let anonymizer = Anonymizer::new(salt);
+    let playback = Playback::new(fixtures.responses, false)
+        .with_mapper(Box::new(move |endpoint| anonymizer.endpoint(endpoint)));
+    let sandbox = tenant.processor.lock().await.sandbox(playback)?;
+    let started = Instant::now();
+    let (results, trace) = sandbox
+        .replay(&WebhookEvent::customer_order(&fixtures.order_id))
+        .await;
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+
+    let output = serde_json::json!({
+        "order_id": fixtures.order_id,
+        "elapsed_ms": elapsed_ms,
+        "results": results?,
+        "writes": trace.writes,
+        "stages": trace.stages,
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
@@ -0,0 +1,5 @@
+pub mod commands;
+pub mod fixtures;
+
+pub use commands::*;
+pub use fixtures::*;
@@ -1,3 +1,11 @@
+pub mod persist;
+pub mod secrets;
 pub mod settings;
+pub mod tls;
+pub mod toggles;
 
+pub use persist::*;
+pub use secrets::*;
 pub use settings::*;
+pub use tls::*;
+pub use toggles::*;
@@ -0,0 +1,27 @@
+//! Атомарная запись файлов состояния: временный файл и переименование
+
+use anyhow::Result;
+use serde::Serialize;
+use std::path::Path;
+use tracing::warn;
+
+/// Записать значение как JSON через временный файл, чтобы при сбое не оставить файл
+/// состояния наполовину записанным
+pub fn write_json_atomic<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    let data = serde_json::to_string_pretty(value)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Сохранить состояние `what`, если задан путь; ошибка записи только логируется
+pub fn persist_json<T: Serialize + ?Sized>(path: Option<&Path>, value: &T, what: &str) {
+    let Some(path) = path else {
+        return;
+    };
+
+    if let Err(e) = write_json_atomic(path, value) {
+        warn!("Failed to persist {}: {:#}", what, e);
+    }
+}
@@ -0,0 +1,111 @@
+//! Шифрование токенов, хранящихся в файлах и переменных окружения (AES-256-GCM)
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use super::Settings;
+
+/// Префикс зашифрованного значения: `enc:v2:<base64(nonce || ciphertext || tag)>`.
+/// Имя тенанта входит в AAD, поэтому значение одного тенанта не расшифруется как токен другого
+pub const ENCRYPTED_PREFIX: &str = "enc:v2:";
+
+/// Префикс значений прежнего формата, зашифрованных без привязки к тенанту
+pub const LEGACY_ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Мастер-ключ шифрования токенов
+pub struct SecretsKey {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl SecretsKey {
+    /// Ключ из SECRETS_KEY или файла SECRETS_KEY_FILE (32 байта в base64); `None`, если не задан
+    pub fn from_settings(settings: &Settings) -> Result<Option<Self>> {
+        if let Some(ref key) = settings.secrets_key {
+            return Self::from_base64(key).map(Some).context("Invalid SECRETS_KEY");
+        }
+
+        let Some(ref path) = settings.secrets_key_file else {
+            return Ok(None);
+        };
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read secrets key {}", path))?;
+        Self::from_base64(data.trim())
+            .map(Some)
+            .with_context(|| format!("Invalid secrets key in {}", path))
+    }
+
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        let bytes = STANDARD.decode(encoded.trim()).context("Key is not valid base64")?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| anyhow!("Key must be 32 bytes, got {}", bytes.len()))?;
+
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Зашифровать значение тенанта `tenant` со случайным nonce
+    pub fn encrypt(&self, plaintext: &str, tenant: &str) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate nonce"))?;
+
+        let mut in_out = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(tenant.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| anyhow!("Failed to encrypt secret"))?;
+
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&in_out);
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(data)))
+    }
+
+    /// Расшифровать значение тенанта `tenant`; значения `enc:v1:` расшифровываются без AAD
+    pub fn decrypt(&self, value: &str, tenant: &str) -> Result<String> {
+        let (encoded, aad) = if let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) {
+            (encoded, tenant.as_bytes())
+        } else if let Some(encoded) = value.strip_prefix(LEGACY_ENCRYPTED_PREFIX) {
+            (encoded, &[][..])
+        } else {
+            return Err(anyhow!("Secret is not encrypted"));
+        };
+        let data = STANDARD.decode(encoded).context("Encrypted secret is not valid base64")?;
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("Encrypted secret is truncated"));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("Invalid nonce"))?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(aad), &mut in_out)
+            .map_err(|_| anyhow!("Failed to decrypt secret: wrong key, tenant or corrupted value"))?;
+
+        String::from_utf8(plaintext.to_vec()).context("Decrypted secret is not valid UTF-8")
+    }
+}
+
+/// Зашифровано ли значение (`enc:v2:` или прежний `enc:v1:`)
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX) || value.starts_with(LEGACY_ENCRYPTED_PREFIX)
+}
+
+/// Значение тенанта `tenant` как есть или расшифрованное, если оно зашифровано
+pub fn reveal(key: Option<&SecretsKey>, value: &str, tenant: &str) -> Result<String> {
+    if !is_encrypted(value) {
+        return Ok(value.to_string());
+    }
+    key.ok_or_else(|| anyhow!("Encrypted secret requires SECRETS_KEY or SECRETS_KEY_FILE"))?
+        .decrypt(value, tenant)
+}
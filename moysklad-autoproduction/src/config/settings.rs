@@ -2,34 +2,503 @@
 
 use std::env;
 
+use crate::models::Decimal;
+
 /// Настройки приложения
 #[derive(Debug, Clone)]
 pub struct Settings {
     /// Токен доступа к API МойСклад
     pub moysklad_token: String,
+
+    /// Логин МойСклад (если токен не задан)
+    pub moysklad_login: Option<String>,
+
+    /// Пароль МойСклад
+    pub moysklad_password: Option<String>,
+
+    /// accountId основного аккаунта (для маршрутизации webhook)
+    pub moysklad_account_id: Option<String>,
+
+    /// JSON-файл с дополнительными аккаунтами (тенантами)
+    pub tenants_file: Option<String>,
+
+    /// ID решения в маркетплейсе МойСклад (Vendor API)
+    pub vendor_app_id: Option<String>,
+
+    /// UID решения: `sub` токенов Vendor API
+    pub vendor_app_uid: Option<String>,
+
+    /// Секретный ключ решения для подписи токенов Vendor API
+    pub vendor_secret_key: Option<String>,
+
+    /// Файл с аккаунтами, установившими решение
+    pub vendor_accounts_file: Option<String>,
+
+    /// Адрес Vendor API МойСклад
+    pub vendor_api_url: String,
+
+    /// Мастер-ключ шифрования токенов (32 байта в base64)
+    pub secrets_key: Option<String>,
+
+    /// Файл с мастер-ключом (если SECRETS_KEY не задан)
+    pub secrets_key_file: Option<String>,
+
+    /// Файл с токенами, заменёнными через /admin/token (хранятся зашифрованными)
+    pub tokens_file: Option<String>,
     
     /// Название склада для отслеживания
     pub store_name: String,
+
+    /// ID склада (приоритетнее названия: не зависит от переименования)
+    pub store_id: Option<String>,
+
+    /// ID организации для создаваемых документов (иначе первая организация аккаунта)
+    pub organization_id: Option<String>,
+
+    /// Контрагенты (названия или ID), для отгрузок которым создаются тех. операции; пусто — все
+    pub agent_filter: Vec<String>,
+
+    /// Каналы продаж (названия или ID), для которых создаются тех. операции; пусто — все
+    pub sales_channel_filter: Vec<String>,
     
     /// Название поля с тех. картой в карточке товара
     pub tech_card_field_name: String,
+
+    /// ID поля с тех. картой (приоритетнее названия)
+    pub tech_card_field_id: Option<String>,
+
+    /// Запасные источники тех. карты по порядку: `description`, `external_code`, `article`
+    pub tech_card_fallbacks: Vec<String>,
+
+    /// Префикс строки с тех. картой в описании товара
+    pub tech_card_description_prefix: String,
+
+    /// JSON файл соответствия групп товаров и шаблонов тех. карт
+    pub folder_tech_card_file: Option<String>,
+
+    /// JSON файл с заменами материалов при нехватке
+    pub material_substitutes_file: Option<String>,
+
+    /// Потери материалов в производстве по умолчанию, %
+    pub waste_percent: f64,
+
+    /// Доп. поле материала с процентом потерь
+    pub waste_field_name: Option<String>,
+
+    /// Цепочка поиска тех. карты: `attribute`, `article`, `code`
+    pub plan_lookup_mode: Vec<String>,
+
+    /// Время жизни найденной тех. карты в кэше, сек (0 — без кэша)
+    pub plan_cache_ttl_secs: u64,
+
+    /// Сколько тех. карт самых частых товаров из истории загрузить при старте
+    pub warmup_plans: usize,
     
+    /// Способ пополнения по умолчанию: `produce`, `move`, `purchase` или `notify_only`
+    pub replenishment_strategy: Option<String>,
+
+    /// Поле товара со способом пополнения
+    pub replenishment_field_name: Option<String>,
+
+    /// Пробный режим: при низком остатке только уведомления, документы не создаются
+    pub notify_only: bool,
+
+    /// Поле-флаг: по товару только уведомления, документы не создаются
+    pub notify_only_field_name: Option<String>,
+
+    /// Склад-источник для пополнения перемещением
+    pub move_source_store_name: Option<String>,
+
+    /// Поставщик для заказов поставщику
+    pub purchase_supplier_name: Option<String>,
+
+    /// Пользовательский справочник МойСклад для журнала автопроизводства
+    pub production_log_entity: Option<String>,
+
+    /// Сотрудник (email или ID), которому ставятся задачи на ручной разбор
+    pub task_assignee: Option<String>,
+
+    /// Причины пропуска, по которым ставится задача
+    pub task_reasons: Vec<String>,
+
+    /// Файл позиций, по которым поставлены задачи
+    pub tasks_file: Option<String>,
+
+    /// Адрес веб-интерфейса МойСклад для ссылок в задачах
+    pub moysklad_app_url: String,
+
+    /// Поле-флаг: товары без тех. карты оприходуются вместо производства
+    pub enter_fallback_field_name: Option<String>,
+
+    /// Поле товара с коэффициентом: единиц производства на единицу продажи
+    pub unit_factor_field_name: Option<String>,
+
+    /// Единица измерения упаковки, в которых ведётся производство (из упаковок товара)
+    pub production_pack_uom: Option<String>,
+
     /// Минимальный порог остатка
     pub min_stock_threshold: f64,
+
+    /// За сколько последних дней считается средняя скорость продаж
+    pub sales_velocity_days: u32,
+
+    /// Порог товара по скорости продаж: средние продажи в день × LEAD_TIME_DAYS
+    pub dynamic_thresholds: bool,
+
+    /// Срок пополнения остатка, дней (для порогов по скорости продаж)
+    pub lead_time_days: f64,
+
+    /// Час ежедневного пересчёта порогов по скорости продаж
+    pub dynamic_threshold_hour: u32,
+
+    /// Максимальное количество, пополняемое автоматически за одну позицию (0 — без ограничения)
+    pub max_auto_quantity: Decimal,
+
+    /// Производить часть количества, если материалов хватает не на всё
+    pub partial_production: bool,
+
+    /// Заполнять сумму тех. операции по закупочным ценам материалов
+    pub processing_cost_from_materials: bool,
+
+    /// Накладные расходы в сумме тех. операции, руб.
+    pub overhead_amount: f64,
+
+    /// Накладные расходы в процентах от стоимости материалов по закупочным ценам
+    pub overhead_percent: f64,
+
+    /// Распределение фиксированных накладных: `document` — на тех. операцию, `unit` — на единицу
+    pub overhead_distribution: String,
+
+    /// Статья расходов накладных для описания тех. операции
+    pub overhead_expense_item: Option<String>,
+
+    /// Копировать проект и канал продаж заказа в тех. операцию
+    pub copy_order_project: bool,
+
+    /// Проект тех. операций (для заказов без проекта при COPY_ORDER_PROJECT)
+    pub production_project: Option<String>,
+
+    /// Раскрывать полуфабрикаты с собственной тех. картой до материалов нижних уровней
+    /// в отчётах; при производстве нехватка полуфабриката — нехватка материала
+    pub bom_rollup: bool,
+
+    /// Максимальная глубина раскрытия тех. карт
+    pub bom_max_depth: u32,
+
+    /// Что делать с документами пополнения удалённого или распроведённого заказа:
+    /// `notify`, `unapply` или `delete`
+    pub on_order_revoked: String,
+
+    /// С каким остатком сравнивается порог: `quantity`, `available` или `free`
+    pub stock_mode: String,
+
+    /// По какому количеству позиции заказа покупателя запускается пополнение:
+    /// `ordered`, `shipped` или `reserve`
+    pub quantity_basis: String,
+
+    /// Учитывать ожидаемое поступление (в пути) в остатке
+    pub count_in_transit: bool,
+
+    /// Учитывать непроведённые тех. операции на склад в остатке
+    pub count_pending_productions: bool,
+
+    /// Не создавать тех. операцию, если на товар уже есть непроведённая или сегодняшняя
+    pub skip_existing_productions: bool,
+
+    /// Окно, в течение которого повторное производство товара перепроверяет остаток, сек
+    pub production_dedup_ttl_secs: u64,
+
+    /// Не запускать автопроизводство товара чаще раза в столько секунд (0 — выключено)
+    pub production_cooldown_secs: u64,
+
+    /// Интервал фонового обновления кэша остатков склада, сек (0 — остатки запрашиваются каждый раз)
+    pub stock_cache_refresh_secs: u64,
     
     /// Порт веб-сервера
     pub server_port: u16,
     
     /// Хост веб-сервера
     pub server_host: String,
+
+    /// Сертификат TLS (PEM, с цепочкой); вместе с ключом включает HTTPS
+    pub tls_cert_file: Option<String>,
+
+    /// Закрытый ключ TLS (PEM)
+    pub tls_key_file: Option<String>,
+
+    /// Максимальный размер тела запроса, байт
+    pub max_payload_bytes: usize,
+
+    /// API-ключи служебных эндпоинтов (`ключ:роль,...`)
+    pub api_keys: Vec<String>,
+
+    /// JSON-файл с пользователями API (`[{"name", "key", "role"}]`)
+    pub api_users_file: Option<String>,
+
+    /// Типы сущностей с отключённой обработкой webhook
+    pub entity_toggles_file: Option<String>,
+
+    /// Правила маршрутизации уведомлений (`failure=log,telegram;success=log`)
+    pub notify_routes: String,
+
+    /// Токен Telegram-бота для уведомлений
+    pub telegram_bot_token: Option<String>,
+
+    /// ID чата Telegram для уведомлений
+    pub telegram_chat_id: Option<String>,
+
+    /// SMTP сервер для email-уведомлений
+    pub smtp_host: Option<String>,
+
+    /// Порт SMTP сервера
+    pub smtp_port: u16,
+
+    /// Логин SMTP
+    pub smtp_username: Option<String>,
+
+    /// Пароль SMTP
+    pub smtp_password: Option<String>,
+
+    /// Адрес отправителя email-уведомлений
+    pub email_from: Option<String>,
+
+    /// Адреса получателей email-уведомлений
+    pub email_to: Vec<String>,
+
+    /// URL для HTTP-уведомлений (JSON POST)
+    pub notify_http_url: Option<String>,
+
+    /// Файл истории обработки (JSON Lines)
+    pub history_file: Option<String>,
+
+    /// Журнал изменений, отправленных в МойСклад (JSON Lines)
+    pub audit_file: Option<String>,
+
+    /// Реестр обработанных заказов (отпечатки позиций)
+    pub processed_orders_file: Option<String>,
+
+    /// Переопределения настроек отдельных товаров
+    pub product_overrides_file: Option<String>,
+
+    /// Итог обработки в исходном документе: `off`, `description` (комментарий) или `file`
+    pub source_report: String,
+
+    /// Шаблон номера тех. операции: `АВТО-{date}-{seq}`; `{date}` — `ГГГГММДД`, `{year}` — год,
+    /// `{seq:4}` — счётчик с ведущими нулями
+    pub name_template: Option<String>,
+
+    /// Счётчики номеров NAME_TEMPLATE для одного экземпляра; при EVENT_QUEUE=redis они в Redis
+    pub name_sequence_file: Option<String>,
+
+    /// Период плановой сводки: `day`, `week` или пусто (отключено)
+    pub summary_schedule: Option<String>,
+
+    /// Час отправки плановой сводки (локальное время)
+    pub summary_hour: u32,
+
+    /// Ежедневная проверка склада, организации, доп. полей и webhook в МойСклад
+    pub drift_check: bool,
+
+    /// Час ежедневной проверки настроек в МойСклад (локальное время)
+    pub drift_check_hour: u32,
+
+    /// Webhook прошлой проверки настроек, с которыми сравнивается следующая
+    pub drift_baseline_file: Option<String>,
+
+    /// Допустимое время от изменения документа до создания тех. операции, сек
+    pub sla_limit_secs: Option<u64>,
+
+    /// Часовой пояс дат МойСклад относительно UTC, часов (время аккаунта, по умолчанию московское)
+    pub moysklad_utc_offset_hours: i32,
+
+    /// Рабочие часы `HH:MM-HH:MM`; вне них производство откладывается
+    pub work_hours: Option<String>,
+
+    /// Рабочие дни недели: `1-5`, `1,2,3,4,5,6` (1 — понедельник)
+    pub work_days: Vec<String>,
+
+    /// Праздничные дни `YYYY-MM-DD`
+    pub holidays: Vec<String>,
+
+    /// Дата документов, созданных вне рабочего времени, — начало следующего рабочего окна
+    pub work_schedule_moment: bool,
+
+    /// Файл очереди повторной обработки
+    pub retry_queue_file: Option<String>,
+
+    /// Начальная задержка повтора, сек (удваивается с каждой попыткой)
+    pub retry_base_delay_secs: u64,
+
+    /// Максимальная задержка повтора, сек
+    pub retry_max_delay_secs: u64,
+
+    /// Интервал проверки очереди повторов, сек
+    pub retry_poll_interval_secs: u64,
+
+    /// Число неудачных попыток, после которого заказ уходит в dead-letter (0 — без ограничения)
+    pub retry_max_attempts: u32,
+
+    /// Файл заказов, снятых с повторов
+    pub dead_letter_file: Option<String>,
+
+    /// Файл очереди позиций, ожидающих материалов
+    pub shortage_queue_file: Option<String>,
+
+    /// Интервал перепроверки позиций, ожидающих материалов, сек (0 — только по приёмке)
+    pub shortage_recheck_interval_secs: u64,
+
+    /// Число сбоев подряд, после которого запросы к МойСклад приостанавливаются
+    pub circuit_breaker_threshold: u32,
+
+    /// Пауза перед пробным запросом после размыкания, сек
+    pub circuit_breaker_cooldown_secs: u64,
+
+    /// Максимум webhook в обработке и ожидании на тенанта (0 — без ограничения)
+    pub webhook_queue_depth: usize,
+
+    /// Значение Retry-After при переполнении очереди webhook, сек
+    pub webhook_retry_after_secs: u64,
+
+    /// Окно склейки повторных webhook по одному документу, сек (0 — обрабатывать сразу)
+    pub webhook_debounce_secs: u64,
+
+    /// Правила приоритета очереди обработки: `agent:<название>=N`, `channel:<название>=N`,
+    /// `shipment:<часов>=N`; пусто — документы обрабатываются по мере поступления
+    pub priority_rules: Vec<String>,
+
+    /// Файл очереди документов, ожидающих обработки по приоритету
+    pub pending_queue_file: Option<String>,
+
+    /// Сколько последних запусков ручной обработки хранить для постраничного чтения
+    pub job_results_max: usize,
+
+    /// До скольких результатов ручная обработка возвращает их прямо в ответе
+    pub job_inline_results: usize,
+
+    /// Очередь событий: `local` (обработка в том же процессе) или `redis` (Redis Streams)
+    pub event_queue: String,
+
+    /// Адрес Redis, напр. `redis://localhost:6379`
+    pub redis_url: Option<String>,
+
+    /// Поток Redis для webhook
+    pub redis_stream: String,
+
+    /// Группа потребителей Redis
+    pub redis_consumer_group: String,
+
+    /// Примерная максимальная длина потока (XADD MAXLEN ~)
+    pub redis_stream_maxlen: usize,
+
+    /// Через сколько секунд неподтверждённое событие другого экземпляра забирается себе
+    /// (XAUTOCLAIM): экземпляр мог упасть и больше не вернуться
+    pub redis_claim_idle_secs: u64,
+
+    /// Имя экземпляра сервиса (потребителя в группе)
+    pub worker_name: String,
+
+    /// Блокировки заказов и товаров: `memory` (один экземпляр) или `redis`
+    pub lock_provider: String,
+
+    /// Время жизни блокировки, сек (страховка от упавшего экземпляра)
+    pub lock_ttl_secs: u64,
+
+    /// Сколько ждать занятую блокировку, сек
+    pub lock_wait_secs: u64,
+
+    /// Таймаут установки соединения с МойСклад, сек
+    pub http_connect_timeout_secs: u64,
+
+    /// Общий таймаут запроса к МойСклад, сек
+    pub http_timeout_secs: u64,
+
+    /// Таймаут чтения ответа, сек
+    pub http_read_timeout_secs: Option<u64>,
+
+    /// Максимум простаивающих соединений в пуле
+    pub http_pool_max_idle_per_host: usize,
+
+    /// Время жизни простаивающего соединения, сек
+    pub http_pool_idle_timeout_secs: u64,
+
+    /// Интервал TCP keep-alive, сек
+    pub http_tcp_keepalive_secs: Option<u64>,
+
+    /// Прокси для запросов к МойСклад (иначе HTTP(S)_PROXY из окружения)
+    pub moysklad_proxy: Option<String>,
+
+    /// Адрес JSON API МойСклад: песочница, прокси или mock-сервер для тестов
+    pub moysklad_api_url: String,
+
+    /// Строк на страницу при постраничной загрузке отчётов об остатках (не больше 1000)
+    pub report_page_size: u32,
+
+    /// Максимальный размер ответа МойСклад, байт (больше — ошибка без чтения до конца)
+    pub max_response_bytes: Option<usize>,
+
+    /// Таймаут этапа получения остатков и товара, сек (0 — без ограничения)
+    pub stock_timeout_secs: u64,
+
+    /// Таймаут этапа поиска тех. карты и проверки материалов, сек (0 — без ограничения)
+    pub plan_timeout_secs: u64,
+
+    /// Таймаут этапа создания и проведения документов, сек (0 — без ограничения)
+    pub create_timeout_secs: u64,
+
+    /// Повторы этапа остатков при временной ошибке или таймауте
+    pub stock_retries: u32,
+
+    /// Повторы этапа тех. карты при временной ошибке или таймауте
+    pub plan_retries: u32,
+
+    /// Повторы этапа создания документов при временной ошибке или таймауте
+    pub create_retries: u32,
+
+    /// URL для отправки результатов обработки каждого заказа
+    pub outgoing_webhook_url: Option<String>,
+
+    /// Секрет HMAC-подписи исходящих webhook
+    pub outgoing_webhook_secret: Option<String>,
+
+    /// Число повторов отправки исходящего webhook
+    pub outgoing_webhook_retries: u32,
+
+    /// URL, на который пересылаются webhook, не обработанные сервисом
+    pub forward_url: Option<String>,
+
+    /// Число повторов пересылки webhook
+    pub forward_retries: u32,
 }
 
 impl Settings {
+    /// Сервис развёрнут как решение маркетплейса: аккаунты подключаются через Vendor API
+    pub fn vendor_enabled(&self) -> bool {
+        self.vendor_secret_key.is_some()
+    }
+
     /// Загрузить настройки из переменных окружения
     pub fn from_env() -> Result<Self, String> {
-        let moysklad_token = env::var("MOYSKLAD_TOKEN")
-            .map(|v| strip_quotes(&v))
-            .map_err(|_| "MOYSKLAD_TOKEN is required".to_string())?;
+        let moysklad_token = env_opt("MOYSKLAD_TOKEN").unwrap_or_default();
+        let moysklad_login = env_opt("MOYSKLAD_LOGIN");
+        let moysklad_password = env_opt("MOYSKLAD_PASSWORD");
+
+        let tenants_file = env_opt("TENANTS_FILE");
+        let vendor_secret_key = env_opt("VENDOR_SECRET_KEY");
+
+        if moysklad_token.is_empty()
+            && (moysklad_login.is_none() || moysklad_password.is_none())
+            && tenants_file.is_none()
+            && vendor_secret_key.is_none()
+        {
+            return Err(
+                "MOYSKLAD_TOKEN, MOYSKLAD_LOGIN/MOYSKLAD_PASSWORD, TENANTS_FILE or VENDOR_SECRET_KEY is required"
+                    .to_string(),
+            );
+        }
+
+        if vendor_secret_key.is_some() && (env_opt("VENDOR_APP_ID").is_none() || env_opt("VENDOR_APP_UID").is_none()) {
+            return Err("VENDOR_APP_ID and VENDOR_APP_UID are required with VENDOR_SECRET_KEY".to_string());
+        }
         
         let store_name = env::var("STORE_NAME")
             .map(|v| strip_quotes(&v))
@@ -54,14 +523,249 @@ impl Settings {
         let server_host = env::var("SERVER_HOST")
             .map(|v| strip_quotes(&v))
             .unwrap_or_else(|_| "0.0.0.0".to_string());
+
+        let moysklad_api_url = env_opt("MOYSKLAD_API_URL")
+            .map(|v| v.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| "https://api.moysklad.ru/api/remap/1.2".to_string());
+        if reqwest::Url::parse(&moysklad_api_url).is_err() {
+            return Err(format!("Invalid MOYSKLAD_API_URL '{}'", moysklad_api_url));
+        }
+
+        let moysklad_proxy = env_opt("MOYSKLAD_PROXY");
+        if let Some(ref proxy) = moysklad_proxy
+            && let Err(e) = reqwest::Proxy::all(proxy)
+        {
+            return Err(format!(
+                "Invalid MOYSKLAD_PROXY '{}': {}",
+                crate::api::redact::redact(proxy),
+                e
+            ));
+        }
+
+        let waste_percent: f64 = env_parse("WASTE_PERCENT", 0.0);
+        if !(0.0..100.0).contains(&waste_percent) {
+            return Err(format!("WASTE_PERCENT must be in [0, 100), got {}", waste_percent));
+        }
+
+        let name_template = env_opt("NAME_TEMPLATE");
+        if let Some(ref template) = name_template {
+            crate::processing::validate_name_template(template)?;
+        }
+
+        let tls_cert_file = env_opt("TLS_CERT_FILE");
+        let tls_key_file = env_opt("TLS_KEY_FILE");
+        if tls_cert_file.is_some() != tls_key_file.is_some() {
+            return Err("TLS_CERT_FILE and TLS_KEY_FILE must be set together".to_string());
+        }
+
+        let event_queue = env_opt("EVENT_QUEUE")
+            .map(|v| v.to_lowercase())
+            .unwrap_or_else(|| "local".to_string());
+        let redis_url = env_opt("REDIS_URL");
+        match event_queue.as_str() {
+            "local" => {}
+            "redis" if redis_url.is_none() => {
+                return Err("EVENT_QUEUE=redis requires REDIS_URL".to_string());
+            }
+            "redis" => {}
+            other => return Err(format!("Unknown EVENT_QUEUE '{}', expected local or redis", other)),
+        }
+
+        // Неподтверждённые события потребителя ищутся по имени: случайное имя теряло бы их при перезапуске
+        let worker_name = match env_opt("WORKER_NAME").or_else(|| env_opt("HOSTNAME")) {
+            Some(name) => name,
+            None if event_queue == "redis" => {
+                return Err("EVENT_QUEUE=redis requires WORKER_NAME (or HOSTNAME)".to_string());
+            }
+            None => "default".to_string(),
+        };
+
+        let lock_provider = env_opt("LOCK_PROVIDER")
+            .map(|v| v.to_lowercase())
+            .unwrap_or_else(|| "memory".to_string());
+        match lock_provider.as_str() {
+            "memory" => {}
+            "redis" if redis_url.is_none() => {
+                return Err("LOCK_PROVIDER=redis requires REDIS_URL".to_string());
+            }
+            "redis" => {}
+            other => return Err(format!("Unknown LOCK_PROVIDER '{}', expected memory or redis", other)),
+        }
+
+        let notify_routes = env_opt("NOTIFY_ROUTES").unwrap_or_default();
+
+        let smtp_port = env_opt("SMTP_PORT")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(587);
+
+        let summary_hour = env_opt("SUMMARY_HOUR")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(9);
+
+        let email_to = env_opt("EMAIL_TO")
+            .map(|v| split_list(&v))
+            .unwrap_or_default();
         
         Ok(Self {
             moysklad_token,
+            moysklad_login,
+            moysklad_password,
+            moysklad_account_id: env_opt("MOYSKLAD_ACCOUNT_ID"),
+            tenants_file,
+            vendor_app_id: env_opt("VENDOR_APP_ID"),
+            vendor_app_uid: env_opt("VENDOR_APP_UID"),
+            vendor_secret_key,
+            vendor_accounts_file: Some(env_opt("VENDOR_ACCOUNTS_FILE").unwrap_or_else(|| "vendor-accounts.json".to_string())),
+            vendor_api_url: env_opt("VENDOR_API_URL")
+                .unwrap_or_else(|| "https://apps-api.moysklad.ru/api/vendor/1.0".to_string()),
+            secrets_key: env_opt("SECRETS_KEY"),
+            secrets_key_file: env_opt("SECRETS_KEY_FILE"),
+            tokens_file: Some(env_opt("TOKENS_FILE").unwrap_or_else(|| "tokens.json".to_string())),
             store_name,
+            store_id: env_opt("STORE_ID"),
+            organization_id: env_opt("ORGANIZATION_ID"),
+            agent_filter: env_opt("AGENT_FILTER").map(|v| split_list(&v)).unwrap_or_default(),
+            sales_channel_filter: env_opt("SALES_CHANNEL_FILTER").map(|v| split_list(&v)).unwrap_or_default(),
             tech_card_field_name,
+            tech_card_field_id: env_opt("TECH_CARD_FIELD_ID"),
+            tech_card_fallbacks: env_opt("TECH_CARD_FALLBACKS").map(|v| split_list(&v)).unwrap_or_default(),
+            tech_card_description_prefix: env_opt("TECH_CARD_DESCRIPTION_PREFIX").unwrap_or_else(|| "Техкарта:".to_string()),
+            folder_tech_card_file: env_opt("FOLDER_TECH_CARD_FILE"),
+            material_substitutes_file: env_opt("MATERIAL_SUBSTITUTES_FILE"),
+            waste_percent,
+            waste_field_name: env_opt("WASTE_FIELD_NAME"),
+            plan_lookup_mode: env_opt("PLAN_LOOKUP_MODE").map(|v| split_list(&v)).unwrap_or_default(),
+            plan_cache_ttl_secs: env_parse("PLAN_CACHE_TTL_SECS", 600),
+            warmup_plans: env_parse("WARMUP_PLANS", 20),
+            replenishment_strategy: env_opt("REPLENISHMENT_STRATEGY"),
+            replenishment_field_name: env_opt("REPLENISHMENT_FIELD_NAME"),
+            notify_only: env_parse("NOTIFY_ONLY", false),
+            notify_only_field_name: env_opt("NOTIFY_ONLY_FIELD_NAME"),
+            move_source_store_name: env_opt("MOVE_SOURCE_STORE_NAME"),
+            purchase_supplier_name: env_opt("PURCHASE_SUPPLIER_NAME"),
+            task_assignee: env_opt("TASK_ASSIGNEE"),
+            task_reasons: split_list(
+                &env_opt("TASK_REASONS").unwrap_or_else(|| "no_tech_card,materials_short".to_string()),
+            ),
+            tasks_file: Some(env_opt("TASKS_FILE").unwrap_or_else(|| "tasks-created.json".to_string())),
+            moysklad_app_url: env_opt("MOYSKLAD_APP_URL")
+                .map(|v| v.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "https://online.moysklad.ru/app".to_string()),
+            production_log_entity: env_opt("PRODUCTION_LOG_ENTITY"),
+            enter_fallback_field_name: env_opt("ENTER_FALLBACK_FIELD_NAME"),
+            unit_factor_field_name: env_opt("UNIT_FACTOR_FIELD_NAME"),
+            production_pack_uom: env_opt("PRODUCTION_PACK_UOM"),
             min_stock_threshold,
+            sales_velocity_days: env_parse("SALES_VELOCITY_DAYS", 30).max(1),
+            dynamic_thresholds: env_parse("DYNAMIC_THRESHOLDS", false),
+            lead_time_days: env_parse("LEAD_TIME_DAYS", 3.0),
+            dynamic_threshold_hour: env_parse("DYNAMIC_THRESHOLD_HOUR", 3).min(23),
+            max_auto_quantity: env_parse("MAX_AUTO_QUANTITY", Decimal::ZERO),
+            partial_production: env_parse("PARTIAL_PRODUCTION", false),
+            processing_cost_from_materials: env_parse("PROCESSING_COST_FROM_MATERIALS", false),
+            overhead_amount: env_parse("OVERHEAD_AMOUNT", 0.0),
+            overhead_percent: env_parse("OVERHEAD_PERCENT", 0.0),
+            overhead_distribution: env_opt("OVERHEAD_DISTRIBUTION").unwrap_or_else(|| "document".to_string()),
+            overhead_expense_item: env_opt("OVERHEAD_EXPENSE_ITEM"),
+            copy_order_project: env_parse("COPY_ORDER_PROJECT", false),
+            production_project: env_opt("PRODUCTION_PROJECT"),
+            bom_rollup: env_parse("BOM_ROLLUP", false),
+            bom_max_depth: env_parse("BOM_MAX_DEPTH", 5),
+            on_order_revoked: env_opt("ON_ORDER_REVOKED").map(|v| v.to_lowercase()).unwrap_or_else(|| "notify".to_string()),
+            stock_mode: env_opt("STOCK_MODE").map(|v| v.to_lowercase()).unwrap_or_else(|| "available".to_string()),
+            quantity_basis: env_opt("QUANTITY_BASIS").map(|v| v.to_lowercase()).unwrap_or_else(|| "ordered".to_string()),
+            count_in_transit: env_parse("COUNT_IN_TRANSIT", false),
+            count_pending_productions: env_parse("COUNT_PENDING_PRODUCTIONS", false),
+            skip_existing_productions: env_parse("SKIP_EXISTING_PRODUCTIONS", false),
+            production_dedup_ttl_secs: env_parse("PRODUCTION_DEDUP_TTL_SECS", 120),
+            production_cooldown_secs: env_parse("PRODUCTION_COOLDOWN_SECS", 3600),
+            stock_cache_refresh_secs: env_parse("STOCK_CACHE_REFRESH_SECS", 0),
             server_port,
             server_host,
+            tls_cert_file,
+            tls_key_file,
+            max_payload_bytes: env_parse("MAX_PAYLOAD_BYTES", 256 * 1024),
+            api_keys: env_opt("API_KEYS").map(|v| split_list(&v)).unwrap_or_default(),
+            api_users_file: env_opt("API_USERS_FILE"),
+            entity_toggles_file: Some(env_opt("ENTITY_TOGGLES_FILE").unwrap_or_else(|| "entity-toggles.json".to_string())),
+            notify_routes,
+            telegram_bot_token: env_opt("TELEGRAM_BOT_TOKEN"),
+            telegram_chat_id: env_opt("TELEGRAM_CHAT_ID"),
+            smtp_host: env_opt("SMTP_HOST"),
+            smtp_port,
+            smtp_username: env_opt("SMTP_USERNAME"),
+            smtp_password: env_opt("SMTP_PASSWORD"),
+            email_from: env_opt("EMAIL_FROM"),
+            email_to,
+            notify_http_url: env_opt("NOTIFY_HTTP_URL"),
+            history_file: Some(env_opt("HISTORY_FILE").unwrap_or_else(|| "history.jsonl".to_string())),
+            audit_file: Some(env_opt("AUDIT_FILE").unwrap_or_else(|| "audit.jsonl".to_string())),
+            processed_orders_file: Some(env_opt("PROCESSED_ORDERS_FILE").unwrap_or_else(|| "processed-orders.json".to_string())),
+            product_overrides_file: Some(env_opt("PRODUCT_OVERRIDES_FILE").unwrap_or_else(|| "product-overrides.json".to_string())),
+            source_report: env_opt("SOURCE_REPORT").unwrap_or_else(|| "off".to_string()),
+            name_template,
+            name_sequence_file: Some(env_opt("NAME_SEQUENCE_FILE").unwrap_or_else(|| "name-sequence.json".to_string())),
+            summary_schedule: env_opt("SUMMARY_SCHEDULE"),
+            summary_hour,
+            drift_check: env_parse("DRIFT_CHECK", true),
+            drift_check_hour: env_parse("DRIFT_CHECK_HOUR", 4).min(23),
+            drift_baseline_file: Some(
+                env_opt("DRIFT_BASELINE_FILE").unwrap_or_else(|| "drift-webhooks.json".to_string()),
+            ),
+            sla_limit_secs: env_opt("SLA_LIMIT_SECS").and_then(|v| v.parse().ok()).filter(|v| *v > 0),
+            moysklad_utc_offset_hours: env_parse("MOYSKLAD_UTC_OFFSET", 3),
+            work_hours: env_opt("WORK_HOURS"),
+            work_days: env_opt("WORK_DAYS").map(|v| split_list(&v)).unwrap_or_default(),
+            holidays: env_opt("HOLIDAYS").map(|v| split_list(&v)).unwrap_or_default(),
+            work_schedule_moment: env_parse("WORK_SCHEDULE_MOMENT", false),
+            retry_queue_file: Some(env_opt("RETRY_QUEUE_FILE").unwrap_or_else(|| "retry-queue.json".to_string())),
+            retry_base_delay_secs: env_parse("RETRY_BASE_DELAY_SECS", 30),
+            retry_max_delay_secs: env_parse("RETRY_MAX_DELAY_SECS", 3600),
+            retry_poll_interval_secs: env_parse("RETRY_POLL_INTERVAL_SECS", 15),
+            retry_max_attempts: env_parse("RETRY_MAX_ATTEMPTS", 10),
+            dead_letter_file: Some(env_opt("DEAD_LETTER_FILE").unwrap_or_else(|| "dead-letters.json".to_string())),
+            shortage_queue_file: Some(env_opt("SHORTAGE_QUEUE_FILE").unwrap_or_else(|| "shortage-queue.json".to_string())),
+            shortage_recheck_interval_secs: env_parse("SHORTAGE_RECHECK_INTERVAL_SECS", 1800),
+            circuit_breaker_threshold: env_parse("CIRCUIT_BREAKER_THRESHOLD", 5),
+            circuit_breaker_cooldown_secs: env_parse("CIRCUIT_BREAKER_COOLDOWN_SECS", 60),
+            webhook_queue_depth: env_parse("WEBHOOK_QUEUE_DEPTH", 200),
+            webhook_retry_after_secs: env_parse("WEBHOOK_RETRY_AFTER_SECS", 30),
+            webhook_debounce_secs: env_parse("WEBHOOK_DEBOUNCE_SECS", 0),
+            priority_rules: env_opt("PRIORITY_RULES").map(|v| split_list(&v)).unwrap_or_default(),
+            pending_queue_file: Some(env_opt("PENDING_QUEUE_FILE").unwrap_or_else(|| "pending-queue.json".to_string())),
+            job_results_max: env_parse("JOB_RESULTS_MAX", 50),
+            job_inline_results: env_parse("JOB_INLINE_RESULTS", 100),
+            event_queue,
+            redis_url,
+            redis_stream: env_opt("REDIS_STREAM").unwrap_or_else(|| "autoproduction:webhooks".to_string()),
+            redis_consumer_group: env_opt("REDIS_CONSUMER_GROUP").unwrap_or_else(|| "autoproduction".to_string()),
+            redis_stream_maxlen: env_parse("REDIS_STREAM_MAXLEN", 100_000),
+            redis_claim_idle_secs: env_parse("REDIS_CLAIM_IDLE_SECS", 300),
+            worker_name,
+            lock_provider,
+            lock_ttl_secs: env_parse("LOCK_TTL_SECS", 120),
+            lock_wait_secs: env_parse("LOCK_WAIT_SECS", 30),
+            http_connect_timeout_secs: env_parse("HTTP_CONNECT_TIMEOUT_SECS", 10),
+            http_timeout_secs: env_parse("HTTP_TIMEOUT_SECS", 30),
+            http_read_timeout_secs: env_opt("HTTP_READ_TIMEOUT_SECS").and_then(|v| v.parse().ok()),
+            http_pool_max_idle_per_host: env_parse("HTTP_POOL_MAX_IDLE", 10),
+            http_pool_idle_timeout_secs: env_parse("HTTP_POOL_IDLE_TIMEOUT_SECS", 90),
+            http_tcp_keepalive_secs: Some(env_parse("HTTP_TCP_KEEPALIVE_SECS", 60)).filter(|secs| *secs > 0),
+            moysklad_proxy,
+            moysklad_api_url,
+            report_page_size: env_parse("REPORT_PAGE_SIZE", 1000).clamp(1, 1000),
+            max_response_bytes: Some(env_parse("MAX_RESPONSE_BYTES", 16 * 1024 * 1024)).filter(|bytes| *bytes > 0),
+            stock_timeout_secs: env_parse("STOCK_TIMEOUT_SECS", 60),
+            plan_timeout_secs: env_parse("PLAN_TIMEOUT_SECS", 60),
+            create_timeout_secs: env_parse("CREATE_TIMEOUT_SECS", 120),
+            stock_retries: env_parse("STOCK_RETRIES", 0),
+            plan_retries: env_parse("PLAN_RETRIES", 0),
+            create_retries: env_parse("CREATE_RETRIES", 0),
+            outgoing_webhook_url: env_opt("OUTGOING_WEBHOOK_URL"),
+            outgoing_webhook_secret: env_opt("OUTGOING_WEBHOOK_SECRET"),
+            outgoing_webhook_retries: env_parse("OUTGOING_WEBHOOK_RETRIES", 3),
+            forward_url: env_opt("FORWARD_URL"),
+            forward_retries: env_parse("FORWARD_RETRIES", 3),
         })
     }
 }
@@ -81,15 +785,182 @@ fn strip_quotes(s: &str) -> String {
     }
 }
 
+/// Read an optional variable, treating empty values as unset
+fn env_opt(key: &str) -> Option<String> {
+    env::var(key)
+        .ok()
+        .map(|v| strip_quotes(&v))
+        .filter(|v| !v.is_empty())
+}
+
+/// Parse an optional variable, falling back to the default when unset or invalid
+fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env_opt(key).and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Split a comma-separated list, dropping empty items
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
             moysklad_token: String::new(),
+            moysklad_login: None,
+            moysklad_password: None,
+            moysklad_account_id: None,
+            tenants_file: None,
+            vendor_app_id: None,
+            vendor_app_uid: None,
+            vendor_secret_key: None,
+            vendor_accounts_file: None,
+            vendor_api_url: "https://apps-api.moysklad.ru/api/vendor/1.0".to_string(),
+            secrets_key: None,
+            secrets_key_file: None,
+            tokens_file: None,
             store_name: "Кобрино FBS".to_string(),
+            store_id: None,
+            organization_id: None,
+            agent_filter: Vec::new(),
+            sales_channel_filter: Vec::new(),
             tech_card_field_name: "Техкарта".to_string(),
+            tech_card_field_id: None,
+            tech_card_fallbacks: Vec::new(),
+            tech_card_description_prefix: "Техкарта:".to_string(),
+            folder_tech_card_file: None,
+            material_substitutes_file: None,
+            waste_percent: 0.0,
+            waste_field_name: None,
+            plan_lookup_mode: Vec::new(),
+            plan_cache_ttl_secs: 600,
+            warmup_plans: 20,
+            replenishment_strategy: None,
+            replenishment_field_name: None,
+            notify_only: false,
+            notify_only_field_name: None,
+            move_source_store_name: None,
+            purchase_supplier_name: None,
+            task_assignee: None,
+            task_reasons: vec!["no_tech_card".to_string(), "materials_short".to_string()],
+            tasks_file: None,
+            moysklad_app_url: "https://online.moysklad.ru/app".to_string(),
+            production_log_entity: None,
+            enter_fallback_field_name: None,
+            unit_factor_field_name: None,
+            production_pack_uom: None,
             min_stock_threshold: 2.0,
+            sales_velocity_days: 30,
+            dynamic_thresholds: false,
+            lead_time_days: 3.0,
+            dynamic_threshold_hour: 3,
+            max_auto_quantity: Decimal::ZERO,
+            partial_production: false,
+            processing_cost_from_materials: false,
+            overhead_amount: 0.0,
+            overhead_percent: 0.0,
+            overhead_distribution: "document".to_string(),
+            overhead_expense_item: None,
+            copy_order_project: false,
+            production_project: None,
+            bom_rollup: false,
+            bom_max_depth: 5,
+            on_order_revoked: "notify".to_string(),
+            stock_mode: "available".to_string(),
+            quantity_basis: "ordered".to_string(),
+            count_in_transit: false,
+            count_pending_productions: false,
+            skip_existing_productions: false,
+            production_dedup_ttl_secs: 120,
+            production_cooldown_secs: 3600,
+            stock_cache_refresh_secs: 0,
             server_port: 8080,
             server_host: "0.0.0.0".to_string(),
+            tls_cert_file: None,
+            tls_key_file: None,
+            max_payload_bytes: 256 * 1024,
+            api_keys: Vec::new(),
+            api_users_file: None,
+            entity_toggles_file: None,
+            notify_routes: String::new(),
+            telegram_bot_token: None,
+            telegram_chat_id: None,
+            smtp_host: None,
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            email_from: None,
+            email_to: Vec::new(),
+            notify_http_url: None,
+            history_file: None,
+            audit_file: None,
+            processed_orders_file: None,
+            product_overrides_file: None,
+            source_report: "off".to_string(),
+            name_template: None,
+            name_sequence_file: None,
+            summary_schedule: None,
+            summary_hour: 9,
+            drift_check: true,
+            drift_check_hour: 4,
+            drift_baseline_file: None,
+            sla_limit_secs: None,
+            moysklad_utc_offset_hours: 3,
+            work_hours: None,
+            work_days: Vec::new(),
+            holidays: Vec::new(),
+            work_schedule_moment: false,
+            retry_queue_file: None,
+            retry_base_delay_secs: 30,
+            retry_max_delay_secs: 3600,
+            retry_poll_interval_secs: 15,
+            retry_max_attempts: 10,
+            dead_letter_file: None,
+            shortage_queue_file: None,
+            shortage_recheck_interval_secs: 1800,
+            circuit_breaker_threshold: 5,
+            circuit_breaker_cooldown_secs: 60,
+            webhook_queue_depth: 200,
+            webhook_retry_after_secs: 30,
+            webhook_debounce_secs: 0,
+            priority_rules: Vec::new(),
+            pending_queue_file: None,
+            job_results_max: 50,
+            job_inline_results: 100,
+            event_queue: "local".to_string(),
+            redis_url: None,
+            redis_stream: "autoproduction:webhooks".to_string(),
+            redis_consumer_group: "autoproduction".to_string(),
+            redis_stream_maxlen: 100_000,
+            redis_claim_idle_secs: 300,
+            worker_name: "default".to_string(),
+            lock_provider: "memory".to_string(),
+            lock_ttl_secs: 120,
+            lock_wait_secs: 30,
+            http_connect_timeout_secs: 10,
+            http_timeout_secs: 30,
+            http_read_timeout_secs: None,
+            http_pool_max_idle_per_host: 10,
+            http_pool_idle_timeout_secs: 90,
+            http_tcp_keepalive_secs: Some(60),
+            moysklad_proxy: None,
+            moysklad_api_url: "https://api.moysklad.ru/api/remap/1.2".to_string(),
+            report_page_size: 1000,
+            max_response_bytes: Some(16 * 1024 * 1024),
+            stock_timeout_secs: 60,
+            plan_timeout_secs: 60,
+            create_timeout_secs: 120,
+            stock_retries: 0,
+            plan_retries: 0,
+            create_retries: 0,
+            outgoing_webhook_url: None,
+            outgoing_webhook_secret: None,
+            outgoing_webhook_retries: 3,
+            forward_url: None,
+            forward_retries: 3,
         }
     }
 }
@@ -0,0 +1,35 @@
+//! TLS для встроенного HTTP сервера
+
+use anyhow::{anyhow, Context, Result};
+use rustls::crypto::ring::default_provider;
+use rustls::ServerConfig;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+/// Конфигурация TLS из сертификата и ключа в формате PEM
+pub fn load_server_config(cert_path: &str, key_path: &str) -> Result<ServerConfig> {
+    let mut cert_reader = BufReader::new(
+        File::open(cert_path).with_context(|| format!("Failed to open TLS certificate {}", cert_path))?,
+    );
+    let certs = rustls_pemfile::certs(&mut cert_reader)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse TLS certificate {}", cert_path))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates found in {}", cert_path));
+    }
+
+    let mut key_reader = BufReader::new(
+        File::open(key_path).with_context(|| format!("Failed to open TLS key {}", key_path))?,
+    );
+    let key = rustls_pemfile::private_key(&mut key_reader)
+        .with_context(|| format!("Failed to parse TLS key {}", key_path))?
+        .ok_or_else(|| anyhow!("No private key found in {}", key_path))?;
+
+    ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")
+}
@@ -0,0 +1,99 @@
+//! Включение и отключение обработки webhook по типам сущностей во время работы
+
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::info;
+
+use super::persist_json;
+
+/// Типы сущностей, webhook которых обрабатывает сервис
+pub const HANDLED_ENTITY_TYPES: [&str; 7] = [
+    "customerorder",
+    "retaildemand",
+    "internalorder",
+    "supply",
+    "inventory",
+    "enter",
+    "loss",
+];
+
+/// Документы, корректирующие остаток без спроса (инвентаризация, оприходование, списание)
+pub const STOCK_CORRECTION_TYPES: [&str; 3] = ["inventory", "enter", "loss"];
+
+/// Отключённые типы сущностей; сохраняются между перезапусками
+pub struct EntityToggles {
+    path: Option<PathBuf>,
+    disabled: Mutex<BTreeSet<String>>,
+}
+
+impl EntityToggles {
+    /// Открыть список, восстановив сохранённые отключения
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut disabled = BTreeSet::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read entity toggles {}", path.display()))?;
+            disabled = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse entity toggles {}", path.display()))?;
+            if !disabled.is_empty() {
+                info!(
+                    "Webhook handling disabled for: {}",
+                    disabled.iter().cloned().collect::<Vec<_>>().join(", ")
+                );
+            }
+        }
+
+        Ok(Self {
+            path,
+            disabled: Mutex::new(disabled),
+        })
+    }
+
+    /// Обрабатывать ли webhook этого типа
+    pub fn is_enabled(&self, entity_type: &str) -> bool {
+        !self
+            .disabled
+            .lock()
+            .expect("entity toggles lock poisoned")
+            .contains(&entity_type.to_lowercase())
+    }
+
+    /// Включить или отключить тип сущности
+    pub fn set(&self, entity_type: &str, enabled: bool) {
+        let mut disabled = self.disabled.lock().expect("entity toggles lock poisoned");
+        let entity_type = entity_type.to_lowercase();
+        let changed = if enabled {
+            disabled.remove(&entity_type)
+        } else {
+            disabled.insert(entity_type)
+        };
+
+        if changed {
+            self.persist(&disabled);
+        }
+    }
+
+    /// Состояние обрабатываемых и отключённых типов
+    pub fn list(&self) -> Vec<(String, bool)> {
+        let disabled = self.disabled.lock().expect("entity toggles lock poisoned");
+        let mut types: BTreeSet<String> = HANDLED_ENTITY_TYPES.iter().map(|t| t.to_string()).collect();
+        types.extend(disabled.iter().cloned());
+
+        types
+            .into_iter()
+            .map(|t| {
+                let enabled = !disabled.contains(&t);
+                (t, enabled)
+            })
+            .collect()
+    }
+
+    fn persist(&self, disabled: &BTreeSet<String>) {
+        persist_json(self.path.as_deref(), disabled, "entity toggles");
+    }
+}
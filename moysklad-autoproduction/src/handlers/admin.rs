@@ -0,0 +1,597 @@
+//! Administrative endpoints
+
+use actix_web::{web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use super::validation::{validate_entity_id, validation_error};
+use super::{resolve_tenant, AppState, TenantQuery};
+use crate::api::redact::error_message;
+use crate::config::HANDLED_ENTITY_TYPES;
+use crate::processing::{export_overrides_csv, parse_overrides_csv, ProductOverride, ReplayEvent};
+
+/// Orders waiting for a retry after Moysklad was unavailable, per tenant
+pub async fn get_retry_queue(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let tenants: Vec<_> = state
+        .tenants
+        .all()
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "tenant": t.name,
+                "size": t.retry_queue.depth(),
+                "entries": t.retry_queue.list(),
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({ "tenants": tenants }))
+}
+
+/// Documents waiting for processing: the priority queue (in processing order)
+/// and the retry queue
+/// Example: GET /admin/queue
+pub async fn get_queue(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "tenant": tenant.name,
+        "priority_enabled": tenant.pending.enabled(),
+        "pending": tenant.pending.list(),
+        "retry": tenant.retry_queue.list(),
+    }))
+}
+
+/// Drop a document from the queues, e.g. a poison message failing on every retry
+/// Example: DELETE /admin/queue/{id}
+pub async fn delete_queue_entry(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &id) {
+        return response;
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let pending = tenant.pending.remove(&id).is_some();
+    let retry = tenant.retry_queue.remove(&id).is_some();
+    if !pending && !retry {
+        return queue_entry_not_found(&id);
+    }
+
+    info!("[{}] Document {} removed from the queue", tenant.name, id);
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "removed",
+        "id": id,
+        "pending": pending,
+        "retry": retry,
+    }))
+}
+
+/// Move a document to the front: processed next from the priority queue,
+/// retried on the next pass of the retry queue
+/// Example: POST /admin/queue/{id}/promote
+pub async fn promote_queue_entry(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &id) {
+        return response;
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let pending = tenant.pending.promote(&id);
+    let retry = tenant.retry_queue.promote(&id);
+    if !pending && !retry {
+        return queue_entry_not_found(&id);
+    }
+
+    info!("[{}] Document {} promoted in the queue", tenant.name, id);
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "promoted",
+        "id": id,
+        "pending": pending,
+        "retry": retry,
+    }))
+}
+
+/// Orders taken off retries after RETRY_MAX_ATTEMPTS failures or a non-transient error,
+/// with their error history
+/// Example: GET /admin/dead-letters
+pub async fn get_dead_letters(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let entries = tenant.dead_letters.list();
+    HttpResponse::Ok().json(serde_json::json!({
+        "tenant": tenant.name,
+        "total": entries.len(),
+        "entries": entries,
+    }))
+}
+
+/// Put a dead-lettered order back to the retry queue with a fresh attempt counter
+/// Example: POST /admin/dead-letters/{id}/requeue
+pub async fn requeue_dead_letter(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &id) {
+        return response;
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let Some(letter) = tenant.dead_letters.remove(&id) else {
+        return dead_letter_not_found(&id);
+    };
+
+    tenant
+        .retry_queue
+        .enqueue(letter.entity_type(), &id, letter.action(), "Requeued from dead letters");
+    tenant.retry_queue.promote(&id);
+    info!("[{}] Order {} requeued from dead letters", tenant.name, id);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "requeued",
+        "id": id,
+    }))
+}
+
+/// Drop a dead-lettered order for good
+/// Example: DELETE /admin/dead-letters/{id}
+pub async fn delete_dead_letter(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &id) {
+        return response;
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    if tenant.dead_letters.remove(&id).is_none() {
+        return dead_letter_not_found(&id);
+    }
+
+    info!("[{}] Order {} removed from dead letters", tenant.name, id);
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "removed",
+        "id": id,
+    }))
+}
+
+fn dead_letter_not_found(id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "status": "error",
+        "message": format!("Order {} is not in dead letters", id)
+    }))
+}
+
+fn queue_entry_not_found(id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "status": "error",
+        "message": format!("Document {} is not queued", id)
+    }))
+}
+
+/// Moysklad API usage per tenant: calls per endpoint, average latency and rate-limit headroom
+pub async fn get_api_usage(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let tenants: Vec<_> = state
+        .tenants
+        .all()
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "tenant": t.name,
+                "usage": t.api_usage.snapshot(),
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({ "tenants": tenants }))
+}
+
+/// Processor caches, queue depths and circuit breaker state per tenant.
+/// A processor busy with an order is reported as `busy` instead of waiting for it.
+pub async fn get_state(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let tenants: Vec<_> = state
+        .tenants
+        .all()
+        .iter()
+        .map(|t| {
+            let processor = t.processor.try_lock().ok().map(|p| p.state());
+            serde_json::json!({
+                "tenant": t.name,
+                "account_id": t.account_id,
+                "circuit": t.circuit_breaker.state(),
+                "retry_queue_depth": t.retry_queue.depth(),
+                "dead_letter_depth": t.dead_letters.depth(),
+                "busy": processor.is_none(),
+                "processor": processor,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({ "tenants": tenants }))
+}
+
+/// Per-product overrides of a tenant
+pub async fn list_product_settings(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let products = tenant.overrides.all();
+    HttpResponse::Ok().json(serde_json::json!({
+        "total": products.len(),
+        "products": products,
+    }))
+}
+
+/// Overrides of one product
+/// Example: GET /admin/products/{id}/settings
+pub async fn get_product_settings(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let product_id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &product_id) {
+        return response;
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    match tenant.overrides.find(&product_id) {
+        Some(settings) => HttpResponse::Ok().json(serde_json::json!({
+            "product_id": product_id,
+            "settings": settings,
+        })),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("No settings for product {}", product_id)
+        })),
+    }
+}
+
+/// Replace overrides of one product
+/// Example: PUT /admin/products/{id}/settings {"threshold": 5, "target_level": 20, "strategy": "produce"}
+pub async fn put_product_settings(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    body: web::Json<ProductOverride>,
+) -> impl Responder {
+    let product_id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &product_id) {
+        return response;
+    }
+    let settings = body.into_inner();
+    if let Err(reason) = settings.validate() {
+        return validation_error(None, &reason);
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let saved = tenant.overrides.set(&product_id, settings);
+    info!("[{}] Product {} settings updated", tenant.name, product_id);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "product_id": product_id,
+        "settings": saved,
+    }))
+}
+
+/// Remove overrides of one product, falling back to attributes and global settings
+pub async fn delete_product_settings(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let product_id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &product_id) {
+        return response;
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    if tenant.overrides.remove(&product_id) {
+        info!("[{}] Product {} settings removed", tenant.name, product_id);
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("No settings for product {}", product_id)
+        }))
+    }
+}
+
+/// Per-product overrides as CSV for editing in a spreadsheet
+/// Example: GET /admin/products/settings/export
+pub async fn export_product_settings(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    match export_overrides_csv(&tenant.overrides.all()) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header((
+                "Content-Disposition",
+                "attachment; filename=\"product-settings.csv\"",
+            ))
+            .body(body),
+        Err(e) => {
+            error!("Failed to export product settings: {:#}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
+
+/// Query parameters for the product settings import
+#[derive(Debug, serde::Deserialize)]
+pub struct ImportQuery {
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+    /// Validate only, do not save
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Upload per-product overrides as CSV (columns as in the export).
+/// Valid rows replace the settings of their products; invalid rows are reported by line number.
+/// Example: POST /admin/products/settings/import?dry_run=true (Content-Type: text/csv)
+pub async fn import_product_settings(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let (rows, errors) = match parse_overrides_csv(&body) {
+        Ok(parsed) => parsed,
+        Err(e) => return validation_error(None, &format!("{:#}", e)),
+    };
+
+    let imported = rows.len();
+    if !query.dry_run && !rows.is_empty() {
+        tenant.overrides.set_many(rows);
+        info!(
+            "[{}] Imported settings for {} products ({} rows rejected)",
+            tenant.name,
+            imported,
+            errors.len()
+        );
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": if errors.is_empty() { "ok" } else { "partial" },
+        "dry_run": query.dry_run,
+        "imported": imported,
+        "rejected": errors.len(),
+        "errors": errors,
+    }))
+}
+
+/// Entity types and whether their webhooks are handled
+pub async fn get_entity_types(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let types: Vec<_> = state
+        .entity_toggles
+        .list()
+        .into_iter()
+        .map(|(entity_type, enabled)| {
+            serde_json::json!({
+                "type": entity_type,
+                "handled": HANDLED_ENTITY_TYPES.contains(&entity_type.as_str()),
+                "enabled": enabled,
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({ "entity_types": types }))
+}
+
+/// Body of an entity type toggle
+#[derive(Debug, serde::Deserialize)]
+pub struct EntityToggleRequest {
+    pub enabled: bool,
+}
+
+/// Enable or disable webhook handling for an entity type
+/// Example: PUT /admin/entity-types/customerorder {"enabled": false}
+pub async fn put_entity_type(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<EntityToggleRequest>,
+) -> impl Responder {
+    let entity_type = path.into_inner().to_lowercase();
+    if entity_type.is_empty() || !entity_type.chars().all(|c| c.is_ascii_lowercase()) {
+        return validation_error(Some("type"), "expected a Moysklad entity type, e.g. customerorder");
+    }
+
+    state.entity_toggles.set(&entity_type, body.enabled);
+    info!(
+        "Webhook handling for {} {}",
+        entity_type,
+        if body.enabled { "enabled" } else { "disabled" }
+    );
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "type": entity_type,
+        "enabled": body.enabled,
+    }))
+}
+
+/// Body of a token rotation request
+#[derive(Debug, serde::Deserialize)]
+pub struct TokenRotationRequest {
+    pub token: String,
+}
+
+/// Replace the Moysklad token of a tenant without a restart.
+/// The token is stored encrypted and is never echoed back.
+/// Example: PUT /admin/token?tenant=shop {"token": "..."}
+pub async fn rotate_token(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+    body: web::Json<TokenRotationRequest>,
+) -> impl Responder {
+    let token = body.into_inner().token.trim().to_string();
+    if token.is_empty() {
+        return validation_error(Some("token"), "must not be empty");
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    if let Err(e) = state.tenants.rotate_token(&tenant, &token).await {
+        error!("[{}] Failed to rotate token: {:#}", tenant.name, e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": error_message(&e)
+        }));
+    }
+
+    let api_available = match tenant.processor.lock().await.probe_api().await {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("[{}] Moysklad API check with the new token failed: {:#}", tenant.name, e);
+            false
+        }
+    };
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "tenant": tenant.name,
+        "status": "rotated",
+        "api_available": api_available,
+    }))
+}
+
+/// Replay a captured event against the current code: the document is processed in a
+/// sandbox that sends nothing to Moysklad and leaves the tenant state untouched. The
+/// body is the CLI `replay` format, optionally with the document as it was when the
+/// event arrived (`entity`) and recorded API responses (`responses`, e.g. `api_calls`
+/// of an earlier replay); without `responses` reads go to Moysklad. The response carries
+/// the processing results, every API response read, the writes that were held back and
+/// each stage attempt, so an incident can be reproduced deterministically.
+/// Example: POST /admin/replay {"id": "...", "type": "customerorder", "accountId": "..."}
+pub async fn replay_event(
+    state: web::Data<Arc<AppState>>,
+    body: web::Json<ReplayEvent>,
+) -> impl Responder {
+    let event = body.into_inner();
+    if let Err(response) = validate_entity_id("id", &event.id) {
+        return response;
+    }
+    if event.action() == "delete" {
+        return validation_error(Some("action"), "Deleted documents have nothing to replay");
+    }
+    if !HANDLED_ENTITY_TYPES.contains(&event.entity_type.to_lowercase().as_str()) {
+        return validation_error(
+            Some("type"),
+            &format!("Replay of {} events is not supported", event.entity_type),
+        );
+    }
+    let tenant = match resolve_tenant(&state, event.account_id.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    info!("[{}] Replaying {} {} event", tenant.name, event.entity_type, event.id);
+
+    // The tenant processor is locked only while the sandbox is built
+    let sandbox = tenant.processor.lock().await.sandbox(event.playback());
+    let sandbox = match sandbox {
+        Ok(sandbox) => sandbox,
+        Err(e) => {
+            error!("[{}] Failed to prepare replay: {:#}", tenant.name, e);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"status": "error", "message": error_message(&e)}));
+        }
+    };
+    let (results, trace) = sandbox.replay(&event.webhook()).await;
+
+    let summary = serde_json::json!({
+        "id": event.id,
+        "type": event.entity_type,
+        "action": event.action(),
+        "snapshot": event.entity.is_some(),
+        "recorded": event.responses.is_some(),
+    });
+
+    match results {
+        Ok(results) => HttpResponse::Ok().json(serde_json::json!({
+            "status": "ok",
+            "tenant": tenant.name,
+            "event": summary,
+            "results": results,
+            "api_calls": trace.api_calls,
+            "writes": trace.writes,
+            "stages": trace.stages,
+        })),
+        Err(e) => {
+            warn!("[{}] Replay failed: {:#}", tenant.name, e);
+
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "tenant": tenant.name,
+                "event": summary,
+                "message": error_message(&e),
+                "api_calls": trace.api_calls,
+                "writes": trace.writes,
+                "stages": trace.stages,
+            }))
+        }
+    }
+}
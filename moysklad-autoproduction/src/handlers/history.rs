@@ -0,0 +1,243 @@
+//! Processing history endpoints
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::sync::Arc;
+use tracing::error;
+
+use super::{resolve_tenant, AppState};
+use crate::api::redact::error_message;
+use crate::models::SkipReason;
+use crate::reports::{
+    export_history, query_history, ExportFormat, GroupKey, HistoryFilter, DEFAULT_QUERY_LIMIT,
+};
+
+/// Query parameters for history export
+#[derive(Debug, serde::Deserialize)]
+pub struct ExportQuery {
+    /// "csv" (default) or "xlsx"
+    pub format: Option<String>,
+    /// Start of the range (RFC 3339 or YYYY-MM-DD), inclusive
+    pub from: Option<String>,
+    /// End of the range (RFC 3339 or YYYY-MM-DD), exclusive
+    pub to: Option<String>,
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+    /// Only records skipped for this reason (e.g. "stock_sufficient")
+    pub reason: Option<String>,
+}
+
+/// Parse a date or datetime query parameter
+pub(crate) fn parse_datetime(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+/// Export processing history as a spreadsheet
+/// Example: GET /history/export?format=xlsx&from=2024-01-01&to=2024-02-01
+pub async fn export_history_file(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<ExportQuery>,
+) -> impl Responder {
+    let format = match query.format.as_deref() {
+        None => ExportFormat::Csv,
+        Some(f) => match ExportFormat::parse(f) {
+            Some(format) => format,
+            None => return bad_request(format!("Unknown format '{}', expected csv or xlsx", f)),
+        },
+    };
+
+    let from = match query.from.as_deref() {
+        None => DateTime::<Utc>::MIN_UTC,
+        Some(v) => match parse_datetime(v) {
+            Some(dt) => dt,
+            None => return bad_request(format!("Invalid 'from' date: {}", v)),
+        },
+    };
+
+    let to = match query.to.as_deref() {
+        None => DateTime::<Utc>::MAX_UTC,
+        Some(v) => match parse_datetime(v) {
+            Some(dt) => dt,
+            None => return bad_request(format!("Invalid 'to' date: {}", v)),
+        },
+    };
+
+    let reason = match query.reason.as_deref() {
+        None => None,
+        Some(r) => match SkipReason::parse(r) {
+            Some(reason) => Some(reason),
+            None => return bad_request(format!("Unknown skip reason '{}'", r)),
+        },
+    };
+
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let mut records = tenant.history.records_between(from, to);
+    if reason.is_some() {
+        records.retain(|r| r.skip_reason == reason);
+    }
+
+    match export_history(&records, format) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"history.{}\"", format.extension()),
+            ))
+            .body(body),
+        Err(e) => {
+            error!("Failed to export history: {:#}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
+
+/// Body of a history query
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct HistoryQueryRequest {
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+    /// Start of the range (RFC 3339 or YYYY-MM-DD), inclusive
+    pub from: Option<String>,
+    /// End of the range (RFC 3339 or YYYY-MM-DD), exclusive
+    pub to: Option<String>,
+    /// Only these product ids
+    pub product_id: Vec<String>,
+    /// Case-insensitive substring of the product name
+    pub product: Option<String>,
+    pub order_id: Option<String>,
+    /// Only records skipped for one of these reasons
+    pub reason: Vec<SkipReason>,
+    pub success: Option<bool>,
+    /// Only records that did (or did not) create a production
+    pub produced: Option<bool>,
+    pub revoked: Option<bool>,
+    /// Aggregate by "day", "product", "reason" and/or "status"
+    pub group_by: Vec<GroupKey>,
+    /// Maximum number of records or groups returned
+    pub limit: Option<usize>,
+}
+
+/// Filtered and optionally aggregated slice of the processing history
+/// Example: POST /history/query {"from": "2024-01-01", "reason": ["materials_short"], "group_by": ["day", "product"]}
+pub async fn query_history_records(
+    state: web::Data<Arc<AppState>>,
+    body: web::Json<HistoryQueryRequest>,
+) -> impl Responder {
+    let request = body.into_inner();
+
+    let from = match request.from.as_deref().map(|v| (v, parse_datetime(v))) {
+        None => None,
+        Some((_, Some(dt))) => Some(dt),
+        Some((v, None)) => return bad_request(format!("Invalid 'from' date: {}", v)),
+    };
+
+    let to = match request.to.as_deref().map(|v| (v, parse_datetime(v))) {
+        None => None,
+        Some((_, Some(dt))) => Some(dt),
+        Some((v, None)) => return bad_request(format!("Invalid 'to' date: {}", v)),
+    };
+
+    let tenant = match resolve_tenant(&state, request.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let filter = HistoryFilter {
+        from,
+        to,
+        product_ids: request.product_id,
+        product_name: request.product,
+        order_id: request.order_id,
+        reasons: request.reason,
+        success: request.success,
+        produced: request.produced,
+        revoked: request.revoked,
+    };
+
+    let records = tenant.history.records_between(
+        from.unwrap_or(DateTime::<Utc>::MIN_UTC),
+        to.unwrap_or(DateTime::<Utc>::MAX_UTC),
+    );
+
+    HttpResponse::Ok().json(query_history(
+        records,
+        &filter,
+        &request.group_by,
+        request.limit.unwrap_or(DEFAULT_QUERY_LIMIT),
+    ))
+}
+
+/// Query parameters for the audit trail
+#[derive(Debug, serde::Deserialize)]
+pub struct AuditQuery {
+    /// Start of the range (RFC 3339 or YYYY-MM-DD), inclusive
+    pub from: Option<String>,
+    /// End of the range (RFC 3339 or YYYY-MM-DD), exclusive
+    pub to: Option<String>,
+    /// Only changes made while processing this order
+    pub order_id: Option<String>,
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+}
+
+/// Write operations sent to Moysklad
+/// Example: GET /audit?from=2024-01-01&order_id=...
+pub async fn get_audit(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    let from = match query.from.as_deref() {
+        None => DateTime::<Utc>::MIN_UTC,
+        Some(v) => match parse_datetime(v) {
+            Some(dt) => dt,
+            None => return bad_request(format!("Invalid 'from' date: {}", v)),
+        },
+    };
+
+    let to = match query.to.as_deref() {
+        None => DateTime::<Utc>::MAX_UTC,
+        Some(v) => match parse_datetime(v) {
+            Some(dt) => dt,
+            None => return bad_request(format!("Invalid 'to' date: {}", v)),
+        },
+    };
+
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let records: Vec<_> = tenant
+        .audit
+        .records_between(from, to)
+        .into_iter()
+        .filter(|r| query.order_id.is_none() || r.order_id == query.order_id)
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "total": records.len(),
+        "records": records,
+    }))
+}
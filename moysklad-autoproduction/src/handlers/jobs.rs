@@ -0,0 +1,55 @@
+//! Paged results of manual processing runs
+
+use actix_web::{web, HttpResponse, Responder};
+use std::sync::Arc;
+
+use super::{resolve_tenant, AppState};
+
+/// Default and maximum page size
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Query parameters for job results
+#[derive(Debug, serde::Deserialize)]
+pub struct JobResultsQuery {
+    pub offset: Option<usize>,
+    /// Page size (default 100, at most 1000)
+    pub limit: Option<usize>,
+    /// Only successful (true) or failed (false) positions
+    pub success: Option<bool>,
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+}
+
+/// A page of results of a processing run
+/// Example: GET /jobs/{id}/results?offset=0&limit=50&success=false
+pub async fn get_job_results(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<JobResultsQuery>,
+) -> impl Responder {
+    let job_id = path.into_inner();
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let Some(job) = tenant.jobs.get(&job_id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Unknown or expired job '{}'", job_id)
+        }));
+    };
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let (total, results) = job.page(query.success, offset, limit);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "job": job.summary(),
+        "total": total,
+        "offset": offset,
+        "limit": limit,
+        "results": results,
+    }))
+}
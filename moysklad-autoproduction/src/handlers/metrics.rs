@@ -0,0 +1,136 @@
+//! Prometheus metrics
+
+use actix_web::{web, HttpResponse, Responder};
+use std::fmt::Write;
+use std::sync::Arc;
+
+use super::AppState;
+
+/// Moysklad API usage and request latency in the Prometheus text format
+pub async fn get_metrics(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let mut out = String::new();
+    let snapshots: Vec<_> = state
+        .tenants
+        .all()
+        .iter()
+        .map(|t| (t.name.clone(), t.api_usage.snapshot()))
+        .collect();
+
+    let per_endpoint: [(&str, &str, &str); 3] = [
+        ("moysklad_api_calls_total", "counter", "Moysklad API calls"),
+        ("moysklad_api_errors_total", "counter", "Moysklad API calls without a successful response"),
+        ("moysklad_api_latency_seconds_sum", "counter", "Total Moysklad API latency"),
+    ];
+    for (name, kind, help) in per_endpoint {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (tenant, usage) in &snapshots {
+            for e in &usage.endpoints {
+                let value = match name {
+                    "moysklad_api_calls_total" => e.calls as f64,
+                    "moysklad_api_errors_total" => e.errors as f64,
+                    _ => e.total_latency_secs,
+                };
+                let _ = writeln!(
+                    out,
+                    "{}{{tenant=\"{}\",endpoint=\"{}\"}} {}",
+                    name,
+                    escape(tenant),
+                    escape(&e.endpoint),
+                    value
+                );
+            }
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP moysklad_api_unknown_fields Skipped response fields absent from the first parsed response"
+    );
+    let _ = writeln!(out, "# TYPE moysklad_api_unknown_fields gauge");
+    for (tenant, usage) in &snapshots {
+        for e in usage.endpoints.iter().filter(|e| !e.new_fields.is_empty()) {
+            let _ = writeln!(
+                out,
+                "moysklad_api_unknown_fields{{tenant=\"{}\",endpoint=\"{}\"}} {}",
+                escape(tenant),
+                escape(&e.endpoint),
+                e.new_fields.len()
+            );
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP moysklad_api_unknown_variants_total Enum values in responses the models do not know"
+    );
+    let _ = writeln!(out, "# TYPE moysklad_api_unknown_variants_total counter");
+    for (tenant, usage) in &snapshots {
+        for e in usage.endpoints.iter().filter(|e| e.unknown_variants > 0) {
+            let _ = writeln!(
+                out,
+                "moysklad_api_unknown_variants_total{{tenant=\"{}\",endpoint=\"{}\"}} {}",
+                escape(tenant),
+                escape(&e.endpoint),
+                e.unknown_variants
+            );
+        }
+    }
+
+    let rate_limit: [(&str, &str); 2] = [
+        ("moysklad_api_rate_limit", "Moysklad API rate limit (X-RateLimit-Limit)"),
+        ("moysklad_api_rate_limit_remaining", "Remaining Moysklad API requests (X-RateLimit-Remaining)"),
+    ];
+    for (name, help) in rate_limit {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (tenant, usage) in &snapshots {
+            let value = match name {
+                "moysklad_api_rate_limit" => usage.rate_limit.limit,
+                _ => usage.rate_limit.remaining,
+            };
+            if let Some(value) = value {
+                let _ = writeln!(out, "{}{{tenant=\"{}\"}} {}", name, escape(tenant), value);
+            }
+        }
+    }
+
+    let _ = writeln!(out, "# HELP autoproduction_intake_queue_depth Webhooks waiting for or holding the processor");
+    let _ = writeln!(out, "# TYPE autoproduction_intake_queue_depth gauge");
+    for tenant in state.tenants.all() {
+        let _ = writeln!(
+            out,
+            "autoproduction_intake_queue_depth{{tenant=\"{}\"}} {}",
+            escape(&tenant.name),
+            tenant.intake.depth()
+        );
+    }
+
+    let _ = writeln!(out, "# HELP autoproduction_skipped_total Skipped positions and orders by reason");
+    let _ = writeln!(out, "# TYPE autoproduction_skipped_total counter");
+    for tenant in state.tenants.all() {
+        for (reason, count) in tenant.skip_stats.snapshot() {
+            let _ = writeln!(
+                out,
+                "autoproduction_skipped_total{{tenant=\"{}\",reason=\"{}\"}} {}",
+                escape(&tenant.name),
+                reason.as_str(),
+                count
+            );
+        }
+    }
+
+    state.request_metrics.render(&mut out);
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(out)
+}
+
+/// Escape a Prometheus label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,3 +1,23 @@
+pub mod admin;
+pub mod history;
+pub mod jobs;
+pub mod metrics;
+pub mod plan;
+pub mod reports;
+pub mod request_log;
+pub mod stock;
+pub mod validation;
+pub mod vendor;
 pub mod webhook;
 
+pub use admin::*;
+pub use history::*;
+pub use jobs::*;
+pub use metrics::*;
+pub use plan::*;
+pub use reports::*;
+pub use request_log::*;
+pub use stock::*;
+pub use validation::*;
+pub use vendor::*;
 pub use webhook::*;
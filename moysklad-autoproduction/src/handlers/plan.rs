@@ -0,0 +1,171 @@
+//! Production planning from customer order demand
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::NaiveDate;
+use std::sync::Arc;
+use tracing::{error, info, info_span, Instrument};
+
+use super::validation::{validate_entity_id, validation_error};
+use super::{resolve_tenant, AppState, TenantQuery};
+use crate::api::redact::error_message;
+use crate::models::{PlanItem, PlanScope, ProcessingResult, ProductInfo};
+use crate::notifications::{Notification, NotificationEvent};
+use crate::processing::OrderProcessor;
+
+/// Body of a plan computation: a shipment date range or a list of order ids
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct PlanComputeRequest {
+    /// First planned shipment date (YYYY-MM-DD, account time), inclusive
+    pub from: Option<NaiveDate>,
+    /// Last planned shipment date (YYYY-MM-DD, account time), inclusive
+    pub to: Option<NaiveDate>,
+    /// Customer order ids; used instead of the date range
+    pub order_ids: Vec<String>,
+}
+
+impl PlanComputeRequest {
+    fn scope(&self) -> Result<PlanScope, HttpResponse> {
+        if !self.order_ids.is_empty() {
+            for id in &self.order_ids {
+                validate_entity_id("order_ids", id)?;
+            }
+            return Ok(PlanScope::Orders(self.order_ids.clone()));
+        }
+
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from <= to => Ok(PlanScope::Period { from, to }),
+            (Some(_), Some(_)) => Err(validation_error(Some("to"), "must not be before 'from'")),
+            _ => Err(validation_error(
+                None,
+                "expected 'order_ids' or both 'from' and 'to' dates",
+            )),
+        }
+    }
+}
+
+/// Aggregate production plan: demand per product minus stock and pending productions,
+/// with tech cards and materials. Nothing is created in Moysklad.
+/// Example: POST /plan/compute {"from": "2024-03-04", "to": "2024-03-10"}
+pub async fn compute_plan(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+    body: web::Json<PlanComputeRequest>,
+) -> impl Responder {
+    let request = body.into_inner();
+    let scope = match request.scope() {
+        Ok(scope) => scope,
+        Err(response) => return response,
+    };
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    info!("Production plan request: {:?}", scope);
+
+    match OrderProcessor::compute_plan(&tenant.processor, &scope).await {
+        Ok(plan) => HttpResponse::Ok().json(plan),
+        Err(e) => {
+            error!("Error computing production plan: {}", e);
+
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
+
+/// Body of a plan execution: the `items` of a computed plan, possibly edited
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct PlanExecuteRequest {
+    pub items: Vec<PlanItem>,
+}
+
+/// Create tech operations for a production plan in the background.
+/// Each line goes through the regular position pipeline (product lock, cooldown,
+/// product settings, unit conversion and quantity limits) without the stock threshold;
+/// lines already covered since the plan was computed and lines with zero quantity are skipped. Progress is read from /jobs/{id}/results.
+/// Example: POST /plan/execute {"items": [{"product_id": "...", "quantity": 12}]}
+pub async fn execute_plan(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+    body: web::Json<PlanExecuteRequest>,
+) -> impl Responder {
+    let request = body.into_inner();
+    for item in &request.items {
+        if let Err(response) = validate_entity_id("product_id", &item.product_id) {
+            return response;
+        }
+    }
+    let items: Vec<PlanItem> =
+        request.items.into_iter().filter(|item| !item.quantity.is_zero()).collect();
+    if items.is_empty() {
+        return validation_error(Some("items"), "no lines with a quantity to produce");
+    }
+
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let job = tenant.jobs.start("plan", items.len());
+    info!("Executing production plan of {} lines as job {}", items.len(), job.id);
+
+    let response = HttpResponse::Accepted().json(serde_json::json!({
+        "status": "accepted",
+        "job": job.summary(),
+        "results_url": format!("/jobs/{}/results", job.id),
+    }));
+
+    let span = info_span!("tenant", name = %tenant.name);
+    tokio::spawn(
+        async move {
+            let plan = tenant.processor.lock().await.plan_document(&job.id).await;
+            match plan {
+                Ok(plan) => {
+                    for item in &items {
+                        // The processor is locked per line so webhooks do not wait for the whole plan
+                        let result = tenant.processor.lock().await.execute_plan_item(&plan, item).await;
+                        job.push(result);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to start production plan job {}: {:#}", job.id, e);
+                    for item in &items {
+                        let failed = ProcessingResult::failed(
+                            "Ошибка создания тех. операции по плану",
+                            error_message(&e),
+                        )
+                        .with_product(ProductInfo::new(&item.product_id, &item.product_name, item.quantity, item.stock));
+                        job.push(failed);
+                    }
+                }
+            }
+            job.finish();
+
+            let summary = job.summary();
+            let event = if summary.failed == 0 {
+                NotificationEvent::Success
+            } else {
+                NotificationEvent::Failure
+            };
+            tenant
+                .notifier
+                .notify(Notification::new(
+                    event,
+                    "Производственный план выполнен".to_string(),
+                    format!(
+                        "Строк: {}, успешно: {}, с ошибками: {}",
+                        summary.total, summary.succeeded, summary.failed
+                    ),
+                ))
+                .await;
+        }
+        .instrument(span),
+    );
+
+    response
+}
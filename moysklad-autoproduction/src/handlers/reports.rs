@@ -0,0 +1,203 @@
+//! Report endpoints
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::Utc;
+use std::sync::Arc;
+use tracing::error;
+
+use super::{resolve_tenant, AppState};
+use crate::api::redact::error_message;
+use crate::processing::OrderProcessor;
+use crate::reports::{ReportPeriod, SlaReport, SummaryReport};
+
+/// Query parameters for the summary report
+#[derive(Debug, serde::Deserialize)]
+pub struct SummaryQuery {
+    /// Report period: "day" or "week"
+    pub period: Option<String>,
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+}
+
+/// Production summary for the last day or week
+/// Example: GET /reports/summary?period=week
+pub async fn get_summary_report(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<SummaryQuery>,
+) -> impl Responder {
+    let period = match query.period.as_deref() {
+        None => ReportPeriod::Day,
+        Some(p) => match ReportPeriod::parse(p) {
+            Some(period) => period,
+            None => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "status": "error",
+                    "message": format!("Unknown period '{}', expected day or week", p)
+                }));
+            }
+        },
+    };
+
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let now = Utc::now();
+    let records = tenant.history.records_between(now - period.duration(), now);
+
+    HttpResponse::Ok().json(SummaryReport::build(&records, period, now))
+}
+
+/// Query parameters for the SLA report
+#[derive(Debug, serde::Deserialize)]
+pub struct SlaQuery {
+    /// Number of days (default 7, max 90)
+    pub days: Option<u32>,
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+}
+
+/// Time from a document change in Moysklad to the created production, p50/p95 per day
+/// Example: GET /reports/sla?days=14
+pub async fn get_sla_report(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<SlaQuery>,
+) -> impl Responder {
+    let days = query.days.unwrap_or(7).clamp(1, 90);
+
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let now = Utc::now();
+    let records = tenant.history.records_between(now - chrono::Duration::days(days as i64), now);
+
+    HttpResponse::Ok().json(SlaReport::build(&records, days, tenant.settings.sla_limit_secs, now))
+}
+
+/// Query parameters for the stock forecast
+#[derive(Debug, serde::Deserialize)]
+pub struct ForecastQuery {
+    /// Forecast horizon in days (default 14, max 90)
+    pub days: Option<u32>,
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+}
+
+/// Projected stock of a product based on open customer orders
+/// Example: GET /forecast/{product_id}?days=7
+pub async fn get_forecast(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<ForecastQuery>,
+) -> impl Responder {
+    let product_id = path.into_inner();
+    let days = query.days.unwrap_or(14).min(90);
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let mut processor = tenant.processor.lock().await;
+
+    match processor.forecast(&product_id, days).await {
+        Ok(forecast) => HttpResponse::Ok().json(forecast),
+        Err(e) => {
+            error!("Error building forecast for product {}: {}", product_id, e);
+
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "product_id": product_id,
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
+
+/// Query parameters for the materials demand report
+#[derive(Debug, serde::Deserialize)]
+pub struct MaterialsDemandQuery {
+    /// Forecast horizon: "14d" or a number of days (default 14, max 90)
+    pub horizon: Option<String>,
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+}
+
+/// Parse a horizon such as "14d" or "14"
+fn parse_horizon(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let days = value.strip_suffix('d').unwrap_or(value);
+    days.parse().ok().filter(|days| (1..=90).contains(days))
+}
+
+/// Raw material requirements for production expected from sales velocity and thresholds,
+/// flagging materials that run out within the horizon
+/// Example: GET /reports/materials-demand?horizon=14d
+pub async fn get_materials_demand_report(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<MaterialsDemandQuery>,
+) -> impl Responder {
+    let horizon = match query.horizon.as_deref() {
+        None => 14,
+        Some(h) => match parse_horizon(h) {
+            Some(days) => days,
+            None => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "status": "error",
+                    "message": format!("Invalid horizon '{}', expected 1d to 90d", h)
+                }));
+            }
+        },
+    };
+
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    match OrderProcessor::materials_demand(&tenant.processor, horizon).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Error building materials demand report: {}", e);
+
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
+
+/// Query parameters for the mapping health report
+#[derive(Debug, serde::Deserialize)]
+pub struct MappingHealthQuery {
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+}
+
+/// Products whose tech card is missing, points to a non-existent
+/// processing plan or has no materials
+/// Example: GET /reports/mapping-health
+pub async fn get_mapping_health_report(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<MappingHealthQuery>,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    match OrderProcessor::mapping_health(&tenant.processor).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Error building mapping health report: {}", e);
+
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
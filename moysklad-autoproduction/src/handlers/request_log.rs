@@ -0,0 +1,145 @@
+//! Request logging with correlation ids and per-endpoint latency histograms
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{info, warn};
+
+use super::AppState;
+
+/// Correlation id header, accepted from the caller or generated
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Histogram bucket bounds, seconds
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Non-cumulative counts per bucket; the last slot is `+Inf`
+    buckets: [u64; BUCKETS.len() + 1],
+    count: u64,
+    sum: f64,
+}
+
+/// Request latency per method, route and status
+#[derive(Default)]
+pub struct RequestMetrics {
+    histograms: Mutex<BTreeMap<(String, String, u16), Histogram>>,
+}
+
+impl RequestMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn observe(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let mut histograms = self.histograms.lock().expect("request metrics lock poisoned");
+        let histogram = histograms
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default();
+
+        let bucket = BUCKETS.iter().position(|b| seconds <= *b).unwrap_or(BUCKETS.len());
+        histogram.buckets[bucket] += 1;
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+
+    /// Append `http_request_duration_seconds` in the Prometheus text format
+    pub fn render(&self, out: &mut String) {
+        let histograms = self.histograms.lock().expect("request metrics lock poisoned");
+
+        let _ = writeln!(out, "# HELP http_request_duration_seconds HTTP request latency");
+        let _ = writeln!(out, "# TYPE http_request_duration_seconds histogram");
+        for ((method, route, status), h) in histograms.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\",status=\"{}\"", method, route, status);
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(&h.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, h.count
+            );
+            let _ = writeln!(out, "http_request_duration_seconds_sum{{{}}} {}", labels, h.sum);
+            let _ = writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, h.count);
+        }
+    }
+}
+
+/// Log method, path, status, duration and correlation id of every request except `/health`
+pub async fn log_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if req.path() == "/health" {
+        return next.call(req).await;
+    }
+
+    let started = Instant::now();
+    let state = req.app_data::<web::Data<Arc<AppState>>>().cloned();
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    // Route template keeps ids out of metric labels
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let result = next.call(req).await;
+    let elapsed = started.elapsed();
+
+    let status = match &result {
+        Ok(response) => response.status().as_u16(),
+        Err(e) => e.as_response_error().status_code().as_u16(),
+    };
+
+    if status >= 500 {
+        warn!(
+            "{} {} -> {} in {:.1} ms [{}]",
+            method,
+            path,
+            status,
+            elapsed.as_secs_f64() * 1000.0,
+            request_id
+        );
+    } else {
+        info!(
+            "{} {} -> {} in {:.1} ms [{}]",
+            method,
+            path,
+            status,
+            elapsed.as_secs_f64() * 1000.0,
+            request_id
+        );
+    }
+
+    if let Some(state) = state {
+        state
+            .request_metrics
+            .observe(&method, &route, status, elapsed.as_secs_f64());
+    }
+
+    let mut response = result?;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
@@ -0,0 +1,110 @@
+//! Stock overview, materials availability and shortage queue endpoints
+
+use actix_web::{web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+use super::{resolve_tenant, AppState, TenantQuery};
+use crate::api::redact::error_message;
+use crate::processing::OrderProcessor;
+
+/// Current stock of all products with a tech card on the monitored store
+/// Example: GET /stock
+pub async fn get_stock(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    match OrderProcessor::stock_overview(&tenant.processor).await {
+        Ok(items) => {
+            let below_threshold = items.iter().filter(|i| i.below_threshold).count();
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "store_name": tenant.settings.store_name,
+                "threshold": tenant.settings.min_stock_threshold,
+                "total": items.len(),
+                "below_threshold": below_threshold,
+                "items": items,
+            }))
+        }
+        Err(e) => {
+            error!("Error building stock overview: {}", e);
+
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
+
+/// Query parameters for the materials check
+#[derive(Debug, serde::Deserialize)]
+pub struct MaterialsQuery {
+    /// Processing plan (tech card) name
+    pub plan: String,
+    /// Quantity to produce (default 1)
+    pub quantity: Option<f64>,
+    /// Tenant name or accountId
+    pub tenant: Option<String>,
+}
+
+/// Materials availability for a processing plan, without creating anything
+/// Example: GET /materials/check?plan=Стол&quantity=5
+pub async fn check_materials(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<MaterialsQuery>,
+) -> impl Responder {
+    let quantity = query.quantity.unwrap_or(1.0);
+    if !quantity.is_finite() || quantity <= 0.0 {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": "quantity must be a positive number"
+        }));
+    }
+
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let mut processor = tenant.processor.lock().await;
+
+    match processor.check_plan_materials(&query.plan, quantity).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Error checking materials for plan {}: {}", query.plan, e);
+
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "plan": query.plan,
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
+
+/// Positions waiting for materials, oldest first
+/// Example: GET /shortages
+pub async fn get_shortages(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    let entries = tenant.shortages.list();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "tenant": tenant.name,
+        "total": entries.len(),
+        "recheck_interval_secs": tenant.settings.shortage_recheck_interval_secs,
+        "entries": entries,
+    }))
+}
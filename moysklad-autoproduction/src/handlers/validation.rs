@@ -0,0 +1,132 @@
+//! Request validation: payload limits, content type and structured 400 errors
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, JsonPayloadError, QueryPayloadError};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use std::sync::Arc;
+use tracing::warn;
+
+use super::AppState;
+use crate::api::redact::sanitize;
+
+/// 400 response with the offending field and the reason
+pub(crate) fn validation_error(field: Option<&str>, reason: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "status": "error",
+        "message": "Invalid request",
+        "field": field,
+        "reason": reason,
+    }))
+}
+
+/// Field name from a serde error such as "missing field `id`"
+pub(crate) fn error_field(message: &str) -> Option<&str> {
+    let start = message.find('`')? + 1;
+    let len = message[start..].find('`')?;
+    Some(&message[start..start + len])
+}
+
+/// Query string deserialization errors as 400 with details
+pub fn query_error_handler(err: QueryPayloadError, req: &HttpRequest) -> Error {
+    let reason = sanitize(&err.to_string());
+    warn!("Invalid query for {}: {}", req.path(), reason);
+
+    let response = validation_error(error_field(&reason), &reason);
+    InternalError::from_response(err, response).into()
+}
+
+/// JSON body errors as 400 (413 for oversized bodies, 415 for wrong content type)
+pub fn json_error_handler(err: JsonPayloadError, req: &HttpRequest) -> Error {
+    // serde echoes values from the request body, which may carry tokens
+    let reason = sanitize(&err.to_string());
+    warn!("Invalid JSON body for {}: {}", req.path(), reason);
+
+    let response = match err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                "status": "error",
+                "message": reason,
+            }))
+        }
+        JsonPayloadError::ContentType => HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+            "status": "error",
+            "message": "Content-Type must be application/json",
+        })),
+        JsonPayloadError::Deserialize(ref e) => {
+            let reason = sanitize(&e.to_string());
+            validation_error(error_field(&reason), &reason)
+        }
+        _ => validation_error(None, &reason),
+    };
+    InternalError::from_response(err, response).into()
+}
+
+/// Moysklad entity IDs are UUIDs
+pub(crate) fn validate_entity_id(field: &str, id: &str) -> Result<(), HttpResponse> {
+    let valid = id.len() == 36
+        && id
+            .chars()
+            .enumerate()
+            .all(|(i, c)| if [8, 13, 18, 23].contains(&i) { c == '-' } else { c.is_ascii_hexdigit() });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(validation_error(Some(field), "expected a Moysklad entity UUID"))
+    }
+}
+
+/// Reject oversized bodies (413) and non-JSON bodies on POST/PUT (415) before they reach handlers
+pub async fn check_payload(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let limit = req
+        .app_data::<web::Data<Arc<AppState>>>()
+        .map(|state| state.settings.max_payload_bytes);
+
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    if let Some(limit) = limit
+        && content_length > limit
+    {
+        let response = HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Payload of {} bytes exceeds the limit of {} bytes", content_length, limit),
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    let has_body = content_length > 0;
+    if has_body && (req.method() == Method::POST || req.method() == Method::PUT) {
+        // CSV uploads are the only non-JSON bodies
+        let expected = if req.path().ends_with("/import") {
+            "text/csv"
+        } else {
+            "application/json"
+        };
+        let matches = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with(expected));
+
+        if !matches {
+            let response = HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                "status": "error",
+                "message": format!("Content-Type must be {}", expected),
+            }));
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
@@ -0,0 +1,212 @@
+//! Moysklad marketplace (Vendor API) lifecycle endpoints
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use super::webhook::AppState;
+use crate::api::redact::error_message;
+use crate::tenants::spawn_tenant_tasks;
+use crate::vendor::{fetch_context, verify_token, VendorAccount, VendorStatus};
+
+/// Path of lifecycle requests: /api/moysklad/vendor/1.0/apps/{appId}/{accountId}
+#[derive(Debug, Deserialize)]
+pub struct VendorAppPath {
+    pub app_id: String,
+    pub account_id: String,
+}
+
+/// Access granted to the solution in the installing account
+#[derive(Debug, Deserialize)]
+pub struct VendorAccess {
+    pub resource: String,
+    pub access_token: Option<String>,
+}
+
+/// Body of the install / resume request
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VendorInstallRequest {
+    #[serde(default)]
+    pub account_name: Option<String>,
+    /// Install, Resume, TariffChanged, ...
+    #[serde(default)]
+    pub cause: Option<String>,
+    #[serde(default)]
+    pub access: Vec<VendorAccess>,
+}
+
+/// Body of the uninstall / suspend request
+#[derive(Debug, Deserialize)]
+pub struct VendorUninstallRequest {
+    /// Uninstall or Suspend
+    #[serde(default)]
+    pub cause: Option<String>,
+}
+
+/// Check the Vendor API token and the application ID of a lifecycle request
+fn authorize(state: &AppState, req: &HttpRequest, app_id: &str) -> Result<(), HttpResponse> {
+    let settings = &state.settings;
+    let (Some(secret), Some(expected), Some(app_uid)) = (
+        settings.vendor_secret_key.as_deref(),
+        settings.vendor_app_id.as_deref(),
+        settings.vendor_app_uid.as_deref(),
+    ) else {
+        return Err(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": "Vendor API is not configured"
+        })));
+    };
+
+    if expected != app_id {
+        warn!("Vendor API request for unknown application {}", app_id);
+        return Err(HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Unknown application {}", app_id)
+        })));
+    }
+
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .unwrap_or_default();
+
+    verify_token(token, secret, app_uid).map_err(|e| {
+        warn!("Rejected Vendor API request: {:#}", e);
+        HttpResponse::Unauthorized().json(serde_json::json!({
+            "status": "error",
+            "message": "Invalid Vendor API token"
+        }))
+    })?;
+
+    Ok(())
+}
+
+/// Solution installed or resumed in an account: store its token and start a tenant
+pub async fn vendor_install(
+    state: web::Data<Arc<AppState>>,
+    req: HttpRequest,
+    path: web::Path<VendorAppPath>,
+    body: web::Json<VendorInstallRequest>,
+) -> impl Responder {
+    if let Err(response) = authorize(&state, &req, &path.app_id) {
+        return response;
+    }
+
+    let Some(access_token) = body.access.iter().find_map(|a| a.access_token.clone()) else {
+        warn!("Install request for account {} carries no access token", path.account_id);
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": "No access token in install request"
+        }));
+    };
+
+    info!(
+        "Solution {} for account {} ({})",
+        body.cause.as_deref().unwrap_or("Install"),
+        path.account_id,
+        body.access.iter().map(|a| a.resource.as_str()).collect::<Vec<_>>().join(", ")
+    );
+
+    let now = Utc::now();
+    let account = VendorAccount {
+        account_id: path.account_id.clone(),
+        account_name: body.account_name.clone(),
+        access_token,
+        status: VendorStatus::Activated,
+        installed_at: now,
+        updated_at: now,
+    };
+
+    match state.tenants.install(account) {
+        Ok(tenant) => {
+            spawn_tenant_tasks(&tenant, state.notifier.clone(), state.forwarder.clone());
+            HttpResponse::Ok().json(serde_json::json!({ "status": "Activated" }))
+        }
+        Err(e) => {
+            error!("Failed to install solution for account {}: {:#}", path.account_id, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
+                "message": error_message(&e)
+            }))
+        }
+    }
+}
+
+/// Solution uninstalled or suspended in an account: stop its tenant
+pub async fn vendor_uninstall(
+    state: web::Data<Arc<AppState>>,
+    req: HttpRequest,
+    path: web::Path<VendorAppPath>,
+    body: Option<web::Json<VendorUninstallRequest>>,
+) -> impl Responder {
+    if let Err(response) = authorize(&state, &req, &path.app_id) {
+        return response;
+    }
+
+    let cause = body.and_then(|b| b.into_inner().cause);
+    let suspend = cause.as_deref() == Some("Suspend");
+
+    if !state.tenants.uninstall(&path.account_id, suspend) {
+        info!("Uninstall request for unknown account {}", path.account_id);
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+/// Installation status of the solution in an account
+pub async fn vendor_status(
+    state: web::Data<Arc<AppState>>,
+    req: HttpRequest,
+    path: web::Path<VendorAppPath>,
+) -> impl Responder {
+    if let Err(response) = authorize(&state, &req, &path.app_id) {
+        return response;
+    }
+
+    let account = state
+        .tenants
+        .vendor_accounts()
+        .and_then(|accounts| accounts.get(&path.account_id));
+
+    match account {
+        Some(account) => HttpResponse::Ok().json(serde_json::json!({ "status": account.status })),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Solution is not installed for account {}", path.account_id)
+        })),
+    }
+}
+
+/// Resolve the user context of the solution iframe (`contextKey` from its URL)
+pub async fn vendor_context(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let context_key = path.into_inner();
+
+    let context = match fetch_context(&state.settings, &context_key).await {
+        Ok(context) => context,
+        Err(e) => {
+            warn!("Failed to resolve solution context: {:#}", e);
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "status": "error",
+                "message": "Invalid or expired context key"
+            }));
+        }
+    };
+
+    let tenant = state.tenants.by_account(&context.account_id);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "account_id": context.account_id,
+        "tenant": tenant.as_ref().map(|t| t.name.clone()),
+        "installed": tenant.is_some(),
+        "user": context,
+    }))
+}
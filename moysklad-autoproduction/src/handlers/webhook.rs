@@ -1,18 +1,54 @@
 //! HTTP request handlers
 
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::body::to_bytes;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, info, info_span, warn, Instrument};
 
-use crate::config::Settings;
-use crate::models::WebhookEvent;
+use super::request_log::RequestMetrics;
+use super::validation::{error_field, validate_entity_id, validation_error};
+use crate::api::redact::error_message;
+use crate::api::{is_transient_error, CircuitState};
+use crate::auth::ApiKeys;
+use crate::config::{EntityToggles, Settings, HANDLED_ENTITY_TYPES};
+use crate::api::redact::sanitize;
+use crate::models::{ProcessingResult, WebhookEvent, WebhookPayload};
 use crate::processing::OrderProcessor;
+use crate::notifications::{is_foreign, DeferredForward, ForwardedWebhook, NotificationRouter, WebhookForwarder};
+use crate::queue::{spawn_debounced_processing, EventStream};
+use crate::tenants::{Tenant, TenantRegistry};
 
 /// Application state
 pub struct AppState {
     pub settings: Settings,
-    pub processor: Mutex<OrderProcessor>,
+    pub notifier: Arc<NotificationRouter>,
+    pub tenants: Arc<TenantRegistry>,
+    pub api_keys: ApiKeys,
+    pub request_metrics: RequestMetrics,
+    /// Redis stream for webhooks in distributed mode
+    pub event_stream: Option<Arc<EventStream>>,
+    /// Entity types with webhook handling switched off at runtime
+    pub entity_toggles: EntityToggles,
+    /// Destination for webhooks this service ignores (FORWARD_URL)
+    pub forwarder: Option<Arc<WebhookForwarder>>,
+}
+
+/// Query parameter selecting a tenant by name or accountId (default tenant if omitted)
+#[derive(Debug, serde::Deserialize)]
+pub struct TenantQuery {
+    pub tenant: Option<String>,
+}
+
+/// Resolve a tenant or build a 404 response
+pub(crate) fn resolve_tenant(state: &AppState, key: Option<&str>) -> Result<Arc<Tenant>, HttpResponse> {
+    state.tenants.resolve(key).ok_or_else(|| {
+        HttpResponse::NotFound().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Unknown tenant '{}'", key.unwrap_or_default())
+        }))
+    })
 }
 
 /// Health check endpoint
@@ -23,25 +59,206 @@ pub async fn health() -> impl Responder {
     }))
 }
 
-/// Query parameters for Moysklad webhook
+/// Readiness check: degraded while Moysklad requests are suspended for any tenant
+pub async fn readyz(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let tenants: Vec<_> = state
+        .tenants
+        .all()
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "tenant": t.name,
+                "circuit": t.circuit_breaker.state(),
+                "retry_queue": t.retry_queue.depth(),
+                "intake_queue": t.intake.depth(),
+                "debounce_pending": t.debounce.depth(),
+                "pending_queue": t.pending.depth(),
+            })
+        })
+        .collect();
+    let ready = state
+        .tenants
+        .all()
+        .iter()
+        .all(|t| t.circuit_breaker.state() == CircuitState::Closed);
+
+    let body = serde_json::json!({
+        "status": if ready { "ready" } else { "degraded" },
+        "tenants": tenants,
+    });
+
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+/// Query parameters for Moysklad webhook. Without `id` and `type` the events
+/// are read from the request body.
 #[derive(Debug, serde::Deserialize)]
 pub struct WebhookQuery {
     /// Entity ID (e.g., customer order ID)
-    pub id: String,
+    pub id: Option<String>,
     /// Entity type (e.g., "CustomerOrder")
     #[serde(rename = "type")]
+    pub entity_type: Option<String>,
+    /// Moysklad account ID used to route the event to a tenant
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    /// Event action: "create", "update" (default) or "delete"
+    pub action: Option<String>,
+}
+
+/// One webhook event: the query parameters or one element of the body's `events`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct IncomingEvent {
+    pub id: String,
     pub entity_type: String,
+    pub account_id: Option<String>,
+    /// Lowercase action, "update" when not given
+    pub action: String,
+    /// Body forwarded to FORWARD_URL for this event
+    pub body: web::Bytes,
+}
+
+/// Events of a webhook request: from the query string when it names the entity,
+/// otherwise from the Moysklad body `{"events":[{"meta":{...},"action":"UPDATE","accountId":"..."}]}`
+pub(crate) fn webhook_events(query: &WebhookQuery, body: &web::Bytes) -> Result<Vec<IncomingEvent>, HttpResponse> {
+    match (&query.id, &query.entity_type) {
+        (Some(id), Some(entity_type)) => {
+            validate_entity_id("id", id)?;
+            return Ok(vec![IncomingEvent {
+                id: id.clone(),
+                entity_type: entity_type.clone(),
+                account_id: query.account_id.clone(),
+                action: query.action.as_deref().unwrap_or("update").to_lowercase(),
+                body: body.clone(),
+            }]);
+        }
+        (Some(_), None) => return Err(validation_error(Some("type"), "missing query parameter")),
+        (None, Some(_)) => return Err(validation_error(Some("id"), "missing query parameter")),
+        (None, None) => {}
+    }
+
+    if body.is_empty() {
+        return Err(validation_error(
+            Some("events"),
+            "expected 'id' and 'type' query parameters or a Moysklad webhook body",
+        ));
+    }
+    let raw: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|e| validation_error(None, &sanitize(&e.to_string())))?;
+    let payload: WebhookPayload = serde_json::from_value(raw.clone()).map_err(|e| {
+        let reason = sanitize(&e.to_string());
+        validation_error(error_field(&reason).or(Some("events")), &reason)
+    })?;
+    if payload.events.is_empty() {
+        return Err(validation_error(Some("events"), "no events"));
+    }
+
+    let raw_events = raw.get("events").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+    payload
+        .events
+        .iter()
+        .enumerate()
+        .map(|(index, event)| {
+            let id = event
+                .entity_id()
+                .ok_or_else(|| validation_error(Some("meta.href"), "no entity ID in href"))?;
+            validate_entity_id("meta.href", id)?;
+            let entity_type = event
+                .entity_type()
+                .ok_or_else(|| validation_error(Some("meta.type"), "missing entity type"))?;
+
+            // A batch is forwarded event by event, so the destination gets only what was not ours
+            let body = if payload.events.len() == 1 {
+                body.clone()
+            } else {
+                let single = serde_json::json!({ "events": [raw_events.get(index)] });
+                web::Bytes::from(single.to_string())
+            };
+
+            Ok(IncomingEvent {
+                id: id.to_string(),
+                entity_type: entity_type.to_string(),
+                account_id: Some(event.account_id.clone()),
+                action: event.action.to_lowercase(),
+                body,
+            })
+        })
+        .collect()
 }
 
-/// Webhook endpoint for receiving events from Moysklad
-/// Moysklad sends: POST /webhook?id={id}&type={type}
+/// Webhook endpoint for receiving events from Moysklad.
+/// Moysklad sends the events in the body: POST /webhook {"events":[{"meta":{...},"action":"UPDATE","accountId":"..."}]};
+/// a URL with query parameters is accepted too: POST /webhook?id={id}&type={type}[&action={action}][&accountId={accountId}].
+/// Several events in one body are handled one by one; the response lists each outcome
+/// and carries the most severe status.
 /// Example: POST /webhook?id=e74614f8-0c05-11f1-0a80-0f27004c4df2&type=CustomerOrder
 pub async fn webhook(
     state: web::Data<Arc<AppState>>,
     query: web::Query<WebhookQuery>,
+    req: HttpRequest,
+    body: web::Bytes,
 ) -> impl Responder {
-    let id = &query.id;
-    let entity_type = &query.entity_type;
+    let events = match webhook_events(&query, &body) {
+        Ok(events) => events,
+        Err(response) => return response,
+    };
+
+    if let [event] = events.as_slice() {
+        return handle_event(&state, &req, event).await;
+    }
+
+    info!("Received webhook with {} events", events.len());
+    let mut status = StatusCode::OK;
+    let mut retry_after = None;
+    let mut outcomes = Vec::with_capacity(events.len());
+    for event in &events {
+        let response = handle_event(&state, &req, event).await;
+        if response.status() > status {
+            status = response.status();
+        }
+        if let Some(value) = response.headers().get("Retry-After") {
+            retry_after = Some(value.clone());
+        }
+        let outcome = to_bytes(response.into_body())
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+            .unwrap_or(serde_json::Value::Null);
+        outcomes.push(outcome);
+    }
+
+    let mut response = HttpResponse::build(status);
+    if let Some(value) = retry_after.filter(|_| status == StatusCode::SERVICE_UNAVAILABLE) {
+        response.insert_header(("Retry-After", value));
+    }
+    response.json(serde_json::json!({
+        "status": "batch",
+        "events": outcomes,
+    }))
+}
+
+/// Handle one webhook event
+/// 503 with Retry-After: Moysklad redelivers the webhook later
+fn busy_response(state: &AppState, id: &str) -> HttpResponse {
+    let retry_after = state.settings.webhook_retry_after_secs;
+
+    HttpResponse::ServiceUnavailable()
+        .insert_header(("Retry-After", retry_after.to_string()))
+        .json(serde_json::json!({
+            "status": "busy",
+            "order_id": id,
+            "message": format!("Intake queue is full, retry in {} s", retry_after)
+        }))
+}
+
+async fn handle_event(state: &AppState, req: &HttpRequest, incoming: &IncomingEvent) -> HttpResponse {
+    let id = &incoming.id;
+    let entity_type = &incoming.entity_type;
+    let body = &incoming.body;
 
     info!(
         "Received webhook: id={}, type={}",
@@ -51,35 +268,170 @@ pub async fn webhook(
     // Normalize entity type to lowercase for comparison
     let entity_type_lower = entity_type.to_lowercase();
 
-    // Process only customer order events
-    if entity_type_lower != "customerorder" {
-        info!("Ignoring non-customerorder event (type={})", entity_type);
+    // Edited tech cards are dropped from the plan cache right away instead of after PLAN_CACHE_TTL_SECS
+    if entity_type_lower == "processingplan" {
+        let tenant = match incoming.account_id.as_deref() {
+            None => state.tenants.default_tenant(),
+            Some(account_id) => state.tenants.by_account(account_id),
+        };
+        let invalidated = tenant.map_or(0, |tenant| tenant.plan_cache.invalidate(id));
+        info!("Tech card {} changed, {} cached lookups dropped", id, invalidated);
+
+        return HttpResponse::Ok().json(serde_json::json!({
+            "status": "invalidated",
+            "plan_id": id,
+            "forwarded": forward_ignored(state, req, body).await
+        }));
+    }
+
+    if !state.entity_toggles.is_enabled(&entity_type_lower) {
+        info!("Handling of {} webhooks is disabled, ignoring", entity_type_lower);
         return HttpResponse::Ok().json(serde_json::json!({
             "status": "ignored",
-            "message": format!("Not a customer order event (type={})", entity_type)
+            "message": format!("Handling of {} events is disabled", entity_type_lower),
+            "forwarded": forward_ignored(state, req, body).await
         }));
     }
 
+    // Process only orders, retail demands, supplies and stock corrections
+    if !HANDLED_ENTITY_TYPES.contains(&entity_type_lower.as_str()) {
+        info!("Ignoring unsupported event (type={})", entity_type);
+        return HttpResponse::Ok().json(serde_json::json!({
+            "status": "ignored",
+            "message": format!("Unsupported entity type (type={})", entity_type),
+            "forwarded": forward_ignored(state, req, body).await
+        }));
+    }
+
+    // Route the event to the tenant owning the account
+    let tenant = match incoming.account_id.as_deref() {
+        None => match state.tenants.default_tenant() {
+            Some(tenant) => tenant,
+            None => {
+                warn!("Ignoring webhook without accountId: no default account");
+                return HttpResponse::Ok().json(serde_json::json!({
+                    "status": "ignored",
+                    "message": "accountId is required",
+                    "forwarded": forward_ignored(state, req, body).await
+                }));
+            }
+        },
+        Some(account_id) => match state.tenants.by_account(account_id) {
+            Some(tenant) => tenant,
+            None => {
+                warn!("Ignoring webhook for unknown account {}", account_id);
+                return HttpResponse::Ok().json(serde_json::json!({
+                    "status": "ignored",
+                    "message": format!("Unknown account {}", account_id),
+                    "forwarded": forward_ignored(state, req, body).await
+                }));
+            }
+        },
+    };
+
+    let action = incoming.action.as_str();
+
+    // Distributed mode: hand the event to the shared stream, any replica will process it
+    if let Some(stream) = &state.event_stream {
+        let forward = state.forwarder.as_ref().map(|_| received_webhook(req, body));
+        return match stream.publish(&tenant.name, &entity_type_lower, id, action, forward.as_ref()).await {
+            Ok(entry_id) => HttpResponse::Accepted().json(serde_json::json!({
+                "status": "queued",
+                "order_id": id,
+                "event_id": entry_id
+            })),
+            Err(e) => {
+                error!("Failed to queue order {}: {:#}", id, e);
+                HttpResponse::ServiceUnavailable()
+                    .insert_header(("Retry-After", state.settings.webhook_retry_after_secs.to_string()))
+                    .json(serde_json::json!({
+                        "status": "error",
+                        "order_id": id,
+                        "message": error_message(&e)
+                    }))
+            }
+        };
+    }
+
     // Build webhook event from query parameters
-    let event = WebhookEvent {
-        meta: None,
-        id: None,
-        name: None,
-        account_id: String::new(),
-        entity_type: entity_type_lower.clone(),
-        action: "update".to_string(),
-        entity: None,
-        content: Some(crate::models::WebhookContent {
-            entity: None,
-            id: Some(id.clone()),
-            entity_type: Some(entity_type_lower),
-        }),
+    let event = WebhookEvent::entity_action(&entity_type_lower, id, action);
+
+    // Deferred modes forward the webhook once processing shows the document is not ours
+    let deferred_forward = || {
+        state
+            .forwarder
+            .as_ref()
+            .map(|forwarder| DeferredForward::new(forwarder.clone(), received_webhook(req, body)))
+    };
+
+    // Moysklad is known to be down: queue right away instead of waiting for timeouts
+    if tenant.circuit_breaker.state() == CircuitState::Open {
+        warn!("Circuit open, order {} queued for retry", id);
+        tenant
+            .retry_queue
+            .enqueue(&entity_type_lower, id, &event.action, "Moysklad API unavailable (circuit open)");
+
+        return HttpResponse::Accepted().json(serde_json::json!({
+            "status": "queued",
+            "order_id": id,
+            "message": "Moysklad API unavailable, queued for retry"
+        }));
+    }
+
+    // Debounce: bursts of events for one document are processed once, after they stop
+    if tenant.debounce.enabled() {
+        if tenant.debounce.push(&entity_type_lower, id, action, deferred_forward()) {
+            spawn_debounced_processing(tenant.clone(), id.clone());
+        } else {
+            info!("Webhook for {} coalesced with a pending one", id);
+        }
+
+        return HttpResponse::Accepted().json(serde_json::json!({
+            "status": "debounced",
+            "order_id": id,
+            "window_secs": tenant.debounce.window().as_secs()
+        }));
+    }
+
+    // Priority queue: when events back up, the most urgent document is processed first.
+    // The queue is bounded by WEBHOOK_QUEUE_DEPTH like direct intake.
+    if tenant.pending.enabled() {
+        let forward = state.forwarder.as_ref().map(|_| received_webhook(req, body));
+        return match tenant.pending.push(&entity_type_lower, id, action, forward) {
+            Some(depth) => HttpResponse::Accepted().json(serde_json::json!({
+                "status": "queued",
+                "order_id": id,
+                "queue_depth": depth
+            })),
+            None => {
+                warn!(
+                    "Pending queue full ({} documents), rejecting order {}",
+                    tenant.pending.max_depth(),
+                    id
+                );
+                busy_response(state, id)
+            }
+        };
+    }
+
+    // Bounded intake: reject bursts instead of piling up requests waiting for the processor
+    let Some(_permit) = tenant.intake.try_acquire() else {
+        warn!(
+            "Intake queue full ({} webhooks), rejecting order {}",
+            tenant.intake.max_depth(),
+            id
+        );
+        return busy_response(state, id);
     };
 
     // Get processor and handle the event
-    let mut processor = state.processor.lock().await;
+    let mut processor = tenant.processor.lock().await;
 
-    match processor.process_webhook(&event).await {
+    match processor
+        .process_webhook(&event)
+        .instrument(info_span!("tenant", name = %tenant.name))
+        .await
+    {
         Ok(results) => {
             let success_count = results.iter().filter(|r| r.success).count();
             let total_count = results.len();
@@ -89,10 +441,32 @@ pub async fn webhook(
                 id, success_count, total_count
             );
 
-            HttpResponse::Ok().json(serde_json::json!({
-                "status": "processed",
+            // Positions that failed on a transient error are retried with the whole order
+            if let Some(failed) = results.iter().find(|r| r.retryable()) {
+                let message = failed.error.as_deref().unwrap_or(&failed.message);
+                warn!("Order {} has positions failed on transient errors, queued for retry", id);
+                tenant.retry_queue.enqueue(&entity_type_lower, id, &event.action, message);
+            }
+
+            // Orders of other stores, counterparties or sales channels belong to other integrations
+            if is_foreign(&results) && state.forwarder.is_some() {
+                let forwarded = forward_ignored(state, req, body).await;
+                info!("Order {} is not ours, forwarded: {}", id, forwarded);
+                let mut body = job_response(&tenant, "ignored", id, results);
+                body["forwarded"] = serde_json::json!(forwarded);
+                return HttpResponse::Ok().json(body);
+            }
+
+            HttpResponse::Ok().json(job_response(&tenant, "processed", id, results))
+        }
+        Err(e) if is_transient_error(&e) => {
+            warn!("Moysklad unavailable while processing order {}, queued for retry: {}", id, e);
+            tenant.retry_queue.enqueue(&entity_type_lower, id, &event.action, &error_message(&e));
+
+            HttpResponse::Accepted().json(serde_json::json!({
+                "status": "queued",
                 "order_id": id,
-                "results": results
+                "message": error_message(&e)
             }))
         }
         Err(e) => {
@@ -101,54 +475,121 @@ pub async fn webhook(
             HttpResponse::InternalServerError().json(serde_json::json!({
                 "status": "error",
                 "order_id": id,
-                "message": e.to_string()
+                "message": error_message(&e)
             }))
         }
     }
 }
 
+/// Results of a processing run, recorded as a job. Large runs are read page by page
+/// from /jobs/{id}/results: the results are inline only up to JOB_INLINE_RESULTS.
+fn job_response(tenant: &Tenant, status: &str, order_id: &str, results: Vec<ProcessingResult>) -> serde_json::Value {
+    let job = tenant.jobs.insert(order_id, results);
+    let mut body = serde_json::json!({
+        "status": status,
+        "order_id": order_id,
+        "job": job.summary(),
+        "results_url": format!("/jobs/{}/results", job.id),
+    });
+    let results = job.results();
+    if results.len() <= tenant.settings.job_inline_results {
+        body["results"] = serde_json::json!(results);
+    }
+
+    body
+}
+
+/// Pass an ignored webhook on to FORWARD_URL as received; true only once the destination
+/// accepted it (failed attempts are retried in the background)
+async fn forward_ignored(state: &AppState, req: &HttpRequest, body: &web::Bytes) -> bool {
+    let Some(forwarder) = &state.forwarder else {
+        return false;
+    };
+
+    forwarder.forward(received_webhook(req, body)).await
+}
+
+/// The webhook as received, for forwarding
+fn received_webhook(req: &HttpRequest, body: &web::Bytes) -> ForwardedWebhook {
+    ForwardedWebhook {
+        query: req.query_string().to_string(),
+        content_type: req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        body: body.to_vec(),
+    }
+}
+
 /// Endpoint for manual customer order processing by ID
 pub async fn process_order(
     state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    query: web::Query<TenantQuery>,
 ) -> impl Responder {
     let order_id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &order_id) {
+        return response;
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
 
     info!("Manual processing request for customer order: {}", order_id);
 
     // Build webhook event
-    let event = WebhookEvent {
-        meta: None,
-        id: None,
-        name: None,
-        account_id: String::new(),
-        entity_type: "customerorder".to_string(),
-        action: "update".to_string(),
-        entity: None,
-        content: Some(crate::models::WebhookContent {
-            entity: None,
-            id: Some(order_id.clone()),
-            entity_type: Some("customerorder".to_string()),
-        }),
-    };
+    let event = WebhookEvent::customer_order(&order_id);
 
-    let mut processor = state.processor.lock().await;
+    let mut processor = tenant.processor.lock().await;
 
-    match processor.process_webhook(&event).await {
-        Ok(results) => {
-            HttpResponse::Ok().json(serde_json::json!({
-                "status": "processed",
+    // A manual run is not deferred by WORK_HOURS, same as the CLI; off-hours documents
+    // are dated by WORK_SCHEDULE_MOMENT
+    match processor
+        .process_now(&event)
+        .instrument(info_span!("tenant", name = %tenant.name))
+        .await
+    {
+        Ok(results) => HttpResponse::Ok().json(job_response(&tenant, "processed", &order_id, results)),
+        Err(e) => {
+            error!("Error processing order {}: {}", order_id, e);
+
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "status": "error",
                 "order_id": order_id,
-                "results": results
+                "message": error_message(&e)
             }))
         }
+    }
+}
+
+/// Dry run of the full pipeline for a customer order: nothing is written to Moysklad
+pub async fn simulate_order(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+) -> impl Responder {
+    let order_id = path.into_inner();
+    if let Err(response) = validate_entity_id("id", &order_id) {
+        return response;
+    }
+    let tenant = match resolve_tenant(&state, query.tenant.as_deref()) {
+        Ok(tenant) => tenant,
+        Err(response) => return response,
+    };
+
+    info!("Simulation request for customer order: {}", order_id);
+
+    match OrderProcessor::simulate_order(&tenant.processor, &order_id).await {
+        Ok(simulation) => HttpResponse::Ok().json(simulation),
         Err(e) => {
-            error!("Error processing order {}: {}", order_id, e);
+            error!("Error simulating order {}: {}", order_id, e);
 
             HttpResponse::InternalServerError().json(serde_json::json!({
                 "status": "error",
                 "order_id": order_id,
-                "message": e.to_string()
+                "message": error_message(&e)
             }))
         }
     }
@@ -156,9 +597,330 @@ pub async fn process_order(
 
 /// Get current configuration
 pub async fn get_config(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let tenants: Vec<_> = state
+        .tenants
+        .all()
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "name": t.name,
+                "account_id": t.account_id,
+                "api_url": t.settings.moysklad_api_url,
+                "store_name": t.settings.store_name,
+                "store_id": t.settings.store_id,
+                "organization_id": t.settings.organization_id,
+                "tech_card_field_name": t.settings.tech_card_field_name,
+                "min_stock_threshold": t.settings.min_stock_threshold,
+            })
+        })
+        .collect();
+
     HttpResponse::Ok().json(serde_json::json!({
+        "api_url": state.settings.moysklad_api_url,
         "store_name": state.settings.store_name,
+        "store_id": state.settings.store_id,
+        "organization_id": state.settings.organization_id,
         "tech_card_field_name": state.settings.tech_card_field_name,
         "min_stock_threshold": state.settings.min_stock_threshold,
+        "notification_channels": state.notifier.channel_names(),
+        "tenants": tenants,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::{json, Map, Value};
+
+    const API: &str = "https://api.moysklad.ru/api/remap/1.2";
+
+    fn param(key: &str, value: &str) -> String {
+        format!("{}={}", key, urlencoding::encode(value))
+    }
+
+    fn uuid() -> impl Strategy<Value = String> {
+        "[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}"
+    }
+
+    /// Query string with the required parameters, optional ones and unknown extras in random order
+    fn webhook_query() -> impl Strategy<Value = (String, String, String)> {
+        (
+            ".{1,40}",
+            "[a-zA-Z]{1,16}",
+            proptest::option::of("[0-9a-f-]{1,36}"),
+            proptest::option::of("(create|update|delete)"),
+            prop::collection::vec(("unknown[a-z]{0,8}", ".{0,16}"), 0..4),
+            any::<prop::sample::Index>(),
+        )
+            .prop_map(|(id, entity_type, account_id, action, extra, shift)| {
+                let mut params = vec![param("id", &id), param("type", &entity_type)];
+                params.extend(account_id.map(|v| param("accountId", &v)));
+                params.extend(action.map(|v| param("action", &v)));
+                params.extend(extra.iter().map(|(k, v)| param(k, v)));
+                let shift = shift.index(params.len());
+                params.rotate_left(shift);
+                (params.join("&"), id, entity_type)
+            })
+    }
+
+    /// Fields the models do not know: the API may add them at any time
+    fn unknown_fields() -> impl Strategy<Value = Map<String, Value>> {
+        prop::collection::btree_map("unknown[A-Z][a-zA-Z]{0,10}", ".{0,16}".prop_map(Value::from), 0..3)
+            .prop_map(|m| m.into_iter().collect())
+    }
+
+    /// One element of `events` as Moysklad sends it, with optional and unknown fields
+    fn notification() -> impl Strategy<Value = (Value, IncomingEvent)> {
+        (
+            uuid(),
+            "(customerorder|retaildemand|supply|processingplan)",
+            "(CREATE|UPDATE|DELETE)",
+            uuid(),
+            proptest::option::of(prop::collection::vec("[a-z]{1,12}", 0..4)),
+            any::<bool>(),
+            unknown_fields(),
+            unknown_fields(),
+        )
+            .prop_map(|(id, entity_type, action, account_id, updated, media_type, meta_extra, extra)| {
+                let mut meta = meta_extra;
+                meta.insert("href".into(), json!(format!("{}/entity/{}/{}", API, entity_type, id)));
+                meta.insert("type".into(), json!(entity_type));
+                if media_type {
+                    meta.insert("mediaType".into(), json!("application/json"));
+                }
+
+                let mut event = extra;
+                event.insert("meta".into(), Value::Object(meta));
+                event.insert("action".into(), json!(action));
+                event.insert("accountId".into(), json!(account_id));
+                if let Some(fields) = updated {
+                    event.insert("updatedFields".into(), json!(fields));
+                }
+
+                let expected = IncomingEvent {
+                    id,
+                    entity_type,
+                    account_id: Some(account_id),
+                    action: action.to_lowercase(),
+                    body: web::Bytes::new(),
+                };
+                (Value::Object(event), expected)
+            })
+    }
+
+    /// Webhook body `{"events":[...]}`, optionally with `auditContext` and unknown fields
+    fn webhook_body() -> impl Strategy<Value = (Value, Vec<IncomingEvent>)> {
+        (
+            prop::collection::vec(notification(), 1..4),
+            proptest::option::of((uuid(), "[a-z]{1,12}")),
+            unknown_fields(),
+        )
+            .prop_map(|(events, audit, extra)| {
+                let mut body = extra;
+                if let Some((audit_id, uid)) = audit {
+                    body.insert(
+                        "auditContext".into(),
+                        json!({"meta": {"type": "audit", "href": format!("{}/audit/{}", API, audit_id)}, "uid": uid, "moment": "2024-01-15 10:00:00"}),
+                    );
+                }
+                let (raw, expected): (Vec<_>, Vec<_>) = events.into_iter().unzip();
+                body.insert("events".into(), Value::Array(raw));
+                (Value::Object(body), expected)
+            })
+    }
+
+    fn no_query() -> WebhookQuery {
+        WebhookQuery { id: None, entity_type: None, account_id: None, action: None }
+    }
+
+    fn without_body(events: Vec<IncomingEvent>) -> Vec<IncomingEvent> {
+        events.into_iter().map(|event| IncomingEvent { body: web::Bytes::new(), ..event }).collect()
+    }
+
+    proptest! {
+        #[test]
+        fn query_tolerates_optional_and_unknown_params((query, id, entity_type) in webhook_query()) {
+            let parsed = web::Query::<WebhookQuery>::from_query(&query).unwrap();
+            prop_assert_eq!(parsed.id.as_deref(), Some(id.as_str()));
+            prop_assert_eq!(parsed.entity_type.as_deref(), Some(entity_type.as_str()));
+        }
+
+        #[test]
+        fn query_without_required_param_is_rejected(
+            (query, ..) in webhook_query(),
+            missing in prop::sample::select(vec!["id=", "type="]),
+        ) {
+            let query: Vec<_> = query.split('&').filter(|p| !p.starts_with(missing)).collect();
+            let parsed = web::Query::<WebhookQuery>::from_query(&query.join("&")).unwrap();
+            prop_assert!(webhook_events(&parsed, &web::Bytes::new()).is_err());
+        }
+
+        #[test]
+        fn arbitrary_query_never_panics(query in ".{0,128}") {
+            let _ = web::Query::<WebhookQuery>::from_query(&query);
+        }
+
+        #[test]
+        fn body_events_are_read_from_meta((body, expected) in webhook_body()) {
+            let bytes = web::Bytes::from(body.to_string());
+            let events = webhook_events(&no_query(), &bytes).unwrap();
+            prop_assert_eq!(without_body(events), expected);
+        }
+
+        #[test]
+        fn body_events_are_forwarded_one_by_one((body, _) in webhook_body()) {
+            let bytes = web::Bytes::from(body.to_string());
+            let events = webhook_events(&no_query(), &bytes).unwrap();
+            if events.len() == 1 {
+                prop_assert_eq!(&events[0].body, &bytes);
+            } else {
+                for (event, raw) in events.iter().zip(body["events"].as_array().unwrap()) {
+                    let forwarded: Value = serde_json::from_slice(&event.body).unwrap();
+                    prop_assert_eq!(&forwarded["events"], &json!([raw]));
+                }
+            }
+        }
+
+        #[test]
+        fn body_event_without_required_field_is_rejected(
+            (body, _) in webhook_body(),
+            field in prop::sample::select(vec!["meta", "action", "accountId"]),
+        ) {
+            let mut body = body;
+            body["events"][0].as_object_mut().unwrap().remove(field);
+            let bytes = web::Bytes::from(body.to_string());
+            prop_assert!(webhook_events(&no_query(), &bytes).is_err());
+        }
+
+        #[test]
+        fn query_and_body_formats_agree((_, expected) in notification()) {
+            let account_id = expected.account_id.clone().unwrap();
+            let query = format!(
+                "id={}&type={}&accountId={}&action={}",
+                expected.id, expected.entity_type, account_id, expected.action.to_uppercase()
+            );
+            let parsed = web::Query::<WebhookQuery>::from_query(&query).unwrap();
+            let events = webhook_events(&parsed, &web::Bytes::new()).unwrap();
+            prop_assert_eq!(without_body(events), vec![expected]);
+        }
+
+        #[test]
+        fn arbitrary_body_never_panics(body in ".{0,256}") {
+            let _ = webhook_events(&no_query(), &web::Bytes::from(body));
+        }
+    }
+
+    /// Status and JSON body of a rejected request
+    async fn rejection(result: Result<Vec<IncomingEvent>, HttpResponse>) -> (StatusCode, Value) {
+        let response = result.expect_err("payload must be rejected");
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn body(text: &str) -> web::Bytes {
+        web::Bytes::from(text.to_string())
+    }
+
+    const ORDER_ID: &str = "e74614f8-0c05-11f1-0a80-0f27004c4df2";
+
+    #[actix_web::test]
+    async fn malformed_webhook_payloads_are_rejected_with_details() {
+        let href = format!("{}/entity/customerorder/{}", API, ORDER_ID);
+        let cases = [
+            ("", None),
+            ("{not json", None),
+            ("[]", Some("events")),
+            (r#"{"events": {}}"#, Some("events")),
+            (r#"{"events": []}"#, Some("events")),
+            (r#"{"auditContext": {}}"#, Some("events")),
+            (r#"{"events": [{"action": "UPDATE", "accountId": "a"}]}"#, Some("meta")),
+            (&*format!(r#"{{"events": [{{"meta": {{"href": "{}", "type": "customerorder"}}, "accountId": "a"}}]}}"#, href), Some("action")),
+            (&*format!(r#"{{"events": [{{"meta": {{"href": "{}"}}, "action": "UPDATE", "accountId": "a"}}]}}"#, href), Some("meta.type")),
+            (r#"{"events": [{"meta": {"href": "https://api.moysklad.ru/api/remap/1.2/entity/customerorder/", "type": "customerorder"}, "action": "UPDATE", "accountId": "a"}]}"#, Some("meta.href")),
+            (r#"{"events": [{"meta": {"href": "https://api.moysklad.ru/api/remap/1.2/entity/customerorder/1; DROP", "type": "customerorder"}, "action": "UPDATE", "accountId": "a"}]}"#, Some("meta.href")),
+            (r#"{"events": [{"meta": {"href": 42, "type": "customerorder"}, "action": "UPDATE", "accountId": "a"}]}"#, None),
+        ];
+
+        for (payload, field) in cases {
+            let (status, body) = rejection(webhook_events(&no_query(), &body(payload))).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "payload {}", payload);
+            assert_eq!(body["status"], "error", "payload {}", payload);
+            if let Some(field) = field {
+                assert_eq!(body["field"], field, "payload {}", payload);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn malformed_webhook_query_is_rejected_with_details() {
+        let cases = [
+            (format!("id={}", ORDER_ID), "type"),
+            ("type=customerorder".to_string(), "id"),
+            ("id=123&type=customerorder".to_string(), "id"),
+            (format!("id={}x&type=customerorder", ORDER_ID), "id"),
+        ];
+
+        for (query, field) in cases {
+            let parsed = web::Query::<WebhookQuery>::from_query(&query).unwrap();
+            let (status, body) = rejection(webhook_events(&parsed, &web::Bytes::new())).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "query {}", query);
+            assert_eq!(body["field"], field, "query {}", query);
+        }
+    }
+
+    #[actix_web::test]
+    async fn payload_limits_and_content_type_are_enforced() {
+        use actix_web::middleware::from_fn;
+        use actix_web::{test, App};
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Body {
+            #[allow(dead_code)]
+            quantity: f64,
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::PayloadConfig::new(64))
+                .app_data(
+                    web::JsonConfig::default()
+                        .limit(64)
+                        .error_handler(super::super::json_error_handler),
+                )
+                .wrap(from_fn(super::super::check_payload))
+                .route("/webhook", web::post().to(|_: web::Bytes| async { HttpResponse::Ok().finish() }))
+                .route("/json", web::post().to(|_: web::Json<Body>| async { HttpResponse::Ok().finish() })),
+        )
+        .await;
+
+        let cases = [
+            ("/webhook", "text/plain", "{}".to_string(), StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            ("/webhook", "application/json", "x".repeat(65), StatusCode::PAYLOAD_TOO_LARGE),
+            ("/webhook", "application/json", "{}".to_string(), StatusCode::OK),
+            ("/json", "application/json", r#"{"quantity": "many"}"#.to_string(), StatusCode::BAD_REQUEST),
+            ("/json", "application/json", "{".to_string(), StatusCode::BAD_REQUEST),
+            ("/json", "application/json", "x".repeat(65), StatusCode::PAYLOAD_TOO_LARGE),
+        ];
+
+        for (path, content_type, payload, expected) in cases {
+            let request = test::TestRequest::post()
+                .uri(path)
+                .insert_header((CONTENT_TYPE, content_type))
+                .set_payload(payload.clone())
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), expected, "{} {} {}", path, content_type, payload);
+        }
+
+        let request = test::TestRequest::post()
+            .uri("/json")
+            .insert_header((CONTENT_TYPE, "application/json"))
+            .set_payload(r#"{"quantity": "many"}"#)
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body["field"], Value::Null);
+        assert!(body["reason"].as_str().unwrap().contains("invalid type"));
+    }
+}
@@ -0,0 +1,43 @@
+//! Журнал изменений, отправленных в МойСклад
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::store::{JsonlStore, Timestamped};
+
+/// Запись журнала: один запрос на изменение (POST/PUT/DELETE)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub method: String,
+    pub endpoint: String,
+    /// SHA-256 тела запроса в hex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_digest: Option<String>,
+    /// ID созданной или изменённой сущности из ответа
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity_id: Option<String>,
+    /// Заказ, обработка которого вызвала изменение
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_name: Option<String>,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Timestamped for AuditRecord {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+/// Журнал изменений
+pub type AuditLog = JsonlStore<AuditRecord>;
+
+/// SHA-256 тела запроса в hex
+pub fn payload_digest(payload: &[u8]) -> String {
+    hex::encode(Sha256::digest(payload))
+}
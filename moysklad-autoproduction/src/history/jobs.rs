@@ -0,0 +1,142 @@
+//! Результаты ручной обработки заказов: хранятся, чтобы клиент получал их постранично
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::models::ProcessingResult;
+
+/// Запуск обработки и его результаты; фоновый запуск пополняется по мере выполнения
+#[derive(Debug)]
+pub struct Job {
+    pub id: String,
+    pub order_id: String,
+    pub created_at: DateTime<Utc>,
+    /// Сколько результатов ожидается
+    pub expected: usize,
+    results: Mutex<Vec<ProcessingResult>>,
+    finished_at: Mutex<Option<DateTime<Utc>>>,
+}
+
+/// Сводка по запуску без самих результатов
+#[derive(Debug, Clone, Serialize)]
+pub struct JobSummary {
+    pub id: String,
+    pub order_id: String,
+    pub created_at: DateTime<Utc>,
+    /// Запуск ещё выполняется
+    pub running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub expected: usize,
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+impl Job {
+    fn new(order_id: &str, expected: usize) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            order_id: order_id.to_string(),
+            created_at: Utc::now(),
+            expected,
+            results: Mutex::new(Vec::new()),
+            finished_at: Mutex::new(None),
+        }
+    }
+
+    pub fn summary(&self) -> JobSummary {
+        let results = self.results.lock().expect("job lock poisoned");
+        let succeeded = results.iter().filter(|r| r.success).count();
+        let finished_at = *self.finished_at.lock().expect("job lock poisoned");
+
+        JobSummary {
+            id: self.id.clone(),
+            order_id: self.order_id.clone(),
+            created_at: self.created_at,
+            running: finished_at.is_none(),
+            finished_at,
+            expected: self.expected,
+            total: results.len(),
+            succeeded,
+            failed: results.len() - succeeded,
+        }
+    }
+
+    /// Добавить результат выполняющегося запуска
+    pub fn push(&self, result: ProcessingResult) {
+        self.results.lock().expect("job lock poisoned").push(result);
+    }
+
+    /// Отметить запуск завершённым
+    pub fn finish(&self) {
+        *self.finished_at.lock().expect("job lock poisoned") = Some(Utc::now());
+    }
+
+    /// Все результаты
+    pub fn results(&self) -> Vec<ProcessingResult> {
+        self.results.lock().expect("job lock poisoned").clone()
+    }
+
+    /// Страница результатов с фильтром по успеху и число результатов, прошедших фильтр
+    pub fn page(&self, success: Option<bool>, offset: usize, limit: usize) -> (usize, Vec<ProcessingResult>) {
+        let results = self.results.lock().expect("job lock poisoned");
+        let matching: Vec<_> = results
+            .iter()
+            .filter(|r| success.is_none_or(|s| r.success == s))
+            .collect();
+        let total = matching.len();
+
+        (total, matching.into_iter().skip(offset).take(limit).cloned().collect())
+    }
+}
+
+/// Последние запуски в памяти; при переполнении вытесняются самые старые
+pub struct JobStore {
+    capacity: usize,
+    jobs: Mutex<VecDeque<Arc<Job>>>,
+}
+
+impl JobStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            jobs: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Сохранить результаты завершённого запуска
+    pub fn insert(&self, order_id: &str, results: Vec<ProcessingResult>) -> Arc<Job> {
+        let job = Job::new(order_id, results.len());
+        *job.results.lock().expect("job lock poisoned") = results;
+        job.finish();
+        self.add(job)
+    }
+
+    /// Начать фоновый запуск на `expected` результатов
+    pub fn start(&self, order_id: &str, expected: usize) -> Arc<Job> {
+        self.add(Job::new(order_id, expected))
+    }
+
+    fn add(&self, job: Job) -> Arc<Job> {
+        let job = Arc::new(job);
+        let mut jobs = self.jobs.lock().expect("job store lock poisoned");
+        while jobs.len() >= self.capacity {
+            jobs.pop_front();
+        }
+        jobs.push_back(job.clone());
+
+        job
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Job>> {
+        self.jobs
+            .lock()
+            .expect("job store lock poisoned")
+            .iter()
+            .find(|job| job.id == id)
+            .cloned()
+    }
+}
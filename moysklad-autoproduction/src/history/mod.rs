@@ -0,0 +1,7 @@
+pub mod audit;
+pub mod jobs;
+pub mod store;
+
+pub use audit::*;
+pub use jobs::*;
+pub use store::*;
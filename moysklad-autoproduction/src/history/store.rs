@@ -0,0 +1,210 @@
+//! История обработки позиций
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::models::{as_f64, MaterialShortage, ProcessingResult, SkipReason};
+
+/// Запись истории обработки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub timestamp: DateTime<Utc>,
+    pub success: bool,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_name: Option<String>,
+    #[serde(default)]
+    pub quantity: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing_name: Option<String>,
+    /// Тип созданного документа, если это не тех. операция
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_materials: Vec<MaterialShortage>,
+    /// Тех. операция отменена после удаления или распроведения заказа
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub revoked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<SkipReason>,
+    /// Тип документа-источника, если это не заказ покупателя
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_type: Option<String>,
+    /// Секунд от изменения документа в МойСклад до создания тех. операции
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sla_secs: Option<f64>,
+}
+
+impl HistoryRecord {
+    /// Создать запись из результата обработки позиции
+    pub fn from_result(result: &ProcessingResult) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            success: result.success,
+            message: result.message.clone(),
+            order_id: result.order_id.clone(),
+            order_name: result.order_name.clone(),
+            product_id: result.product.as_ref().map(|p| p.id.clone()),
+            product_name: result.product.as_ref().map(|p| p.name.clone()),
+            quantity: result.product.as_ref().map_or(0.0, |p| as_f64(p.quantity)),
+            processing_id: result.processing_id.clone(),
+            processing_name: result.processing_name.clone(),
+            document_type: result.document_type.clone(),
+            error: result.error.clone(),
+            missing_materials: result.missing_materials.clone(),
+            revoked: false,
+            skip_reason: result.skip_reason,
+            order_type: None,
+            sla_secs: None,
+        }
+    }
+}
+
+impl HistoryStore {
+    /// Товары, по которым чаще всего создавались документы, — не больше `limit`
+    pub fn top_products(&self, limit: usize) -> Vec<String> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for record in self.records.read().expect("store lock poisoned").iter() {
+            if let (Some(product_id), Some(_)) = (&record.product_id, &record.processing_id) {
+                *counts.entry(product_id.clone()).or_default() += 1;
+            }
+        }
+
+        let mut products: Vec<(String, usize)> = counts.into_iter().collect();
+        products.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        products.into_iter().take(limit).map(|(id, _)| id).collect()
+    }
+
+    /// Время последней неотменённой тех. операции на товар
+    pub fn last_production(&self, product_id: &str) -> Option<DateTime<Utc>> {
+        self.records
+            .read()
+            .expect("store lock poisoned")
+            .iter()
+            .rev()
+            .find(|r| {
+                r.product_id.as_deref() == Some(product_id)
+                    && r.processing_id.is_some()
+                    && !r.revoked
+            })
+            .map(|r| r.timestamp)
+    }
+}
+
+/// Запись с меткой времени
+pub trait Timestamped {
+    fn timestamp(&self) -> DateTime<Utc>;
+}
+
+impl Timestamped for HistoryRecord {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+/// Хранилище записей в памяти с дозаписью в JSON Lines файл
+pub struct JsonlStore<T> {
+    path: Option<PathBuf>,
+    records: RwLock<Vec<T>>,
+}
+
+/// История обработки позиций
+pub type HistoryStore = JsonlStore<HistoryRecord>;
+
+impl<T: Serialize + DeserializeOwned + Clone + Timestamped> JsonlStore<T> {
+    /// Открыть хранилище, загрузив ранее сохранённые записи
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut records = Vec::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+
+            for (line_no, line) in BufReader::new(file).lines().enumerate() {
+                let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(&line) {
+                    Ok(record) => records.push(record),
+                    Err(e) => warn!("Skipping malformed line {} in {}: {}", line_no + 1, path.display(), e),
+                }
+            }
+
+            info!("Loaded {} records from {}", records.len(), path.display());
+        }
+
+        Ok(Self {
+            path,
+            records: RwLock::new(records),
+        })
+    }
+
+    /// Добавить запись
+    pub fn append(&self, record: T) {
+        if let Some(ref path) = self.path
+            && let Err(e) = Self::write_line(path, &record)
+        {
+            warn!("Failed to persist record to {}: {:#}", path.display(), e);
+        }
+
+        self.records
+            .write()
+            .expect("store lock poisoned")
+            .push(record);
+    }
+
+    fn write_line(path: &PathBuf, record: &T) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        let line = serde_json::to_string(record)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    /// Записи, удовлетворяющие условию
+    pub fn find(&self, predicate: impl Fn(&T) -> bool) -> Vec<T> {
+        self.records
+            .read()
+            .expect("store lock poisoned")
+            .iter()
+            .filter(|r| predicate(r))
+            .cloned()
+            .collect()
+    }
+
+    /// Записи за период [from, to)
+    pub fn records_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<T> {
+        self.records
+            .read()
+            .expect("store lock poisoned")
+            .iter()
+            .filter(|r| r.timestamp() >= from && r.timestamp() < to)
+            .cloned()
+            .collect()
+    }
+}
@@ -3,61 +3,222 @@
 //! Сервис отслеживает подтверждённые заказы покупателей и автоматически создаёт
 //! тех. операции для пополнения остатков через производство.
 
+use actix_web::middleware::from_fn;
 use actix_web::{web, App, HttpServer};
+use clap::Parser;
 use std::sync::Arc;
 use tracing::info;
 
 mod api;
+mod auth;
+mod cli;
 mod config;
 mod handlers;
+mod history;
 mod models;
+mod notifications;
 mod processing;
+mod queue;
+mod reports;
+mod tenants;
+mod vendor;
 
+use auth::ApiKeys;
+use cli::{Cli, Command};
 use config::Settings;
 use handlers::AppState;
-use processing::OrderProcessor;
+use notifications::{NotificationRouter, WebhookForwarder};
+use tenants::{spawn_tenant_tasks, TenantRegistry};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Инициализация логирования
-    tracing_subscriber::fmt()
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve);
+    let serving = matches!(command, Command::Serve);
+
+    // Инициализация логирования (для разовых команд — в stderr, stdout занят результатом)
+    let subscriber = tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
         .with_target(false)
-        .with_thread_ids(false)
-        .pretty()
-        .init();
+        .with_thread_ids(false);
+    if serving {
+        subscriber.pretty().init();
+    } else {
+        subscriber.with_writer(std::io::stderr).init();
+    }
     
     // Загрузка конфигурации
     dotenvy::dotenv().ok();
     let settings = Settings::from_env().expect("Failed to load settings");
-    
+
+    if !serving {
+        if let Err(e) = cli::run(command, cli.tenant.as_deref(), settings).await {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    serve(settings).await
+}
+
+/// Запустить HTTP сервер
+async fn serve(settings: Settings) -> std::io::Result<()> {
     info!("Starting moysklad-autoproduction service");
+    info!("Moysklad API: {}", api::redact::redact(&settings.moysklad_api_url));
     info!("Monitoring store: {}", settings.store_name);
     info!("Tech card field: {}", settings.tech_card_field_name);
     info!("Min stock threshold: {}", settings.min_stock_threshold);
     
+    // Настраиваем уведомления
+    let notifier = Arc::new(NotificationRouter::from_settings(&settings));
+    info!("Notification channels: {}", notifier.channel_names().join(", "));
+
+    // Аккаунты МойСклад (тенанты)
+    let tenants = Arc::new(
+        TenantRegistry::load(&settings, notifier.clone())
+            .map_err(|e| std::io::Error::other(format!("{:#}", e)))?,
+    );
+    info!("Tenants: {}", tenants.all().iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join(", "));
+
+    // Прокси-режим: необработанные webhook уходят в другую интеграцию
+    let forwarder = settings.forward_url.clone().map(|url| {
+        info!("Forwarding ignored webhooks to {}", api::redact::redact(&url));
+        Arc::new(WebhookForwarder::new(url, settings.forward_retries))
+    });
+
+    // Фоновые задачи тенантов; подключённые через маркетплейс запускаются при установке
+    for tenant in tenants.all() {
+        spawn_tenant_tasks(&tenant, notifier.clone(), forwarder.clone());
+    }
+
+    // Распределённый режим: webhook идут через Redis Streams, экземпляры читают их группой
+    let event_stream = if settings.event_queue == "redis" {
+        let stream = Arc::new(
+            queue::EventStream::connect(&settings)
+                .await
+                .map_err(|e| std::io::Error::other(format!("{:#}", e)))?,
+        );
+        queue::spawn_stream_consumer(stream.clone(), tenants.clone(), forwarder.clone());
+        Some(stream)
+    } else {
+        None
+    };
+
+    // Типы сущностей, обработка которых отключена через /admin/entity-types
+    let entity_toggles = config::EntityToggles::open(
+        settings.entity_toggles_file.as_deref().map(std::path::PathBuf::from),
+    )
+    .map_err(|e| std::io::Error::other(format!("{:#}", e)))?;
+
+    // API-ключи служебных эндпоинтов
+    let api_keys = ApiKeys::from_settings(&settings).map_err(|e| std::io::Error::other(format!("{:#}", e)))?;
+
     // Создаём состояние приложения
-    let processor = OrderProcessor::new(settings.clone());
     let app_state = Arc::new(AppState {
         settings: settings.clone(),
-        processor: tokio::sync::Mutex::new(processor),
+        notifier,
+        tenants,
+        api_keys,
+        request_metrics: handlers::RequestMetrics::new(),
+        event_stream,
+        entity_toggles,
+        forwarder,
     });
     
     let host = settings.server_host.clone();
     let port = settings.server_port;
     
-    info!("Starting HTTP server on {}:{}", host, port);
+    // HTTPS, если заданы сертификат и ключ
+    let tls = match (settings.tls_cert_file.as_deref(), settings.tls_key_file.as_deref()) {
+        (Some(cert), Some(key)) => Some(
+            config::load_server_config(cert, key)
+                .map_err(|e| std::io::Error::other(format!("{:#}", e)))?,
+        ),
+        _ => None,
+    };
+
+    info!(
+        "Starting {} server on {}:{}",
+        if tls.is_some() { "HTTPS" } else { "HTTP" },
+        host,
+        port
+    );
     
     // Запуск HTTP сервера
-    HttpServer::new(move || {
+    let max_payload = settings.max_payload_bytes;
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(app_state.clone()))
+            .app_data(web::PayloadConfig::new(max_payload))
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(max_payload)
+                    .error_handler(handlers::json_error_handler),
+            )
+            .app_data(web::QueryConfig::default().error_handler(handlers::query_error_handler))
+            .wrap(from_fn(auth::require_role))
+            .wrap(from_fn(handlers::check_payload))
+            .wrap(from_fn(handlers::log_requests))
             .route("/health", web::get().to(handlers::health))
+            .route("/readyz", web::get().to(handlers::readyz))
             .route("/webhook", web::post().to(handlers::webhook))
+            .route(
+                "/api/moysklad/vendor/1.0/apps/{app_id}/{account_id}",
+                web::put().to(handlers::vendor_install),
+            )
+            .route(
+                "/api/moysklad/vendor/1.0/apps/{app_id}/{account_id}",
+                web::delete().to(handlers::vendor_uninstall),
+            )
+            .route(
+                "/api/moysklad/vendor/1.0/apps/{app_id}/{account_id}/status",
+                web::get().to(handlers::vendor_status),
+            )
+            .route("/vendor/context/{context_key}", web::get().to(handlers::vendor_context))
             .route("/order/{id}/process", web::post().to(handlers::process_order))
+            .route("/order/{id}/simulate", web::post().to(handlers::simulate_order))
+            .route("/jobs/{id}/results", web::get().to(handlers::get_job_results))
+            .route("/plan/compute", web::post().to(handlers::compute_plan))
+            .route("/plan/execute", web::post().to(handlers::execute_plan))
             .route("/config", web::get().to(handlers::get_config))
-    })
-    .bind((host.as_str(), port))?
-    .run()
-    .await
+            .route("/reports/summary", web::get().to(handlers::get_summary_report))
+            .route("/reports/sla", web::get().to(handlers::get_sla_report))
+            .route("/reports/materials-demand", web::get().to(handlers::get_materials_demand_report))
+            .route("/reports/mapping-health", web::get().to(handlers::get_mapping_health_report))
+            .route("/history/export", web::get().to(handlers::export_history_file))
+            .route("/history/query", web::post().to(handlers::query_history_records))
+            .route("/audit", web::get().to(handlers::get_audit))
+            .route("/forecast/{product_id}", web::get().to(handlers::get_forecast))
+            .route("/stock", web::get().to(handlers::get_stock))
+            .route("/materials/check", web::get().to(handlers::check_materials))
+            .route("/shortages", web::get().to(handlers::get_shortages))
+            .route("/metrics", web::get().to(handlers::get_metrics))
+            .route("/admin/retry-queue", web::get().to(handlers::get_retry_queue))
+            .route("/admin/queue", web::get().to(handlers::get_queue))
+            .route("/admin/queue/{id}", web::delete().to(handlers::delete_queue_entry))
+            .route("/admin/queue/{id}/promote", web::post().to(handlers::promote_queue_entry))
+            .route("/admin/dead-letters", web::get().to(handlers::get_dead_letters))
+            .route("/admin/dead-letters/{id}", web::delete().to(handlers::delete_dead_letter))
+            .route("/admin/dead-letters/{id}/requeue", web::post().to(handlers::requeue_dead_letter))
+            .route("/admin/api-usage", web::get().to(handlers::get_api_usage))
+            .route("/admin/replay", web::post().to(handlers::replay_event))
+            .route("/admin/state", web::get().to(handlers::get_state))
+            .route("/admin/token", web::put().to(handlers::rotate_token))
+            .route("/admin/entity-types", web::get().to(handlers::get_entity_types))
+            .route("/admin/entity-types/{type}", web::put().to(handlers::put_entity_type))
+            .route("/admin/products/settings", web::get().to(handlers::list_product_settings))
+            .route("/admin/products/settings/export", web::get().to(handlers::export_product_settings))
+            .route("/admin/products/settings/import", web::post().to(handlers::import_product_settings))
+            .route("/admin/products/{id}/settings", web::get().to(handlers::get_product_settings))
+            .route("/admin/products/{id}/settings", web::put().to(handlers::put_product_settings))
+            .route("/admin/products/{id}/settings", web::delete().to(handlers::delete_product_settings))
+    });
+
+    let server = match tls {
+        Some(tls) => server.bind_rustls_0_23((host.as_str(), port), tls)?,
+        None => server.bind((host.as_str(), port))?,
+    };
+
+    server.run().await
 }
@@ -0,0 +1,35 @@
+//! Суммы и количества документов МойСклад в десятичной арифметике: суммы — в копейках,
+//! количества — с QUANTITY_DECIMALS знаками после запятой
+
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::RoundingStrategy;
+
+pub use rust_decimal::Decimal;
+
+/// Знаков после запятой в количестве, которые принимает МойСклад
+pub const QUANTITY_DECIMALS: u32 = 4;
+
+/// Значение из f64 (остатки, пороги, количества заказов); не число — 0
+pub fn decimal(value: f64) -> Decimal {
+    Decimal::from_f64(value).unwrap_or_default()
+}
+
+/// Значение для расчётов с остатками и порогами
+pub fn as_f64(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or_default()
+}
+
+/// Количество для документа из f64
+pub fn to_quantity(value: f64) -> Decimal {
+    round_quantity(decimal(value))
+}
+
+/// Количество, округлённое до знаков, которые принимает МойСклад
+pub fn round_quantity(value: Decimal) -> Decimal {
+    value.round_dp_with_strategy(QUANTITY_DECIMALS, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Сумма, округлённая до целых копеек
+pub fn round_kopecks(value: Decimal) -> Decimal {
+    value.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+}
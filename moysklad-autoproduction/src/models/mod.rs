@@ -1,3 +1,5 @@
+pub mod amount;
 pub mod moysklad;
 
+pub use amount::*;
 pub use moysklad::*;
@@ -1,6 +1,29 @@
 //! Типы данных для API МойСклад
 
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+
+thread_local! {
+    /// Значения перечислений, которых модели не знают, встреченные при текущем разборе
+    static UNKNOWN_VARIANTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Отметить значение перечисления, которое модель разобрала как неизвестное (`Тип::значение`)
+fn note_unknown_variant(value: String) {
+    UNKNOWN_VARIANTS.with(|values| values.borrow_mut().push(value));
+}
+
+/// Забрать неизвестные значения перечислений, встреченные с прошлого вызова в этом потоке
+pub fn take_unknown_variants() -> Vec<String> {
+    UNKNOWN_VARIANTS.with(|values| std::mem::take(&mut *values.borrow_mut()))
+}
+
+/// Разобрать дату МойСклад (`2024-01-15 10:00:00.000`)
+pub fn parse_moment(moment: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(moment, "%Y-%m-%d %H:%M:%S%.f").ok()
+}
 
 /// Метаданные сущности
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +45,72 @@ pub struct Meta {
     pub offset: Option<u32>,
 }
 
+impl Meta {
+    /// Ссылка на сущность по href и типу
+    pub fn entity(href: String, entity_type: &str) -> Self {
+        Self {
+            href,
+            metadata_href: None,
+            entity_type: Some(entity_type.to_string()),
+            media_type: None,
+            size: None,
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+/// Вид ассортимента в позиции документа
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssortmentKind {
+    Product,
+    Variant,
+    Service,
+    Bundle,
+    Consignment,
+    Other(String),
+}
+
+impl AssortmentKind {
+    /// Определить вид по `meta.type`, а если его нет — по пути `meta.href`
+    pub fn from_meta(meta: &Meta) -> Self {
+        let entity_type = meta.entity_type.clone().unwrap_or_else(|| {
+            meta.href
+                .rsplit('/')
+                .nth(1)
+                .unwrap_or_default()
+                .to_string()
+        });
+
+        match entity_type.as_str() {
+            "product" => Self::Product,
+            "variant" => Self::Variant,
+            "service" => Self::Service,
+            "bundle" => Self::Bundle,
+            "consignment" => Self::Consignment,
+            _ => Self::Other(entity_type),
+        }
+    }
+
+    /// Может ли позиция пополняться производством по тех. карте
+    pub fn is_producible(&self) -> bool {
+        matches!(self, Self::Product)
+    }
+
+    /// Название вида для сообщений
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Product => "товар",
+            Self::Variant => "модификация",
+            Self::Service => "услуга",
+            Self::Bundle => "комплект",
+            Self::Consignment => "серия",
+            Self::Other(name) => name,
+        }
+    }
+}
+
 /// Ссылка на сущность
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityRef {
@@ -41,9 +130,139 @@ pub struct Product {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub code: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub article: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "externalCode")]
     pub external_code: Option<String>,
+    /// Путь группы товара, например `Свечи/Ароматические`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "pathName")]
+    pub path_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "productFolder")]
+    pub product_folder: Option<EntityRef>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attributes: Option<Vec<Attribute>>,
+    /// Закупочная цена
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "buyPrice")]
+    pub buy_price: Option<Price>,
+    /// Упаковки товара
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packs: Option<Vec<ProductPack>>,
+}
+
+/// Упаковка товара: `quantity` единиц товара в одной упаковке единицы `uom`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductPack {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uom: Option<EntityRef>,
+    pub quantity: Decimal,
+}
+
+/// Цена в копейках
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Price {
+    pub value: Decimal,
+}
+
+/// Группа товаров
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductFolder {
+    pub meta: Meta,
+    pub name: String,
+    /// Путь родительской группы
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "pathName")]
+    pub path_name: Option<String>,
+}
+
+impl ProductFolder {
+    /// Полный путь группы, включая её название
+    pub fn full_path(&self) -> String {
+        match self.path_name.as_deref().filter(|p| !p.is_empty()) {
+            Some(parent) => format!("{}/{}", parent, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Тип дополнительного поля. Неизвестный тип разбирается в `Other` и отмечается
+/// в статистике API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeType {
+    String,
+    Text,
+    Long,
+    Double,
+    Boolean,
+    Time,
+    Link,
+    File,
+    CustomEntity,
+    /// Ссылка на сущность: сотрудник, договор, проект, склад, товар, контрагент
+    Entity(String),
+    Other(String),
+}
+
+impl AttributeType {
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "string" => Self::String,
+            "text" => Self::Text,
+            "long" => Self::Long,
+            "double" => Self::Double,
+            "boolean" => Self::Boolean,
+            "time" => Self::Time,
+            "link" => Self::Link,
+            "file" => Self::File,
+            "customentity" => Self::CustomEntity,
+            "employee" | "contract" | "project" | "store" | "product" | "counterparty" => {
+                Self::Entity(s.to_string())
+            }
+            _ => Self::Other(s.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::String => "string",
+            Self::Text => "text",
+            Self::Long => "long",
+            Self::Double => "double",
+            Self::Boolean => "boolean",
+            Self::Time => "time",
+            Self::Link => "link",
+            Self::File => "file",
+            Self::CustomEntity => "customentity",
+            Self::Entity(name) | Self::Other(name) => name,
+        }
+    }
+
+    /// Текстовое поле (строка или текст)
+    pub fn is_text(&self) -> bool {
+        matches!(self, Self::String | Self::Text)
+    }
+}
+
+impl Serialize for AttributeType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for AttributeType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let parsed = Self::parse(&String::deserialize(deserializer)?);
+        if let Self::Other(ref name) = parsed {
+            note_unknown_variant(format!("AttributeType::{}", name));
+        }
+        Ok(parsed)
+    }
 }
 
 /// Дополнительное поле (атрибут)
@@ -52,19 +271,59 @@ pub struct Attribute {
     pub id: String,
     pub name: String,
     #[serde(rename = "type")]
-    pub attr_type: String,
+    pub attr_type: AttributeType,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub value: Option<AttributeValue>,
 }
 
-/// Значение атрибута
+/// Описание дополнительного поля (метаданные атрибута)
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeMetadata {
+    pub meta: Meta,
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub attr_type: AttributeType,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Значение атрибута. Разбирается через `serde_json::Value`: числа с точной записью
+/// (arbitrary_precision) не проходят разбор `untagged`.
+#[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum AttributeValue {
     String(String),
     Number(f64),
     Boolean(bool),
     EntityRef(EntityRef),
+    /// Значение, формат которого модели не знают: товар разбирается без него,
+    /// значение отмечается в статистике API
+    Other(serde_json::Value),
+}
+
+impl<'de> Deserialize<'de> for AttributeValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde_json::Value;
+
+        let value = Value::deserialize(deserializer)?;
+        let kind = match value {
+            Value::String(s) => return Ok(Self::String(s)),
+            Value::Bool(b) => return Ok(Self::Boolean(b)),
+            Value::Number(ref n) => match n.as_f64() {
+                Some(n) => return Ok(Self::Number(n)),
+                None => "number",
+            },
+            Value::Object(_) => match serde_json::from_value::<EntityRef>(value.clone()) {
+                Ok(entity) => return Ok(Self::EntityRef(entity)),
+                Err(_) => "object",
+            },
+            Value::Array(_) => "array",
+            Value::Null => "null",
+        };
+        note_unknown_variant(format!("AttributeValue::{}", kind));
+        Ok(Self::Other(value))
+    }
 }
 
 impl Attribute {
@@ -75,7 +334,32 @@ impl Attribute {
             Some(AttributeValue::Number(n)) => Some(n.to_string()),
             Some(AttributeValue::Boolean(b)) => Some(b.to_string()),
             Some(AttributeValue::EntityRef(e)) => e.name.clone(),
-            None => None,
+            Some(AttributeValue::Other(_)) | None => None,
+        }
+    }
+
+    /// Числовое значение атрибута; в строке допускаются десятичная запятая и знак `%`
+    pub fn as_number(&self) -> Option<f64> {
+        match &self.value {
+            Some(AttributeValue::Number(n)) => Some(*n),
+            Some(AttributeValue::String(s)) => {
+                s.trim().trim_end_matches('%').trim().replace(',', ".").parse().ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Значение атрибута-флага: `true`, ненулевое число или непустая строка
+    pub fn is_set(&self) -> bool {
+        match &self.value {
+            Some(AttributeValue::Boolean(b)) => *b,
+            Some(AttributeValue::Number(n)) => *n != 0.0,
+            Some(AttributeValue::String(s)) => {
+                let s = s.trim().to_lowercase();
+                !s.is_empty() && s != "0" && s != "false" && s != "нет"
+            }
+            Some(AttributeValue::EntityRef(_)) => true,
+            Some(AttributeValue::Other(_)) | None => false,
         }
     }
 }
@@ -85,6 +369,7 @@ impl Attribute {
 pub struct StockByStoreRow {
     pub meta: Meta,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "stockByStore")]
     pub stock_by_store: Option<Vec<StoreStockInfo>>,
 }
 
@@ -93,9 +378,146 @@ pub struct StockByStoreRow {
 pub struct StoreStockInfo {
     pub meta: Meta,
     pub name: String,
-    pub stock: f64,
-    pub reserve: f64,
-    pub in_transit: f64,
+    #[serde(default)]
+    pub stock: Decimal,
+    #[serde(default)]
+    pub reserve: Decimal,
+    #[serde(default)]
+    #[serde(rename = "inTransit")]
+    pub in_transit: Decimal,
+}
+
+/// С каким остатком сравнивается порог (STOCK_MODE)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StockMode {
+    /// Физический остаток
+    Quantity,
+    /// Доступный: остаток − резерв
+    #[default]
+    Available,
+    /// Свободный: остаток − резерв − ожидание
+    Free,
+}
+
+impl StockMode {
+    /// Разобрать режим из строки
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "quantity" | "stock" => Some(Self::Quantity),
+            "available" => Some(Self::Available),
+            "free" => Some(Self::Free),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Quantity => "quantity",
+            Self::Available => "available",
+            Self::Free => "free",
+        }
+    }
+
+    /// Остаток для сравнения с порогом
+    pub fn effective(&self, stock: Decimal, reserve: Decimal, in_transit: Decimal) -> Decimal {
+        match self {
+            Self::Quantity => stock,
+            Self::Available => stock - reserve,
+            Self::Free => stock - reserve - in_transit,
+        }
+    }
+}
+
+/// По какому количеству позиции заказа покупателя запускается пополнение (QUANTITY_BASIS)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuantityBasis {
+    /// Заказанное количество
+    #[default]
+    Ordered,
+    /// Отгруженное количество: при частичной отгрузке — только отгруженная часть
+    Shipped,
+    /// Оставшийся резерв: зарезервированное и ещё не отгруженное
+    Reserve,
+}
+
+impl QuantityBasis {
+    /// Разобрать основание из строки
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "ordered" | "quantity" => Some(Self::Ordered),
+            "shipped" => Some(Self::Shipped),
+            "reserve" | "reserved" => Some(Self::Reserve),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ordered => "ordered",
+            Self::Shipped => "shipped",
+            Self::Reserve => "reserve",
+        }
+    }
+
+    /// Количество позиции по основанию
+    pub fn quantity(&self, position: &CustomerOrderPosition) -> Decimal {
+        match self {
+            Self::Ordered => position.quantity,
+            Self::Shipped => position.shipped,
+            Self::Reserve => (position.reserve.unwrap_or_default() - position.shipped).max(Decimal::ZERO),
+        }
+    }
+
+    /// Заменить количества позиций заказа на количества по основанию
+    pub fn apply(&self, mut order: CustomerOrder) -> CustomerOrder {
+        for position in order.positions.iter_mut().flat_map(|p| p.rows.iter_mut()) {
+            position.quantity = self.quantity(position);
+        }
+        order
+    }
+}
+
+/// Строка отчёта «Остатки» с фильтром по складу
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockRow {
+    pub meta: Meta,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub article: Option<String>,
+    #[serde(default)]
+    pub stock: Decimal,
+    #[serde(default)]
+    pub reserve: Decimal,
+    #[serde(default)]
+    #[serde(rename = "inTransit")]
+    pub in_transit: Decimal,
+}
+
+/// Строка отчёта прибыльности по товарам
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SalesRow {
+    pub assortment: Assortment,
+    #[serde(default)]
+    #[serde(rename = "sellQuantity")]
+    pub sell_quantity: f64,
+    #[serde(default)]
+    #[serde(rename = "returnQuantity")]
+    pub return_quantity: f64,
+}
+
+impl SalesRow {
+    /// ID товара из meta.href
+    pub fn product_id(&self) -> &str {
+        self.assortment.meta.href.rsplit('/').next().unwrap_or("")
+    }
+
+    /// Продано за вычетом возвратов
+    pub fn net_quantity(&self) -> f64 {
+        (self.sell_quantity - self.return_quantity).max(0.0)
+    }
 }
 
 /// Техническая карта
@@ -105,11 +527,38 @@ pub struct ProcessingPlan {
     pub id: String,
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "externalCode")]
     pub external_code: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub products: Option<ProcessingPlanProductsExpanded>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub materials: Option<ProcessingPlanMaterialsExpanded>,
+    /// Момент последнего изменения
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+}
+
+impl ProcessingPlan {
+    /// Производит ли тех. карта товар (или модификацию товара с этим ID)
+    pub fn outputs(&self, product_id: &str) -> bool {
+        let id_of = |entity: &EntityRef| {
+            entity
+                .id
+                .clone()
+                .unwrap_or_else(|| entity.meta.href.rsplit('/').next().unwrap_or("").to_string())
+        };
+
+        self.products
+            .as_ref()
+            .and_then(|p| p.rows.as_ref())
+            .is_some_and(|rows| {
+                rows.iter().any(|row| {
+                    id_of(&row.assortment) == product_id || id_of(&row.product) == product_id
+                })
+            })
+    }
 }
 
 /// Продукты тех. карты (развёрнутые)
@@ -135,7 +584,7 @@ pub struct ProcessingPlanProduct {
     pub id: Option<String>,
     pub product: EntityRef,
     pub assortment: EntityRef,
-    pub quantity: f64,
+    pub quantity: Decimal,
 }
 
 /// Материал в тех. карте (из чего производим)
@@ -145,7 +594,7 @@ pub struct ProcessingPlanMaterial {
     pub id: Option<String>,
     pub product: EntityRef,
     pub assortment: EntityRef,
-    pub quantity: f64,
+    pub quantity: Decimal,
 }
 
 /// Технологическая операция
@@ -182,10 +631,32 @@ pub struct Processing {
     pub updated: Option<String>,
 }
 
-/// Продукты тех. операции (с мета-ссылкой)
+impl Processing {
+    /// Количество товара в продуктах тех. операции (при `expand=products`)
+    pub fn produced_quantity(&self, product_id: &str) -> Decimal {
+        self.products
+            .iter()
+            .filter_map(|products| products.rows.as_ref())
+            .flatten()
+            .filter(|row| row.assortment.meta.href.rsplit('/').next() == Some(product_id))
+            .map(|row| row.quantity)
+            .sum()
+    }
+}
+
+/// Продукты тех. операции (с мета-ссылкой, при `expand=products` — со строками)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessingProducts {
     pub meta: Meta,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<Vec<ProcessingProductRow>>,
+}
+
+/// Продукт тех. операции
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessingProductRow {
+    pub quantity: Decimal,
+    pub assortment: EntityRef,
 }
 
 /// Материалы тех. операции (с мета-ссылкой)
@@ -215,13 +686,151 @@ pub struct CustomerOrder {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub agent: Option<EntityRef>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<EntityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "salesChannel")]
+    pub sales_channel: Option<EntityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub positions: Option<CustomerOrderPositions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "deliveryPlannedMoment")]
+    pub delivery_planned_moment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+}
+
+/// Тип документа-основания для тех. операций по производственному плану
+pub const PRODUCTION_PLAN_TYPE: &str = "productionplan";
+
+impl CustomerOrder {
+    /// Производственный план как документ-основание: позиции плана обрабатываются тем же
+    /// конвейером, что и позиции заказа. `id` — задание выполнения плана (/jobs/{id}).
+    pub fn production_plan(id: &str, moment: String, organization: EntityRef, store: EntityRef) -> Self {
+        Self {
+            meta: Meta::entity(String::new(), PRODUCTION_PLAN_TYPE),
+            id: id.to_string(),
+            name: format!("план {}", id),
+            external_code: None,
+            moment,
+            applicable: true,
+            status_name: None,
+            state: None,
+            store: Some(store),
+            organization,
+            agent: None,
+            project: None,
+            sales_channel: None,
+            positions: None,
+            delivery_planned_moment: None,
+            created: None,
+            updated: None,
+        }
+    }
+}
+
+/// Розничная продажа (RetailDemand), проведённая через МойСклад Кассу
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetailDemand {
+    pub meta: Meta,
+    pub id: String,
+    pub name: String,
+    pub moment: String,
+    pub applicable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<EntityRef>,
+    pub organization: EntityRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<EntityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<EntityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "salesChannel")]
+    pub sales_channel: Option<EntityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub positions: Option<CustomerOrderPositions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+}
+
+impl From<RetailDemand> for CustomerOrder {
+    /// Розничная продажа обрабатывается тем же конвейером, что и заказ покупателя
+    fn from(demand: RetailDemand) -> Self {
+        Self {
+            meta: demand.meta,
+            id: demand.id,
+            name: demand.name,
+            external_code: None,
+            moment: demand.moment,
+            applicable: demand.applicable,
+            status_name: None,
+            state: None,
+            store: demand.store,
+            organization: demand.organization,
+            agent: demand.agent,
+            project: demand.project,
+            sales_channel: demand.sales_channel,
+            positions: demand.positions,
+            delivery_planned_moment: None,
+            created: demand.created,
+            updated: demand.updated,
+        }
+    }
+}
+
+/// Внутренний заказ (InternalOrder): явная заявка на пополнение склада
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InternalOrder {
+    pub meta: Meta,
+    pub id: String,
+    pub name: String,
+    pub moment: String,
+    pub applicable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<EntityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<EntityRef>,
+    pub organization: EntityRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<EntityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub positions: Option<CustomerOrderPositions>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "deliveryPlannedMoment")]
+    pub delivery_planned_moment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub created: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated: Option<String>,
 }
 
+impl From<InternalOrder> for CustomerOrder {
+    fn from(order: InternalOrder) -> Self {
+        Self {
+            meta: order.meta,
+            id: order.id,
+            name: order.name,
+            external_code: None,
+            moment: order.moment,
+            applicable: order.applicable,
+            status_name: None,
+            state: order.state,
+            store: order.store,
+            organization: order.organization,
+            agent: None,
+            project: order.project,
+            sales_channel: None,
+            positions: order.positions,
+            delivery_planned_moment: order.delivery_planned_moment,
+            created: order.created,
+            updated: order.updated,
+        }
+    }
+}
+
 /// Позиции заказа покупателя
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CustomerOrderPositions {
@@ -236,67 +845,297 @@ pub struct CustomerOrderPosition {
     pub id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub meta: Option<Meta>,
-    pub assortment: EntityRef,
+    pub assortment: Assortment,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub product: Option<EntityRef>,
-    pub quantity: f64,
+    pub quantity: Decimal,
+    /// Цена в копейках
     #[serde(default)]
-    pub price: f64,
+    pub price: Decimal,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub discount: Option<f64>,
+    pub discount: Option<Decimal>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub vat: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub reserve: Option<f64>,
+    pub reserve: Option<Decimal>,
+    #[serde(default)]
+    pub shipped: Decimal,
 }
 
-/// Событие webhook от МойСклад
+/// Ассортимент позиции (при `expand=positions.assortment` — с полями товара)
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WebhookEvent {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub meta: Option<Meta>,
+pub struct Assortment {
+    pub meta: Meta,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
-    pub account_id: String,
-    pub entity_type: String,
-    pub action: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub entity: Option<CustomerOrder>,
+    pub code: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<WebhookContent>,
-}
-
-/// Контент webhook события
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WebhookContent {
+    pub article: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub entity: Option<CustomerOrder>,
+    pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub id: Option<String>,
+    #[serde(rename = "externalCode")]
+    pub external_code: Option<String>,
+    /// Путь группы товара, например `Свечи/Ароматические`
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub entity_type: Option<String>,
-}
-
-/// Ответ API с пагинацией
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ApiResponse<T> {
+    #[serde(rename = "pathName")]
+    pub path_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub meta: Option<ResponseMeta>,
+    #[serde(rename = "productFolder")]
+    pub product_folder: Option<EntityRef>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub rows: Option<Vec<T>>,
+    pub attributes: Option<Vec<Attribute>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub context: Option<Context>,
+    #[serde(rename = "buyPrice")]
+    pub buy_price: Option<Price>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packs: Option<Vec<ProductPack>>,
 }
 
-/// Метаданные ответа
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ResponseMeta {
-    pub href: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(rename = "type")]
-    pub meta_type: Option<String>,
+impl Assortment {
+    /// Товар из развёрнутого ассортимента, если в ответе есть его поля
+    pub fn to_product(&self) -> Option<Product> {
+        Some(Product {
+            meta: self.meta.clone(),
+            id: self.id.clone()?,
+            name: self.name.clone()?,
+            code: self.code.clone(),
+            article: self.article.clone(),
+            description: self.description.clone(),
+            external_code: self.external_code.clone(),
+            path_name: self.path_name.clone(),
+            product_folder: self.product_folder.clone(),
+            attributes: Some(self.attributes.clone().unwrap_or_default()),
+            buy_price: self.buy_price.clone(),
+            packs: self.packs.clone(),
+        })
+    }
+}
+
+impl From<Product> for Assortment {
+    /// Ассортимент позиции, собранной не из документа (строка производственного плана)
+    fn from(product: Product) -> Self {
+        Self {
+            meta: product.meta,
+            id: Some(product.id),
+            name: Some(product.name),
+            code: product.code,
+            article: product.article,
+            description: product.description,
+            external_code: product.external_code,
+            path_name: product.path_name,
+            product_folder: product.product_folder,
+            attributes: product.attributes,
+            buy_price: product.buy_price,
+            packs: product.packs,
+        }
+    }
+}
+
+impl CustomerOrderPosition {
+    /// Позиция с ассортиментом и количеством, без цены
+    pub fn of(assortment: Assortment, quantity: Decimal) -> Self {
+        Self {
+            id: None,
+            meta: None,
+            assortment,
+            product: None,
+            quantity,
+            price: Decimal::ZERO,
+            discount: None,
+            vat: None,
+            reserve: None,
+            shipped: Decimal::ZERO,
+        }
+    }
+}
+
+/// Приёмка: поступление товаров и материалов на склад
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Supply {
+    pub meta: Meta,
+    pub id: String,
+    pub name: String,
+    pub applicable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<EntityRef>,
+}
+
+/// Корректировка остатка: инвентаризация, оприходование или списание
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockCorrection {
+    pub meta: Meta,
+    pub id: String,
+    pub name: String,
+    pub moment: String,
+    /// У инвентаризации признака проведения нет
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applicable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<EntityRef>,
+    pub organization: EntityRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<EntityRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub positions: Option<CustomerOrderPositions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<String>,
+}
+
+impl From<StockCorrection> for CustomerOrder {
+    /// Позиции корректировки проверяются тем же конвейером, что и заказ покупателя
+    fn from(correction: StockCorrection) -> Self {
+        Self {
+            meta: correction.meta,
+            id: correction.id,
+            name: correction.name,
+            external_code: None,
+            moment: correction.moment,
+            applicable: correction.applicable.unwrap_or(true),
+            status_name: None,
+            state: None,
+            store: correction.store,
+            organization: correction.organization,
+            agent: None,
+            project: correction.project,
+            sales_channel: None,
+            positions: correction.positions,
+            delivery_planned_moment: None,
+            created: correction.created,
+            updated: correction.updated,
+        }
+    }
+}
+
+/// Подписка на webhook в МойСклад
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub meta: Meta,
+    pub id: String,
+    pub url: String,
+    #[serde(rename = "entityType")]
+    pub entity_type: String,
+    pub action: String,
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Событие webhook от МойСклад
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub account_id: String,
+    pub entity_type: String,
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity: Option<CustomerOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<WebhookContent>,
+}
+
+impl WebhookEvent {
+    /// Событие изменения заказа покупателя по его ID
+    pub fn customer_order(order_id: &str) -> Self {
+        Self::customer_order_action(order_id, "update")
+    }
+
+    /// Событие заказа покупателя с действием (`create`, `update`, `delete`)
+    pub fn customer_order_action(order_id: &str, action: &str) -> Self {
+        Self::entity_action("customerorder", order_id, action)
+    }
+
+    /// Событие документа указанного типа (`customerorder`, `retaildemand`, `internalorder`, `supply`) с действием
+    pub fn entity_action(entity_type: &str, id: &str, action: &str) -> Self {
+        Self {
+            meta: None,
+            id: None,
+            name: None,
+            account_id: String::new(),
+            entity_type: entity_type.to_lowercase(),
+            action: action.to_lowercase(),
+            entity: None,
+            content: Some(WebhookContent {
+                entity: None,
+                id: Some(id.to_string()),
+                entity_type: Some(entity_type.to_lowercase()),
+            }),
+        }
+    }
+}
+
+/// Контент webhook события
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookContent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity: Option<CustomerOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<String>,
+}
+
+/// Тело webhook МойСклад: `{"events":[{"meta":{...},"action":"UPDATE","accountId":"..."}]}`.
+/// В одном запросе может прийти несколько событий.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub events: Vec<WebhookNotification>,
+}
+
+/// Событие из тела webhook МойСклад
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookNotification {
+    /// Ссылка на изменённую сущность: ID — последний сегмент href
+    pub meta: Meta,
+    /// `CREATE`, `UPDATE` или `DELETE`
+    pub action: String,
+    #[serde(rename = "accountId")]
+    pub account_id: String,
+    /// Изменённые поля (для webhook с `diffType=FIELDS`)
+    #[serde(rename = "updatedFields", default, skip_serializing_if = "Option::is_none")]
+    pub updated_fields: Option<Vec<String>>,
+}
+
+impl WebhookNotification {
+    /// ID сущности из href
+    pub fn entity_id(&self) -> Option<&str> {
+        let path = self.meta.href.split('?').next().unwrap_or_default();
+        path.rsplit('/').next().filter(|id| !id.is_empty())
+    }
+
+    /// Тип сущности из meta
+    pub fn entity_type(&self) -> Option<&str> {
+        self.meta.entity_type.as_deref().filter(|t| !t.is_empty())
+    }
+}
+
+/// Ответ API с пагинацией
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiResponse<T> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ResponseMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<Vec<T>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<Context>,
+}
+
+/// Метаданные ответа
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseMeta {
+    pub href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "type")]
+    pub meta_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "mediaType")]
     pub media_type: Option<String>,
@@ -330,13 +1169,153 @@ pub struct CreateProcessingRequest {
     #[serde(rename = "productsStore")]
     pub products_store: EntityRefSmall,
     pub organization: EntityRefSmall,
-    pub quantity: f64,
+    pub quantity: Decimal,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Дата документа; по умолчанию — время создания
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moment: Option<String>,
     #[serde(rename = "processingSum")]
-    pub processing_sum: f64,
+    pub processing_sum: Decimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<EntityRefSmall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "salesChannel")]
+    pub sales_channel: Option<EntityRefSmall>,
+    /// Продукты и материалы явно — когда состав отличается от тех. карты (замены, потери);
+    /// иначе состав и позиции тех. карты подставляет МойСклад
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub products: Option<Vec<DocumentPosition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub materials: Option<Vec<DocumentPosition>>,
+}
+
+/// Оприходование
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Enter {
+    pub meta: Meta,
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applicable: Option<bool>,
+}
+
+/// Запрос на создание оприходования
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateEnterRequest {
+    pub organization: EntityRefSmall,
+    pub store: EntityRefSmall,
+    pub applicable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub positions: Vec<DocumentPosition>,
+}
+
+/// Перемещение
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Move {
+    pub meta: Meta,
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applicable: Option<bool>,
+}
+
+/// Запрос на создание перемещения
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateMoveRequest {
+    pub organization: EntityRefSmall,
+    #[serde(rename = "sourceStore")]
+    pub source_store: EntityRefSmall,
+    #[serde(rename = "targetStore")]
+    pub target_store: EntityRefSmall,
+    pub applicable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub positions: Vec<DocumentPosition>,
+}
+
+/// Заказ поставщику
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurchaseOrder {
+    pub meta: Meta,
+    pub id: String,
+    pub name: String,
+}
+
+/// Запрос на создание заказа поставщику
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatePurchaseOrderRequest {
+    pub organization: EntityRefSmall,
+    pub agent: EntityRefSmall,
+    pub store: EntityRefSmall,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub positions: Vec<DocumentPosition>,
+}
+
+/// Задача сотруднику
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub meta: Meta,
+    pub id: String,
+}
+
+/// Запрос на создание задачи
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateTaskRequest {
+    pub description: String,
+    pub assignee: EntityRefSmall,
+    /// Документ, к которому относится задача
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation: Option<EntityRefSmall>,
+}
+
+/// Метаданные настроек компании (пользовательские справочники)
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompanySettingsMetadata {
+    #[serde(rename = "customEntities", default)]
+    pub custom_entities: Vec<CustomEntityMetadata>,
+}
+
+/// Пользовательский справочник
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomEntityMetadata {
+    pub meta: Meta,
+    pub name: String,
+}
+
+impl CustomEntityMetadata {
+    /// ID справочника (последний сегмент ссылки на метаданные)
+    pub fn id(&self) -> Option<&str> {
+        self.meta.href.rsplit('/').next()
+    }
+}
+
+/// Элемент пользовательского справочника
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomEntityElement {
+    pub id: String,
+    pub name: String,
+}
+
+/// Запрос на создание элемента пользовательского справочника
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateCustomEntityElementRequest {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Позиция документа (оприходование, перемещение, заказ поставщику)
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentPosition {
+    pub quantity: Decimal,
+    pub assortment: EntityRefSmall,
 }
 
 /// Ссылка на тех. карту
@@ -364,10 +1343,353 @@ pub struct ProcessingResult {
     pub processing_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub processing_name: Option<String>,
+    /// Тип созданного документа, если это не тех. операция (`move`, `enter`, `purchaseorder`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub product: Option<ProductInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_materials: Vec<MaterialShortage>,
+    /// Уже существующая тех. операция на этот товар, из-за которой новая не создана
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub existing_processing: Option<EntityRef>,
+    /// Почему позиция или заказ пропущены
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<SkipReason>,
+    /// Этап и код ошибки, если позиция не обработана из-за ошибки
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_details: Option<PositionErrorInfo>,
+    /// По какому количеству позиции заказа покупателя считалось пополнение
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity_basis: Option<QuantityBasis>,
+}
+
+impl ProcessingResult {
+    fn new(success: bool, message: String) -> Self {
+        Self {
+            success,
+            message,
+            order_id: None,
+            order_name: None,
+            processing_id: None,
+            processing_name: None,
+            document_type: None,
+            product: None,
+            error: None,
+            missing_materials: Vec::new(),
+            existing_processing: None,
+            skip_reason: None,
+            error_details: None,
+            quantity_basis: None,
+        }
+    }
+
+    /// Обработано без создания документа и без пропуска
+    pub fn done(message: impl Into<String>) -> Self {
+        Self::new(true, message.into())
+    }
+
+    /// Пропущено по причине `reason`, документ не создан
+    pub fn skipped(reason: SkipReason, message: impl Into<String>) -> Self {
+        Self {
+            skip_reason: Some(reason),
+            ..Self::new(true, message.into())
+        }
+    }
+
+    /// Не обработано из-за ошибки
+    pub fn failed(message: impl Into<String>, error: impl Into<String>) -> Self {
+        Self {
+            error: Some(error.into()),
+            ..Self::new(false, message.into())
+        }
+    }
+
+    /// Создан документ пополнения (тех. операция, оприходование, перемещение, заказ поставщику)
+    pub fn produced(
+        message: impl Into<String>,
+        processing_id: impl Into<String>,
+        processing_name: impl Into<String>,
+    ) -> Self {
+        Self {
+            processing_id: Some(processing_id.into()),
+            processing_name: Some(processing_name.into()),
+            ..Self::new(true, message.into())
+        }
+    }
+
+    /// Документ, к которому относится результат
+    pub fn for_order(self, order: &CustomerOrder) -> Self {
+        self.for_order_id(&order.id, Some(order.name.clone()))
+    }
+
+    /// Документ по ID, когда сам документ недоступен (удалён)
+    pub fn for_order_id(mut self, order_id: &str, order_name: Option<String>) -> Self {
+        self.order_id = Some(order_id.to_string());
+        self.order_name = order_name;
+        self
+    }
+
+    pub fn with_product(mut self, product: ProductInfo) -> Self {
+        self.product = Some(product);
+        self
+    }
+
+    /// Тип созданного документа (по нему документ отменяется при удалении заказа)
+    pub fn with_document_type(mut self, entity_type: &str) -> Self {
+        self.document_type = (entity_type != "processing").then(|| entity_type.to_string());
+        self
+    }
+
+    /// Причина, по которой документ не создан
+    pub fn with_reason(mut self, reason: SkipReason) -> Self {
+        self.skip_reason = Some(reason);
+        self
+    }
+
+    /// Позиция не обработана из-за временной ошибки и может быть обработана повторно
+    pub fn retryable(&self) -> bool {
+        self.error_details.as_ref().is_some_and(|d| d.retryable)
+    }
+}
+
+/// Этап обработки позиции
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionStage {
+    /// Блокировка товара
+    Lock,
+    /// Склад и остатки
+    Stock,
+    /// Карточка товара
+    Product,
+    /// Тех. карта
+    TechCard,
+    /// Проверка материалов
+    Materials,
+    /// Создание документа
+    Create,
+    /// Проведение документа
+    Apply,
+}
+
+impl PositionStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lock => "lock",
+            Self::Stock => "stock",
+            Self::Product => "product",
+            Self::TechCard => "tech_card",
+            Self::Materials => "materials",
+            Self::Create => "create",
+            Self::Apply => "apply",
+        }
+    }
+}
+
+/// Машиночитаемое описание ошибки обработки позиции
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionErrorInfo {
+    pub stage: PositionStage,
+    /// Код вида `create.1021`, `apply.http_412`, `stock.network`
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Код ошибки МойСклад из ответа API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moysklad_code: Option<i64>,
+    pub retryable: bool,
+}
+
+/// Причина пропуска позиции или заказа
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Заказ не проведён
+    NotApplicable,
+    /// Заказ с другого склада
+    OtherStore,
+    /// Остаток не ниже порога
+    StockSufficient,
+    /// У товара нет тех. карты
+    NoTechCard,
+    /// Услуга, комплект и прочий непроизводимый ассортимент
+    NotProducible,
+    /// Исключено настройками
+    Excluded,
+    /// Уже обработано или тех. операция уже существует
+    Duplicate,
+    /// Не хватает материалов
+    MaterialsShort,
+    /// Количество отклонено как подозрительное
+    SuspiciousQuantity,
+    /// Контрагент не входит в AGENT_FILTER
+    OtherAgent,
+    /// Канал продаж не входит в SALES_CHANNEL_FILTER
+    OtherSalesChannel,
+    /// Вне рабочего времени, обработка отложена (WORK_HOURS)
+    OffHours,
+    /// Товар уже производился в пределах PRODUCTION_COOLDOWN_SECS
+    Cooldown,
+    /// Тех. карта производит другой товар
+    TechCardMismatch,
+    /// Только уведомление, документ не создаётся (NOTIFY_ONLY)
+    NotifyOnly,
+}
+
+impl SkipReason {
+    /// Все причины
+    pub const ALL: [SkipReason; 15] = [
+        SkipReason::NotApplicable,
+        SkipReason::OtherStore,
+        SkipReason::StockSufficient,
+        SkipReason::NoTechCard,
+        SkipReason::NotProducible,
+        SkipReason::Excluded,
+        SkipReason::Duplicate,
+        SkipReason::MaterialsShort,
+        SkipReason::SuspiciousQuantity,
+        SkipReason::OtherAgent,
+        SkipReason::OtherSalesChannel,
+        SkipReason::OffHours,
+        SkipReason::Cooldown,
+        SkipReason::TechCardMismatch,
+        SkipReason::NotifyOnly,
+    ];
+
+    /// Разобрать причину из строки
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_lowercase();
+        Self::ALL.into_iter().find(|reason| reason.as_str() == s)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NotApplicable => "not_applicable",
+            Self::OtherStore => "other_store",
+            Self::StockSufficient => "stock_sufficient",
+            Self::NoTechCard => "no_tech_card",
+            Self::NotProducible => "not_producible",
+            Self::Excluded => "excluded",
+            Self::Duplicate => "duplicate",
+            Self::MaterialsShort => "materials_short",
+            Self::SuspiciousQuantity => "suspicious_quantity",
+            Self::OtherAgent => "other_agent",
+            Self::OtherSalesChannel => "other_sales_channel",
+            Self::OffHours => "off_hours",
+            Self::Cooldown => "cooldown",
+            Self::TechCardMismatch => "tech_card_mismatch",
+            Self::NotifyOnly => "notify_only",
+        }
+    }
+}
+
+/// Нехватка материала для производства
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialShortage {
+    pub name: String,
+    pub quantity: Decimal,
+    /// Уровень в цепочке тех. карт (0 — материалы основной тех. карты)
+    #[serde(default)]
+    pub level: u32,
+    /// Полуфабрикат, для производства которого нужен материал
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+impl MaterialShortage {
+    /// Описание нехватки для сообщений
+    pub fn describe(&self) -> String {
+        match self.parent {
+            Some(ref parent) => format!(
+                "{}: нужно {}, нет в наличии (для полуфабриката '{}', уровень {})",
+                self.name, self.quantity, parent, self.level
+            ),
+            None => format!("{}: нужно {}, нет в наличии", self.name, self.quantity),
+        }
+    }
+}
+
+/// Потребность в материале и его наличие на складе
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialRequirement {
+    pub id: String,
+    pub name: String,
+    pub required: Decimal,
+    pub stock: Decimal,
+    pub reserve: Decimal,
+    pub available: Decimal,
+    pub missing: Decimal,
+    /// Уровень в цепочке тех. карт (0 — материалы основной тех. карты)
+    #[serde(default)]
+    pub level: u32,
+    /// Полуфабрикат, для производства которого нужен материал
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Нехватка покрывается производством по собственной тех. карте
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub produced_by: Option<String>,
+    /// Нехватка покрывается заменителем (MATERIAL_SUBSTITUTES_FILE)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub substituted_by: Option<String>,
+}
+
+/// Замена недостающего материала в тех. операции
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterialSubstitution {
+    pub material_id: String,
+    pub material: String,
+    pub substitute_id: String,
+    pub substitute: String,
+    /// Заменённое количество исходного материала
+    pub quantity: Decimal,
+    /// Расход заменителя: `quantity` × коэффициент
+    pub substitute_quantity: Decimal,
+    #[serde(skip)]
+    pub substitute_meta: Option<Meta>,
+}
+
+impl MaterialSubstitution {
+    /// Описание для тех. операции и сообщений
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {} заменено на {} {}",
+            self.material, self.quantity, self.substitute, self.substitute_quantity
+        )
+    }
+}
+
+/// Стоимость материала в производстве, суммы в копейках
+#[derive(Debug, Clone, Serialize)]
+pub struct MaterialCost {
+    pub id: String,
+    pub name: String,
+    pub quantity: Decimal,
+    /// Закупочная цена за единицу; 0, если не задана в карточке
+    pub unit_price: Decimal,
+    pub cost: Decimal,
+}
+
+/// Себестоимость производства по закупочным ценам материалов, в копейках
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProductionCost {
+    /// Сумма тех. операции: материалы (PROCESSING_COST_FROM_MATERIALS) и накладные
+    pub total: Decimal,
+    pub materials: Vec<MaterialCost>,
+    /// Накладные расходы (OVERHEAD_AMOUNT, OVERHEAD_PERCENT)
+    pub overhead: Decimal,
+}
+
+/// Отчёт о доступности материалов тех. карты
+#[derive(Debug, Clone, Serialize)]
+pub struct MaterialsReport {
+    pub plan_id: String,
+    pub plan_name: String,
+    pub quantity: f64,
+    pub available: bool,
+    pub materials: Vec<MaterialRequirement>,
 }
 
 /// Информация о продукте
@@ -375,6 +1697,429 @@ pub struct ProcessingResult {
 pub struct ProductInfo {
     pub id: String,
     pub name: String,
-    pub quantity: f64,
-    pub stock_before: f64,
+    pub quantity: Decimal,
+    pub stock_before: Decimal,
+    /// Из чего сложился остаток, с которым сравнивался порог
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stock: Option<StockSnapshot>,
+    /// Нужное количество, если материалов хватило только на часть (`quantity`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested: Option<Decimal>,
+    /// Единица `quantity` после пересчёта в единицы производства; без пересчёта —
+    /// единица товара
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+}
+
+impl ProductInfo {
+    pub fn new(id: &str, name: &str, quantity: Decimal, stock_before: Decimal) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            quantity,
+            stock_before,
+            stock: None,
+            requested: None,
+            unit: None,
+        }
+    }
+
+    /// Доля нужного количества, на которую запущено пополнение
+    pub fn covered_share(&self) -> Decimal {
+        match self.requested {
+            Some(requested) if requested > Decimal::ZERO => (self.quantity / requested).min(Decimal::ONE),
+            _ => Decimal::ONE,
+        }
+    }
+}
+
+/// Остатки товара на складе
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockSnapshot {
+    pub stock: Decimal,
+    pub reserve: Decimal,
+    pub in_transit: Decimal,
+    pub available: Decimal,
+    /// Количество в непроведённых тех. операциях на склад
+    #[serde(default)]
+    pub pending_production: Decimal,
+    /// Остаток для сравнения с порогом (STOCK_MODE, ожидание, тех. операции в работе)
+    #[serde(default)]
+    pub effective: Decimal,
+}
+
+/// Какие заказы покупателей учитывать в производственном плане
+#[derive(Debug, Clone)]
+pub enum PlanScope {
+    /// Проведённые заказы со склада с плановой отгрузкой в периоде (даты включительно)
+    Period { from: NaiveDate, to: NaiveDate },
+    /// Заказы по ID
+    Orders(Vec<String>),
+}
+
+/// Строка производственного плана
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanItem {
+    pub product_id: String,
+    #[serde(default)]
+    pub product_name: String,
+    /// Неотгруженное количество в заказах
+    #[serde(default)]
+    pub demand: Decimal,
+    /// Остаток на складе (без учёта резерва: резервируют те же заказы)
+    #[serde(default)]
+    pub stock: Decimal,
+    /// Количество в непроведённых тех. операциях на склад
+    #[serde(default)]
+    pub in_production: Decimal,
+    /// Сколько произвести
+    pub quantity: Decimal,
+    /// Заказы, из которых сложилась потребность
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub orders: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tech_card: Option<EntityRef>,
+    /// Материалы на `quantity`; остатки материалов проверяются для каждой строки отдельно
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub materials: Vec<MaterialRequirement>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub materials_available: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PlanItem {
+    fn new(product_id: String, product_name: String) -> Self {
+        Self {
+            product_id,
+            product_name,
+            demand: Decimal::ZERO,
+            stock: Decimal::ZERO,
+            in_production: Decimal::ZERO,
+            quantity: Decimal::ZERO,
+            orders: Vec::new(),
+            tech_card: None,
+            materials: Vec::new(),
+            materials_available: None,
+            error: None,
+        }
+    }
+}
+
+/// Производственный план по потребности заказов покупателей, без создания документов
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductionPlan {
+    pub computed_at: DateTime<Utc>,
+    /// Учтённых заказов
+    pub orders: usize,
+    /// Заказы, не вошедшие в план, и причины
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    pub items: Vec<PlanItem>,
+}
+
+impl ProductionPlan {
+    /// Пустой план; строки добавляются по позициям заказов
+    pub fn new() -> Self {
+        Self {
+            computed_at: Utc::now(),
+            orders: 0,
+            notes: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Добавить неотгруженное количество позиции к строке товара
+    pub fn add_demand(&mut self, product_id: &str, product_name: &str, order: &str, quantity: Decimal) {
+        let index = match self.items.iter().position(|item| item.product_id == product_id) {
+            Some(index) => index,
+            None => {
+                self.items.push(PlanItem::new(product_id.to_string(), product_name.to_string()));
+                self.items.len() - 1
+            }
+        };
+
+        let item = &mut self.items[index];
+        item.demand += quantity;
+        if !item.orders.iter().any(|o| o == order) {
+            item.orders.push(order.to_string());
+        }
+    }
+}
+
+impl Default for ProductionPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Товар с остатком ниже порога
+#[derive(Debug, Clone, Serialize)]
+pub struct StockScanItem {
+    pub product_id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub article: Option<String>,
+    pub stock: Decimal,
+    pub reserve: Decimal,
+    pub available: Decimal,
+    pub threshold: f64,
+}
+
+/// Остаток товара с тех. картой на отслеживаемом складе
+#[derive(Debug, Clone, Serialize)]
+pub struct StockOverviewItem {
+    pub product_id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub article: Option<String>,
+    pub tech_card: String,
+    pub stock: Decimal,
+    pub reserve: Decimal,
+    pub available: Decimal,
+    pub threshold: f64,
+    pub below_threshold: bool,
+    /// Хватает ли материалов на пополнение до порога (только для товаров ниже порога)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub materials_available: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Результат проверки настроек аккаунта
+#[derive(Debug, Clone, Serialize)]
+pub struct SetupCheck {
+    pub store: EntityRef,
+    pub organization: EntityRef,
+    pub tech_card_attribute: AttributeMetadata,
+}
+
+/// Снимок внутреннего состояния процессора (для отладки)
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessorState {
+    pub store: Option<EntityRef>,
+    pub organization: Option<EntityRef>,
+    pub source_store: Option<EntityRef>,
+    pub supplier: Option<EntityRef>,
+    pub tech_card_attribute: Option<AttributeMetadata>,
+    pub production_log_entity_id: Option<String>,
+    /// Тех. карт в кэше (PLAN_CACHE_TTL_SECS)
+    pub cached_plans: usize,
+    pub tech_card_sources: Vec<String>,
+    pub plan_lookup: Vec<String>,
+    pub default_replenishment: String,
+    /// Пробный режим NOTIFY_ONLY: документы не создаются
+    pub notify_only: bool,
+    pub stock_mode: String,
+    pub quantity_basis: String,
+    /// Товары с недавно запущенным производством: (ID, возраст отметки, сек)
+    pub in_progress: Vec<(String, u64)>,
+    pub processed_orders: usize,
+    /// Поставщик блокировок заказов и товаров: `memory` или `redis`
+    pub lock_provider: String,
+    /// Товаров с порогом по скорости продаж (DYNAMIC_THRESHOLDS)
+    pub dynamic_thresholds: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamic_thresholds_at: Option<DateTime<Utc>>,
+    /// Возраст кэша остатков, сек (STOCK_CACHE_REFRESH_SECS; нет — кэш выключен или пуст)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stock_cache_age_secs: Option<u64>,
+    pub stock_cache_products: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::{json, Map, Value};
+
+    /// Произвольный JSON ограниченной глубины
+    fn arb_json() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            ".{0,16}".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(3, 32, 6, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..6).prop_map(Value::from),
+                prop::collection::btree_map("[a-zA-Z_]{1,12}", inner, 0..6)
+                    .prop_map(|m| Value::Object(m.into_iter().collect())),
+            ]
+        })
+    }
+
+    /// Поле отсутствует, равно null или задано
+    fn optional<S: Strategy<Value = Value>>(value: S) -> impl Strategy<Value = Option<Value>> {
+        prop_oneof![Just(None), Just(Some(Value::Null)), value.prop_map(Some)]
+    }
+
+    /// Поля, которых нет в моделях: API может добавить их в любой момент
+    fn unknown_fields() -> impl Strategy<Value = Map<String, Value>> {
+        prop::collection::btree_map("unknown[A-Z][a-zA-Z]{0,10}", arb_json(), 0..4)
+            .prop_map(|m| m.into_iter().collect())
+    }
+
+    fn with_fields(mut object: Map<String, Value>, fields: Vec<(&str, Option<Value>)>) -> Value {
+        for (key, value) in fields {
+            if let Some(value) = value {
+                object.insert(key.to_string(), value);
+            }
+        }
+        Value::Object(object)
+    }
+
+    fn meta() -> impl Strategy<Value = Value> {
+        (
+            "[a-z]{1,16}",
+            "[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}",
+            optional(Just(json!("application/json"))),
+            optional(any::<u32>().prop_map(Value::from)),
+            unknown_fields(),
+        )
+            .prop_map(|(entity_type, id, media_type, size, extra)| {
+                with_fields(
+                    extra,
+                    vec![
+                        (
+                            "href",
+                            Some(json!(format!(
+                                "https://api.moysklad.ru/api/remap/1.2/entity/{}/{}",
+                                entity_type, id
+                            ))),
+                        ),
+                        ("type", Some(json!(entity_type))),
+                        ("media_type", media_type),
+                        ("size", size),
+                    ],
+                )
+            })
+    }
+
+    fn content() -> impl Strategy<Value = Value> {
+        (
+            optional("[0-9a-f-]{1,36}".prop_map(Value::from)),
+            optional("[a-z]{1,16}".prop_map(Value::from)),
+            unknown_fields(),
+        )
+            .prop_map(|(id, entity_type, extra)| {
+                with_fields(extra, vec![("id", id), ("entity_type", entity_type)])
+            })
+    }
+
+    /// Тело webhook: обязательные поля заданы, необязательные и неизвестные — как придётся
+    fn webhook_body() -> impl Strategy<Value = (Value, String, String, String)> {
+        (
+            "[0-9a-f-]{0,36}",
+            "[a-zA-Z]{1,16}",
+            "(create|update|delete|CREATE|UPDATE|DELETE)",
+            optional(meta()),
+            optional(".{0,36}".prop_map(Value::from)),
+            optional(".{0,32}".prop_map(Value::from)),
+            optional(content()),
+            unknown_fields(),
+        )
+            .prop_map(|(account_id, entity_type, action, meta, id, name, content, extra)| {
+                let body = with_fields(
+                    extra,
+                    vec![
+                        ("account_id", Some(json!(account_id))),
+                        ("entity_type", Some(json!(entity_type))),
+                        ("action", Some(json!(action))),
+                        ("meta", meta),
+                        ("id", id),
+                        ("name", name),
+                        ("content", content),
+                    ],
+                );
+                (body, account_id, entity_type, action)
+            })
+    }
+
+    #[test]
+    fn unknown_attribute_types_and_values_are_parsed_and_noted() {
+        take_unknown_variants();
+        let attributes: Vec<Attribute> = serde_json::from_value(json!([
+            {"id": "1", "name": "Техкарта", "type": "string", "value": "ТК-1"},
+            {"id": "2", "name": "Координаты", "type": "geo", "value": {"lat": 55.7, "lon": 37.6}},
+            {"id": "3", "name": "Цвета", "type": "string", "value": ["красный"]},
+        ]))
+        .unwrap();
+
+        assert_eq!(attributes[0].attr_type, AttributeType::String);
+        assert_eq!(attributes[1].attr_type, AttributeType::Other("geo".to_string()));
+        assert!(matches!(attributes[2].value, Some(AttributeValue::Other(_))));
+        assert_eq!(
+            take_unknown_variants(),
+            vec!["AttributeType::geo", "AttributeValue::object", "AttributeValue::array"]
+        );
+        assert!(take_unknown_variants().is_empty());
+    }
+
+    #[test]
+    fn document_quantities_stay_exact_on_the_wire() {
+        let position: CustomerOrderPosition = serde_json::from_str(
+            r#"{
+                "assortment": {"meta": {"href": "https://api.moysklad.ru/api/remap/1.2/entity/product/p1"}},
+                "quantity": 0.1,
+                "shipped": 0.2,
+                "reserve": 1.0000000000000001
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(position.quantity + position.shipped, "0.3".parse::<Decimal>().unwrap());
+        assert_eq!(position.reserve, Some("1.0000000000000001".parse().unwrap()));
+
+        let request = DocumentPosition {
+            quantity: position.quantity + position.shipped,
+            assortment: EntityRefSmall { meta: position.assortment.meta },
+        };
+        let body = serde_json::to_string(&request).unwrap();
+        assert!(body.starts_with(r#"{"quantity":0.3,"#), "{}", body);
+    }
+
+    proptest! {
+        #[test]
+        fn webhook_body_tolerates_optional_and_unknown_fields(
+            (body, account_id, entity_type, action) in webhook_body()
+        ) {
+            let event: WebhookEvent = serde_json::from_value(body).unwrap();
+            prop_assert_eq!(event.account_id, account_id);
+            prop_assert_eq!(event.entity_type, entity_type);
+            prop_assert_eq!(event.action, action);
+        }
+
+        #[test]
+        fn webhook_body_without_required_field_is_rejected(
+            (body, ..) in webhook_body(),
+            field in prop::sample::select(vec!["account_id", "entity_type", "action"]),
+        ) {
+            let mut body = body;
+            body.as_object_mut().unwrap().remove(field);
+            prop_assert!(serde_json::from_value::<WebhookEvent>(body).is_err());
+        }
+
+        #[test]
+        fn webhook_body_survives_serialization((body, ..) in webhook_body()) {
+            let event: WebhookEvent = serde_json::from_value(body).unwrap();
+            let text = serde_json::to_string(&event).unwrap();
+            let again: WebhookEvent = serde_json::from_str(&text).unwrap();
+            prop_assert_eq!(again.account_id, event.account_id);
+            prop_assert_eq!(again.id, event.id);
+        }
+
+        #[test]
+        fn arbitrary_json_never_panics(value in arb_json()) {
+            let _ = serde_json::from_value::<WebhookEvent>(value.clone());
+            let _ = serde_json::from_value::<CustomerOrder>(value);
+        }
+
+        #[test]
+        fn arbitrary_text_never_panics(text in ".{0,256}") {
+            let _ = serde_json::from_str::<WebhookEvent>(&text);
+        }
+    }
 }
@@ -0,0 +1,155 @@
+//! Пересылка webhook, которые сервис не обрабатывает, в другую интеграцию (FORWARD_URL)
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Client;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::models::{ProcessingResult, SkipReason};
+
+/// Входящий webhook как он был получен
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ForwardedWebhook {
+    /// Строка запроса без `?`
+    pub query: String,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Пересылка webhook без изменений: одна регистрация в МойСклад обслуживает несколько сервисов
+pub struct WebhookForwarder {
+    client: Client,
+    url: String,
+    retries: u32,
+}
+
+impl WebhookForwarder {
+    pub fn new(url: String, retries: u32) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self { client, url, retries }
+    }
+
+    /// Переслать webhook: первая попытка сразу, повторы — в фоне, не задерживая ответ МойСклад.
+    /// `true` — webhook доставлен первой попыткой.
+    pub async fn forward(self: &Arc<Self>, webhook: ForwardedWebhook) -> bool {
+        match self.send(&webhook).await {
+            Ok(()) => {
+                debug!("Webhook forwarded ({})", webhook.query);
+                true
+            }
+            Err(e) if self.retries > 0 => {
+                warn!("Failed to forward webhook ({}): {:#}, retrying in background", webhook.query, e);
+                let this = self.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = this.retry(&webhook).await {
+                        warn!("Failed to forward webhook ({}): {:#}", webhook.query, e);
+                    }
+                });
+                false
+            }
+            Err(e) => {
+                warn!("Failed to forward webhook ({}): {:#}", webhook.query, e);
+                false
+            }
+        }
+    }
+
+    /// Повторы после неудачной первой попытки: до FORWARD_RETRIES раз с растущей паузой
+    async fn retry(&self, webhook: &ForwardedWebhook) -> Result<()> {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            tokio::time::sleep(Duration::from_secs(1 << attempt.min(6))).await;
+
+            match self.send(webhook).await {
+                Ok(()) => {
+                    debug!("Webhook forwarded ({}) on retry {}", webhook.query, attempt);
+                    return Ok(());
+                }
+                Err(e) if attempt < self.retries => {
+                    warn!("Webhook forward retry {} failed: {:#}", attempt, e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn send(&self, webhook: &ForwardedWebhook) -> Result<()> {
+        let url = match (webhook.query.is_empty(), self.url.contains('?')) {
+            (true, _) => self.url.clone(),
+            (false, true) => format!("{}&{}", self.url, webhook.query),
+            (false, false) => format!("{}?{}", self.url, webhook.query),
+        };
+
+        let mut request = self.client.post(&url).body(webhook.body.clone());
+        if let Some(ref content_type) = webhook.content_type {
+            request = request.header("Content-Type", content_type);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to forward webhook")?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("Forward endpoint returned {}", status));
+        }
+
+        Ok(())
+    }
+}
+
+/// Webhook, который пересылается, если после отложенной обработки (поток, склейка,
+/// очередь приоритетов) документ оказался чужим
+#[derive(Clone)]
+pub struct DeferredForward {
+    forwarder: Arc<WebhookForwarder>,
+    webhook: ForwardedWebhook,
+}
+
+impl DeferredForward {
+    pub fn new(forwarder: Arc<WebhookForwarder>, webhook: ForwardedWebhook) -> Self {
+        Self { forwarder, webhook }
+    }
+
+    /// Исходный webhook (для очередей, которые сохраняются в файл)
+    pub fn into_webhook(self) -> ForwardedWebhook {
+        self.webhook
+    }
+
+    /// Переслать в фоне, если все позиции документа пропущены как чужие;
+    /// `true` — документ чужой и пересылка начата
+    pub fn forward_if_foreign(self, results: &[ProcessingResult]) -> bool {
+        if !is_foreign(results) {
+            return false;
+        }
+        tokio::spawn(async move { self.forwarder.forward(self.webhook).await });
+        true
+    }
+}
+
+impl fmt::Debug for DeferredForward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeferredForward").field("query", &self.webhook.query).finish()
+    }
+}
+
+/// Все позиции пропущены, потому что документ относится к другому складу,
+/// контрагенту или каналу продаж
+pub fn is_foreign(results: &[ProcessingResult]) -> bool {
+    !results.is_empty()
+        && results.iter().all(|r| {
+            matches!(
+                r.skip_reason,
+                Some(SkipReason::OtherStore | SkipReason::OtherAgent | SkipReason::OtherSalesChannel)
+            )
+        })
+}
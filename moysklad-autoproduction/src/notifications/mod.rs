@@ -0,0 +1,9 @@
+pub mod forward;
+pub mod notifier;
+pub mod outgoing;
+pub mod router;
+
+pub use forward::*;
+pub use notifier::*;
+pub use outgoing::*;
+pub use router::*;
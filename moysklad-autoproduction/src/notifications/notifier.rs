@@ -0,0 +1,299 @@
+//! Каналы доставки уведомлений
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::api::redact::sanitize;
+use crate::models::SkipReason;
+
+/// Тип события, о котором отправляется уведомление
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationEvent {
+    /// Ошибка обработки позиции или заказа
+    Failure,
+    /// Недостаточно материалов для производства
+    Shortage,
+    /// Тех. операция успешно создана
+    Success,
+    /// Сводный отчёт
+    Summary,
+    /// Позиция или заказ пропущены (маршрут можно задать для причины: `skipped.no_tech_card`)
+    Skipped,
+    /// Тех. операция создана позже SLA_LIMIT_SECS после изменения документа
+    Sla,
+    /// Заказ снят с повторов после RETRY_MAX_ATTEMPTS неудачных попыток
+    DeadLetter,
+    /// Склад, организация, доп. поле или webhook изменены или удалены в МойСклад
+    Drift,
+}
+
+impl NotificationEvent {
+    /// Все типы событий
+    pub const ALL: [NotificationEvent; 8] = [
+        NotificationEvent::Failure,
+        NotificationEvent::Shortage,
+        NotificationEvent::Success,
+        NotificationEvent::Summary,
+        NotificationEvent::Skipped,
+        NotificationEvent::Sla,
+        NotificationEvent::DeadLetter,
+        NotificationEvent::Drift,
+    ];
+
+    /// Разобрать тип события из строки настроек
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "failure" => Some(Self::Failure),
+            "shortage" => Some(Self::Shortage),
+            "success" => Some(Self::Success),
+            "summary" => Some(Self::Summary),
+            "skipped" => Some(Self::Skipped),
+            "sla" => Some(Self::Sla),
+            "dead_letter" => Some(Self::DeadLetter),
+            "drift" => Some(Self::Drift),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Failure => "failure",
+            Self::Shortage => "shortage",
+            Self::Success => "success",
+            Self::Summary => "summary",
+            Self::Skipped => "skipped",
+            Self::Sla => "sla",
+            Self::DeadLetter => "dead_letter",
+            Self::Drift => "drift",
+        }
+    }
+}
+
+/// Уведомление
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub event: NotificationEvent,
+    pub title: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<SkipReason>,
+}
+
+impl Notification {
+    pub fn new(event: NotificationEvent, title: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            event,
+            title: title.into(),
+            text: text.into(),
+            reason: None,
+        }
+    }
+
+    /// Уведомление о пропуске с причиной (для маршрутизации по причине)
+    pub fn skipped(reason: SkipReason, title: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            reason: Some(reason),
+            ..Self::new(NotificationEvent::Skipped, title, text)
+        }
+    }
+}
+
+/// Канал доставки уведомлений
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Имя канала, используемое в правилах маршрутизации
+    fn name(&self) -> &str;
+
+    /// Отправить уведомление
+    async fn send(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Канал, только пишущий уведомления в лог
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        match notification.event {
+            NotificationEvent::Failure | NotificationEvent::DeadLetter => {
+                error!("[notify] {}: {}", notification.title, notification.text)
+            }
+            NotificationEvent::Shortage | NotificationEvent::Sla | NotificationEvent::Drift => {
+                warn!("[notify] {}: {}", notification.title, notification.text)
+            }
+            NotificationEvent::Success | NotificationEvent::Summary | NotificationEvent::Skipped => {
+                info!("[notify] {}: {}", notification.title, notification.text)
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Уведомления в Telegram через Bot API
+pub struct TelegramNotifier {
+    client: Client,
+    bot_token: String,
+    chat_id: String,
+}
+
+impl TelegramNotifier {
+    pub fn new(bot_token: String, chat_id: String) -> Self {
+        Self {
+            client: Client::new(),
+            bot_token,
+            chat_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
+        let response = self
+            .client
+            .post(&url)
+            .json(&serde_json::json!({
+                "chat_id": self.chat_id,
+                "text": format!("{}\n\n{}", notification.title, notification.text),
+            }))
+            .send()
+            .await
+            // URL запроса содержит токен бота
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to send Telegram notification")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Telegram API error {}: {}", status, sanitize(&body)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Уведомления по электронной почте через SMTP
+pub struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailNotifier {
+    pub fn new(
+        host: &str,
+        port: u16,
+        username: Option<String>,
+        password: Option<String>,
+        from: &str,
+        to: &[String],
+    ) -> Result<Self> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+            .context("Failed to create SMTP transport")?
+            .port(port);
+
+        if let (Some(username), Some(password)) = (username, password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        let from = from
+            .parse()
+            .with_context(|| format!("Invalid sender address: {}", from))?;
+        let to = to
+            .iter()
+            .map(|addr| {
+                addr.parse()
+                    .with_context(|| format!("Invalid recipient address: {}", addr))
+            })
+            .collect::<Result<Vec<Mailbox>>>()?;
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            to,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(notification.title.clone());
+
+        for recipient in &self.to {
+            builder = builder.to(recipient.clone());
+        }
+
+        let message = builder
+            .body(notification.text.clone())
+            .context("Failed to build email message")?;
+
+        self.transport
+            .send(message)
+            .await
+            .context("Failed to send email notification")?;
+
+        Ok(())
+    }
+}
+
+/// Уведомления на произвольный HTTP endpoint (JSON POST)
+pub struct HttpNotifier {
+    client: Client,
+    url: String,
+}
+
+impl HttpNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: Client::new(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for HttpNotifier {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(notification)
+            .send()
+            .await
+            .context("Failed to send HTTP notification")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("Notification endpoint returned {}", status));
+        }
+
+        Ok(())
+    }
+}
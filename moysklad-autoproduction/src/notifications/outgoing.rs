@@ -0,0 +1,110 @@
+//! Исходящие webhook с результатами обработки
+
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::Serialize;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::models::ProcessingResult;
+
+/// Тело исходящего webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct OutgoingPayload {
+    pub order_id: String,
+    pub order_name: String,
+    pub results: Vec<ProcessingResult>,
+}
+
+/// Отправка результатов обработки во внешнюю систему.
+/// Тело подписывается HMAC-SHA256 (`X-Signature: sha256=<hex>`), если задан секрет.
+pub struct OutgoingWebhook {
+    client: Client,
+    url: String,
+    secret: Option<String>,
+    retries: u32,
+}
+
+impl OutgoingWebhook {
+    pub fn new(url: String, secret: Option<String>, retries: u32) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            client,
+            url,
+            secret,
+            retries,
+        }
+    }
+
+    /// Отправить в фоне, не задерживая обработку
+    pub fn deliver(self: &Arc<Self>, payload: OutgoingPayload) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = this.send_with_retries(&payload).await {
+                warn!(
+                    "Failed to deliver outgoing webhook for order {}: {:#}",
+                    payload.order_id, e
+                );
+            }
+        });
+    }
+
+    async fn send_with_retries(&self, payload: &OutgoingPayload) -> Result<()> {
+        let body = serde_json::to_vec(payload)?;
+        let mut attempt = 0;
+
+        loop {
+            match self.send(&body).await {
+                Ok(()) => {
+                    debug!("Outgoing webhook delivered for order {}", payload.order_id);
+                    return Ok(());
+                }
+                Err(e) if attempt < self.retries => {
+                    attempt += 1;
+                    let delay = Duration::from_secs(1 << attempt.min(6));
+                    warn!(
+                        "Outgoing webhook attempt {} failed: {:#}, retrying in {:?}",
+                        attempt, e, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn send(&self, body: &[u8]) -> Result<()> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_vec());
+
+        if let Some(ref secret) = self.secret {
+            request = request.header("X-Signature", format!("sha256={}", sign(secret, body)));
+        }
+
+        let response = request.send().await.context("Failed to send outgoing webhook")?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(anyhow!("Outgoing webhook endpoint returned {}", status));
+        }
+
+        Ok(())
+    }
+}
+
+/// HMAC-SHA256 подпись тела в hex
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
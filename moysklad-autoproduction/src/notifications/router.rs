@@ -0,0 +1,146 @@
+//! Маршрутизация уведомлений по каналам
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, error, warn};
+
+use super::notifier::*;
+use crate::config::Settings;
+use crate::models::SkipReason;
+
+/// Маршрутизатор уведомлений: решает, в какие каналы отправлять событие
+pub struct NotificationRouter {
+    channels: HashMap<String, Arc<dyn Notifier>>,
+    routes: HashMap<NotificationEvent, Vec<String>>,
+    /// Маршруты пропусков по причине (`skipped.<причина>`), приоритетнее `skipped`
+    reason_routes: HashMap<SkipReason, Vec<String>>,
+}
+
+impl NotificationRouter {
+    /// Собрать маршрутизатор из настроек
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mut channels: HashMap<String, Arc<dyn Notifier>> = HashMap::new();
+        channels.insert("log".to_string(), Arc::new(LogNotifier));
+
+        if let (Some(token), Some(chat_id)) =
+            (&settings.telegram_bot_token, &settings.telegram_chat_id)
+        {
+            channels.insert(
+                "telegram".to_string(),
+                Arc::new(TelegramNotifier::new(token.clone(), chat_id.clone())),
+            );
+        }
+
+        if let (Some(host), Some(from)) = (&settings.smtp_host, &settings.email_from)
+            && !settings.email_to.is_empty()
+        {
+            match EmailNotifier::new(
+                host,
+                settings.smtp_port,
+                settings.smtp_username.clone(),
+                settings.smtp_password.clone(),
+                from,
+                &settings.email_to,
+            ) {
+                Ok(notifier) => {
+                    channels.insert("email".to_string(), Arc::new(notifier));
+                }
+                Err(e) => error!("Email notifications disabled: {:#}", e),
+            }
+        }
+
+        if let Some(ref url) = settings.notify_http_url {
+            channels.insert("http".to_string(), Arc::new(HttpNotifier::new(url.clone())));
+        }
+
+        let (routes, reason_routes) = parse_routes(&settings.notify_routes);
+
+        for names in routes.values().chain(reason_routes.values()) {
+            for name in names {
+                if !channels.contains_key(name) {
+                    warn!("Notification channel '{}' is routed but not configured", name);
+                }
+            }
+        }
+
+        Self {
+            channels,
+            routes,
+            reason_routes,
+        }
+    }
+
+    /// Отправить уведомление во все каналы, назначенные для события.
+    /// Ошибки доставки логируются и не прерывают обработку.
+    pub async fn notify(&self, notification: Notification) {
+        let reason_route = notification
+            .reason
+            .and_then(|reason| self.reason_routes.get(&reason));
+        let Some(names) = reason_route.or_else(|| self.routes.get(&notification.event)) else {
+            debug!("No route for notification event {}", notification.event.as_str());
+            return;
+        };
+
+        for name in names {
+            let Some(channel) = self.channels.get(name) else {
+                continue;
+            };
+
+            if let Err(e) = channel.send(&notification).await {
+                warn!("Failed to deliver notification via {}: {:#}", channel.name(), e);
+            }
+        }
+    }
+
+    /// Названия настроенных каналов
+    pub fn channel_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.channels.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+/// Разобрать правила маршрутизации вида `failure=log,telegram;success=log;skipped.no_tech_card=email`.
+/// События без явного правила отправляются только в лог; пропуски без правила не отправляются.
+fn parse_routes(
+    spec: &str,
+) -> (HashMap<NotificationEvent, Vec<String>>, HashMap<SkipReason, Vec<String>>) {
+    let mut routes: HashMap<NotificationEvent, Vec<String>> = NotificationEvent::ALL
+        .iter()
+        .filter(|event| **event != NotificationEvent::Skipped)
+        .map(|event| (*event, vec!["log".to_string()]))
+        .collect();
+    let mut reason_routes = HashMap::new();
+
+    for rule in spec.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+        let Some((event, channels)) = rule.split_once('=') else {
+            warn!("Invalid notification route '{}', expected event=channel,...", rule);
+            continue;
+        };
+
+        let channels: Vec<String> = channels
+            .split(',')
+            .map(|c| c.trim().to_lowercase())
+            .filter(|c| !c.is_empty())
+            .collect();
+
+        if let Some(reason) = event.trim().to_lowercase().strip_prefix("skipped.") {
+            match SkipReason::parse(reason) {
+                Some(reason) => {
+                    reason_routes.insert(reason, channels);
+                }
+                None => warn!("Unknown skip reason '{}'", reason),
+            }
+            continue;
+        }
+
+        let Some(event) = NotificationEvent::parse(event) else {
+            warn!("Unknown notification event '{}'", event.trim());
+            continue;
+        };
+
+        routes.insert(event, channels);
+    }
+
+    (routes, reason_routes)
+}
@@ -0,0 +1,189 @@
+//! Ежедневная проверка настроек в МойСклад (DRIFT_CHECK): переименованные, удалённые
+//! и отключённые сущности, на которые опирается сервис
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use super::OrderProcessor;
+use crate::config::{persist_json, HANDLED_ENTITY_TYPES};
+use crate::models::{EntityRef, Webhook};
+use crate::notifications::{Notification, NotificationEvent};
+use crate::reports::scheduler::until_next_run;
+use crate::reports::ReportPeriod;
+use crate::tenants::{Tenant, DEFAULT_TENANT};
+
+/// Сообщение о переименовании склада или организации
+pub fn renamed(kind: &str, cached: &EntityRef, current: &EntityRef) -> Option<String> {
+    let (before, after) = (cached.name.as_deref()?, current.name.as_deref()?);
+    (before != after).then(|| format!("{} '{}' теперь называется '{}'", kind, before, after))
+}
+
+/// Изменения webhook на обрабатываемые документы относительно прошлой проверки
+pub fn webhook_changes(baseline: &[Webhook], current: &[Webhook]) -> Vec<String> {
+    baseline
+        .iter()
+        .filter(|before| before.enabled && HANDLED_ENTITY_TYPES.contains(&before.entity_type.as_str()))
+        .filter_map(|before| {
+            let label = format!("{} {} → {}", before.entity_type, before.action, before.url);
+            match current.iter().find(|webhook| webhook.id == before.id) {
+                None => Some(format!("Webhook {} удалён", label)),
+                Some(now) if !now.enabled => Some(format!("Webhook {} отключён", label)),
+                Some(now) if now.url != before.url => {
+                    Some(format!("Webhook {} теперь отправляется на {}", label, now.url))
+                }
+                Some(_) => None,
+            }
+        })
+        .collect()
+}
+
+/// Webhook прошлой проверки. Сохраняются между перезапусками: иначе webhook, удалённые
+/// или отключённые, пока сервис не работал, стали бы новой точкой отсчёта
+pub struct WebhookBaseline {
+    path: Option<PathBuf>,
+    webhooks: Option<Vec<Webhook>>,
+}
+
+impl WebhookBaseline {
+    /// Открыть список, восстановив webhook прошлой проверки
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut webhooks = None;
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read webhook baseline {}", path.display()))?;
+            let list: Vec<Webhook> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse webhook baseline {}", path.display()))?;
+            info!("Restored {} webhooks of the last drift check", list.len());
+            webhooks = Some(list);
+        }
+
+        Ok(Self { path, webhooks })
+    }
+
+    /// Webhook прошлой проверки; `None` — проверок ещё не было
+    pub fn webhooks(&self) -> Option<&[Webhook]> {
+        self.webhooks.as_deref()
+    }
+
+    /// Запомнить webhook текущей проверки
+    pub fn replace(&mut self, webhooks: Vec<Webhook>) {
+        persist_json(self.path.as_deref(), &webhooks, "webhook baseline");
+        self.webhooks = Some(webhooks);
+    }
+}
+
+/// Проверять настройки при старте (запоминаются webhook для сравнения) и ежедневно
+/// в `hour` часов; изменения отправляются уведомлением `drift`
+pub fn spawn_drift_checker(tenant: Arc<Tenant>, hour: u32) {
+    tokio::spawn(async move {
+        info!("Configuration drift for {} checked daily at {:02}:00", tenant.name, hour);
+
+        let path = tenant.settings.drift_baseline_file.as_deref().map(PathBuf::from);
+        let mut baseline = WebhookBaseline::open(path).unwrap_or_else(|e| {
+            warn!("[{}] {:#}; webhooks are compared from this check on", tenant.name, e);
+            WebhookBaseline { path: None, webhooks: None }
+        });
+
+        loop {
+            let result = OrderProcessor::check_drift(&tenant.processor, &mut baseline).await;
+            match result {
+                Ok(changes) if changes.is_empty() => {
+                    debug!("[{}] No configuration drift in Moysklad", tenant.name)
+                }
+                Ok(changes) => {
+                    warn!("[{}] Configuration drift in Moysklad: {}", tenant.name, changes.join("; "));
+
+                    let title = if tenant.name == DEFAULT_TENANT {
+                        "Настройки в МойСклад изменились".to_string()
+                    } else {
+                        format!("[{}] Настройки в МойСклад изменились", tenant.name)
+                    };
+                    tenant
+                        .notifier
+                        .notify(Notification::new(NotificationEvent::Drift, title, changes.join("\n")))
+                        .await;
+                }
+                Err(e) => warn!("[{}] Configuration drift check failed: {:#}", tenant.name, e),
+            }
+
+            tokio::time::sleep(until_next_run(ReportPeriod::Day, hour)).await;
+            if tenant.is_removed() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entity(name: &str) -> EntityRef {
+        serde_json::from_value(json!({"meta": {"href": "https://api/entity/store/1"}, "id": "1", "name": name}))
+            .unwrap()
+    }
+
+    fn webhook(id: &str, entity_type: &str, url: &str, enabled: bool) -> Webhook {
+        serde_json::from_value(json!({
+            "meta": {"href": format!("https://api/entity/webhook/{}", id)},
+            "id": id,
+            "url": url,
+            "entityType": entity_type,
+            "action": "UPDATE",
+            "enabled": enabled,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn renamed_reports_only_a_changed_name() {
+        assert_eq!(
+            renamed("Склад", &entity("Основной"), &entity("Главный")).as_deref(),
+            Some("Склад 'Основной' теперь называется 'Главный'")
+        );
+        assert_eq!(renamed("Склад", &entity("Основной"), &entity("Основной")), None);
+
+        let mut unnamed = entity("Основной");
+        unnamed.name = None;
+        assert_eq!(renamed("Склад", &unnamed, &entity("Главный")), None);
+    }
+
+    #[test]
+    fn webhook_changes_report_deleted_disabled_and_redirected_webhooks() {
+        let baseline = vec![
+            webhook("1", "customerorder", "https://a/webhook", true),
+            webhook("2", "retaildemand", "https://a/webhook", true),
+            webhook("3", "supply", "https://a/webhook", true),
+            webhook("4", "demand", "https://a/webhook", true),
+            webhook("5", "inventory", "https://a/webhook", false),
+            webhook("6", "loss", "https://a/webhook", true),
+        ];
+        let current = vec![
+            webhook("2", "retaildemand", "https://a/webhook", false),
+            webhook("3", "supply", "https://b/webhook", true),
+            webhook("5", "inventory", "https://a/webhook", true),
+            webhook("6", "loss", "https://a/webhook", true),
+        ];
+
+        assert_eq!(
+            webhook_changes(&baseline, &current),
+            vec![
+                "Webhook customerorder UPDATE → https://a/webhook удалён",
+                "Webhook retaildemand UPDATE → https://a/webhook отключён",
+                "Webhook supply UPDATE → https://a/webhook теперь отправляется на https://b/webhook",
+            ]
+        );
+    }
+
+    #[test]
+    fn webhook_changes_are_empty_without_changes() {
+        let webhooks = vec![webhook("1", "customerorder", "https://a/webhook", true)];
+        assert!(webhook_changes(&webhooks, &webhooks).is_empty());
+    }
+}
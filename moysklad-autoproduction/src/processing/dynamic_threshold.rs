@@ -0,0 +1,75 @@
+//! Пороги остатка по скорости продаж (DYNAMIC_THRESHOLDS)
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::reports::scheduler::until_next_run;
+use crate::reports::ReportPeriod;
+use crate::tenants::Tenant;
+
+/// Рассчитанные пороги: средние продажи в день за SALES_VELOCITY_DAYS × LEAD_TIME_DAYS.
+/// После расчёта у товаров без продаж за период порог 0; до первого расчёта
+/// используется MIN_STOCK_THRESHOLD.
+#[derive(Debug, Clone, Default)]
+pub struct DynamicThresholds {
+    thresholds: HashMap<String, f64>,
+    computed_at: Option<DateTime<Utc>>,
+}
+
+impl DynamicThresholds {
+    /// Порог товара, если пороги рассчитаны: без продаж за период — 0
+    pub fn get(&self, product_id: &str) -> Option<f64> {
+        self.computed_at?;
+        Some(self.thresholds.get(product_id).copied().unwrap_or(0.0))
+    }
+
+    /// Заменить пороги расчётом по продажам за `velocity_days`; возвращает число товаров
+    pub fn replace(
+        &mut self,
+        sales: HashMap<String, f64>,
+        velocity_days: u32,
+        lead_time_days: f64,
+    ) -> usize {
+        self.thresholds = sales
+            .into_iter()
+            .filter(|(_, sold)| *sold > 0.0)
+            .map(|(id, sold)| (id, sold / velocity_days.max(1) as f64 * lead_time_days))
+            .collect();
+        self.computed_at = Some(Utc::now());
+        self.thresholds.len()
+    }
+
+    /// Число товаров с рассчитанным порогом
+    pub fn count(&self) -> usize {
+        self.thresholds.len()
+    }
+
+    pub fn computed_at(&self) -> Option<DateTime<Utc>> {
+        self.computed_at
+    }
+}
+
+/// Пересчитывать пороги при старте и ежедневно в `hour` часов
+pub fn spawn_threshold_refresher(tenant: Arc<Tenant>, hour: u32) {
+    tokio::spawn(async move {
+        info!("Dynamic thresholds for {} recalculated daily at {:02}:00", tenant.name, hour);
+
+        loop {
+            match tenant.processor.lock().await.refresh_dynamic_thresholds().await {
+                Ok(products) => info!(
+                    "[{}] Dynamic thresholds recalculated for {} products",
+                    tenant.name, products
+                ),
+                // Остаются прежние пороги, до их расчёта — MIN_STOCK_THRESHOLD
+                Err(e) => warn!("[{}] Failed to recalculate dynamic thresholds: {:#}", tenant.name, e),
+            }
+
+            tokio::time::sleep(until_next_run(ReportPeriod::Day, hour)).await;
+            if tenant.is_removed() {
+                break;
+            }
+        }
+    });
+}
@@ -0,0 +1,107 @@
+//! Типизированная ошибка обработки позиции
+
+use std::fmt;
+
+use crate::api::{is_transient_error, ApiError};
+use crate::models::{PositionErrorInfo, PositionStage};
+
+/// Ошибка обработки позиции: этап, код ошибки МойСклад и признак временности
+#[derive(Debug)]
+pub struct PositionError {
+    pub stage: PositionStage,
+    /// HTTP статус ответа МойСклад
+    pub status: Option<u16>,
+    /// Код ошибки МойСклад из тела ответа (errors[0].code)
+    pub moysklad_code: Option<i64>,
+    /// Временная ошибка: позицию имеет смысл обработать повторно
+    pub retryable: bool,
+    source: anyhow::Error,
+}
+
+impl PositionError {
+    pub fn new(stage: PositionStage, source: anyhow::Error) -> Self {
+        let status = source.chain().find_map(|cause| match cause.downcast_ref::<ApiError>() {
+            Some(ApiError::Status { status, .. }) => Some(*status),
+            _ => None,
+        });
+        let moysklad_code = source.chain().find_map(|cause| match cause.downcast_ref::<ApiError>() {
+            Some(ApiError::Status { body, .. }) => moysklad_error_code(body),
+            _ => None,
+        });
+
+        Self {
+            stage,
+            status,
+            moysklad_code,
+            retryable: is_transient_error(&source),
+            source,
+        }
+    }
+
+    /// Машиночитаемый код: `<этап>.<код МойСклад | http_<статус> | timeout | network | error>`
+    pub fn code(&self) -> String {
+        let timed_out = self
+            .source
+            .chain()
+            .any(|cause| matches!(cause.downcast_ref::<ApiError>(), Some(ApiError::Timeout { .. })));
+        let cause = match (self.moysklad_code, self.status) {
+            (Some(code), _) => code.to_string(),
+            (None, Some(status)) => format!("http_{}", status),
+            (None, None) if timed_out => "timeout".to_string(),
+            (None, None) if self.retryable => "network".to_string(),
+            (None, None) => "error".to_string(),
+        };
+        format!("{}.{}", self.stage.as_str(), cause)
+    }
+
+    /// Описание ошибки для ответа и истории
+    pub fn info(&self) -> PositionErrorInfo {
+        PositionErrorInfo {
+            stage: self.stage,
+            code: self.code(),
+            status: self.status,
+            moysklad_code: self.moysklad_code,
+            retryable: self.retryable,
+        }
+    }
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.source)
+    }
+}
+
+impl std::error::Error for PositionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+/// Пометить ошибку этапом обработки позиции
+pub trait StageExt<T> {
+    fn stage(self, stage: PositionStage) -> Result<T, PositionError>;
+}
+
+impl<T> StageExt<T> for anyhow::Result<T> {
+    fn stage(self, stage: PositionStage) -> Result<T, PositionError> {
+        self.map_err(|e| PositionError::new(stage, e))
+    }
+}
+
+/// Код первой ошибки из тела ответа МойСклад: {"errors":[{"code":1021,...}]}
+fn moysklad_error_code(body: &str) -> Option<i64> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    value.get("errors")?.get(0)?.get("code")?.as_i64()
+}
+
+/// Попытка этапа обработки, записанная при воспроизведении события
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StageTrace {
+    pub stage: PositionStage,
+    /// Номер повтора, 0 — первая попытка
+    pub attempt: u32,
+    pub elapsed_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
@@ -0,0 +1,94 @@
+//! Тех. карты по группам товаров: шаблон названия для всех товаров группы
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use tracing::info;
+
+use crate::models::Product;
+
+/// Правило: товары группы (и её подгрупп) используют тех. карту по шаблону
+#[derive(Debug, Clone, Deserialize)]
+pub struct FolderMapping {
+    /// Путь группы, например `Свечи/Ароматические`
+    pub folder: String,
+    /// Шаблон названия тех. карты: `{article}`, `{code}`, `{external_code}`, `{name}`, `{folder}`
+    pub pattern: String,
+}
+
+/// Таблица соответствия групп товаров и тех. карт
+#[derive(Debug, Clone, Default)]
+pub struct FolderTechCards {
+    /// Отсортированы от более глубоких групп к более общим
+    mappings: Vec<FolderMapping>,
+}
+
+impl FolderTechCards {
+    /// Загрузить таблицу из JSON файла (`[{"folder": ..., "pattern": ...}]`)
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read folder mapping {}", path.display()))?;
+        let mut mappings: Vec<FolderMapping> = serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse folder mapping {}", path.display()))?;
+
+        for mapping in &mut mappings {
+            mapping.folder = normalize(&mapping.folder);
+        }
+        mappings.sort_by_key(|m| std::cmp::Reverse(m.folder.split('/').count()));
+
+        info!("Loaded {} folder tech card mappings", mappings.len());
+        Ok(Self { mappings })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Название тех. карты для товара по самой глубокой подходящей группе
+    pub fn find(&self, product: &Product) -> Option<String> {
+        let path = normalize(product.path_name.as_deref()?);
+
+        self.mappings
+            .iter()
+            .find(|m| path == m.folder || path.starts_with(&format!("{}/", m.folder)))
+            .and_then(|m| render(&m.pattern, product, &path))
+    }
+}
+
+/// Путь группы без лишних пробелов и разделителей по краям
+fn normalize(path: &str) -> String {
+    path.split('/')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Подставить поля товара в шаблон. `None`, если нужного поля у товара нет.
+fn render(pattern: &str, product: &Product, folder: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let end = start + rest[start..].find('}')?;
+        let value = match &rest[start + 1..end] {
+            "article" => product.article.clone(),
+            "code" => product.code.clone(),
+            "external_code" => product.external_code.clone(),
+            "name" => Some(product.name.clone()),
+            "folder" => folder.rsplit('/').next().map(str::to_string),
+            _ => None,
+        };
+        result.push_str(value.filter(|v| !v.trim().is_empty())?.trim());
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+
+    let result = result.trim().to_string();
+    (!result.is_empty()).then_some(result)
+}
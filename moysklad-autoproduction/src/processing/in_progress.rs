@@ -0,0 +1,55 @@
+//! Реестр товаров с недавно запущенным производством
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Товары, по которым недавно создана тех. операция.
+/// Запись живёт TTL: отчёт об остатках МойСклад обновляется с задержкой,
+/// и повторный заказ того же товара может увидеть ещё не пополненный остаток.
+pub struct InProgressRegistry {
+    ttl: Duration,
+    entries: HashMap<String, Instant>,
+}
+
+impl InProgressRegistry {
+    /// Создать реестр; нулевой TTL отключает дедупликацию
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Отметить начало производства товара.
+    /// Возвращает `false`, если производство этого товара уже запущено в пределах TTL.
+    pub fn try_begin(&mut self, product_id: &str) -> bool {
+        if self.ttl.is_zero() {
+            return true;
+        }
+
+        let now = Instant::now();
+        let ttl = self.ttl;
+        self.entries.retain(|_, started| now.duration_since(*started) < ttl);
+
+        if self.entries.contains_key(product_id) {
+            return false;
+        }
+
+        self.entries.insert(product_id.to_string(), now);
+        true
+    }
+
+    /// Снять отметку (тех. операция не была создана)
+    pub fn cancel(&mut self, product_id: &str) {
+        self.entries.remove(product_id);
+    }
+
+    /// Товары с запущенным производством в пределах TTL и возраст отметки, сек
+    pub fn active(&self) -> Vec<(String, u64)> {
+        self.entries
+            .iter()
+            .filter(|(_, started)| started.elapsed() < self.ttl)
+            .map(|(id, started)| (id.clone(), started.elapsed().as_secs()))
+            .collect()
+    }
+}
@@ -0,0 +1,197 @@
+//! Блокировки заказов и товаров, общие для всех экземпляров сервиса
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tracing::{debug, warn};
+
+use crate::config::Settings;
+
+/// Пауза между попытками взять занятую блокировку
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Снимает блокировку, только если её значение совпадает с токеном владельца
+const UNLOCK_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+else
+    return 0
+end
+"#;
+
+/// Взятая блокировка
+#[derive(Debug)]
+pub struct LockGuard {
+    key: String,
+    token: String,
+}
+
+/// Поставщик блокировок
+#[async_trait]
+pub trait LockProvider: Send + Sync {
+    /// Взять блокировку на `ttl`; `None`, если она занята
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<Option<LockGuard>>;
+
+    /// Снять блокировку, если она ещё принадлежит владельцу
+    async fn unlock(&self, guard: LockGuard) -> Result<()>;
+
+    fn name(&self) -> &'static str;
+}
+
+/// Блокировки в памяти процесса (один экземпляр сервиса)
+#[derive(Default)]
+pub struct MemoryLocks {
+    held: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+#[async_trait]
+impl LockProvider for MemoryLocks {
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<Option<LockGuard>> {
+        let mut held = self.held.lock().expect("lock registry poisoned");
+        let now = Instant::now();
+        held.retain(|_, (_, expires)| *expires > now);
+
+        if held.contains_key(key) {
+            return Ok(None);
+        }
+
+        let token = uuid::Uuid::new_v4().to_string();
+        held.insert(key.to_string(), (token.clone(), now + ttl));
+        Ok(Some(LockGuard { key: key.to_string(), token }))
+    }
+
+    async fn unlock(&self, guard: LockGuard) -> Result<()> {
+        let mut held = self.held.lock().expect("lock registry poisoned");
+        if held.get(&guard.key).is_some_and(|(token, _)| *token == guard.token) {
+            held.remove(&guard.key);
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Блокировки в Redis (`SET NX PX`), общие для нескольких реплик
+pub struct RedisLocks {
+    client: redis::Client,
+    conn: OnceCell<ConnectionManager>,
+}
+
+impl RedisLocks {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(url).context("Invalid REDIS_URL")?,
+            conn: OnceCell::new(),
+        })
+    }
+
+    /// Соединение устанавливается при первой блокировке
+    async fn connection(&self) -> Result<ConnectionManager> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(conn.clone())
+    }
+}
+
+#[async_trait]
+impl LockProvider for RedisLocks {
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<Option<LockGuard>> {
+        let mut conn = self.connection().await?;
+        let token = uuid::Uuid::new_v4().to_string();
+
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await
+            .with_context(|| format!("Failed to acquire lock {}", key))?;
+
+        Ok(reply.map(|_| LockGuard { key: key.to_string(), token }))
+    }
+
+    async fn unlock(&self, guard: LockGuard) -> Result<()> {
+        let mut conn = self.connection().await?;
+        let _: i64 = redis::Script::new(UNLOCK_SCRIPT)
+            .key(&guard.key)
+            .arg(&guard.token)
+            .invoke_async(&mut conn)
+            .await
+            .with_context(|| format!("Failed to release lock {}", guard.key))?;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+}
+
+/// Блокировки тенанта: ожидание занятой блокировки и префикс ключей
+pub struct Locks {
+    provider: Arc<dyn LockProvider>,
+    prefix: String,
+    ttl: Duration,
+    wait: Duration,
+}
+
+impl Locks {
+    /// Поставщик по LOCK_PROVIDER; ключи тенанта отделены префиксом
+    pub fn from_settings(settings: &Settings, tenant: &str) -> Result<Self> {
+        let provider: Arc<dyn LockProvider> = match settings.lock_provider.as_str() {
+            "redis" => {
+                let url = settings
+                    .redis_url
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("LOCK_PROVIDER=redis requires REDIS_URL"))?;
+                Arc::new(RedisLocks::new(url)?)
+            }
+            _ => Arc::new(MemoryLocks::default()),
+        };
+
+        Ok(Self {
+            provider,
+            prefix: format!("autoproduction:lock:{}:", tenant),
+            ttl: Duration::from_secs(settings.lock_ttl_secs.max(1)),
+            wait: Duration::from_secs(settings.lock_wait_secs),
+        })
+    }
+
+    /// Взять блокировку, дождавшись её освобождения не дольше LOCK_WAIT_SECS
+    pub async fn acquire(&self, key: &str) -> Result<LockGuard> {
+        let key = format!("{}{}", self.prefix, key);
+        let deadline = Instant::now() + self.wait;
+
+        loop {
+            if let Some(guard) = self.provider.try_lock(&key, self.ttl).await? {
+                return Ok(guard);
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow!("Lock {} is held by another worker", key));
+            }
+            debug!("Waiting for lock {}", key);
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Снять блокировку; ошибка только логируется — блокировка истечёт по TTL
+    pub async fn release(&self, guard: LockGuard) {
+        if let Err(e) = self.provider.unlock(guard).await {
+            warn!("{:#}", e);
+        }
+    }
+
+    pub fn provider_name(&self) -> &'static str {
+        self.provider.name()
+    }
+}
@@ -1,3 +1,39 @@
+pub mod drift;
+pub mod dynamic_threshold;
+pub mod error;
+pub mod folder_map;
+pub mod in_progress;
+pub mod lock;
+pub mod numbering;
+pub mod overhead;
+pub mod overrides;
+pub mod plan_cache;
+pub mod processed;
 pub mod processor;
+pub mod replay;
+pub mod replenishment;
+pub mod schedule;
+pub mod skip_stats;
+pub mod source_report;
+pub mod stock_cache;
+pub mod strategy;
+pub mod substitutes;
+pub mod tasks;
+pub mod tech_card;
+pub mod unit_conversion;
 
+pub use drift::*;
+pub use dynamic_threshold::*;
+pub use folder_map::*;
+pub use lock::*;
+pub use numbering::*;
+pub use overrides::*;
+pub use plan_cache::*;
+pub use processed::*;
 pub use processor::*;
+pub use replay::*;
+pub use schedule::*;
+pub use skip_stats::*;
+pub use stock_cache::*;
+pub use substitutes::*;
+pub use tasks::*;
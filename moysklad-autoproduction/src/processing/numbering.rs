@@ -0,0 +1,169 @@
+//! Номера автоматически созданных тех. операций по шаблону NAME_TEMPLATE
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use redis::aio::ConnectionManager;
+use std::path::PathBuf;
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+use crate::config::persist_json;
+
+/// Проверить шаблон: без `{seq}` номера повторялись бы
+pub fn validate_name_template(template: &str) -> Result<(), String> {
+    if seq_placeholder(template).is_none() {
+        return Err(format!("NAME_TEMPLATE '{}' must contain {{seq}} or {{seq:N}}", template));
+    }
+    Ok(())
+}
+
+/// Плейсхолдер счётчика и ширина номера с ведущими нулями
+fn seq_placeholder(template: &str) -> Option<(&str, usize)> {
+    let start = template.find("{seq")?;
+    let end = start + template[start..].find('}')?;
+    let placeholder = &template[start..=end];
+    match &placeholder[4..placeholder.len() - 1] {
+        "" => Some((placeholder, 0)),
+        width => width.strip_prefix(':')?.parse().ok().map(|width| (placeholder, width)),
+    }
+}
+
+/// Шаблон с подставленной датой: ключ счётчика, чтобы `{date}` начинал нумерацию заново
+fn with_date(template: &str, date: NaiveDate) -> String {
+    template
+        .replace("{date}", &date.format("%Y%m%d").to_string())
+        .replace("{year}", &date.format("%Y").to_string())
+}
+
+/// Счётчики с датой в ключе хранятся в Redis не дольше этого срока
+const REDIS_COUNTER_TTL_SECS: u64 = 400 * 24 * 60 * 60;
+
+/// Где хранятся счётчики
+enum Counters {
+    /// Файл NAME_SEQUENCE_FILE: только для одного экземпляра сервиса
+    File {
+        path: Option<PathBuf>,
+        /// Асинхронный мьютекс: запись файла идёт в blocking-потоке, а порядок записей сохраняется
+        counters: tokio::sync::Mutex<BTreeMap<String, u64>>,
+    },
+    /// Redis (`INCR`) при EVENT_QUEUE=redis: номера не повторяются между репликами
+    Redis {
+        client: redis::Client,
+        conn: Box<OnceCell<ConnectionManager>>,
+        prefix: String,
+    },
+}
+
+/// Персистентные счётчики номеров. Номер расходуется и при неудачном создании
+/// тех. операции: в нумерации возможны пропуски, но не повторы.
+pub struct NameSequence {
+    template: Option<String>,
+    counters: Counters,
+}
+
+impl NameSequence {
+    /// Открыть счётчики в файле, восстановив сохранённые значения
+    pub fn open(template: Option<String>, path: Option<PathBuf>) -> Result<Self> {
+        let mut counters = BTreeMap::new();
+
+        if let (Some(_), Some(path)) = (&template, &path)
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read name sequence {}", path.display()))?;
+            counters = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse name sequence {}", path.display()))?;
+            info!("Loaded {} name sequence counters", counters.len());
+        }
+
+        Ok(Self {
+            template,
+            counters: Counters::File {
+                path,
+                counters: tokio::sync::Mutex::new(counters),
+            },
+        })
+    }
+
+    /// Счётчики в Redis, общие для всех реплик; ключи тенанта отделены префиксом
+    pub fn redis(template: Option<String>, url: &str, tenant: &str) -> Result<Self> {
+        Ok(Self {
+            template,
+            counters: Counters::Redis {
+                client: redis::Client::open(url).context("Invalid REDIS_URL")?,
+                conn: Box::default(),
+                prefix: format!("autoproduction:seq:{}:", tenant),
+            },
+        })
+    }
+
+    /// Следующий номер на дату `date`; `None` — нумерует МойСклад
+    pub async fn next(&self, date: NaiveDate) -> Option<String> {
+        let template = self.template.as_deref()?;
+        let (placeholder, width) = seq_placeholder(template)?;
+        let key = with_date(template, date);
+
+        let seq = match self.counters {
+            Counters::File { ref path, ref counters } => {
+                let mut counters = counters.lock().await;
+                let seq = counters.get(&key).copied().unwrap_or(0) + 1;
+                // Счётчики прошлых дат больше не понадобятся
+                counters.retain(|k, _| k == &key);
+                counters.insert(key.clone(), seq);
+
+                let (path, snapshot) = (path.clone(), counters.clone());
+                let persisted = tokio::task::spawn_blocking(move || {
+                    persist_json(path.as_deref(), &snapshot, "name sequence")
+                })
+                .await;
+                if let Err(e) = persisted {
+                    warn!("Failed to persist name sequence: {}", e);
+                }
+                seq
+            }
+            Counters::Redis { ref client, ref conn, ref prefix } => {
+                let expires = key != template;
+                match redis_incr(client, conn, &format!("{}{}", prefix, key), expires).await {
+                    Ok(seq) => seq,
+                    Err(e) => {
+                        // Номер из локального счётчика мог бы повторить номер другой реплики
+                        warn!("Name sequence is unavailable, Moysklad numbers the document: {:#}", e);
+                        return None;
+                    }
+                }
+            }
+        };
+
+        Some(key.replace(placeholder, &format!("{:0width$}", seq, width = width)))
+    }
+}
+
+/// Увеличить счётчик в Redis; счётчик с датой в ключе истекает через REDIS_COUNTER_TTL_SECS
+async fn redis_incr(
+    client: &redis::Client,
+    conn: &OnceCell<ConnectionManager>,
+    key: &str,
+    expires: bool,
+) -> Result<u64> {
+    let mut conn = conn
+        .get_or_try_init(|| ConnectionManager::new(client.clone()))
+        .await
+        .context("Failed to connect to Redis")?
+        .clone();
+
+    let seq: u64 = redis::cmd("INCR")
+        .arg(key)
+        .query_async(&mut conn)
+        .await
+        .with_context(|| format!("Failed to increment {}", key))?;
+    if expires {
+        let _: bool = redis::cmd("EXPIRE")
+            .arg(key)
+            .arg(REDIS_COUNTER_TTL_SECS)
+            .query_async(&mut conn)
+            .await
+            .with_context(|| format!("Failed to set expiry of {}", key))?;
+    }
+    Ok(seq)
+}
@@ -0,0 +1,75 @@
+//! Накладные расходы в сумме тех. операции (OVERHEAD_AMOUNT, OVERHEAD_PERCENT)
+
+use crate::config::Settings;
+use crate::models::{decimal, round_kopecks, Decimal};
+
+/// Как фиксированная сумма накладных относится к тех. операции
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverheadDistribution {
+    /// Сумма на каждую тех. операцию
+    Document,
+    /// Сумма на единицу произведённого товара
+    Unit,
+}
+
+impl OverheadDistribution {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "document" => Some(Self::Document),
+            "unit" => Some(Self::Unit),
+            _ => None,
+        }
+    }
+}
+
+/// Накладные расходы, добавляемые к сумме тех. операции
+#[derive(Debug, Clone)]
+pub struct Overhead {
+    /// Фиксированная сумма, копейки
+    amount: Decimal,
+    /// Процент от стоимости материалов по закупочным ценам
+    percent: Decimal,
+    distribution: OverheadDistribution,
+    expense_item: Option<String>,
+}
+
+impl Overhead {
+    /// Накладные из настроек; `None`, если сумма и процент не заданы
+    pub fn from_settings(settings: &Settings, distribution: OverheadDistribution) -> Option<Self> {
+        let amount = decimal(settings.overhead_amount.max(0.0)) * Decimal::ONE_HUNDRED;
+        let percent = decimal(settings.overhead_percent.max(0.0));
+        if amount.is_zero() && percent.is_zero() {
+            return None;
+        }
+
+        Some(Self {
+            amount,
+            percent,
+            distribution,
+            expense_item: settings.overhead_expense_item.clone(),
+        })
+    }
+
+    /// Нужна ли стоимость материалов для расчёта
+    pub fn needs_materials_cost(&self) -> bool {
+        self.percent > Decimal::ZERO
+    }
+
+    /// Накладные на `quantity` единиц при стоимости материалов `materials_cost`, копейки
+    pub fn sum(&self, quantity: Decimal, materials_cost: Decimal) -> Decimal {
+        let fixed = match self.distribution {
+            OverheadDistribution::Document => self.amount,
+            OverheadDistribution::Unit => self.amount * quantity,
+        };
+        round_kopecks(fixed + materials_cost * self.percent / Decimal::ONE_HUNDRED)
+    }
+
+    /// Строка для описания тех. операции
+    pub fn describe(&self, sum: Decimal) -> String {
+        let rubles = sum / Decimal::ONE_HUNDRED;
+        match &self.expense_item {
+            Some(item) => format!("Накладные расходы: {:.2} ₽ (статья расходов '{}')", rubles, item),
+            None => format!("Накладные расходы: {:.2} ₽", rubles),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Настройки отдельных товаров, переопределяющие атрибуты и общие настройки
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::info;
+
+use super::replenishment::ReplenishmentKind;
+use crate::config::persist_json;
+use crate::models::{decimal, round_quantity, Decimal};
+
+/// Переопределения для товара; незаданные поля берутся из атрибутов и общих настроек
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProductOverride {
+    /// Порог остатка вместо MIN_STOCK_THRESHOLD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    /// Пополнять до этого уровня, а не на количество позиции
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_level: Option<f64>,
+    /// Способ пополнения: `produce`, `move`, `purchase`, `notify_only`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    /// Название тех. карты вместо значения из карточки товара
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tech_card: Option<String>,
+    /// Не пополнять товар автоматически
+    #[serde(default)]
+    pub excluded: bool,
+    /// Потери материала в производстве, % (вместо поля WASTE_FIELD_NAME и WASTE_PERCENT)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waste_percent: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl ProductOverride {
+    /// Проверить значения; возвращает описание первой ошибки
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(threshold) = self.threshold
+            && (!threshold.is_finite() || threshold < 0.0)
+        {
+            return Err(format!("threshold must be a non-negative number, got {}", threshold));
+        }
+        if let Some(target) = self.target_level {
+            if !target.is_finite() || target < 0.0 {
+                return Err(format!("target_level must be a non-negative number, got {}", target));
+            }
+            if self.threshold.is_some_and(|t| target < t) {
+                return Err("target_level must not be below threshold".to_string());
+            }
+        }
+        if let Some(waste) = self.waste_percent
+            && !(0.0..100.0).contains(&waste)
+        {
+            return Err(format!("waste_percent must be in [0, 100), got {}", waste));
+        }
+        if let Some(ref strategy) = self.strategy
+            && ReplenishmentKind::parse(strategy).is_none()
+        {
+            return Err(format!(
+                "Unknown strategy '{}', expected produce, move, purchase or notify_only",
+                strategy
+            ));
+        }
+        Ok(())
+    }
+
+    /// Способ пополнения, если задан
+    pub fn replenishment(&self) -> Option<ReplenishmentKind> {
+        self.strategy.as_deref().and_then(ReplenishmentKind::parse)
+    }
+
+    /// Количество к пополнению: до целевого уровня, но не меньше количества позиции
+    pub fn replenish_quantity(&self, quantity: Decimal, stock: Decimal) -> Decimal {
+        match self.target_level {
+            Some(target) => quantity.max(round_quantity(decimal(target) - stock)),
+            None => quantity,
+        }
+    }
+}
+
+/// Персистентный справочник переопределений по ID товара
+pub struct ProductOverrides {
+    path: Option<PathBuf>,
+    items: Mutex<BTreeMap<String, ProductOverride>>,
+}
+
+impl ProductOverrides {
+    /// Открыть справочник, восстановив сохранённые записи
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut items = BTreeMap::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read product overrides {}", path.display()))?;
+            items = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse product overrides {}", path.display()))?;
+            info!("Loaded overrides for {} products", items.len());
+        }
+
+        Ok(Self {
+            path,
+            items: Mutex::new(items),
+        })
+    }
+
+    /// Переопределения товара (пустые, если не заданы)
+    pub fn get(&self, product_id: &str) -> ProductOverride {
+        self.find(product_id).unwrap_or_default()
+    }
+
+    /// Переопределения товара, если они заданы
+    pub fn find(&self, product_id: &str) -> Option<ProductOverride> {
+        self.items
+            .lock()
+            .expect("product overrides lock poisoned")
+            .get(product_id)
+            .cloned()
+    }
+
+    /// Все переопределения
+    pub fn all(&self) -> BTreeMap<String, ProductOverride> {
+        self.items.lock().expect("product overrides lock poisoned").clone()
+    }
+
+    /// Заменить переопределения товара
+    pub fn set(&self, product_id: &str, mut value: ProductOverride) -> ProductOverride {
+        value.updated_at = Some(Utc::now());
+
+        let mut items = self.items.lock().expect("product overrides lock poisoned");
+        items.insert(product_id.to_string(), value.clone());
+        self.persist(&items);
+        value
+    }
+
+    /// Заменить переопределения нескольких товаров одной записью в файл
+    pub fn set_many(&self, values: Vec<(String, ProductOverride)>) {
+        let now = Utc::now();
+        let mut items = self.items.lock().expect("product overrides lock poisoned");
+        for (product_id, mut value) in values {
+            value.updated_at = Some(now);
+            items.insert(product_id, value);
+        }
+        self.persist(&items);
+    }
+
+    /// Удалить переопределения товара
+    pub fn remove(&self, product_id: &str) -> bool {
+        let mut items = self.items.lock().expect("product overrides lock poisoned");
+        let removed = items.remove(product_id).is_some();
+        if removed {
+            self.persist(&items);
+        }
+        removed
+    }
+
+    fn persist(&self, items: &BTreeMap<String, ProductOverride>) {
+        persist_json(self.path.as_deref(), items, "product overrides");
+    }
+}
+
+/// Колонки CSV с настройками товаров
+const CSV_HEADERS: [&str; 7] = [
+    "product_id",
+    "threshold",
+    "target_level",
+    "strategy",
+    "tech_card",
+    "excluded",
+    "waste_percent",
+];
+
+/// Ошибка в строке импортируемого CSV
+#[derive(Debug, Clone, Serialize)]
+pub struct CsvRowError {
+    /// Номер строки файла (заголовок — строка 1)
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<String>,
+    pub message: String,
+}
+
+/// Выгрузить настройки товаров в CSV (разделитель `;`, как в выгрузке истории)
+pub fn export_overrides_csv(items: &BTreeMap<String, ProductOverride>) -> Result<Vec<u8>> {
+    // BOM, чтобы Excel корректно открывал кириллицу
+    let mut buffer = "\u{feff}".as_bytes().to_vec();
+    {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b';')
+            .from_writer(&mut buffer);
+
+        writer.write_record(CSV_HEADERS)?;
+        for (product_id, item) in items {
+            writer.write_record([
+                product_id.clone(),
+                item.threshold.map(|v| v.to_string()).unwrap_or_default(),
+                item.target_level.map(|v| v.to_string()).unwrap_or_default(),
+                item.strategy.clone().unwrap_or_default(),
+                item.tech_card.clone().unwrap_or_default(),
+                if item.excluded { "да" } else { "" }.to_string(),
+                item.waste_percent.map(|v| v.to_string()).unwrap_or_default(),
+            ])?;
+        }
+        writer.flush().context("Failed to write CSV")?;
+    }
+
+    Ok(buffer)
+}
+
+/// Настройки товара из строки CSV: ID товара и настройки
+pub type ParsedOverride = (String, ProductOverride);
+
+/// Разобрать CSV с настройками товаров. Разделитель `;` или `,` определяется по заголовку,
+/// колонки — по именам из заголовка. Строки с ошибками возвращаются отдельно.
+pub fn parse_overrides_csv(data: &[u8]) -> Result<(Vec<ParsedOverride>, Vec<CsvRowError>)> {
+    let text = std::str::from_utf8(data).context("CSV must be UTF-8")?;
+    let text = text.trim_start_matches('\u{feff}');
+    let header_line = text.lines().next().unwrap_or_default();
+    let delimiter = if header_line.contains(';') { b';' } else { b',' };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+
+    let headers: Vec<String> = reader
+        .headers()
+        .context("Failed to read CSV header")?
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+    let column = |name: &str| headers.iter().position(|h| h == name);
+    let Some(id_column) = column("product_id") else {
+        anyhow::bail!("CSV header must contain a product_id column");
+    };
+    let columns: Vec<Option<usize>> = CSV_HEADERS.iter().map(|&h| column(h)).collect();
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let line = index + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(CsvRowError { line, product_id: None, message: e.to_string() });
+                continue;
+            }
+        };
+
+        let product_id = record.get(id_column).unwrap_or_default().to_string();
+        let values: Vec<Option<&str>> = columns
+            .iter()
+            .map(|c| c.and_then(|c| record.get(c)).filter(|v| !v.is_empty()))
+            .collect();
+
+        let parsed = if uuid::Uuid::parse_str(&product_id).is_err() {
+            Err("product_id must be a Moysklad entity UUID".to_string())
+        } else {
+            row_override(&values)
+        };
+
+        match parsed {
+            Ok(item) => rows.push((product_id, item)),
+            Err(message) => errors.push(CsvRowError {
+                line,
+                product_id: Some(product_id).filter(|id| !id.is_empty()),
+                message,
+            }),
+        }
+    }
+
+    Ok((rows, errors))
+}
+
+/// Настройки из значений строки в порядке CSV_HEADERS
+fn row_override(values: &[Option<&str>]) -> Result<ProductOverride, String> {
+    let item = ProductOverride {
+        threshold: values[1].map(|v| parse_number("threshold", v)).transpose()?,
+        target_level: values[2].map(|v| parse_number("target_level", v)).transpose()?,
+        strategy: values[3].map(str::to_string),
+        tech_card: values[4].map(str::to_string),
+        excluded: values[5].map(|v| parse_flag("excluded", v)).transpose()?.unwrap_or(false),
+        waste_percent: values[6].map(|v| parse_number("waste_percent", v)).transpose()?,
+        updated_at: None,
+    };
+    item.validate()?;
+    Ok(item)
+}
+
+/// Число из CSV; допускается десятичная запятая
+fn parse_number(field: &str, value: &str) -> Result<f64, String> {
+    value
+        .replace(',', ".")
+        .parse()
+        .map_err(|_| format!("{} must be a number, got '{}'", field, value))
+}
+
+fn parse_flag(field: &str, value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "да" | "+" => Ok(true),
+        "0" | "false" | "no" | "нет" | "-" => Ok(false),
+        _ => Err(format!("{} must be true or false, got '{}'", field, value)),
+    }
+}
@@ -0,0 +1,108 @@
+//! Кэш найденных тех. карт и потерь материалов
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::ProcessingPlan;
+
+/// Найденные тех. карты по товару и названию тех. карты (все подходящие, сначала новые).
+/// Запись живёт TTL или до webhook об изменении тех. карты, чтобы правки материалов
+/// в МойСклад подхватывались без перезапуска.
+pub struct PlanCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Vec<ProcessingPlan>)>>,
+    /// Значение поля WASTE_FIELD_NAME по ID материала (None — поле не заполнено)
+    waste: Mutex<HashMap<String, (Instant, Option<f64>)>>,
+}
+
+impl PlanCache {
+    /// Создать кэш; нулевой TTL отключает кэширование
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            waste: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Тех. карты товара, если они найдены в пределах TTL
+    pub fn get(&self, product_id: &str, tech_card_name: &str) -> Option<Vec<ProcessingPlan>> {
+        if !self.enabled() {
+            return None;
+        }
+
+        self.entries
+            .lock()
+            .expect("plan cache lock poisoned")
+            .get(&cache_key(product_id, tech_card_name))
+            .filter(|(found, _)| found.elapsed() < self.ttl)
+            .map(|(_, plans)| plans.clone())
+    }
+
+    /// Запомнить найденные тех. карты
+    pub fn insert(&self, product_id: &str, tech_card_name: &str, plans: &[ProcessingPlan]) {
+        if !self.enabled() {
+            return;
+        }
+
+        let ttl = self.ttl;
+        let mut entries = self.entries.lock().expect("plan cache lock poisoned");
+        entries.retain(|_, (found, _)| found.elapsed() < ttl);
+        entries.insert(cache_key(product_id, tech_card_name), (Instant::now(), plans.to_vec()));
+    }
+
+    /// Забыть записи с тех. картой `plan_id` (webhook processingplan); возвращает число записей
+    pub fn invalidate(&self, plan_id: &str) -> usize {
+        let mut entries = self.entries.lock().expect("plan cache lock poisoned");
+        let before = entries.len();
+        entries.retain(|_, (_, plans)| plans.iter().all(|plan| plan.id != plan_id));
+        before - entries.len()
+    }
+
+    /// Процент потерь материала из его поля, если он прочитан в пределах TTL
+    pub fn waste_percent(&self, material_id: &str) -> Option<Option<f64>> {
+        if !self.enabled() {
+            return None;
+        }
+
+        self.waste
+            .lock()
+            .expect("plan cache lock poisoned")
+            .get(material_id)
+            .filter(|(found, _)| found.elapsed() < self.ttl)
+            .map(|(_, percent)| *percent)
+    }
+
+    /// Запомнить процент потерь материала из его поля
+    pub fn insert_waste_percent(&self, material_id: &str, percent: Option<f64>) {
+        if !self.enabled() {
+            return;
+        }
+
+        let ttl = self.ttl;
+        let mut waste = self.waste.lock().expect("plan cache lock poisoned");
+        waste.retain(|_, (found, _)| found.elapsed() < ttl);
+        waste.insert(material_id.to_string(), (Instant::now(), percent));
+    }
+
+    /// Число тех. карт в кэше
+    pub fn count(&self) -> usize {
+        let ttl = self.ttl;
+        self.entries
+            .lock()
+            .expect("plan cache lock poisoned")
+            .values()
+            .filter(|(found, _)| found.elapsed() < ttl)
+            .map(|(_, plans)| plans.len())
+            .sum()
+    }
+}
+
+fn cache_key(product_id: &str, tech_card_name: &str) -> String {
+    format!("{}/{}", product_id, tech_card_name)
+}
@@ -0,0 +1,129 @@
+//! Отпечатки обработанных заказов: повторное проведение без изменений не вызывает производство
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::info;
+
+use crate::config::persist_json;
+use crate::models::{CustomerOrder, CustomerOrderPosition, Decimal};
+
+/// Состояние обработанного заказа
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderSnapshot {
+    pub order_id: String,
+    /// Отпечаток позиций при последней полностью успешной обработке
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// Обработанное количество по позициям
+    pub positions: BTreeMap<String, Decimal>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Ключ позиции: ID позиции, иначе ссылка на ассортимент
+pub fn position_key(position: &CustomerOrderPosition) -> String {
+    position
+        .id
+        .clone()
+        .unwrap_or_else(|| position.assortment.meta.href.clone())
+}
+
+/// Отпечаток заказа: SHA-256 отсортированных пар «позиция:количество»
+pub fn order_fingerprint(order: &CustomerOrder) -> String {
+    let mut lines: Vec<String> = order
+        .positions
+        .iter()
+        .flat_map(|p| p.rows.iter())
+        .map(|p| format!("{}:{}", position_key(p), p.quantity))
+        .collect();
+    lines.sort();
+
+    let mut hasher = Sha256::new();
+    hasher.update(order.id.as_bytes());
+    for line in &lines {
+        hasher.update(b"\n");
+        hasher.update(line.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Персистентный реестр обработанных заказов
+pub struct ProcessedOrders {
+    path: Option<PathBuf>,
+    orders: Mutex<BTreeMap<String, OrderSnapshot>>,
+}
+
+impl ProcessedOrders {
+    /// Открыть реестр, восстановив сохранённые записи
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut orders = BTreeMap::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read processed orders {}", path.display()))?;
+            let list: Vec<OrderSnapshot> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse processed orders {}", path.display()))?;
+            for snapshot in list {
+                orders.insert(snapshot.order_id.clone(), snapshot);
+            }
+            info!("Restored {} processed orders", orders.len());
+        }
+
+        Ok(Self {
+            path,
+            orders: Mutex::new(orders),
+        })
+    }
+
+    /// Состояние заказа
+    pub fn get(&self, order_id: &str) -> Option<OrderSnapshot> {
+        self.orders
+            .lock()
+            .expect("processed orders lock poisoned")
+            .get(order_id)
+            .cloned()
+    }
+
+    /// Сохранить обработанные позиции; отпечаток — только если обработаны все
+    pub fn record(&self, order_id: &str, fingerprint: Option<String>, positions: Vec<(String, Decimal)>) {
+        let mut orders = self.orders.lock().expect("processed orders lock poisoned");
+
+        let snapshot = orders
+            .entry(order_id.to_string())
+            .or_insert_with(|| OrderSnapshot {
+                order_id: order_id.to_string(),
+                fingerprint: None,
+                positions: BTreeMap::new(),
+                updated_at: Utc::now(),
+            });
+        snapshot.fingerprint = fingerprint;
+        snapshot.positions.extend(positions);
+        snapshot.updated_at = Utc::now();
+
+        self.persist(&orders);
+    }
+
+    /// Количество заказов в реестре
+    pub fn len(&self) -> usize {
+        self.orders.lock().expect("processed orders lock poisoned").len()
+    }
+
+    /// Забыть заказ (его тех. операции отменены)
+    pub fn remove(&self, order_id: &str) {
+        let mut orders = self.orders.lock().expect("processed orders lock poisoned");
+        if orders.remove(order_id).is_some() {
+            self.persist(&orders);
+        }
+    }
+
+    fn persist(&self, orders: &BTreeMap<String, OrderSnapshot>) {
+        let list: Vec<&OrderSnapshot> = orders.values().collect();
+        persist_json(self.path.as_deref(), &list, "processed orders");
+    }
+}
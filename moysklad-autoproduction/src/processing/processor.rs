@@ -1,430 +1,3954 @@
 //! Обработчик заказов покупателей и создание тех. операций
 
-use crate::api::MoyskladClient;
-use crate::config::Settings;
+use crate::api::{
+    is_not_found, is_transient_error, mark_write_timeout, ApiError, ApiUsage, CircuitBreaker, MoyskladClient,
+    Playback, RecordedResponse, RecordedWrite,
+};
+use crate::config::{Settings, HANDLED_ENTITY_TYPES, STOCK_CORRECTION_TYPES};
+use crate::history::{AuditLog, HistoryRecord, HistoryStore};
 use crate::models::*;
+use crate::notifications::{
+    Notification, NotificationEvent, NotificationRouter, OutgoingPayload, OutgoingWebhook,
+};
+use crate::queue::{PriorityInput, RetryQueue, ShortageEntry, ShortageQueue};
+use crate::reports::{
+    MappingHealthReport, MappingIssue, MappingIssueKind, MaterialUsage, MaterialsDemandReport,
+    ProductDemand, StockForecast,
+};
+use super::drift::{renamed, webhook_changes, WebhookBaseline};
+use super::dynamic_threshold::DynamicThresholds;
+use super::error::{PositionError, StageExt, StageTrace};
+use super::folder_map::FolderTechCards;
+use super::in_progress::InProgressRegistry;
+use super::lock::Locks;
+use super::numbering::NameSequence;
+use super::overhead::{Overhead, OverheadDistribution};
+use super::overrides::ProductOverrides;
+use super::plan_cache::PlanCache;
+use super::processed::{order_fingerprint, position_key, OrderSnapshot, ProcessedOrders};
+use super::replenishment::ReplenishmentKind;
+use super::schedule::WorkSchedule;
+use super::skip_stats::SkipStats;
+use super::source_report::{is_last_report, report_filename, report_text, with_report, SourceReportMode};
+use super::stock_cache::StockCache;
+use super::strategy::{Demand, ReplenishRequest, StrategySet};
+use super::substitutes::MaterialSubstitutes;
+use super::tasks::CreatedTasks;
+use super::tech_card::{
+    parse_lookups, parse_sources, plan_version, split_names, PlanLookup, TechCardSource,
+};
+use super::unit_conversion::UnitConversion;
 use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use std::time::Duration;
 use tracing::{debug, error, info, warn};
 
+/// Пауза перед повторным поиском сотрудника TASK_ASSIGNEE после неудачи
+const TASK_ASSIGNEE_RETRY: Duration = Duration::from_secs(600);
+
+/// Прогноз учитывает просроченные заказы с плановой отгрузкой не раньше стольких дней назад
+const FORECAST_OVERDUE_DAYS: i64 = 30;
+
+/// Выполнить этап обработки позиции с таймаутом и повторами из настроек этапа.
+/// Вызов повторяется только при временной ошибке или таймауте.
+///
+/// `staged!(write ...)` — создание или проведение документа: после таймаута документ
+/// мог быть создан, поэтому такой вызов не повторяется, а ошибка не считается временной.
+macro_rules! staged {
+    ($self:ident, $stage:expr, $call:expr) => {
+        staged!(@run $self, $stage, $call, false)
+    };
+    (write $self:ident, $stage:expr, $call:expr) => {
+        staged!(@run $self, $stage, $call, true)
+    };
+    (@run $self:ident, $stage:expr, $call:expr, $write:expr) => {{
+        let stage: PositionStage = $stage;
+        let (limit, retries) = $self.stage_limits(stage);
+        let mut attempt = 0;
+        loop {
+            let started = std::time::Instant::now();
+            let result = match limit {
+                Some(limit) => tokio::time::timeout(limit, $call)
+                    .await
+                    .unwrap_or_else(|_| Err(ApiError::Timeout { secs: limit.as_secs() }.into())),
+                None => $call.await,
+            };
+            let result = if $write { result.map_err(mark_write_timeout) } else { result };
+            $self.trace_stage(stage, attempt, started, &result);
+            match result {
+                Err(e) if attempt < retries && is_transient_error(&e) => {
+                    attempt += 1;
+                    warn!(
+                        "Stage {} failed, retry {} of {}: {:#}",
+                        stage.as_str(),
+                        attempt,
+                        retries,
+                        e
+                    );
+                }
+                result => break result.stage(stage),
+            }
+        }
+    }};
+}
+
+/// Результаты обработки позиций документа
+struct PositionResults {
+    results: Vec<ProcessingResult>,
+    /// Количество по позиции (ключ position_key), на которое уже запущено пополнение.
+    /// Уменьшение количества не отменяет запущенное производство, поэтому не меньше прошлого.
+    covered: Vec<(String, Decimal)>,
+}
+
+/// Документ пополнения, созданный для заказа и ещё не отменённый
+struct CreatedDocument {
+    id: String,
+    name: String,
+    /// Сущность МойСклад: `processing`, `move`, `enter` или `purchaseorder`
+    entity_type: String,
+}
+
+/// Данные для выборок по всему каталогу без блокировки процессора
+struct CatalogueContext {
+    client: Arc<MoyskladClient>,
+    store: EntityRef,
+    attribute: AttributeMetadata,
+    stock_mode: StockMode,
+    velocity_days: u32,
+    utc_offset_hours: i32,
+}
+
+/// Что произошло при воспроизведении события
+#[derive(Debug, Default, serde::Serialize)]
+pub struct PlaybackTrace {
+    /// Ответы на GET запросы — из записи или прочитанные из МойСклад
+    pub api_calls: Vec<RecordedResponse>,
+    /// Изменения, которые обработка отправила бы в МойСклад
+    pub writes: Vec<RecordedWrite>,
+    /// Попытки этапов обработки позиций
+    pub stages: Vec<StageTrace>,
+}
+
+/// Результат пробной обработки позиции и изменения её документа, которые были бы
+/// отправлены в МойСклад
+#[derive(Debug, serde::Serialize)]
+pub struct PositionSimulation {
+    #[serde(flatten)]
+    pub result: ProcessingResult,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub writes: Vec<RecordedWrite>,
+}
+
+/// Результат пробной обработки заказа покупателя (без записи в МойСклад)
+#[derive(Debug, serde::Serialize)]
+pub struct OrderSimulation {
+    pub order_id: String,
+    pub positions: Vec<PositionSimulation>,
+    /// Изменения, не относящиеся к документам позиций (например, комментарий заказа)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub writes: Vec<RecordedWrite>,
+}
+
 /// Процессор обработки заказов покупателей
 pub struct OrderProcessor {
-    client: MoyskladClient,
+    client: Arc<MoyskladClient>,
+    breaker: Arc<CircuitBreaker>,
+    usage: Arc<ApiUsage>,
+    skip_stats: Arc<SkipStats>,
     settings: Settings,
+    notifier: Arc<NotificationRouter>,
+    history: Arc<HistoryStore>,
+    processed: Arc<ProcessedOrders>,
+    overrides: Arc<ProductOverrides>,
+    shortages: Arc<ShortageQueue>,
+    retry_queue: Arc<RetryQueue>,
+    names: Arc<NameSequence>,
+    schedule: Option<WorkSchedule>,
+    outgoing: Option<Arc<OutgoingWebhook>>,
     store_cache: Option<EntityRef>,
     organization_cache: Option<EntityRef>,
+    project_cache: Option<EntityRef>,
+    source_store_cache: Option<EntityRef>,
+    supplier_cache: Option<EntityRef>,
+    task_assignee_cache: Option<EntityRef>,
+    /// Когда сотрудника TASK_ASSIGNEE не удалось найти: до повтора задачи не ставятся
+    task_assignee_failed_at: Option<std::time::Instant>,
+    /// Позиции документов, по которым уже поставлена задача
+    tasks_created: Arc<CreatedTasks>,
+    production_uom_cache: Option<EntityRef>,
+    production_log_cache: Option<String>,
+    tech_card_attribute_cache: Option<AttributeMetadata>,
+    plan_cache: Arc<PlanCache>,
+    tech_card_sources: Vec<TechCardSource>,
+    folder_tech_cards: FolderTechCards,
+    substitutes: MaterialSubstitutes,
+    plan_lookups: Vec<PlanLookup>,
+    in_progress: InProgressRegistry,
+    dynamic_thresholds: DynamicThresholds,
+    stock_cache: Arc<StockCache>,
+    locks: Locks,
+    default_replenishment: ReplenishmentKind,
+    stock_mode: StockMode,
+    quantity_basis: QuantityBasis,
+    overhead: Option<Overhead>,
+    source_report: SourceReportMode,
+    task_reasons: Vec<SkipReason>,
+    strategies: StrategySet,
+    /// Попытки этапов при воспроизведении события (только у процессора `sandbox`)
+    stage_trace: Option<std::sync::Mutex<Vec<StageTrace>>>,
+}
+
+/// Персистентные хранилища тенанта, с которыми работает процессор
+pub struct ProcessorStores {
+    pub history: Arc<HistoryStore>,
+    pub audit: Arc<AuditLog>,
+    pub processed: Arc<ProcessedOrders>,
+    pub overrides: Arc<ProductOverrides>,
+    pub shortages: Arc<ShortageQueue>,
+    pub retry_queue: Arc<RetryQueue>,
+    pub names: Arc<NameSequence>,
+    pub tasks: Arc<CreatedTasks>,
 }
 
 impl OrderProcessor {
     /// Создать новый процессор
-    pub fn new(settings: Settings) -> Self {
-        let token = settings.moysklad_token.clone();
-        let client = MoyskladClient::new(token);
+    pub fn new(
+        settings: Settings,
+        notifier: Arc<NotificationRouter>,
+        stores: ProcessorStores,
+        folder_tech_cards: FolderTechCards,
+        substitutes: MaterialSubstitutes,
+        schedule: Option<WorkSchedule>,
+        locks: Locks,
+    ) -> Self {
+        let ProcessorStores { history, audit, processed, overrides, shortages, retry_queue, names, tasks } =
+            stores;
+        let breaker = Arc::new(CircuitBreaker::new(
+            settings.circuit_breaker_threshold,
+            std::time::Duration::from_secs(settings.circuit_breaker_cooldown_secs),
+        ));
+        let usage = Arc::new(ApiUsage::new());
+        let client = Arc::new(MoyskladClient::new(
+            &settings,
+            breaker.clone(),
+            usage.clone(),
+            Some(audit),
+        ));
+        let outgoing = settings.outgoing_webhook_url.clone().map(|url| {
+            Arc::new(OutgoingWebhook::new(
+                url,
+                settings.outgoing_webhook_secret.clone(),
+                settings.outgoing_webhook_retries,
+            ))
+        });
+
+        let (tech_card_sources, unknown) = parse_sources(&settings.tech_card_fallbacks);
+        if !unknown.is_empty() {
+            warn!("Unknown tech card fallback sources ignored: {}", unknown.join(", "));
+        }
+
+        let stock_mode = StockMode::parse(&settings.stock_mode).unwrap_or_else(|| {
+            warn!("Unknown stock mode '{}', using available", settings.stock_mode);
+            StockMode::Available
+        });
+
+        let quantity_basis = QuantityBasis::parse(&settings.quantity_basis).unwrap_or_else(|| {
+            warn!("Unknown quantity basis '{}', using ordered", settings.quantity_basis);
+            QuantityBasis::Ordered
+        });
+
+        let overhead_distribution = OverheadDistribution::parse(&settings.overhead_distribution)
+            .unwrap_or_else(|| {
+                warn!(
+                    "Unknown overhead distribution '{}', using document",
+                    settings.overhead_distribution
+                );
+                OverheadDistribution::Document
+            });
+        let overhead = Overhead::from_settings(&settings, overhead_distribution);
+
+        let source_report = SourceReportMode::parse(&settings.source_report).unwrap_or_else(|| {
+            warn!("Unknown source report mode '{}', using off", settings.source_report);
+            SourceReportMode::Off
+        });
+
+        let task_reasons = settings
+            .task_reasons
+            .iter()
+            .filter_map(|reason| {
+                let parsed = SkipReason::parse(reason);
+                if parsed.is_none() {
+                    warn!("Unknown task reason '{}' ignored", reason);
+                }
+                parsed
+            })
+            .collect();
+
+        let (plan_lookups, unknown) = parse_lookups(&settings.plan_lookup_mode);
+        if !unknown.is_empty() {
+            warn!("Unknown plan lookup modes ignored: {}", unknown.join(", "));
+        }
+        info!(
+            "Processing plan lookup: {}",
+            plan_lookups.iter().map(|l| l.as_str()).collect::<Vec<_>>().join(" -> ")
+        );
+
+        let default_replenishment = settings
+            .replenishment_strategy
+            .as_deref()
+            .map(|s| {
+                ReplenishmentKind::parse(s).unwrap_or_else(|| {
+                    warn!("Unknown replenishment strategy '{}', using produce", s);
+                    ReplenishmentKind::Produce
+                })
+            })
+            .unwrap_or(ReplenishmentKind::Produce);
+
+        let in_progress = InProgressRegistry::new(std::time::Duration::from_secs(
+            settings.production_dedup_ttl_secs,
+        ));
+        let plan_cache = Arc::new(PlanCache::new(Duration::from_secs(settings.plan_cache_ttl_secs)));
+        let stock_cache =
+            Arc::new(StockCache::new(Duration::from_secs(settings.stock_cache_refresh_secs)));
 
         Self {
             client,
+            breaker,
+            usage,
+            skip_stats: Arc::new(SkipStats::new()),
             settings,
+            notifier,
+            history,
+            processed,
+            overrides,
+            shortages,
+            retry_queue,
+            names,
+            schedule,
+            outgoing,
             store_cache: None,
             organization_cache: None,
+            project_cache: None,
+            source_store_cache: None,
+            supplier_cache: None,
+            task_assignee_cache: None,
+            task_assignee_failed_at: None,
+            tasks_created: tasks,
+            production_uom_cache: None,
+            production_log_cache: None,
+            tech_card_attribute_cache: None,
+            plan_cache,
+            tech_card_sources,
+            folder_tech_cards,
+            substitutes,
+            plan_lookups,
+            in_progress,
+            dynamic_thresholds: DynamicThresholds::default(),
+            stock_cache,
+            locks,
+            default_replenishment,
+            stock_mode,
+            quantity_basis,
+            overhead,
+            source_report,
+            task_reasons,
+            strategies: StrategySet::standard(),
+            stage_trace: None,
         }
     }
 
-    /// Получить кэшированный склад
-    async fn get_store(&mut self) -> Result<EntityRef> {
-        if let Some(ref store) = self.store_cache {
+    /// Процессор для воспроизведения с теми же настройками: запросы к МойСклад обслуживает
+    /// `playback`, изменения не отправляются. История, очереди, нумерация, блокировки
+    /// и уведомления — отдельные, в памяти, поэтому состояние тенанта не меняется.
+    /// Попытки этапов записываются.
+    pub fn sandbox(&self, playback: Playback) -> Result<Self> {
+        let mut settings = self.settings.clone();
+        settings.history_file = None;
+        settings.audit_file = None;
+        settings.processed_orders_file = None;
+        settings.name_sequence_file = None;
+        settings.retry_queue_file = None;
+        settings.dead_letter_file = None;
+        settings.shortage_queue_file = None;
+        settings.tasks_file = None;
+        settings.outgoing_webhook_url = None;
+        settings.telegram_bot_token = None;
+        settings.smtp_host = None;
+        settings.notify_http_url = None;
+        settings.notify_routes = String::new();
+        settings.lock_provider = "memory".to_string();
+
+        let notifier = Arc::new(NotificationRouter::from_settings(&settings));
+        let stores = ProcessorStores {
+            history: Arc::new(HistoryStore::open(None)?),
+            audit: Arc::new(AuditLog::open(None)?),
+            processed: Arc::new(ProcessedOrders::open(None)?),
+            overrides: self.overrides.clone(),
+            shortages: Arc::new(ShortageQueue::open(None)?),
+            retry_queue: Arc::new(RetryQueue::open(
+                None,
+                settings.retry_base_delay_secs,
+                settings.retry_max_delay_secs,
+                settings.retry_max_attempts,
+            )?),
+            names: Arc::new(NameSequence::open(settings.name_template.clone(), None)?),
+            tasks: Arc::new(CreatedTasks::open(None)?),
+        };
+        let locks = Locks::from_settings(&settings, "playback")?;
+
+        let mut sandbox = Self::new(
+            settings,
+            notifier,
+            stores,
+            self.folder_tech_cards.clone(),
+            self.substitutes.clone(),
+            self.schedule.clone(),
+            locks,
+        );
+        sandbox.client = Arc::new(self.client.playback(
+            sandbox.breaker.clone(),
+            sandbox.usage.clone(),
+            playback,
+        ));
+        sandbox.dynamic_thresholds = self.dynamic_thresholds.clone();
+        sandbox.stage_trace = Some(std::sync::Mutex::new(Vec::new()));
+        Ok(sandbox)
+    }
+
+    /// Записать попытку этапа при воспроизведении
+    fn trace_stage<T, E: std::fmt::Display>(
+        &self,
+        stage: PositionStage,
+        attempt: u32,
+        started: std::time::Instant,
+        result: &std::result::Result<T, E>,
+    ) {
+        if let Some(ref trace) = self.stage_trace {
+            trace.lock().expect("stage trace lock poisoned").push(StageTrace {
+                stage,
+                attempt,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+                error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            });
+        }
+    }
+
+    /// Ответы API, изменения и попытки этапов, записанные процессором `sandbox`
+    pub fn playback_trace(&self) -> PlaybackTrace {
+        PlaybackTrace {
+            api_calls: self.client.take_recording(),
+            writes: self.client.playback_writes(),
+            stages: self
+                .stage_trace
+                .as_ref()
+                .map(|trace| std::mem::take(&mut *trace.lock().expect("stage trace lock poisoned")))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Получить кэшированный склад-источник перемещений
+    async fn get_source_store(&mut self) -> Result<EntityRef> {
+        if let Some(ref store) = self.source_store_cache {
             return Ok(store.clone());
         }
 
+        let name = self
+            .settings
+            .move_source_store_name
+            .clone()
+            .ok_or_else(|| anyhow!("MOVE_SOURCE_STORE_NAME is not set"))?;
         let store = self
             .client
-            .find_store_by_name(&self.settings.store_name)
+            .find_store_by_name(&name)
+            .await?
+            .ok_or_else(|| anyhow!("Source store '{}' not found", name))?;
+
+        info!("Found source store: {:?}", store.name);
+        self.source_store_cache = Some(store.clone());
+        Ok(store)
+    }
+
+    /// Записать созданную тех. операцию в справочник МойСклад (PRODUCTION_LOG_ENTITY).
+    /// Ошибка записи не прерывает обработку заказа.
+    async fn log_production(
+        &mut self,
+        order: &CustomerOrder,
+        processing_name: &str,
+        product_name: &str,
+        quantity: Decimal,
+    ) {
+        let Some(entity_name) = self.settings.production_log_entity.clone() else {
+            return;
+        };
+
+        let entity_id = match self.production_log_cache {
+            Some(ref id) => id.clone(),
+            None => match self.client.find_custom_entity(&entity_name).await {
+                Ok(Some(id)) => {
+                    self.production_log_cache = Some(id.clone());
+                    id
+                }
+                Ok(None) => {
+                    warn!("Custom entity '{}' not found, production not logged", entity_name);
+                    return;
+                }
+                Err(e) => {
+                    warn!("Failed to find custom entity '{}': {:#}", entity_name, e);
+                    return;
+                }
+            },
+        };
+
+        let request = CreateCustomEntityElementRequest {
+            name: format!("{} — {} x{}", order.name, product_name, quantity),
+            code: Some(processing_name.to_string()),
+            description: Some(format!(
+                "Заказ: {}\nТовар: {}\nКоличество: {}\nТех. операция: {}\nВремя: {}",
+                order.name,
+                product_name,
+                quantity,
+                processing_name,
+                chrono::Utc::now().to_rfc3339()
+            )),
+        };
+
+        match self.client.create_custom_entity_element(&entity_id, &request).await {
+            Ok(element) => debug!("Production {} logged to '{}' as {} ({})", processing_name, entity_name, element.name, element.id),
+            Err(e) => warn!("Failed to log production {} to '{}': {:#}", processing_name, entity_name, e),
+        }
+    }
+
+    /// Получить кэшированного поставщика для заказов поставщику
+    async fn get_supplier(&mut self) -> Result<EntityRef> {
+        if let Some(ref supplier) = self.supplier_cache {
+            return Ok(supplier.clone());
+        }
+
+        let name = self
+            .settings
+            .purchase_supplier_name
+            .clone()
+            .ok_or_else(|| anyhow!("PURCHASE_SUPPLIER_NAME is not set"))?;
+        let supplier = self
+            .client
+            .find_counterparty_by_name(&name)
             .await?
-            .ok_or_else(|| anyhow!("Store '{}' not found", self.settings.store_name))?;
+            .ok_or_else(|| anyhow!("Supplier '{}' not found", name))?;
+
+        info!("Found supplier: {:?}", supplier.name);
+        self.supplier_cache = Some(supplier.clone());
+        Ok(supplier)
+    }
+
+    /// Получить кэшированного сотрудника TASK_ASSIGNEE. После неудачного поиска
+    /// сотрудник не запрашивается TASK_ASSIGNEE_RETRY.
+    async fn get_task_assignee(&mut self) -> Result<EntityRef> {
+        if let Some(ref assignee) = self.task_assignee_cache {
+            return Ok(assignee.clone());
+        }
+        if let Some(failed_at) = self.task_assignee_failed_at
+            && failed_at.elapsed() < TASK_ASSIGNEE_RETRY
+        {
+            return Err(anyhow!("Task assignee lookup failed recently"));
+        }
+
+        let value = self
+            .settings
+            .task_assignee
+            .clone()
+            .ok_or_else(|| anyhow!("TASK_ASSIGNEE is not set"))?;
+        let assignee = self
+            .client
+            .find_employee(&value)
+            .await
+            .and_then(|found| found.ok_or_else(|| anyhow!("Employee '{}' not found", value)))
+            .inspect_err(|_| self.task_assignee_failed_at = Some(std::time::Instant::now()))?;
+
+        info!("Found task assignee: {:?}", assignee.name);
+        self.task_assignee_cache = Some(assignee.clone());
+        self.task_assignee_failed_at = None;
+        Ok(assignee)
+    }
+
+    /// Получить кэшированный склад: по STORE_ID, иначе по STORE_NAME
+    async fn get_store(&mut self) -> Result<EntityRef> {
+        if let Some(ref store) = self.store_cache {
+            return Ok(store.clone());
+        }
+
+        let store = match self.settings.store_id {
+            Some(ref id) => self.client.get_store_by_id(id).await.map_err(|e| {
+                if is_not_found(&e) {
+                    anyhow!("Store {} from STORE_ID not found", id)
+                } else {
+                    e
+                }
+            })?,
+            None if self.settings.store_name.is_empty() => {
+                return Err(anyhow!("No store configured: set STORE_ID or STORE_NAME"));
+            }
+            None => self
+                .client
+                .find_store_by_name(&self.settings.store_name)
+                .await?
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Store '{}' not found: check STORE_NAME or set STORE_ID",
+                        self.settings.store_name
+                    )
+                })?,
+        };
 
         info!("Found store: {:?} ({:?})", store.name, store.id);
         self.store_cache = Some(store.clone());
         Ok(store)
     }
 
-    /// Получить кэшированную организацию
+    /// Получить кэшированную организацию: по ORGANIZATION_ID, иначе первую в аккаунте
     async fn get_organization(&mut self) -> Result<EntityRef> {
         if let Some(ref org) = self.organization_cache {
             return Ok(org.clone());
         }
 
-        let org = self
-            .client
-            .get_organization()
-            .await?
-            .ok_or_else(|| anyhow!("No organization found"))?;
+        let org = match self.settings.organization_id {
+            Some(ref id) => self.client.get_organization_by_id(id).await.map_err(|e| {
+                if is_not_found(&e) {
+                    anyhow!("Organization {} from ORGANIZATION_ID not found", id)
+                } else {
+                    e
+                }
+            })?,
+            None => self
+                .client
+                .get_organization()
+                .await?
+                .ok_or_else(|| anyhow!("No organization in the account: set ORGANIZATION_ID"))?,
+        };
 
         info!("Found organization: {:?} ({:?})", org.name, org.id);
         self.organization_cache = Some(org.clone());
         Ok(org)
     }
 
-    /// Обработать webhook событие
-    pub async fn process_webhook(&mut self, event: &WebhookEvent) -> Result<Vec<ProcessingResult>> {
-        info!(
-            "Processing webhook event: type={}, action={}",
-            event.entity_type, event.action
-        );
-
-        // Проверяем, что это событие заказа покупателя
-        if event.entity_type != "customerorder" {
-            debug!("Ignoring non-customerorder event: {}", event.entity_type);
-            return Ok(vec![]);
+    /// Найти и закэшировать проект PRODUCTION_PROJECT, если он задан
+    async fn resolve_production_project(&mut self) -> Result<()> {
+        if self.project_cache.is_some() {
+            return Ok(());
         }
-
-        // Получаем данные заказа
-        let order = if let Some(ref order) = event.entity {
-            order.clone()
-        } else if let Some(ref content) = event.content {
-            if let Some(ref id) = content.id {
-                self.client.get_customer_order(id).await?
-            } else {
-                return Err(anyhow!("No order ID in webhook content"));
-            }
-        } else {
-            return Err(anyhow!("No order data in webhook event"));
+        let Some(name) = self.settings.production_project.clone() else {
+            return Ok(());
         };
 
-        // Проверяем, что заказ проведён (подтверждён)
-        if !order.applicable {
-            info!("Order {} is not applicable, skipping", order.name);
-            return Ok(vec![ProcessingResult {
-                success: true,
-                message: "Заказ не проведён, пропускаем".to_string(),
-                order_id: Some(order.id.clone()),
-                order_name: Some(order.name.clone()),
-                processing_id: None,
-                processing_name: None,
-                product: None,
-                error: None,
-            }]);
-        }
+        let project = self
+            .client
+            .find_project_by_name(&name)
+            .await?
+            .ok_or_else(|| anyhow!("Project '{}' not found", name))?;
 
-        // Проверяем склад (если в заказе указан склад — сравниваем с настройкой)
+        info!("Found production project: {:?}", project.name);
+        self.project_cache = Some(project);
+        Ok(())
+    }
+
+    /// Прогноз остатков товара на отслеживаемом складе на `days` дней вперёд
+    pub async fn forecast(&mut self, product_id: &str, days: u32) -> Result<StockForecast> {
         let store = self.get_store().await?;
-        if let Some(ref order_store) = order.store {
-            let order_store_id = order_store.id.as_ref().ok_or_else(|| anyhow!("Order store ID missing"))?;
-            let cached_store_id = store.id.as_ref().ok_or_else(|| anyhow!("Cached store ID missing"))?;
+        let store_id = store.id.as_ref().ok_or_else(|| anyhow!("Store ID missing"))?;
 
-            if order_store_id != cached_store_id {
-                info!(
-                    "Order store '{:?}' doesn't match monitored store '{:?}', skipping",
-                    order_store.name, store.name
-                );
-                return Ok(vec![ProcessingResult {
-                    success: true,
-                    message: format!("Заказ с другого склада ({:?})", order_store.name),
-                    order_id: Some(order.id.clone()),
-                    order_name: Some(order.name.clone()),
-                    processing_id: None,
-                    processing_name: None,
-                    product: None,
-                    error: None,
-                }]);
-            }
-        }
+        let product = self.client.get_product(product_id).await?;
+        let stock_info = self.client.get_product_stock_info(product_id, store_id).await?;
+        let today = account_now(self.settings.moysklad_utc_offset_hours).date_naive();
+        let from = today - chrono::Duration::days(FORECAST_OVERDUE_DAYS);
+        let to = today + chrono::Duration::days(days as i64);
+        let orders = self
+            .client
+            .get_open_customer_orders_with_product(
+                &product.meta.href,
+                &store.meta.href,
+                &from.format("%Y-%m-%d 00:00:00").to_string(),
+                &to.format("%Y-%m-%d 23:59:59").to_string(),
+            )
+            .await?;
 
-        // Обрабатываем позиции заказа
-        self.process_order_positions(&order).await
+        Ok(StockForecast::build(
+            product_id,
+            stock_info.as_ref(),
+            &orders,
+            store_id,
+            decimal(self.threshold_for(product_id)),
+            today,
+            days,
+        ))
     }
 
-    /// Обработать позиции заказа покупателя
-    async fn process_order_positions(&mut self, order: &CustomerOrder) -> Result<Vec<ProcessingResult>> {
-        let mut results = Vec::new();
+    /// Склад, поле тech. карты и клиент для выборок по всему каталогу: выборки идут без
+    /// блокировки процессора, процессор берётся только на поиск тех. карты товара
+    async fn catalogue_context(shared: &Mutex<Self>) -> Result<CatalogueContext> {
+        let mut processor = shared.lock().await;
+        Ok(CatalogueContext {
+            client: processor.client.clone(),
+            store: processor.get_store().await?,
+            attribute: processor.tech_card_attribute().await?,
+            stock_mode: processor.stock_mode,
+            velocity_days: processor.settings.sales_velocity_days,
+            utc_offset_hours: processor.settings.moysklad_utc_offset_hours,
+        })
+    }
 
-        let positions = match &order.positions {
-            Some(p) => &p.rows,
-            None => {
-                warn!("Order {} has no positions", order.name);
-                return Ok(results);
-            }
-        };
+    /// Прогноз потребности в материалах на `horizon_days` дней: ожидаемое производство товаров
+    /// с тех. картой (средние продажи за SALES_VELOCITY_DAYS плюс порог сверх остатка),
+    /// разложенное по материалам тех. карт
+    pub async fn materials_demand(shared: &Mutex<Self>, horizon_days: u32) -> Result<MaterialsDemandReport> {
+        let context = Self::catalogue_context(shared).await?;
+        let CatalogueContext { client, store, attribute, stock_mode, velocity_days, utc_offset_hours } = &context;
+        let products = client.get_products_with_attribute(&attribute.meta.href).await?;
+        let stock: HashMap<String, f64> = client
+            .get_store_stock(&store.meta.href)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let id = row.meta.href.rsplit('/').next().unwrap_or("").to_string();
+                (id, as_f64(stock_mode.effective(row.stock, row.reserve, row.in_transit)))
+            })
+            .collect();
 
-        info!("Processing {} positions in order {}", positions.len(), order.name);
+        let sales = recent_sales(client, store, *velocity_days, *utc_offset_hours).await?;
 
-        for position in positions {
-            match self.process_position(order, position).await {
-                Ok(result) => results.push(result),
-                Err(e) => {
-                    error!("Error processing position: {}", e);
-                    let product_info = self.extract_product_info_from_position(position);
-                    results.push(ProcessingResult {
-                        success: false,
-                        message: format!("Ошибка обработки позиции: {}", e),
-                        order_id: Some(order.id.clone()),
-                        order_name: Some(order.name.clone()),
-                        processing_id: None,
-                        processing_name: None,
-                        product: Some(product_info),
-                        error: Some(e.to_string()),
-                    });
+        let mut demands = Vec::with_capacity(products.len());
+        let mut usage = Vec::new();
+        for product in &products {
+            let processor = shared.lock().await;
+            let daily_sales = sales.get(&product.id).copied().unwrap_or(0.0) / *velocity_days as f64;
+            let mut demand = ProductDemand::new(
+                &product.id,
+                &product.name,
+                daily_sales,
+                stock.get(&product.id).copied().unwrap_or(0.0),
+                processor.threshold_for(&product.id),
+                horizon_days,
+            );
+
+            if demand.production > 0.0 {
+                let tech_card_name = processor.find_tech_card_name(product, &attribute.id);
+                let expanded = match processor.find_plan(product, &tech_card_name).await {
+                    Ok(Some(plan)) => {
+                        demand.tech_card = Some(plan.name.clone());
+                        processor.plan_material_usage(&plan, &product.name, demand.production).await
+                    }
+                    Ok(None) => Err(anyhow!("Тех. карта '{}' не найдена", tech_card_name)),
+                    Err(e) => Err(e),
+                };
+                match expanded {
+                    Ok(items) => usage.extend(items),
+                    Err(e) => demand.error = Some(e.to_string()),
                 }
             }
+            demands.push(demand);
         }
 
-        Ok(results)
+        Ok(MaterialsDemandReport::build(
+            horizon_days,
+            *velocity_days,
+            demands,
+            &usage,
+            &stock,
+            chrono::Utc::now(),
+            account_now(*utc_offset_hours).date_naive(),
+        ))
     }
 
-    /// Извлечь информацию о продукте из позиции
-    fn extract_product_info_from_position(&self, position: &CustomerOrderPosition) -> ProductInfo {
-        let product_id = position.assortment.meta.href
-            .rsplit('/')
-            .next()
-            .unwrap_or("unknown")
-            .to_string();
+    /// Проверить настройку тех. карт у всех товаров каталога: товары без тех. карты,
+    /// ссылки на несуществующие тех. карты и тех. карты без материалов
+    pub async fn mapping_health(shared: &Mutex<Self>) -> Result<MappingHealthReport> {
+        let context = Self::catalogue_context(shared).await?;
+        let CatalogueContext { client, attribute, .. } = &context;
+        // Весь каталог, а не только товары с остатком: у товара без остатков тех. карта
+        // нужна так же, как у остальных
+        let products = client.get_products().await?;
 
-        ProductInfo {
-            id: product_id,
-            name: position.assortment.name.clone().unwrap_or_else(|| "unknown".to_string()),
-            quantity: position.quantity,
-            stock_before: 0.0,
-        }
-    }
+        let mut issues = Vec::new();
+        for product in &products {
+            let processor = shared.lock().await;
+            let tech_card_name = processor.find_tech_card_name(product, &attribute.id);
+            let tech_card = Some(tech_card_name.clone()).filter(|name| !name.is_empty());
+            let issue = |kind, tech_card: Option<String>, message: String| MappingIssue {
+                product_id: product.id.clone(),
+                product: product.name.clone(),
+                kind,
+                tech_card,
+                message,
+            };
 
-    /// Обработать одну позицию заказа покупателя
-    async fn process_position(
-        &mut self,
-        order: &CustomerOrder,
-        position: &CustomerOrderPosition,
-    ) -> Result<ProcessingResult> {
-        // Извлекаем ID продукта из meta.href ассортимента
-        let product_id = position.assortment.meta.href
-            .rsplit('/')
-            .next()
-            .ok_or_else(|| anyhow!("Cannot extract product ID from assortment href"))?
-            .to_string();
+            let plans = match processor.find_plans(product, &tech_card_name).await {
+                Ok(plans) => plans,
+                Err(e) => {
+                    issues.push(issue(MappingIssueKind::LookupFailed, tech_card, e.to_string()));
+                    continue;
+                }
+            };
+            drop(processor);
 
-        let product_name = position.assortment.name.clone()
-            .unwrap_or_else(|| "unknown".to_string());
-        let quantity = position.quantity;
+            if plans.is_empty() {
+                issues.push(match tech_card {
+                    None => issue(
+                        MappingIssueKind::MissingTechCard,
+                        None,
+                        format!("Не заполнено поле '{}'", attribute.name),
+                    ),
+                    Some(name) => issue(
+                        MappingIssueKind::PlanNotFound,
+                        Some(name.clone()),
+                        format!("Тех. карта '{}' не найдена", name),
+                    ),
+                });
+                continue;
+            }
 
-        info!(
-            "Processing position: product={}, quantity={}",
-            product_name, quantity
-        );
+            for plan in &plans {
+                let has_materials = plan
+                    .materials
+                    .as_ref()
+                    .and_then(|m| m.rows.as_ref())
+                    .is_some_and(|rows| !rows.is_empty());
+                if !has_materials {
+                    issues.push(issue(
+                        MappingIssueKind::EmptyMaterials,
+                        Some(plan.name.clone()),
+                        format!("В тех. карте '{}' нет материалов", plan.name),
+                    ));
+                }
+            }
+        }
 
-        // Получаем текущий остаток товара
+        Ok(MappingHealthReport::build(products.len(), issues, chrono::Utc::now()))
+    }
+
+    /// Пересчитать пороги по скорости продаж (DYNAMIC_THRESHOLDS); возвращает число товаров
+    pub async fn refresh_dynamic_thresholds(&mut self) -> Result<usize> {
         let store = self.get_store().await?;
-        let store_id = store.id.as_ref().ok_or_else(|| anyhow!("Store ID missing"))?;
-        let current_stock = self.client.get_product_stock(&product_id, store_id).await?;
+        let sales = recent_sales(
+            &self.client,
+            &store,
+            self.settings.sales_velocity_days,
+            self.settings.moysklad_utc_offset_hours,
+        )
+        .await?;
 
-        info!(
-            "Current stock for {}: {} (threshold: {})",
-            product_name, current_stock, self.settings.min_stock_threshold
-        );
+        Ok(self.dynamic_thresholds.replace(
+            sales,
+            self.settings.sales_velocity_days,
+            self.settings.lead_time_days,
+        ))
+    }
 
-        // Проверяем, нужно ли пополнение
-        if current_stock >= self.settings.min_stock_threshold {
-            info!("Stock is sufficient, skipping production for {}", product_name);
-            return Ok(ProcessingResult {
-                success: true,
-                message: format!(
-                    "Остаток достаточен ({} >= {})",
-                    current_stock, self.settings.min_stock_threshold
-                ),
-                order_id: Some(order.id.clone()),
-                order_name: Some(order.name.clone()),
-                processing_id: None,
-                processing_name: None,
-                product: Some(ProductInfo {
-                    id: product_id.clone(),
-                    name: product_name.clone(),
-                    quantity,
-                    stock_before: current_stock,
-                }),
-                error: None,
+    /// Расход материалов тех. карты на `quantity` товара с учётом потерь
+    async fn plan_material_usage(
+        &self,
+        processing_plan: &ProcessingPlan,
+        product: &str,
+        quantity: f64,
+    ) -> Result<Vec<MaterialUsage>> {
+        let rows = processing_plan
+            .materials
+            .as_ref()
+            .and_then(|m| m.rows.as_ref())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let mut usage = Vec::with_capacity(rows.len());
+        for material in rows {
+            let material_id = material.product.meta.href.rsplit('/').next().unwrap_or("");
+            let waste = self.waste_factor(material_id).await?;
+            usage.push(MaterialUsage {
+                material_id: material_id.to_string(),
+                material: material.product.name.clone().unwrap_or_else(|| "unknown".to_string()),
+                product: product.to_string(),
+                quantity: as_f64(material_requirement(material.quantity, decimal(quantity), waste)),
             });
         }
+        Ok(usage)
+    }
 
-        // Получаем товар для чтения атрибутов
-        let product = self.client.get_product(&product_id).await?;
-
-        // Ищем название тех. карты в атрибутах
-        let tech_card_name = self.find_tech_card_name(&product)?;
-
-        if tech_card_name.is_empty() {
-            warn!("No tech card found for product {}", product_name);
-            return Ok(ProcessingResult {
-                success: false,
-                message: "Тех. карта не найдена в карточке товара".to_string(),
-                order_id: Some(order.id.clone()),
-                order_name: Some(order.name.clone()),
-                processing_id: None,
-                processing_name: None,
-                product: Some(ProductInfo {
-                    id: product_id.clone(),
-                    name: product_name.clone(),
-                    quantity,
-                    stock_before: current_stock,
-                }),
-                error: Some("Тех. карта не найдена".to_string()),
-            });
+    /// Получить кэшированное описание поля с тех. картой.
+    /// Поле ищется по ID (TECH_CARD_FIELD_ID), иначе по названию; дальше
+    /// значения сопоставляются по ID, поэтому переименование поля не ломает поиск.
+    pub async fn tech_card_attribute(&mut self) -> Result<AttributeMetadata> {
+        if let Some(ref attribute) = self.tech_card_attribute_cache {
+            return Ok(attribute.clone());
         }
 
-        info!("Found tech card name: {}", tech_card_name);
+        let attributes = self.client.get_product_attributes_metadata().await?;
+        let attribute = match self.settings.tech_card_field_id {
+            Some(ref id) => attributes.into_iter().find(|a| &a.id == id).ok_or_else(|| {
+                anyhow!("Product attribute with ID '{}' not found", id)
+            })?,
+            None => attributes
+                .into_iter()
+                .find(|a| a.name == self.settings.tech_card_field_name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Product attribute '{}' not found",
+                        self.settings.tech_card_field_name
+                    )
+                })?,
+        };
 
-        // Получаем тех. карту
-        let processing_plan = self
-            .client
-            .find_processing_plan_by_name(&tech_card_name)
-            .await?
-            .ok_or_else(|| anyhow!("Processing plan '{}' not found", tech_card_name))?;
+        if !attribute.attr_type.is_text() {
+            warn!(
+                "Tech card field '{}' has type '{}', expected string or text",
+                attribute.name,
+                attribute.attr_type.as_str()
+            );
+        }
 
-        info!("Found processing plan: {} ({})", processing_plan.name, processing_plan.id);
+        info!("Found tech card field: {} ({})", attribute.name, attribute.id);
+        self.tech_card_attribute_cache = Some(attribute.clone());
+        Ok(attribute)
+    }
 
-        // Проверяем доступность материалов
-        let materials_check = self
-            .check_materials_availability(&processing_plan, quantity, store_id)
-            .await?;
+    /// Кэш тех. карт: сбрасывается webhook об их изменении
+    pub fn plan_cache(&self) -> Arc<PlanCache> {
+        self.plan_cache.clone()
+    }
 
-        if !materials_check.available {
-            let missing = materials_check
-                .missing
-                .iter()
-                .map(|(name, qty)| format!("{}: нужно {}, нет в наличии", name, qty))
-                .collect::<Vec<_>>()
-                .join(", ");
+    /// Выключатель запросов к МойСклад
+    pub fn circuit_breaker(&self) -> Arc<CircuitBreaker> {
+        self.breaker.clone()
+    }
 
-            warn!("Insufficient materials for production: {}", missing);
-            return Ok(ProcessingResult {
-                success: false,
-                message: format!("Недостаточно материалов: {}", missing),
-                order_id: Some(order.id.clone()),
-                order_name: Some(order.name.clone()),
-                processing_id: None,
-                processing_name: None,
-                product: Some(ProductInfo {
-                    id: product_id.clone(),
-                    name: product_name.clone(),
-                    quantity,
-                    stock_before: current_stock,
-                }),
-                error: Some(format!("Недостаточно материалов: {}", missing)),
-            });
+    /// Снимок кэшей и внутреннего состояния
+    pub fn state(&self) -> ProcessorState {
+        ProcessorState {
+            store: self.store_cache.clone(),
+            organization: self.organization_cache.clone(),
+            source_store: self.source_store_cache.clone(),
+            supplier: self.supplier_cache.clone(),
+            tech_card_attribute: self.tech_card_attribute_cache.clone(),
+            production_log_entity_id: self.production_log_cache.clone(),
+            cached_plans: self.plan_cache.count(),
+            tech_card_sources: self.tech_card_sources.iter().map(|s| format!("{:?}", s)).collect(),
+            plan_lookup: self.plan_lookups.iter().map(|l| l.as_str().to_string()).collect(),
+            default_replenishment: self.default_replenishment.as_str().to_string(),
+            notify_only: self.settings.notify_only,
+            stock_mode: self.stock_mode.as_str().to_string(),
+            quantity_basis: self.quantity_basis.as_str().to_string(),
+            in_progress: self.in_progress.active(),
+            processed_orders: self.processed.len(),
+            lock_provider: self.locks.provider_name().to_string(),
+            dynamic_thresholds: self.dynamic_thresholds.count(),
+            dynamic_thresholds_at: self.dynamic_thresholds.computed_at(),
+            stock_cache_age_secs: self.stock_cache.age_secs(),
+            stock_cache_products: self.stock_cache.count(),
         }
+    }
 
-        // Создаём тех. операцию
-        let organization = self.get_organization().await?;
-        let processing = self
-            .create_processing_operation(
-                &processing_plan,
-                &store,
-                &organization,
-                quantity,
-                order,
-                &product_name,
-            )
-            .await?;
+    /// Клиент, склад и кэш для фонового обновления остатков (STOCK_CACHE_REFRESH_SECS)
+    pub async fn stock_cache_source(
+        &mut self,
+    ) -> Result<(Arc<MoyskladClient>, String, Arc<StockCache>)> {
+        let store = self.get_store().await?;
+        Ok((self.client.clone(), store.meta.href, self.stock_cache.clone()))
+    }
+
+    /// Статистика обращений к API МойСклад
+    pub fn api_usage(&self) -> Arc<ApiUsage> {
+        self.usage.clone()
+    }
+
+    /// Счётчики пропусков по причинам
+    pub fn skip_stats(&self) -> Arc<SkipStats> {
+        self.skip_stats.clone()
+    }
+
+    /// Проверить доступность API МойСклад
+    pub async fn probe_api(&self) -> Result<()> {
+        self.client.ping().await
+    }
+
+    /// Заменить токен доступа к API МойСклад
+    pub async fn set_token(&self, token: String) {
+        self.client.set_token(token).await
+    }
+
+    /// Проверить доступ к API и найти склад и организацию
+    pub async fn check_setup(&mut self) -> Result<SetupCheck> {
+        self.probe_api().await?;
+
+        Ok(SetupCheck {
+            store: self.get_store().await?,
+            organization: self.get_organization().await?,
+            tech_card_attribute: self.tech_card_attribute().await?,
+        })
+    }
+
+    /// Прогреть кэши при старте: склад, организация, поле с тех. картой
+    /// и тех. карты самых частых товаров из истории (WARMUP_PLANS).
+    /// Возвращает число закэшированных тех. карт.
+    pub async fn warm_up(shared: &Mutex<Self>) -> Result<usize> {
+        let (client, attribute, top_products) = {
+            let mut processor = shared.lock().await;
+            processor.get_store().await?;
+            processor.get_organization().await?;
+            let attribute = processor.tech_card_attribute().await?;
+
+            if !processor.plan_cache.enabled() {
+                return Ok(0);
+            }
+            let top_products = processor.history.top_products(processor.settings.warmup_plans);
+            (processor.client.clone(), attribute, top_products)
+        };
+
+        // Процессор берётся на каждый товар, чтобы первые webhook не ждали весь прогрев
+        let mut warmed = 0;
+        for product_id in top_products {
+            let product = match client.get_product(&product_id).await {
+                Ok(product) => product,
+                Err(e) => {
+                    debug!("Skipping warm-up of product {}: {:#}", product_id, e);
+                    continue;
+                }
+            };
+            let processor = shared.lock().await;
+            let tech_card_name = processor.find_tech_card_name(&product, &attribute.id);
+            match processor.find_plan(&product, &tech_card_name).await {
+                Ok(Some(_)) => warmed += 1,
+                Ok(None) => {}
+                Err(e) => debug!("Skipping warm-up of tech card for {}: {:#}", product.name, e),
+            }
+        }
+
+        Ok(warmed)
+    }
+
+    /// Сравнить склад, организацию, доп. поля товаров и webhook в МойСклад с тем, что сервис
+    /// нашёл раньше; возвращает изменения. Кэши обновляются, поэтому о переименовании
+    /// сообщается один раз, а удалённые сущности ищутся заново при следующей обработке.
+    /// Запросы идут без блокировки процессора: он берётся только на чтение и обновление кэшей.
+    pub async fn check_drift(shared: &Mutex<Self>, baseline: &mut WebhookBaseline) -> Result<Vec<String>> {
+        let (client, settings, store, organization, attribute) = {
+            let processor = shared.lock().await;
+            (
+                processor.client.clone(),
+                processor.settings.clone(),
+                processor.store_cache.clone(),
+                processor.organization_cache.clone(),
+                processor.tech_card_attribute_cache.clone(),
+            )
+        };
+        let mut changes = Vec::new();
+
+        match store {
+            Some(cached) => match client.get_store_by_id(cached.id.as_deref().unwrap_or_default()).await {
+                Ok(current) => {
+                    changes.extend(renamed("Склад", &cached, &current).map(|change| {
+                        if settings.store_id.is_none() {
+                            format!("{}: после перезапуска задайте STORE_NAME или STORE_ID", change)
+                        } else {
+                            change
+                        }
+                    }));
+                    shared.lock().await.store_cache = Some(current);
+                }
+                Err(e) if is_not_found(&e) => {
+                    changes.push(format!("Склад {} удалён", entity_label(Some(&cached))));
+                    shared.lock().await.store_cache = None;
+                }
+                Err(e) => return Err(e),
+            },
+            None => {
+                if let Err(e) = shared.lock().await.get_store().await {
+                    if is_transient_error(&e) {
+                        return Err(e);
+                    }
+                    changes.push(format!("Склад не найден: {}", e));
+                }
+            }
+        }
+
+        match organization {
+            Some(cached) => {
+                match client.get_organization_by_id(cached.id.as_deref().unwrap_or_default()).await {
+                    Ok(current) => {
+                        changes.extend(renamed("Организация", &cached, &current));
+                        shared.lock().await.organization_cache = Some(current);
+                    }
+                    Err(e) if is_not_found(&e) => {
+                        changes.push(format!("Организация {} удалена", entity_label(Some(&cached))));
+                        shared.lock().await.organization_cache = None;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            None => {
+                if let Err(e) = shared.lock().await.get_organization().await {
+                    if is_transient_error(&e) {
+                        return Err(e);
+                    }
+                    changes.push(format!("Организация не найдена: {}", e));
+                }
+            }
+        }
+
+        let attributes = client.get_product_attributes_metadata().await?;
+        match attribute {
+            Some(cached) => match attributes.iter().find(|a| a.id == cached.id) {
+                Some(current) => {
+                    if current.name != cached.name {
+                        changes.push(format!(
+                            "Доп. поле товаров '{}' теперь называется '{}'{}",
+                            cached.name,
+                            current.name,
+                            if settings.tech_card_field_id.is_none() {
+                                ": после перезапуска задайте TECH_CARD_FIELD_NAME или TECH_CARD_FIELD_ID"
+                            } else {
+                                ""
+                            }
+                        ));
+                    }
+                    shared.lock().await.tech_card_attribute_cache = Some(current.clone());
+                }
+                None => {
+                    changes.push(format!("Доп. поле товаров '{}' удалено", cached.name));
+                    shared.lock().await.tech_card_attribute_cache = None;
+                }
+            },
+            None => {
+                if let Err(e) = shared.lock().await.tech_card_attribute().await {
+                    if is_transient_error(&e) {
+                        return Err(e);
+                    }
+                    changes.push(format!("Поле тех. карты не найдено: {}", e));
+                }
+            }
+        }
+
+        let fields = [
+            ("WASTE_FIELD_NAME", &settings.waste_field_name),
+            ("REPLENISHMENT_FIELD_NAME", &settings.replenishment_field_name),
+            ("NOTIFY_ONLY_FIELD_NAME", &settings.notify_only_field_name),
+            ("ENTER_FALLBACK_FIELD_NAME", &settings.enter_fallback_field_name),
+            ("UNIT_FACTOR_FIELD_NAME", &settings.unit_factor_field_name),
+        ];
+        for (variable, name) in fields {
+            if let Some(name) = name.as_deref().filter(|n| !attributes.iter().any(|a| a.name == *n)) {
+                changes.push(format!("Доп. поле товаров '{}' из {} не найдено", name, variable));
+            }
+        }
+
+        let webhooks = client.get_webhooks().await?;
+        match baseline.webhooks() {
+            Some(before) => changes.extend(webhook_changes(before, &webhooks)),
+            None if !webhooks
+                .iter()
+                .any(|w| w.enabled && HANDLED_ENTITY_TYPES.contains(&w.entity_type.as_str())) =>
+            {
+                changes.push("Нет включённых webhook на обрабатываемые документы".to_string());
+            }
+            None => {}
+        }
+        baseline.replace(webhooks);
+
+        Ok(changes)
+    }
+
+    /// Товары с доступным остатком ниже порога на отслеживаемом складе
+    pub async fn scan_stock(&mut self) -> Result<Vec<StockScanItem>> {
+        let store = self.get_store().await?;
+
+        let items = self
+            .client
+            .get_store_stock(&store.meta.href)
+            .await?
+            .into_iter()
+            .filter_map(|row| {
+                let product_id = row.meta.href.rsplit('/').next().unwrap_or("").to_string();
+                let threshold = self.threshold_for(&product_id);
+                (self.stock_mode.effective(row.stock, row.reserve, row.in_transit) < decimal(threshold)).then_some(
+                    StockScanItem {
+                        product_id,
+                        name: row.name,
+                        article: row.article,
+                        stock: row.stock,
+                        reserve: row.reserve,
+                        available: row.stock - row.reserve,
+                        threshold,
+                    },
+                )
+            })
+            .collect();
+
+        Ok(items)
+    }
+
+    /// Остатки всех товаров с тех. картой на отслеживаемом складе.
+    /// Для товаров ниже порога проверяется наличие материалов на пополнение до порога.
+    pub async fn stock_overview(shared: &Mutex<Self>) -> Result<Vec<StockOverviewItem>> {
+        let context = Self::catalogue_context(shared).await?;
+        let CatalogueContext { client, store, attribute, stock_mode, .. } = &context;
+        let store_id = store.id.clone().ok_or_else(|| anyhow!("Store ID missing"))?;
+
+        let products = client.get_products_with_attribute(&attribute.meta.href).await?;
+        let stock: HashMap<String, StockRow> = client
+            .get_store_stock(&store.meta.href)
+            .await?
+            .into_iter()
+            .map(|row| (row.meta.href.rsplit('/').next().unwrap_or("").to_string(), row))
+            .collect();
+
+        let mut items = Vec::with_capacity(products.len());
+        for product in &products {
+            let processor = shared.lock().await;
+            let (stock_qty, reserve, in_transit) = stock
+                .get(&product.id)
+                .map(|row| (row.stock, row.reserve, row.in_transit))
+                .unwrap_or_default();
+            let available = stock_qty - reserve;
+            let threshold = processor.threshold_for(&product.id);
+            let below_threshold = stock_mode.effective(stock_qty, reserve, in_transit) < decimal(threshold);
+
+            let mut item = StockOverviewItem {
+                product_id: product.id.clone(),
+                name: product.name.clone(),
+                article: product.article.clone(),
+                tech_card: processor.find_tech_card_name(product, &attribute.id),
+                stock: stock_qty,
+                reserve,
+                available,
+                threshold,
+                below_threshold,
+                materials_available: None,
+                error: None,
+            };
+
+            if below_threshold {
+                let quantity = (decimal(threshold) - available).ceil().max(Decimal::ONE);
+                match processor.materials_for(&item.tech_card, quantity, &store_id).await {
+                    Ok(check) => item.materials_available = Some(check.available()),
+                    Err(e) => item.error = Some(e.to_string()),
+                }
+            }
+
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+
+    /// Проверить наличие материалов тех. карты без создания тех. операции
+    pub async fn check_plan_materials(
+        &mut self,
+        plan_name: &str,
+        quantity: f64,
+    ) -> Result<MaterialsReport> {
+        let store = self.get_store().await?;
+        let store_id = store.id.as_ref().ok_or_else(|| anyhow!("Store ID missing"))?;
+
+        if self.settings.bom_rollup {
+            self.tech_card_attribute().await?;
+        }
+
+        let processing_plan = self
+            .client
+            .find_processing_plan_by_name(plan_name)
+            .await?
+            .ok_or_else(|| anyhow!("Processing plan '{}' not found", plan_name))?;
+
+        let check = self
+            .report_materials_availability(&processing_plan, to_quantity(quantity), store_id)
+            .await?;
+
+        Ok(MaterialsReport {
+            plan_id: processing_plan.id,
+            plan_name: processing_plan.name,
+            quantity,
+            available: check.available(),
+            materials: check.materials,
+        })
+    }
+
+    /// Проверить материалы тех. карты по её названию
+    async fn materials_for(
+        &self,
+        tech_card_name: &str,
+        quantity: Decimal,
+        store_id: &str,
+    ) -> Result<MaterialsCheckResult> {
+        let processing_plan = self
+            .client
+            .find_processing_plan_by_name(tech_card_name)
+            .await?
+            .ok_or_else(|| anyhow!("Processing plan '{}' not found", tech_card_name))?;
+
+        self.report_materials_availability(&processing_plan, round_quantity(quantity), store_id)
+            .await
+    }
+
+    /// Обработать webhook событие
+    pub async fn process_webhook(&mut self, event: &WebhookEvent) -> Result<Vec<ProcessingResult>> {
+        if let Some(deferred) = self.defer_off_hours(event) {
+            return Ok(vec![deferred]);
+        }
+        self.process_now(event).await
+    }
+
+    /// Рабочее время по WORK_HOURS (без календаря — всегда)
+    pub fn is_working_time(&self) -> bool {
+        self.schedule.as_ref().is_none_or(|s| s.is_open(chrono::Utc::now()))
+    }
+
+    /// Время последнего производства товара, если окно PRODUCTION_COOLDOWN_SECS ещё не истекло
+    /// (явные заявки внутренних заказов не ограничиваются)
+    fn cooldown_since(
+        &self,
+        product_id: &str,
+        demand: Demand,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        if demand == Demand::Explicit || self.settings.production_cooldown_secs == 0 {
+            return None;
+        }
+        let window = chrono::Duration::seconds(self.settings.production_cooldown_secs as i64);
+        self.history
+            .last_production(product_id)
+            .filter(|last| chrono::Utc::now() - *last < window)
+    }
+
+    /// Контрагент, канал продаж и плановая отгрузка документа для приоритета в очереди.
+    /// Процессор блокируется только на время копирования клиента: документ читается
+    /// параллельно с обработкой.
+    pub async fn priority_input(shared: &Mutex<Self>, entity_type: &str, id: &str) -> Result<PriorityInput> {
+        let (client, utc_offset_hours) = {
+            let processor = shared.lock().await;
+            (processor.client.clone(), processor.settings.moysklad_utc_offset_hours)
+        };
+
+        let (agent, sales_channel, shipment) = match entity_type {
+            "customerorder" => {
+                let order = client.get_customer_order(id).await?;
+                (order.agent, order.sales_channel, order.delivery_planned_moment)
+            }
+            "retaildemand" => {
+                let demand = client.get_retail_demand(id).await?;
+                (demand.agent, demand.sales_channel, None)
+            }
+            _ => return Ok(PriorityInput::default()),
+        };
+
+        Ok(PriorityInput {
+            agent,
+            sales_channel,
+            shipment: shipment.and_then(|moment| moysklad_moment_utc(&moment, utc_offset_hours)),
+        })
+    }
+
+    /// Вне рабочего времени событие откладывается в очередь повторов до начала рабочего окна
+    fn defer_off_hours(&self, event: &WebhookEvent) -> Option<ProcessingResult> {
+        let schedule = self.schedule.as_ref()?;
+        let now = chrono::Utc::now();
+        if event.action == "delete" || schedule.is_open(now) {
+            return None;
+        }
+        let id = event_order_id(event)?;
+        let until = schedule.next_open(now)?;
+
+        self.retry_queue.defer(&event.entity_type, &id, &event.action, until);
+        Some(
+            ProcessingResult::skipped(
+                SkipReason::OffHours,
+                format!("Вне рабочего времени, обработка отложена до {}", until),
+            )
+            .for_order_id(&id, None),
+        )
+    }
+
+    /// Обработать событие сразу, без учёта рабочего календаря (ручной запуск)
+    pub async fn process_now(&mut self, event: &WebhookEvent) -> Result<Vec<ProcessingResult>> {
+        // Нехватка по перепроверенным позициям уже учтена при постановке в очередь
+        if event.entity_type == "supply" {
+            return self.process_supply(event).await;
+        }
+
+        let results = self.process_locked(event).await?;
+        self.report_skips(&results).await;
+        self.create_followup_tasks(&event.entity_type, &results).await;
+        Ok(results)
+    }
+
+    /// Поставить задачу TASK_ASSIGNEE по позициям, которые нужно разобрать вручную
+    /// (TASK_REASONS). По позиции документа задача ставится один раз.
+    async fn create_followup_tasks(&mut self, entity_type: &str, results: &[ProcessingResult]) {
+        if self.settings.task_assignee.is_none() || self.task_reasons.is_empty() {
+            return;
+        }
+
+        for result in results {
+            let (Some(reason), Some(product), Some(order_id)) =
+                (result.skip_reason, &result.product, &result.order_id)
+            else {
+                continue;
+            };
+            let key = format!("{}:{}:{}", order_id, product.id, reason.as_str());
+            if !self.task_reasons.contains(&reason) || self.tasks_created.contains(&key) {
+                continue;
+            }
+
+            let assignee = match self.get_task_assignee().await {
+                Ok(assignee) => assignee,
+                Err(e) => {
+                    warn!("Failed to resolve task assignee: {:#}", e);
+                    return;
+                }
+            };
+
+            let request = CreateTaskRequest {
+                description: format!(
+                    "Автопроизводство: '{}' ({} шт.) в документе {} нужно разобрать вручную.\n{}\n\
+                     Документ: {}\nТовар: {}",
+                    product.name,
+                    product.quantity,
+                    result.order_name.as_deref().unwrap_or(order_id),
+                    result.error.as_deref().unwrap_or(&result.message),
+                    self.app_link(entity_type, order_id),
+                    self.app_link("product", &product.id),
+                ),
+                assignee: EntityRefSmall { meta: assignee.meta },
+                operation: Some(EntityRefSmall {
+                    meta: Meta::entity(
+                        format!("{}/entity/{}/{}", self.settings.moysklad_api_url, entity_type, order_id),
+                        entity_type,
+                    ),
+                }),
+            };
+
+            match self.client.create_task(&request).await {
+                Ok(task) => {
+                    info!("Task {} created for {} in {}", task.id, product.name, order_id);
+                    self.tasks_created.insert(key);
+                }
+                Err(e) => warn!("Failed to create task for {} in {}: {:#}", product.name, order_id, e),
+            }
+        }
+    }
+
+    /// Ссылка на карточку сущности в веб-интерфейсе МойСклад
+    fn app_link(&self, entity_type: &str, id: &str) -> String {
+        let section = if entity_type == "product" { "good" } else { entity_type };
+        format!("{}/#{}/edit?id={}", self.settings.moysklad_app_url, section, id)
+    }
+
+    /// Записать итог обработки в исходный документ (SOURCE_REPORT). Ошибка записи
+    /// на результат обработки не влияет.
+    async fn report_to_source(&self, entity_type: &str, results: &[ProcessingResult]) {
+        if self.source_report == SourceReportMode::Off {
+            return;
+        }
+        let Some(order_id) = results.iter().find_map(|r| r.order_id.as_deref()) else {
+            return;
+        };
+        let Some(report) = report_text(results) else {
+            return;
+        };
+
+        let written = match self.source_report {
+            SourceReportMode::Off => return,
+            SourceReportMode::Description => {
+                match self.client.get_document_description(entity_type, order_id).await {
+                    Ok(current) => {
+                        let description = with_report(current.as_deref(), &report);
+                        if current.as_deref() == Some(description.as_str()) {
+                            return;
+                        }
+                        self.client
+                            .update_document_description(entity_type, order_id, &description)
+                            .await
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            SourceReportMode::File => {
+                match self.client.get_attached_files(entity_type, order_id).await {
+                    Ok(files) => {
+                        if is_last_report(&files, &report) {
+                            return;
+                        }
+                        let timestamp = account_now(self.settings.moysklad_utc_offset_hours)
+                            .format("%Y%m%d-%H%M%S")
+                            .to_string();
+                        self.client
+                            .attach_file(entity_type, order_id, &report_filename(&timestamp, &report), report.as_bytes())
+                            .await
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        if let Err(e) = written {
+            warn!("Failed to write processing report to {} {}: {:#}", entity_type, order_id, e);
+        }
+    }
+
+    /// Обработать событие документа под блокировкой заказа
+    async fn process_locked(&mut self, event: &WebhookEvent) -> Result<Vec<ProcessingResult>> {
+        // Заказ обрабатывается одним экземпляром сервиса за раз
+        let order_lock = match event_order_id(event) {
+            Some(order_id) => Some(self.locks.acquire(&format!("order:{}", order_id)).await?),
+            None => None,
+        };
+
+        let results = if STOCK_CORRECTION_TYPES.contains(&event.entity_type.as_str()) {
+            self.process_stock_correction(event).await
+        } else {
+            self.process_event(event).await
+        };
+        // Описание документа читается и заменяется под той же блокировкой
+        if let Ok(ref results) = results {
+            self.report_to_source(&event.entity_type, results).await;
+        }
+        if let Some(lock) = order_lock {
+            self.locks.release(lock).await;
+        }
+        results
+    }
+
+    /// Приёмка материалов: повторить заказы, позиции которых не произведены из-за нехватки
+    async fn process_supply(&mut self, event: &WebhookEvent) -> Result<Vec<ProcessingResult>> {
+        if event.action == "delete" {
+            return Ok(vec![]);
+        }
+
+        let supply_id = event
+            .content
+            .as_ref()
+            .and_then(|c| c.id.clone())
+            .ok_or_else(|| anyhow!("No supply ID in webhook content"))?;
+        let supply = self.client.get_supply(&supply_id).await?;
+
+        if !supply.applicable {
+            info!("Supply {} is not applicable, skipping", supply.name);
+            return Ok(vec![]);
+        }
+
+        let store = self.get_store().await?;
+        let supply_store_id = supply.store.as_ref().and_then(|s| s.id.as_ref());
+        if supply_store_id.is_some() && supply_store_id != store.id.as_ref() {
+            info!("Supply {} is for another store, skipping", supply.name);
+            return Ok(vec![]);
+        }
+
+        if self.shortages.depth() == 0 {
+            info!("Supply {} applied, no positions waiting for materials", supply.name);
+            return Ok(vec![]);
+        }
+
+        info!("Supply {} applied, rechecking positions waiting for materials", supply.name);
+        self.recheck_shortages().await
+    }
+
+    /// Инвентаризация, оприходование или списание: товары, остаток которых после корректировки
+    /// ниже порога, пополняются тем же конвейером, что и по заказу, — до порога
+    async fn process_stock_correction(
+        &mut self,
+        event: &WebhookEvent,
+    ) -> Result<Vec<ProcessingResult>> {
+        // Удаление корректировки возвращает остаток
+        if event.action == "delete" {
+            return Ok(vec![]);
+        }
+
+        let id = event
+            .content
+            .as_ref()
+            .and_then(|c| c.id.clone())
+            .ok_or_else(|| anyhow!("No document ID in webhook content"))?;
+        let correction = self.client.get_stock_correction(&event.entity_type, &id).await?;
+
+        if correction.applicable == Some(false) {
+            info!("{} {} is not applicable, skipping", event.entity_type, correction.name);
+            return Ok(vec![]);
+        }
+
+        let store = self.get_store().await?;
+        let correction_store_id = correction.store.as_ref().and_then(|s| s.id.as_ref());
+        if correction_store_id.is_some() && correction_store_id != store.id.as_ref() {
+            info!("{} {} is for another store, skipping", event.entity_type, correction.name);
+            return Ok(vec![]);
+        }
+
+        // Оприходования, созданные сервисом вместо тех. операции, уже учтены
+        if !self
+            .history
+            .find(|r| r.processing_id.as_deref() == Some(correction.id.as_str()))
+            .is_empty()
+        {
+            debug!("{} {} was created by the service, skipping", event.entity_type, correction.name);
+            return Ok(vec![]);
+        }
+
+        info!(
+            "{} {} changed stock, checking products against thresholds",
+            event.entity_type, correction.name
+        );
+
+        let mut order: CustomerOrder = correction.into();
+
+        // Тот же документ с теми же количествами уже пополнен (повторный webhook)
+        let fingerprint = order_fingerprint(&order);
+        let previous = self.processed.get(&order.id);
+        if previous.is_some_and(|snapshot| snapshot.fingerprint.as_deref() == Some(fingerprint.as_str())) {
+            info!("{} {} already processed with the same quantities, skipping", event.entity_type, order.name);
+            return Ok(vec![ProcessingResult::skipped(
+                SkipReason::Duplicate,
+                "Документ уже обработан, количества не изменились",
+            )
+            .for_order(&order)]);
+        }
+
+        // Пополнение — на нехватку до порога, а не на количество документа.
+        // Товары без нехватки пропускаются здесь и в конвейер не попадают.
+        let mut sufficient = Vec::new();
+        if let Some(positions) = order.positions.as_mut() {
+            let mut short = Vec::with_capacity(positions.rows.len());
+            for mut position in positions.rows.drain(..) {
+                let product_id = position
+                    .assortment
+                    .meta
+                    .href
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+
+                let threshold = decimal(self.threshold_for(&product_id));
+                let stock = self.threshold_stock(&product_id, &store, threshold).await?;
+                let shortfall = round_quantity(threshold - stock.effective);
+                if shortfall <= Decimal::ZERO {
+                    let product_name = position.assortment.name.clone().unwrap_or_default();
+                    let current_stock = stock.effective;
+                    sufficient.push(
+                        ProcessingResult::skipped(
+                            SkipReason::StockSufficient,
+                            format!("Остаток достаточен ({} >= {})", current_stock, threshold),
+                        )
+                        .with_product(ProductInfo {
+                            stock: Some(stock),
+                            ..ProductInfo::new(&product_id, &product_name, Decimal::ZERO, current_stock)
+                        }),
+                    );
+                    continue;
+                }
+                position.quantity = shortfall;
+                short.push(position);
+            }
+            positions.rows = short;
+        }
+
+        let mut results: Vec<ProcessingResult> =
+            sufficient.into_iter().map(|result| result.for_order(&order)).collect();
+        let mut covered = Vec::new();
+        if order.positions.as_ref().is_some_and(|positions| !positions.rows.is_empty()) {
+            let processed = self.process_order_positions(&order, None, Demand::Threshold).await?;
+            results.extend(processed.results);
+            covered = processed.covered;
+        }
+
+        // Отпечаток — только если все товары пополнены до порога полностью
+        let all_processed = results
+            .iter()
+            .all(|r| r.success && r.product.as_ref().is_none_or(|p| p.requested.is_none()));
+        self.processed
+            .record(&order.id, all_processed.then_some(fingerprint), covered);
+
+        Ok(results)
+    }
+
+    /// Повторно обработать заказы с позициями, ожидающими материалов: позиции, для которых
+    /// материалы появились, производятся, остальные остаются в очереди
+    pub async fn recheck_shortages(&mut self) -> Result<Vec<ProcessingResult>> {
+        let orders = self.shortages.orders();
+        let mut results = Vec::new();
+
+        for (order_id, entity_type) in orders {
+            let retry = WebhookEvent::entity_action(&entity_type, &order_id, "update");
+            match self.process_locked(&retry).await {
+                Ok(order_results) => results.extend(order_results),
+                // Заказ удалён без webhook: ждать материалов для него больше незачем
+                Err(e) if is_not_found(&e) => {
+                    info!("Order {} waiting for materials no longer exists, dropping", order_id);
+                    self.shortages.remove_order(&order_id);
+                }
+                Err(e) => warn!("Failed to recheck order {} waiting for materials: {:#}", order_id, e),
+            }
+        }
+
+        let produced = results.iter().filter(|r| r.processing_id.is_some()).count();
+        info!(
+            "Rechecked positions waiting for materials: {} produced, {} still waiting",
+            produced,
+            self.shortages.depth()
+        );
+        Ok(results)
+    }
+
+    /// Учесть пропуски в счётчиках и отправить уведомления по причинам
+    async fn report_skips(&self, results: &[ProcessingResult]) {
+        for result in results {
+            let Some(reason) = result.skip_reason else {
+                continue;
+            };
+
+            self.skip_stats.record(reason);
+
+            let subject = match (&result.product, &result.order_name) {
+                (Some(product), Some(order)) => format!("'{}' в заказе {}", product.name, order),
+                (None, Some(order)) => format!("заказ {}", order),
+                (Some(product), None) => format!("'{}'", product.name),
+                (None, None) => String::new(),
+            };
+            self.notifier
+                .notify(Notification::skipped(
+                    reason,
+                    format!("Пропущено ({}): {}", reason.as_str(), subject),
+                    result.message.clone(),
+                ))
+                .await;
+        }
+    }
+
+    async fn process_event(&mut self, event: &WebhookEvent) -> Result<Vec<ProcessingResult>> {
+        info!(
+            "Processing webhook event: type={}, action={}",
+            event.entity_type, event.action
+        );
+
+        // Обрабатываются заказы покупателей, розничные продажи, внутренние заказы и приёмки
+        if !HANDLED_ENTITY_TYPES.contains(&event.entity_type.as_str()) {
+            debug!("Ignoring unsupported event: {}", event.entity_type);
+            return Ok(vec![]);
+        }
+
+        // Заказ удалён: получить его уже нельзя, работаем по истории
+        if event.action == "delete" {
+            let order_id = event
+                .content
+                .as_ref()
+                .and_then(|c| c.id.clone())
+                .ok_or_else(|| anyhow!("No order ID in webhook content"))?;
+            return self.revoke_order_productions(&order_id, None, "удалён").await;
+        }
+
+        // Получаем данные заказа
+        let order = if let Some(ref order) = event.entity {
+            order.clone()
+        } else if let Some(ref content) = event.content {
+            if let Some(ref id) = content.id {
+                match event.entity_type.as_str() {
+                    "retaildemand" => self.client.get_retail_demand(id).await?.into(),
+                    "internalorder" => self.client.get_internal_order(id).await?.into(),
+                    _ => self.client.get_customer_order(id).await?,
+                }
+            } else {
+                return Err(anyhow!("No order ID in webhook content"));
+            }
+        } else {
+            return Err(anyhow!("No order data in webhook event"));
+        };
+
+        // Проверяем, что заказ проведён (подтверждён)
+        if !order.applicable {
+            if self.has_active_productions(&order.id) {
+                return self
+                    .revoke_order_productions(&order.id, Some(order.name.clone()), "распроведён")
+                    .await;
+            }
+
+            info!("Order {} is not applicable, skipping", order.name);
+            self.shortages.remove_order(&order.id);
+            return Ok(vec![ProcessingResult::skipped(
+                SkipReason::NotApplicable,
+                "Заказ не проведён, пропускаем",
+            )
+            .for_order(&order)]);
+        }
+
+        // Проверяем склад (если в заказе указан склад — сравниваем с настройкой)
+        let store = self.get_store().await?;
+        if let Some(ref order_store) = order.store {
+            let order_store_id = order_store.id.as_ref().ok_or_else(|| anyhow!("Order store ID missing"))?;
+            let cached_store_id = store.id.as_ref().ok_or_else(|| anyhow!("Cached store ID missing"))?;
+
+            if order_store_id != cached_store_id {
+                info!(
+                    "Order store '{:?}' doesn't match monitored store '{:?}', skipping",
+                    order_store.name, store.name
+                );
+                return Ok(vec![ProcessingResult::skipped(
+                    SkipReason::OtherStore,
+                    format!("Заказ с другого склада ({:?})", order_store.name),
+                )
+                .for_order(&order)]);
+            }
+        }
+
+        // Фильтры по контрагенту и каналу продаж (у внутренних заказов их нет)
+        if event.entity_type != "internalorder" {
+            let agent = order.agent.as_ref();
+            let channel = order.sales_channel.as_ref();
+            let filtered = if !matches_filter(&self.settings.agent_filter, agent) {
+                Some((
+                    SkipReason::OtherAgent,
+                    format!("Контрагент {} не входит в AGENT_FILTER", entity_label(agent)),
+                ))
+            } else if !matches_filter(&self.settings.sales_channel_filter, channel) {
+                Some((
+                    SkipReason::OtherSalesChannel,
+                    format!("Канал продаж {} не входит в SALES_CHANNEL_FILTER", entity_label(channel)),
+                ))
+            } else {
+                None
+            };
+
+            if let Some((reason, message)) = filtered {
+                info!("Order {} filtered out ({}), skipping", order.name, reason.as_str());
+                return Ok(vec![ProcessingResult::skipped(reason, message).for_order(&order)]);
+            }
+        }
+
+        // Заказ покупателя обрабатывается по количествам QUANTITY_BASIS (до сравнения с прошлой
+        // обработкой: при частичной отгрузке отгруженное или резерв меняются без смены заказанного)
+        let basis = (event.entity_type == "customerorder").then_some(self.quantity_basis);
+        let order = match basis {
+            Some(basis) => basis.apply(order),
+            None => order,
+        };
+
+        // Заказ уже обработан с теми же количествами (например, распроведён и проведён снова)
+        let fingerprint = order_fingerprint(&order);
+        let previous = self.processed.get(&order.id);
+        if let Some(ref snapshot) = previous
+            && snapshot.fingerprint.as_deref() == Some(fingerprint.as_str())
+        {
+            info!("Order {} already processed with the same quantities, skipping", order.name);
+            return Ok(vec![ProcessingResult::skipped(
+                SkipReason::Duplicate,
+                "Заказ уже обработан, количества не изменились",
+            )
+            .for_order(&order)]);
+        }
+
+        // Обрабатываем позиции заказа (при редактировании — только прирост количества)
+        // Внутренний заказ — явная заявка на производство заказанных количеств
+        let demand = if event.entity_type == "internalorder" {
+            Demand::Explicit
+        } else {
+            Demand::Threshold
+        };
+        let PositionResults { mut results, covered } = self
+            .process_order_positions(&order, previous.as_ref(), demand)
+            .await?;
+        for result in &mut results {
+            result.quantity_basis = basis;
+        }
+
+        // Отпечаток — только если все позиции пополнены полностью: иначе остаток
+        // дообработается при следующем изменении документа
+        let all_processed = results
+            .iter()
+            .all(|r| r.success && r.product.as_ref().is_none_or(|p| p.requested.is_none()));
+        self.processed
+            .record(&order.id, all_processed.then_some(fingerprint), covered);
+
+        Ok(results)
+    }
+
+    /// Документы пополнения, созданные для заказа и ещё не отменённые
+    fn active_productions(&self, order_id: &str) -> Vec<CreatedDocument> {
+        let records = self
+            .history
+            .find(|r| r.order_id.as_deref() == Some(order_id) && r.processing_id.is_some());
+
+        let mut active: Vec<CreatedDocument> = Vec::new();
+        for record in &records {
+            let id = record.processing_id.clone().unwrap_or_default();
+            if record.revoked {
+                active.retain(|document| document.id != id);
+            } else if record.success && !active.iter().any(|document| document.id == id) {
+                active.push(CreatedDocument {
+                    id,
+                    name: record.processing_name.clone().unwrap_or_default(),
+                    entity_type: record.document_type.clone().unwrap_or_else(|| "processing".to_string()),
+                });
+            }
+        }
+        active
+    }
+
+    fn has_active_productions(&self, order_id: &str) -> bool {
+        !self.active_productions(order_id).is_empty()
+    }
+
+    /// Заказ удалён или распроведён: отменить созданные для него документы согласно
+    /// ON_ORDER_REVOKED (`notify`, `unapply`, `delete`). Каждый документ отменяется
+    /// через свою сущность: тех. операция, перемещение, оприходование, заказ поставщику.
+    async fn revoke_order_productions(
+        &mut self,
+        order_id: &str,
+        order_name: Option<String>,
+        reason: &str,
+    ) -> Result<Vec<ProcessingResult>> {
+        self.shortages.remove_order(order_id);
+
+        let productions = self.active_productions(order_id);
+        let order_label = order_name.clone().unwrap_or_else(|| order_id.to_string());
+
+        if productions.is_empty() {
+            info!("Order {} {}, no productions to revoke", order_label, reason);
+            return Ok(vec![ProcessingResult::done(format!(
+                "Заказ {}, созданных документов нет",
+                reason
+            ))
+            .for_order_id(order_id, order_name)]);
+        }
+
+        let mode = self.settings.on_order_revoked.as_str();
+        info!(
+            "Order {} {}, {} productions found (mode: {})",
+            order_label, reason, productions.len(), mode
+        );
+
+        self.client
+            .set_audit_order(Some((order_id.to_string(), order_label.clone())));
+
+        let mut results = Vec::new();
+        for document in productions {
+            let outcome = match mode {
+                "unapply" => self
+                    .client
+                    .unapply_document(&document.entity_type, &document.id)
+                    .await
+                    .map(|_| "проведение отменено"),
+                "delete" => self
+                    .client
+                    .delete_document(&document.entity_type, &document.id)
+                    .await
+                    .map(|_| "документ удалён"),
+                _ => Ok("требует проверки оператором"),
+            };
+
+            let revoked = mode == "unapply" || mode == "delete";
+            let label = document_label(&document.entity_type);
+            let mut result = match outcome {
+                Ok(done) => ProcessingResult::done(format!(
+                    "Заказ {}: {} {} — {}",
+                    reason, label, document.name, done
+                )),
+                Err(e) => {
+                    error!("Failed to revoke {} {}: {}", document.entity_type, document.name, e);
+                    ProcessingResult::failed(
+                        format!("Не удалось отменить документ {} ({})", document.name, label),
+                        e.to_string(),
+                    )
+                }
+            }
+            .with_document_type(&document.entity_type)
+            .for_order_id(order_id, order_name.clone());
+            result.processing_id = Some(document.id.clone());
+            result.processing_name = Some(document.name.clone());
+
+            if revoked && result.success {
+                let mut record = HistoryRecord::from_result(&result);
+                record.revoked = true;
+                self.history.append(record);
+            }
+            results.push(result);
+        }
+
+        self.client.set_audit_order(None);
+
+        // Тех. операции отменены: при повторном проведении заказ обрабатывается заново
+        if results.iter().all(|r| r.success) && (mode == "unapply" || mode == "delete") {
+            self.processed.remove(order_id);
+        }
+
+        let summary = results
+            .iter()
+            .map(|r| r.message.clone())
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.notifier
+            .notify(Notification::new(
+                NotificationEvent::Failure,
+                format!("Заказ {} {}", order_label, reason),
+                summary,
+            ))
+            .await;
+
+        Ok(results)
+    }
+
+    /// Обработать позиции заказа покупателя
+    async fn process_order_positions(
+        &mut self,
+        order: &CustomerOrder,
+        previous: Option<&OrderSnapshot>,
+        demand: Demand,
+    ) -> Result<PositionResults> {
+        let mut results = Vec::new();
+        let mut covered = Vec::new();
+
+        let positions = match &order.positions {
+            Some(p) => &p.rows,
+            None => {
+                warn!("Order {} has no positions", order.name);
+                return Ok(PositionResults { results, covered });
+            }
+        };
+
+        info!("Processing {} positions in order {}", positions.len(), order.name);
+
+        // Изменения в МойСклад записываются в журнал с привязкой к заказу
+        self.client
+            .set_audit_order(Some((order.id.clone(), order.name.clone())));
+
+        for position in positions {
+            // Количество, по которому пополнение уже запускалось при прошлой обработке
+            let done = previous
+                .and_then(|s| s.positions.get(&position_key(position)).copied())
+                .unwrap_or_default();
+            if done >= position.quantity {
+                let product_info = self.extract_product_info_from_position(position);
+                info!(
+                    "Position {} already processed ({} >= {}), skipping",
+                    product_info.name, done, position.quantity
+                );
+                results.push(
+                    ProcessingResult::skipped(
+                        SkipReason::Duplicate,
+                        format!("Позиция уже обработана ({})", done),
+                    )
+                    .for_order(order)
+                    .with_product(product_info),
+                );
+                continue;
+            }
+
+            let mut delta = position.clone();
+            delta.quantity = position.quantity - done;
+            if done > Decimal::ZERO {
+                info!(
+                    "Position quantity increased {} -> {}, processing delta {}",
+                    done, position.quantity, delta.quantity
+                );
+            }
+
+            // Проверка остатка и создание документа по товару — под общей блокировкой,
+            // чтобы параллельные реплики не пополнили его дважды
+            let product_lock = self
+                .locks
+                .acquire(&format!(
+                    "product:{}",
+                    position.assortment.meta.href.rsplit('/').next().unwrap_or_default()
+                ))
+                .await;
+            let result = match product_lock {
+                Ok(lock) => {
+                    let result = self.process_position(order, &delta, demand).await;
+                    self.locks.release(lock).await;
+                    result
+                }
+                Err(e) => Err(PositionError::new(PositionStage::Lock, e)),
+            };
+
+            match result {
+                Ok(result) => {
+                    // При частичном производстве пополнение запущено только на часть прироста
+                    if result.success {
+                        let share = result.product.as_ref().map_or(Decimal::ONE, |p| p.covered_share());
+                        covered.push((position_key(position), done + delta.quantity * share));
+                    }
+                    results.push(result);
+                }
+                Err(e) => {
+                    let product_info = self.extract_product_info_from_position(position);
+                    // Временные ошибки обработаются повторно через очередь — без оповещения
+                    if e.retryable {
+                        warn!(
+                            "Transient error processing position {} ({}): {}",
+                            product_info.name,
+                            e.code(),
+                            e
+                        );
+                    } else {
+                        error!("Error processing position ({}): {}", e.code(), e);
+                        self.notifier
+                            .notify(Notification::new(
+                                NotificationEvent::Failure,
+                                format!("Ошибка обработки заказа {}", order.name),
+                                format!("Товар '{}': {} [{}]", product_info.name, e, e.code()),
+                            ))
+                            .await;
+                    }
+                    let mut failed = ProcessingResult::failed(
+                        format!("Ошибка обработки позиции: {}", e),
+                        e.to_string(),
+                    )
+                    .for_order(order)
+                    .with_product(product_info);
+                    failed.error_details = Some(e.info());
+                    results.push(failed);
+                }
+            }
+        }
+
+        self.client.set_audit_order(None);
+
+        let order_type = order
+            .meta
+            .entity_type
+            .clone()
+            .filter(|t| t != "customerorder");
+        // Время от изменения документа в МойСклад до создания тех. операции
+        let event_moment = order
+            .updated
+            .as_deref()
+            .and_then(|moment| moysklad_moment_utc(moment, self.settings.moysklad_utc_offset_hours));
+        let mut slowest: Option<f64> = None;
+        for result in &results {
+            let mut record = HistoryRecord::from_result(result);
+            record.order_type = order_type.clone();
+            if result.success && result.processing_id.is_some() && result.skip_reason.is_none() {
+                record.sla_secs = event_moment
+                    .map(|moment| (record.timestamp - moment).num_milliseconds().max(0) as f64 / 1000.0);
+                if let Some(secs) = record.sla_secs {
+                    slowest = Some(slowest.map_or(secs, |s| s.max(secs)));
+                }
+            }
+            self.history.append(record);
+        }
+
+        if let (Some(limit), Some(secs)) = (self.settings.sla_limit_secs, slowest)
+            && secs > limit as f64
+        {
+            warn!(
+                "Order {}: production created {:.0} s after the change, SLA {} s",
+                order.name, secs, limit
+            );
+            self.notifier
+                .notify(Notification::new(
+                    NotificationEvent::Sla,
+                    format!("Заказ {}: превышен SLA", order.name),
+                    format!(
+                        "Тех. операция создана через {:.0} с после изменения заказа (SLA {} с)",
+                        secs, limit
+                    ),
+                ))
+                .await;
+        }
+
+        // Нехватка материалов — в очередь ожидания, успешная обработка снимает позицию с неё
+        for result in &results {
+            let Some(ref product) = result.product else {
+                continue;
+            };
+            if result.skip_reason == Some(SkipReason::MaterialsShort) {
+                self.shortages.add(ShortageEntry {
+                    order_id: order.id.clone(),
+                    order_name: Some(order.name.clone()),
+                    entity_type: order_type.clone(),
+                    product_id: product.id.clone(),
+                    product_name: product.name.clone(),
+                    quantity: product.quantity,
+                    missing: result.missing_materials.clone(),
+                    queued_at: chrono::Utc::now(),
+                    checked_at: None,
+                    checks: 0,
+                });
+            } else if result.success {
+                self.shortages.remove(&order.id, &product.id);
+            }
+        }
+
+        if let Some(ref outgoing) = self.outgoing {
+            outgoing.deliver(OutgoingPayload {
+                order_id: order.id.clone(),
+                order_name: order.name.clone(),
+                results: results.clone(),
+            });
+        }
+
+        Ok(PositionResults { results, covered })
+    }
+
+    /// Извлечь информацию о продукте из позиции
+    fn extract_product_info_from_position(&self, position: &CustomerOrderPosition) -> ProductInfo {
+        let product_id = position.assortment.meta.href
+            .rsplit('/')
+            .next()
+            .unwrap_or("unknown")
+            .to_string();
+
+        ProductInfo::new(
+            &product_id,
+            position.assortment.name.as_deref().unwrap_or("unknown"),
+            position.quantity,
+            Decimal::ZERO,
+        )
+    }
+
+    /// Обработать одну позицию заказа покупателя.
+    /// `demand` — откуда потребность: у внутреннего заказа и строки плана производится
+    /// заданное количество без учёта порога.
+    async fn process_position(
+        &mut self,
+        order: &CustomerOrder,
+        position: &CustomerOrderPosition,
+        demand: Demand,
+    ) -> Result<ProcessingResult, PositionError> {
+        // Извлекаем ID продукта из meta.href ассортимента
+        let product_id = position.assortment.meta.href
+            .rsplit('/')
+            .next()
+            .ok_or_else(|| anyhow!("Cannot extract product ID from assortment href"))
+            .stage(PositionStage::Product)?
+            .to_string();
+
+        let product_name = position.assortment.name.clone()
+            .unwrap_or_else(|| "unknown".to_string());
+        let quantity = position.quantity;
+
+        info!(
+            "Processing position: product={}, quantity={}",
+            product_name, quantity
+        );
+
+        // Услуги, комплекты и прочие виды ассортимента не производятся
+        let kind = AssortmentKind::from_meta(&position.assortment.meta);
+        if !kind.is_producible() {
+            info!("Position {} is a {}, not producible", product_name, kind.label());
+            return Ok(ProcessingResult::skipped(
+                SkipReason::NotProducible,
+                format!("Позиция не производится ({})", kind.label()),
+            )
+            .for_order(order)
+            .with_product(ProductInfo::new(&product_id, &product_name, quantity, Decimal::ZERO)));
+        }
+
+        // Настройки товара, заданные через /admin/products/{id}/settings
+        let overrides = self.overrides.get(&product_id);
+        if overrides.excluded && demand != Demand::Explicit {
+            info!("Product {} is excluded from autoproduction", product_name);
+            return Ok(ProcessingResult::skipped(
+                SkipReason::Excluded,
+                "Товар исключён из автопополнения",
+            )
+            .for_order(order)
+            .with_product(ProductInfo::new(&product_id, &product_name, quantity, Decimal::ZERO)));
+        }
+        let threshold = decimal(self.threshold_for(&product_id));
+
+        // Получаем текущий остаток товара
+        let store = staged!(self, PositionStage::Stock, self.get_store())?;
+        let stock = if demand.ignores_threshold() {
+            staged!(self, PositionStage::Stock, self.stock_snapshot(&product_id, &store, false))?
+        } else {
+            staged!(self, PositionStage::Stock, self.threshold_stock(&product_id, &store, threshold))?
+        };
+        let current_stock = stock.effective;
+
+        info!(
+            "Current stock for {}: {} (threshold: {}, in transit: {}, pending production: {})",
+            product_name,
+            current_stock,
+            threshold,
+            stock.in_transit,
+            stock.pending_production
+        );
+
+        // Проверяем, нужно ли пополнение
+        if !demand.ignores_threshold() && current_stock >= threshold {
+            info!("Stock is sufficient, skipping production for {}", product_name);
+            return Ok(ProcessingResult::skipped(
+                SkipReason::StockSufficient,
+                format!("Остаток достаточен ({} >= {})", current_stock, threshold),
+            )
+            .for_order(order)
+            .with_product(ProductInfo {
+                stock: Some(stock),
+                ..ProductInfo::new(&product_id, &product_name, quantity, current_stock)
+            }));
+        }
+
+        // Товар для чтения атрибутов: из развёрнутой позиции или отдельным запросом
+        let product = staged!(
+            self,
+            PositionStage::Product,
+            self.position_product(position, &product_id)
+        )?;
+
+        // Способ пополнения: в пробном режиме (NOTIFY_ONLY) только уведомление, внутренний
+        // заказ и план всегда производятся, иначе из настроек товара, его поля или по умолчанию
+        let kind = if self.is_notify_only(&product) {
+            ReplenishmentKind::NotifyOnly
+        } else if demand.ignores_threshold() {
+            ReplenishmentKind::Produce
+        } else {
+            overrides.replenishment().unwrap_or_else(|| {
+                ReplenishmentKind::for_product(
+                    &product,
+                    self.settings.replenishment_field_name.as_deref(),
+                    self.default_replenishment,
+                )
+            })
+        };
+
+        // С целевым уровнем пополняется до него, а не только на количество позиции
+        let replenish_quantity = if demand.ignores_threshold() {
+            quantity
+        } else {
+            overrides.replenish_quantity(quantity, current_stock)
+        };
+        if replenish_quantity > quantity {
+            info!(
+                "Replenishing {} up to target level: {} instead of {}",
+                product_name, replenish_quantity, quantity
+            );
+        }
+
+        // Тех. операция создаётся в единицах производства: проверяется количество после пересчёта
+        let conversion = if kind == ReplenishmentKind::Produce {
+            staged!(self, PositionStage::Product, self.unit_conversion(&product))?
+        } else {
+            None
+        };
+        let document_quantity = conversion
+            .as_ref()
+            .map_or(replenish_quantity, |c| c.apply(replenish_quantity));
+
+        // Ошибочное количество из заказа не должно превращаться в документ в МойСклад
+        let replenish_quantity = match validate_quantity(document_quantity, self.settings.max_auto_quantity) {
+            Ok(_) if conversion.is_some() => replenish_quantity,
+            Ok(quantity) => quantity,
+            Err(problem) => {
+                warn!("Rejected quantity {} for {}: {}", replenish_quantity, product_name, problem);
+                self.notifier
+                    .notify(Notification::new(
+                        NotificationEvent::Failure,
+                        format!("Подозрительное количество для '{}'", product_name),
+                        format!("Заказ {}: {}", order.name, problem),
+                    ))
+                    .await;
+                return Ok(ProcessingResult::failed(format!("Количество отклонено: {}", problem), problem)
+                    .with_reason(SkipReason::SuspiciousQuantity)
+                    .for_order(order)
+                    .with_product(ProductInfo {
+                        stock: Some(stock),
+                        ..ProductInfo::new(&product_id, &product_name, replenish_quantity, current_stock)
+                    }));
+            }
+        };
+
+        let strategy = self.strategies.get(kind);
+        debug!("Replenishing {} with {} strategy", product_name, strategy.kind().as_str());
+
+        let request = ReplenishRequest {
+            order,
+            position,
+            product: &product,
+            info: ProductInfo::new(&product_id, &product_name, replenish_quantity, current_stock),
+            store: &store,
+            demand,
+            conversion,
+        };
+        let mut result = strategy.replenish(self, request).await?;
+        if let Some(ref mut info) = result.product {
+            info.stock = Some(stock);
+        }
+        Ok(result)
+    }
+
+    /// Пополнить остаток производством по тех. карте
+    /// (товары без тех. карты с флагом ENTER_FALLBACK_FIELD_NAME оприходуются)
+    pub(crate) async fn produce(
+        &mut self,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError> {
+        let ReplenishRequest { order, position, product, info, store, demand, conversion } = request;
+        let product_id = info.id;
+        let product_name = info.name;
+        let quantity = info.quantity;
+        let current_stock = info.stock_before;
+        let store_id = store
+            .id
+            .as_ref()
+            .ok_or_else(|| anyhow!("Store ID missing"))
+            .stage(PositionStage::Stock)?;
+
+        // Ищем название тех. карты в атрибутах
+        let tech_card_attribute = staged!(
+            self,
+            PositionStage::TechCard,
+            self.tech_card_attribute()
+        )?;
+        let tech_card_name = self.find_tech_card_name(product, &tech_card_attribute.id);
+
+        // Товар продаётся в других единицах, чем производится (упаковки и штуки)
+        let production_quantity = conversion.as_ref().map_or(quantity, |c| c.apply(quantity));
+
+        let selected = staged!(
+            self,
+            PositionStage::TechCard,
+            self.select_plan(product, &tech_card_name, production_quantity, store_id)
+        )?;
+        let (processing_plan, plan_choice, plan_check) = match selected {
+            Some(selection) => (Some(selection.plan), selection.reason, selection.check),
+            None => (None, None, None),
+        };
+        let no_tech_card = processing_plan.is_none() && tech_card_name.is_empty();
+
+        // Товары без тех. карты, отмеченные флагом, оприходуются
+        if no_tech_card && self.is_enter_fallback(product) {
+            info!("No tech card for {}, creating enter document", product_name);
+            let organization = staged!(self, PositionStage::Create, self.get_organization())?;
+            let enter = staged!(
+                write self,
+                PositionStage::Create,
+                self.create_enter_operation(&position.assortment.meta, store, &organization, quantity, order)
+            )?;
+            self.stock_cache.invalidate(&product_id);
+
+            self.notifier
+                .notify(Notification::new(
+                    NotificationEvent::Success,
+                    format!("Создано оприходование {}", enter.name),
+                    format!(
+                        "Оприходование {} шт. '{}' для заказа {}",
+                        quantity, product_name, order.name
+                    ),
+                ))
+                .await;
+
+            return Ok(ProcessingResult::produced(
+                format!(
+                    "Создано оприходование {} шт. '{}' (товар без тех. карты)",
+                    quantity, product_name
+                ),
+                &enter.id,
+                &enter.name,
+            )
+            .with_document_type("enter")
+            .for_order(order)
+            .with_product(ProductInfo::new(&product_id, &product_name, quantity, current_stock)));
+        }
+
+        if no_tech_card {
+            warn!("No tech card found for product {}", product_name);
+            self.notifier
+                .notify(Notification::new(
+                    NotificationEvent::Failure,
+                    format!("Тех. карта не найдена (заказ {})", order.name),
+                    format!("В карточке товара '{}' не заполнено поле '{}'", product_name, self.settings.tech_card_field_name),
+                ))
+                .await;
+            return Ok(ProcessingResult::failed(
+                "Тех. карта не найдена в карточке товара",
+                "Тех. карта не найдена",
+            )
+            .with_reason(SkipReason::NoTechCard)
+            .for_order(order)
+            .with_product(ProductInfo::new(&product_id, &product_name, quantity, current_stock)));
+        }
+
+        // Получаем тех. карту
+        let processing_plan = processing_plan
+            .ok_or_else(|| anyhow!("Processing plan '{}' not found", tech_card_name))
+            .stage(PositionStage::TechCard)?;
+
+        info!("Found processing plan: {} ({})", processing_plan.name, processing_plan.id);
+
+        // Тех. карта, найденная по названию, артикулу или группе, может производить другой товар
+        if !processing_plan.outputs(&product_id) {
+            warn!(
+                "Processing plan {} does not produce {} ({})",
+                processing_plan.name, product_name, product_id
+            );
+            let problem = format!(
+                "Тех. карта '{}' не производит товар '{}'",
+                processing_plan.name, product_name
+            );
+            self.notifier
+                .notify(Notification::new(
+                    NotificationEvent::Failure,
+                    format!("Тех. карта не соответствует товару (заказ {})", order.name),
+                    problem.clone(),
+                ))
+                .await;
+            return Ok(ProcessingResult::failed(format!("Несоответствие тех. карты: {}", problem), problem)
+                .with_reason(SkipReason::TechCardMismatch)
+                .for_order(order)
+                .with_product(ProductInfo::new(&product_id, &product_name, quantity, current_stock)));
+        }
+
+        let (quantity, conversion_note) = match conversion {
+            Some(ref conversion) => {
+                let converted = conversion.apply(quantity);
+                let note = conversion.describe(quantity, converted);
+                info!("Production quantity for {} converted: {}", product_name, note);
+                (converted, Some(note))
+            }
+            None => (quantity, None),
+        };
+        let production_unit = conversion.as_ref().map(UnitConversion::unit);
+
+        // Тех. операция на этот товар уже есть (непроведённая или сегодняшняя): дубликат не создаём
+        // (строка плана уже учитывает тех. операции в работе)
+        if self.settings.skip_existing_productions && !demand.ignores_threshold() {
+            let existing = staged!(
+                self,
+                PositionStage::Create,
+                self.client.find_recent_productions(&product_id, &store.meta.href)
+            )?;
+            if let Some(existing) = existing.into_iter().next() {
+                info!(
+                    "Processing {} already produces {}, skipping",
+                    existing.name, product_name
+                );
+                let mut result = ProcessingResult::skipped(
+                    SkipReason::Duplicate,
+                    format!(
+                        "Тех. операция {} на '{}' уже существует, новая не создана",
+                        existing.name, product_name
+                    ),
+                )
+                .for_order(order)
+                .with_product(ProductInfo::new(&product_id, &product_name, quantity, current_stock));
+                result.existing_processing = Some(EntityRef {
+                    meta: existing.meta,
+                    id: Some(existing.id),
+                    name: Some(existing.name),
+                });
+                return Ok(result);
+            }
+        }
+
+        // Товар недавно уже производился: новую тех. операцию не создаём до конца окна
+        if let Some(last) = self.cooldown_since(&product_id, demand) {
+            info!("{} was produced at {}, cooldown active, skipping", product_name, last);
+            return Ok(ProcessingResult::skipped(
+                SkipReason::Cooldown,
+                format!(
+                    "'{}' уже запускался в производство {}, повторный запуск не раньше чем через {} сек.",
+                    product_name,
+                    last.format("%Y-%m-%d %H:%M:%S UTC"),
+                    self.settings.production_cooldown_secs
+                ),
+            )
+            .for_order(order)
+            .with_product(ProductInfo::new(&product_id, &product_name, quantity, current_stock)));
+        }
+
+        // Проверяем доступность материалов (если не проверены при выборе тех. карты)
+        let materials_check = match plan_check {
+            Some(check) => check,
+            None => staged!(
+                self,
+                PositionStage::Materials,
+                self.check_materials_availability(&processing_plan, quantity, store_id)
+            )?,
+        };
+
+        // Частичное производство: столько, на сколько хватает материалов
+        let partial_quantity = if !materials_check.available() && self.settings.partial_production {
+            Some(materials_check.max_producible(quantity)).filter(|q| *q >= Decimal::ONE)
+        } else {
+            None
+        };
+
+        if !materials_check.available() && partial_quantity.is_none() {
+            let missing = materials_check
+                .missing
+                .iter()
+                .map(|m| m.describe())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            warn!("Insufficient materials for production: {}", missing);
+            // О позиции из очереди ожидания уже сообщили при постановке в неё
+            if !self.shortages.contains(&order.id, &product_id) {
+                self.notifier
+                    .notify(Notification::new(
+                        NotificationEvent::Shortage,
+                        format!("Недостаточно материалов для '{}'", product_name),
+                        format!("Заказ {}, количество {}: {}", order.name, quantity, missing),
+                    ))
+                    .await;
+            }
+            let message = format!("Недостаточно материалов: {}", missing);
+            return Ok(ProcessingResult {
+                missing_materials: materials_check.missing,
+                ..ProcessingResult::failed(message.clone(), message)
+                    .with_reason(SkipReason::MaterialsShort)
+                    .for_order(order)
+                    .with_product(ProductInfo::new(&product_id, &product_name, quantity, current_stock))
+            });
+        }
+
+        let produce_quantity = partial_quantity.unwrap_or(quantity);
+        let shortfall = match partial_quantity {
+            Some(partial) => {
+                let missing = materials_check
+                    .missing
+                    .iter()
+                    .map(|m| m.describe())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                warn!(
+                    "Materials cover {} of {} for {}: {}",
+                    partial, quantity, product_name, missing
+                );
+                self.notifier
+                    .notify(Notification::new(
+                        NotificationEvent::Shortage,
+                        format!("Частичное производство '{}'", product_name),
+                        format!(
+                            "Заказ {}: будет произведено {} из {} шт., на остальные {} шт. не хватает: {}",
+                            order.name, partial, quantity, quantity - partial, missing
+                        ),
+                    ))
+                    .await;
+                materials_check.missing
+            }
+            None => Vec::new(),
+        };
+
+        // Тот же товар недавно уже запускался в производство: после создания
+        // тех. операции перепроверим остаток (кроме явной заявки внутреннего заказа и плана)
+        let began = self.in_progress.try_begin(&product_id);
+        let concurrent = !began && !demand.ignores_threshold();
+        if concurrent {
+            info!("Production of {} started recently, stock will be rechecked", product_name);
+        }
+
+        // Замены рассчитаны на всё количество: при частичном производстве они пересчитываются
+        // на произведённое, чтобы заменитель расходовался только на недостающее
+        let mut adjustments = materials_check.adjustments;
+        if partial_quantity.is_some() && !adjustments.substitutions.is_empty() {
+            let partial_check = staged!(
+                self,
+                PositionStage::Materials,
+                self.check_materials_availability(&processing_plan, produce_quantity, store_id)
+            )?;
+            adjustments = partial_check.adjustments;
+        }
+        // Остатки материалов и заменителей изменятся тех. операцией
+        let consumed: Vec<String> = materials_check
+            .materials
+            .iter()
+            .map(|m| m.id.clone())
+            .chain(adjustments.substitutions.iter().map(|s| s.substitute_id.clone()))
+            .collect();
+
+        // Создаём тех. операцию
+        let organization = staged!(self, PositionStage::Create, self.get_organization())?;
+        staged!(self, PositionStage::Create, self.resolve_production_project())?;
+        let created = staged!(
+            write self,
+            PositionStage::Create,
+            self.create_processing_operation(
+                &processing_plan,
+                store,
+                &organization,
+                produce_quantity,
+                order,
+                &adjustments
+            )
+        );
+        let processing = match created {
+            Ok(processing) => processing,
+            Err(e) => {
+                if began {
+                    self.in_progress.cancel(&product_id);
+                }
+                return Err(e);
+            }
+        };
+        self.stock_cache.invalidate(&product_id);
+        for material_id in &consumed {
+            self.stock_cache.invalidate(material_id);
+        }
+
+        if concurrent {
+            let stock_now = staged!(
+                self,
+                PositionStage::Stock,
+                self.client.get_product_stock(&product_id, store_id)
+            )?;
+            let threshold = decimal(self.threshold_for(&product_id));
+            if stock_now >= threshold {
+                info!(
+                    "Stock for {} already restored ({}), cancelling processing {}",
+                    product_name, stock_now, processing.name
+                );
+                staged!(
+                    self,
+                    PositionStage::Create,
+                    self.client.delete_processing(&processing.id)
+                )?;
+
+                return Ok(ProcessingResult::skipped(
+                    SkipReason::Duplicate,
+                    format!(
+                        "Остаток уже восстановлен параллельной тех. операцией ({} >= {})",
+                        stock_now, threshold
+                    ),
+                )
+                .for_order(order)
+                .with_product(ProductInfo::new(&product_id, &product_name, produce_quantity, current_stock)));
+            }
+        }
 
         // Проводим тех. операцию
-        let applied_processing = self.client.apply_processing(&processing.id).await?;
+        let applied_processing = staged!(
+            write self,
+            PositionStage::Apply,
+            self.client.apply_processing(&processing.id)
+        )?;
+
+        info!(
+            "Successfully created and applied processing: {} ({})",
+            applied_processing.name, applied_processing.id
+        );
+
+        self.log_production(order, &applied_processing.name, &product_name, produce_quantity)
+            .await;
+
+        self.notifier
+            .notify(Notification::new(
+                NotificationEvent::Success,
+                format!("Создана тех. операция {}", applied_processing.name),
+                match conversion_note {
+                    Some(ref note) => format!(
+                        "Производство {} шт. '{}' для заказа {} (пересчёт единиц: {})",
+                        produce_quantity, product_name, order.name, note
+                    ),
+                    None => format!(
+                        "Производство {} шт. '{}' для заказа {}",
+                        produce_quantity, product_name, order.name
+                    ),
+                },
+            ))
+            .await;
+
+        let message = if produce_quantity < quantity {
+            format!(
+                "Создана тех. операция для производства {} из {} шт. '{}', не хватает материалов на {} шт.",
+                produce_quantity, quantity, product_name, quantity - produce_quantity
+            )
+        } else {
+            format!(
+                "Создана тех. операция для производства {} шт. '{}'",
+                quantity, product_name
+            )
+        };
+        let message = match conversion_note {
+            Some(note) => format!("{} (пересчёт единиц: {})", message, note),
+            None => message,
+        };
+        let message = match plan_choice {
+            Some(choice) => format!("{} (тех. карта '{}' {})", message, processing_plan.name, choice),
+            None => message,
+        };
+
+        let mut produced = ProductInfo::new(&product_id, &product_name, produce_quantity, current_stock);
+        if produce_quantity < quantity {
+            produced.requested = Some(quantity);
+        }
+        produced.unit = production_unit;
+        Ok(ProcessingResult {
+            missing_materials: shortfall,
+            ..ProcessingResult::produced(message, &applied_processing.id, &applied_processing.name)
+                .for_order(order)
+                .with_product(produced)
+        })
+    }
+
+    /// Пробная обработка заказа процессором `sandbox`: тот же конвейер, что при
+    /// вебхуке, изменения не отправляются в МойСклад. Процессор блокируется только на
+    /// время подготовки `sandbox`.
+    pub async fn simulate_order(shared: &Mutex<Self>, order_id: &str) -> Result<OrderSimulation> {
+        let sandbox = shared.lock().await.sandbox(Playback::new(Vec::new(), true))?;
+        let (results, trace) = sandbox.replay(&WebhookEvent::customer_order(order_id)).await;
+
+        let mut writes = trace.writes;
+        let mut positions = Vec::new();
+        for result in results? {
+            // Изменения документа позиции: его создание и следующие запросы по его id
+            let own = match result.processing_id.clone() {
+                Some(id) => {
+                    let (own, rest) = writes.into_iter().partition(|write: &RecordedWrite| {
+                        write.id.as_deref() == Some(id.as_str()) || write.endpoint.contains(&id)
+                    });
+                    writes = rest;
+                    own
+                }
+                None => Vec::new(),
+            };
+            positions.push(PositionSimulation { result, writes: own });
+        }
+
+        Ok(OrderSimulation {
+            order_id: order_id.to_string(),
+            positions,
+            writes,
+        })
+    }
+
+    /// Повторить событие процессором `sandbox`: полная обработка документа, изменения
+    /// не отправляются в МойСклад. Трасса возвращается и при ошибке обработки.
+    pub async fn replay(mut self, event: &WebhookEvent) -> (Result<Vec<ProcessingResult>>, PlaybackTrace) {
+        let results = self.process_now(event).await;
+        (results, self.playback_trace())
+    }
+
+    /// Записать ответы API, которые читает обработка заказа: заказ, склад, товары,
+    /// тех. карты, остатки и материалы. Заказ обрабатывается процессором `sandbox`,
+    /// поэтому записываются ровно те запросы, что нужны для воспроизведения.
+    pub async fn record_order_responses(&self, order_id: &str) -> Result<Vec<RecordedResponse>> {
+        let (results, trace) = self
+            .sandbox(Playback::new(Vec::new(), true))?
+            .replay(&WebhookEvent::customer_order(order_id))
+            .await;
+        results?;
+        Ok(trace.api_calls)
+    }
+
+    /// Производственный план: неотгруженная потребность заказов по товарам за вычетом
+    /// остатка и непроведённых тех. операций на склад. Документы не создаются.
+    pub async fn compute_plan(shared: &Mutex<Self>, scope: &PlanScope) -> Result<ProductionPlan> {
+        let context = Self::catalogue_context(shared).await?;
+        let CatalogueContext { client, store, attribute, .. } = &context;
+        let store_id = store.id.clone().ok_or_else(|| anyhow!("Store ID missing"))?;
+        let mut plan = ProductionPlan::new();
+
+        let orders = match scope {
+            PlanScope::Period { from, to } => {
+                client
+                    .get_customer_orders_shipping_between(
+                        &store.meta.href,
+                        &from.format("%Y-%m-%d 00:00:00").to_string(),
+                        &to.format("%Y-%m-%d 23:59:59").to_string(),
+                    )
+                    .await?
+            }
+            PlanScope::Orders(ids) => {
+                let mut orders = Vec::with_capacity(ids.len());
+                for id in ids {
+                    let order = client.get_customer_order(id).await?;
+                    if !order.applicable {
+                        plan.notes.push(format!("Заказ {} не проведён", order.name));
+                    } else if order.store.as_ref().is_some_and(|s| s.id != store.id) {
+                        plan.notes.push(format!("Заказ {} с другого склада", order.name));
+                    } else {
+                        orders.push(order);
+                    }
+                }
+                orders
+            }
+        };
+
+        // Позиция, по которой определяется тех. карта товара
+        let mut positions: HashMap<String, CustomerOrderPosition> = HashMap::new();
+        for order in &orders {
+            for position in order.positions.iter().flat_map(|p| &p.rows) {
+                let remaining = position.quantity - position.shipped;
+                let producible = AssortmentKind::from_meta(&position.assortment.meta).is_producible();
+                if remaining <= Decimal::ZERO || !producible {
+                    continue;
+                }
+                let Some(product_id) = position.assortment.meta.href.rsplit('/').next() else {
+                    continue;
+                };
+                let product_name = position.assortment.name.as_deref().unwrap_or("unknown");
+                plan.add_demand(product_id, product_name, &order.name, remaining);
+                positions.entry(product_id.to_string()).or_insert_with(|| position.clone());
+            }
+        }
+        plan.orders = orders.len();
+
+        let stock: HashMap<String, Decimal> = client
+            .get_store_stock(&store.meta.href)
+            .await?
+            .into_iter()
+            .map(|row| (row.meta.href.rsplit('/').next().unwrap_or("").to_string(), row.stock))
+            .collect();
+        let pending = client.get_pending_productions(&store.meta.href).await?;
+
+        for item in &mut plan.items {
+            item.stock = stock.get(&item.product_id).copied().unwrap_or_default();
+            item.in_production = pending
+                .iter()
+                .map(|processing| processing.produced_quantity(&item.product_id))
+                .sum();
+            let shortfall = item.demand - item.stock - item.in_production;
+            item.quantity = round_quantity(shortfall).max(Decimal::ZERO);
+            if item.quantity.is_zero() {
+                continue;
+            }
+
+            let Some(position) = positions.get(&item.product_id) else {
+                continue;
+            };
+            let processor = shared.lock().await;
+            if let Err(e) = processor.plan_item_materials(item, position, &attribute.id, &store_id).await {
+                item.error = Some(e.to_string());
+            }
+        }
+
+        plan.items.sort_by(|a, b| a.product_name.cmp(&b.product_name));
+        Ok(plan)
+    }
+
+    /// Тех. карта и материалы строки плана
+    async fn plan_item_materials(
+        &self,
+        item: &mut PlanItem,
+        position: &CustomerOrderPosition,
+        attribute_id: &str,
+        store_id: &str,
+    ) -> Result<()> {
+        let product = self.position_product(position, &item.product_id).await?;
+        let tech_card_name = self.find_tech_card_name(&product, attribute_id);
+        let selected = self
+            .select_plan(&product, &tech_card_name, item.quantity, store_id)
+            .await?;
+        let Some(PlanSelection { plan: processing_plan, .. }) = selected else {
+            item.error = Some(if tech_card_name.is_empty() {
+                "Тех. карта не найдена в карточке товара".to_string()
+            } else {
+                format!("Тех. карта '{}' не найдена", tech_card_name)
+            });
+            return Ok(());
+        };
+        if !processing_plan.outputs(&item.product_id) {
+            item.error = Some(format!("Тех. карта '{}' не производит этот товар", processing_plan.name));
+            return Ok(());
+        }
+
+        let check = self
+            .report_materials_availability(&processing_plan, item.quantity, store_id)
+            .await?;
+        item.tech_card = Some(EntityRef {
+            meta: processing_plan.meta.clone(),
+            id: Some(processing_plan.id.clone()),
+            name: Some(processing_plan.name.clone()),
+        });
+        item.materials_available = Some(check.available());
+        item.materials = check.materials;
+        Ok(())
+    }
+
+    /// Документ-основание для выполнения производственного плана заданием `job_id`
+    pub async fn plan_document(&mut self, job_id: &str) -> Result<CustomerOrder> {
+        let store = self.get_store().await?;
+        let organization = self.get_organization().await?;
+        let offset = chrono::Duration::hours(self.settings.moysklad_utc_offset_hours as i64);
+        let moment = (chrono::Utc::now() + offset).format("%Y-%m-%d %H:%M:%S").to_string();
+        Ok(CustomerOrder::production_plan(job_id, moment, organization, store))
+    }
+
+    /// Создать тех. операцию по строке производственного плана (возможно, исправленной
+    /// вручную). Строка обрабатывается как позиция документа `plan` под блокировкой товара:
+    /// с паузой, исключениями и способом пополнения из настроек товара, но без порога.
+    pub async fn execute_plan_item(&mut self, plan: &CustomerOrder, item: &PlanItem) -> ProcessingResult {
+        let product_lock = self.locks.acquire(&format!("product:{}", item.product_id)).await;
+        let outcome = match product_lock {
+            Ok(lock) => {
+                let outcome = self.process_plan_item(plan, item).await;
+                self.locks.release(lock).await;
+                outcome
+            }
+            Err(e) => Err(PositionError::new(PositionStage::Lock, e)),
+        };
+
+        let result = match outcome {
+            Ok(result) => {
+                info!("Plan item {}: {}", item.product_name, result.message);
+                result
+            }
+            Err(e) => {
+                error!("Failed to execute plan item {} ({}): {}", item.product_name, e.code(), e);
+                let mut failed = ProcessingResult::failed(
+                    format!("Ошибка создания тех. операции по плану: {}", e),
+                    e.to_string(),
+                )
+                .for_order(plan)
+                .with_product(ProductInfo::new(&item.product_id, &item.product_name, item.quantity, item.stock));
+                failed.error_details = Some(e.info());
+                failed
+            }
+        };
+
+        let mut record = HistoryRecord::from_result(&result);
+        record.order_type = plan.meta.entity_type.clone();
+        self.history.append(record);
+        result
+    }
+
+    async fn process_plan_item(
+        &mut self,
+        plan: &CustomerOrder,
+        item: &PlanItem,
+    ) -> Result<ProcessingResult, PositionError> {
+        let store = staged!(self, PositionStage::Stock, self.get_store())?;
+        let store_id = store
+            .id
+            .clone()
+            .ok_or_else(|| anyhow!("Store ID missing"))
+            .stage(PositionStage::Stock)?;
+
+        // План мог устареть: потребность уже покрыта остатком и тех. операциями в работе,
+        // или строка уже выполнялась — с расчёта плана прибыло не меньше её количества
+        if item.quantity > Decimal::ZERO {
+            let stock = staged!(
+                self,
+                PositionStage::Stock,
+                self.client.get_product_stock_info(&item.product_id, &store_id)
+            )?
+            .map(|s| s.stock)
+            .unwrap_or_default();
+            let in_production = staged!(
+                self,
+                PositionStage::Stock,
+                self.client.get_pending_production_quantity(&item.product_id, &store.meta.href)
+            )?;
+            let available = stock + in_production;
+            let arrived = available - (item.stock + item.in_production);
+            if (item.demand > Decimal::ZERO && available >= item.demand) || arrived >= item.quantity {
+                return Ok(ProcessingResult::skipped(
+                    SkipReason::StockSufficient,
+                    format!(
+                        "Строка плана уже покрыта: потребность {}, остаток {}, в производстве {}",
+                        item.demand, stock, in_production
+                    ),
+                )
+                .for_order(plan)
+                .with_product(ProductInfo::new(&item.product_id, &item.product_name, item.quantity, stock)));
+            }
+        }
+
+        let product = staged!(
+            self,
+            PositionStage::Product,
+            self.client.get_product(&item.product_id)
+        )?;
+        let position = CustomerOrderPosition::of(product.into(), item.quantity);
+        self.process_position(plan, &position, Demand::Plan).await
+    }
+
+    /// Товар позиции: берётся из `expand=positions.assortment`, запрос к API —
+    /// только если ассортимент пришёл свёрнутым
+    async fn position_product(
+        &self,
+        position: &CustomerOrderPosition,
+        product_id: &str,
+    ) -> Result<Product> {
+        let mut product = match position.assortment.to_product() {
+            Some(product) => {
+                debug!("Using expanded assortment for {}", product.name);
+                product
+            }
+            None => self.client.get_product(product_id).await?,
+        };
+
+        // Путь группы нужен для тех. карт по группам товаров
+        if !self.folder_tech_cards.is_empty()
+            && product.path_name.is_none()
+            && let Some(ref folder) = product.product_folder
+        {
+            match self.client.get_product_folder(&folder.meta.href).await {
+                Ok(folder) => product.path_name = Some(folder.full_path()),
+                Err(e) => warn!("Failed to resolve folder of {}: {:#}", product.name, e),
+            }
+        }
+
+        Ok(product)
+    }
+
+    /// Найти название тех. карты: из настроек товара, в поле по ID, затем в запасных
+    /// источниках (TECH_CARD_FALLBACKS) в заданном порядке и по группе товара
+    fn find_tech_card_name(&self, product: &Product, attribute_id: &str) -> String {
+        if let Some(name) = self.overrides.get(&product.id).tech_card.filter(|n| !n.trim().is_empty()) {
+            debug!("Tech card for {} taken from product settings: {}", product.name, name);
+            return name;
+        }
+
+        let from_attribute = product
+            .attributes
+            .iter()
+            .flatten()
+            .find(|attr| attr.id == attribute_id)
+            .and_then(|attr| attr.as_string())
+            .filter(|value| !value.trim().is_empty());
+
+        if let Some(name) = from_attribute {
+            return name;
+        }
+
+        for source in &self.tech_card_sources {
+            if let Some(name) = source.find(product, &self.settings.tech_card_description_prefix) {
+                debug!("Tech card for {} taken from {:?}: {}", product.name, source, name);
+                return name;
+            }
+        }
+
+        if let Some(name) = self.folder_tech_cards.find(product) {
+            debug!("Tech card for {} taken from folder mapping: {}", product.name, name);
+            return name;
+        }
+
+        String::new()
+    }
+
+    /// Таймаут и число повторов этапа обработки позиции
+    fn stage_limits(&self, stage: PositionStage) -> (Option<Duration>, u32) {
+        let (secs, retries) = match stage {
+            PositionStage::Lock | PositionStage::Stock | PositionStage::Product => {
+                (self.settings.stock_timeout_secs, self.settings.stock_retries)
+            }
+            PositionStage::TechCard | PositionStage::Materials => {
+                (self.settings.plan_timeout_secs, self.settings.plan_retries)
+            }
+            PositionStage::Create | PositionStage::Apply => {
+                (self.settings.create_timeout_secs, self.settings.create_retries)
+            }
+        };
+        (Some(Duration::from_secs(secs)).filter(|d| !d.is_zero()), retries)
+    }
+
+    /// Порог остатка товара: из его настроек, по скорости продаж (DYNAMIC_THRESHOLDS)
+    /// или MIN_STOCK_THRESHOLD
+    fn threshold_for(&self, product_id: &str) -> f64 {
+        self.overrides
+            .get(product_id)
+            .threshold
+            .or_else(|| self.dynamic_thresholds.get(product_id))
+            .unwrap_or(self.settings.min_stock_threshold)
+    }
+
+    /// Остаток для проверки порога: сначала из кэша (STOCK_CACHE_REFRESH_SECS). Остаток
+    /// ниже порога перечитывается из МойСклад, чтобы документ не создавался по устаревшему
+    /// остатку; без кэша — сразу из МойСклад.
+    async fn threshold_stock(&self, product_id: &str, store: &EntityRef, threshold: Decimal) -> Result<StockSnapshot> {
+        if self.stock_cache.get(product_id, &store.meta.href).is_some() {
+            let cached = self.stock_snapshot(product_id, store, true).await?;
+            if cached.effective >= threshold {
+                return Ok(cached);
+            }
+            debug!("Cached stock of {} is below threshold, reading it from Moysklad", product_id);
+        }
+        self.stock_snapshot(product_id, store, false).await
+    }
+
+    /// Остаток товара для решения о пополнении: по STOCK_MODE, плюс ожидание
+    /// (COUNT_IN_TRANSIT) и непроведённые тех. операции на склад (COUNT_PENDING_PRODUCTIONS).
+    /// `cached` — взять остаток из кэша, если он свежий; иначе остаток читается из МойСклад.
+    async fn stock_snapshot(&self, product_id: &str, store: &EntityRef, cached: bool) -> Result<StockSnapshot> {
+        let store_id = store.id.as_ref().ok_or_else(|| anyhow!("Store ID missing"))?;
+        let levels = if cached { self.stock_cache.get(product_id, &store.meta.href) } else { None };
+        let (stock, reserve, in_transit) = match levels {
+            Some(levels) => levels,
+            None => self
+                .client
+                .get_product_stock_info(product_id, store_id)
+                .await?
+                .map(|s| (s.stock, s.reserve, s.in_transit))
+                .unwrap_or_default(),
+        };
+
+        let pending_production = if self.settings.count_pending_productions {
+            self.client
+                .get_pending_production_quantity(product_id, &store.meta.href)
+                .await?
+        } else {
+            Decimal::ZERO
+        };
+
+        let mut effective = self.stock_mode.effective(stock, reserve, in_transit) + pending_production;
+        if self.settings.count_in_transit {
+            effective += in_transit;
+        }
+
+        Ok(StockSnapshot {
+            stock,
+            reserve,
+            in_transit,
+            available: stock - reserve,
+            pending_production,
+            effective,
+        })
+    }
+
+    /// Найти тех. карту товара: из кэша или по цепочке PLAN_LOOKUP_MODE.
+    /// `tech_card_name` — название из поля с тех. картой (может быть пустым).
+    /// Из нескольких подходящих тех. карт берётся первая (см. plans_by_names).
+    async fn find_plan(&self, product: &Product, tech_card_name: &str) -> Result<Option<ProcessingPlan>> {
+        Ok(self.find_plans(product, tech_card_name).await?.into_iter().next())
+    }
+
+    /// Все подходящие тех. карты товара в порядке выбора
+    async fn find_plans(&self, product: &Product, tech_card_name: &str) -> Result<Vec<ProcessingPlan>> {
+        if let Some(plans) = self.plan_cache.get(&product.id, tech_card_name) {
+            debug!("Processing plans for {} taken from cache: {}", product.name, plan_names(&plans));
+            return Ok(plans);
+        }
+
+        let plans = self.lookup_plans(product, tech_card_name).await?;
+        if !plans.is_empty() {
+            self.plan_cache.insert(&product.id, tech_card_name, &plans);
+        }
+        Ok(plans)
+    }
+
+    /// Найти тех. карты по цепочке PLAN_LOOKUP_MODE
+    async fn lookup_plans(&self, product: &Product, tech_card_name: &str) -> Result<Vec<ProcessingPlan>> {
+        for lookup in &self.plan_lookups {
+            let plans = match lookup {
+                PlanLookup::Attribute if !tech_card_name.is_empty() => {
+                    self.plans_by_names(tech_card_name).await?
+                }
+                PlanLookup::Article => match non_empty(&product.article) {
+                    Some(article) => self
+                        .client
+                        .find_processing_plan_by_external_code(article)
+                        .await?
+                        .into_iter()
+                        .collect(),
+                    None => Vec::new(),
+                },
+                PlanLookup::Code => match non_empty(&product.code) {
+                    Some(code) => {
+                        self.client.find_processing_plan_by_code(code).await?.into_iter().collect()
+                    }
+                    None => Vec::new(),
+                },
+                PlanLookup::Attribute => Vec::new(),
+            };
+
+            if plans.is_empty() {
+                debug!("No processing plan for {} by {}", product.name, lookup.as_str());
+                continue;
+            }
+            info!(
+                "Processing plan for {} found by {}: {}",
+                product.name,
+                lookup.as_str(),
+                plan_names(&plans)
+            );
+            return Ok(plans);
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Тех. карты по названиям из поля (несколько — через запятую) вместе с их версиями
+    /// `<название> vN`. Названия — в порядке поля, версии одного названия — сначала
+    /// старшие, затем недавно изменённые.
+    async fn plans_by_names(&self, tech_card_name: &str) -> Result<Vec<ProcessingPlan>> {
+        let mut plans: Vec<ProcessingPlan> = Vec::new();
+        for base in split_names(tech_card_name) {
+            let mut versioned = self.plan_versions(base).await?;
+            versioned.sort_by(|(a_version, a), (b_version, b)| {
+                b_version.cmp(a_version).then_with(|| b.updated.cmp(&a.updated))
+            });
+            for (_, plan) in versioned {
+                if !plans.iter().any(|known| known.id == plan.id) {
+                    plans.push(plan);
+                }
+            }
+        }
+        Ok(plans)
+    }
+
+    /// Тех. карта `base` по точному названию и её версии `<base> vN`
+    async fn plan_versions(&self, base: &str) -> Result<Vec<(u32, ProcessingPlan)>> {
+        let mut versioned = Vec::new();
+        if let Some(plan) = self.client.find_processing_plan_by_name(base).await? {
+            versioned.push((0, plan));
+        }
+
+        for name in self.client.find_processing_plan_names(base).await? {
+            let Some(version) = plan_version(&name, base).filter(|version| *version > 0) else {
+                continue;
+            };
+            if let Some(plan) = self.client.find_processing_plan_by_name(&name).await? {
+                versioned.push((version, plan));
+            }
+        }
+        Ok(versioned)
+    }
 
-        info!(
-            "Successfully created and applied processing: {} ({})",
-            applied_processing.name, applied_processing.id
-        );
+    /// Выбрать тех. карту для производства `quantity`: из нескольких подходящих — первую
+    /// (см. plans_by_names), на которую хватает материалов, иначе первую. Причина выбора
+    /// и проверка материалов возвращаются, если тех. карт несколько.
+    async fn select_plan(
+        &self,
+        product: &Product,
+        tech_card_name: &str,
+        quantity: Decimal,
+        store_id: &str,
+    ) -> Result<Option<PlanSelection>> {
+        let mut candidates = self.find_plans(product, tech_card_name).await?;
+        // Тех. карты других товаров не выбираются, если есть тех. карты этого товара
+        if candidates.iter().any(|plan| plan.outputs(&product.id)) {
+            candidates.retain(|plan| plan.outputs(&product.id));
+        }
+        if candidates.len() <= 1 {
+            return Ok(candidates.pop().map(|plan| PlanSelection { plan, reason: None, check: None }));
+        }
 
-        Ok(ProcessingResult {
-            success: true,
-            message: format!(
-                "Создана тех. операция для производства {} шт. '{}'",
-                quantity, product_name
+        let names = plan_names(&candidates);
+        let mut checks = Vec::with_capacity(candidates.len());
+        let mut chosen = None;
+        for (index, plan) in candidates.iter().enumerate() {
+            let check = self.check_materials_availability(plan, quantity, store_id).await?;
+            let available = check.available();
+            checks.push(check);
+            if available {
+                chosen = Some(index);
+                break;
+            }
+        }
+
+        let reason = match chosen {
+            Some(_) => format!("выбрана из {} ({}): хватает материалов", candidates.len(), names),
+            None => format!(
+                "выбрана из {} ({}): первая по порядку, материалов не хватает ни на одну",
+                candidates.len(),
+                names
             ),
-            order_id: Some(order.id.clone()),
-            order_name: Some(order.name.clone()),
-            processing_id: Some(applied_processing.id.clone()),
-            processing_name: Some(applied_processing.name.clone()),
-            product: Some(ProductInfo {
-                id: product_id.clone(),
-                name: product_name.clone(),
-                quantity,
-                stock_before: current_stock,
-            }),
-            error: None,
+        };
+        // Проверена каждая тех. карта до выбранной включительно
+        let index = chosen.unwrap_or(0);
+        let plan = candidates.swap_remove(index);
+        info!("Processing plan for {}: {} {}", product.name, plan.name, reason);
+        Ok(Some(PlanSelection {
+            plan,
+            reason: Some(reason),
+            check: checks.into_iter().nth(index),
+        }))
+    }
+
+    /// Проверить доступность материалов для производства. Полуфабрикаты не раскрываются
+    /// и при BOM_ROLLUP: тех. операция списывает полуфабрикат со склада, а сама его
+    /// не производит, поэтому его нехватка — нехватка материала.
+    async fn check_materials_availability(
+        &self,
+        processing_plan: &ProcessingPlan,
+        quantity: Decimal,
+        store_id: &str,
+    ) -> Result<MaterialsCheckResult> {
+        self.check_materials_level(processing_plan, quantity, store_id, 0, None, None)
+            .await
+    }
+
+    /// Проверить материалы для отчёта. При BOM_ROLLUP нехватка полуфабриката с собственной
+    /// тех. картой раскрывается до материалов нижних уровней: сколько сырья нужно, чтобы
+    /// произвести полуфабрикат отдельной тех. операцией.
+    async fn report_materials_availability(
+        &self,
+        processing_plan: &ProcessingPlan,
+        quantity: Decimal,
+        store_id: &str,
+    ) -> Result<MaterialsCheckResult> {
+        let rollup = self.settings.bom_rollup.then(|| vec![processing_plan.id.clone()]);
+        self.check_materials_level(processing_plan, quantity, store_id, 0, None, rollup)
+            .await
+    }
+
+    /// Проверить материалы одного уровня цепочки тех. карт. `rollup` — раскрывать
+    /// полуфабрикаты; в нём тех. карты цепочки, пройденные до этого уровня.
+    fn check_materials_level<'a>(
+        &'a self,
+        processing_plan: &'a ProcessingPlan,
+        quantity: Decimal,
+        store_id: &'a str,
+        level: u32,
+        parent: Option<String>,
+        rollup: Option<Vec<String>>,
+    ) -> Pin<Box<dyn Future<Output = Result<MaterialsCheckResult>> + Send + 'a>> {
+        Box::pin(async move {
+            let materials_expanded = match &processing_plan.materials {
+                Some(m) => m,
+                None => return Ok(MaterialsCheckResult::default()),
+            };
+
+            let materials = match &materials_expanded.rows {
+                Some(r) => r,
+                None => return Ok(MaterialsCheckResult::default()),
+            };
+
+            let mut result = MaterialsCheckResult::default();
+
+            for material in materials {
+                let material_id = material.product.meta.href
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or("");
+
+                let waste = self.waste_factor(material_id).await?;
+                // Тот же расход, что попадёт в тех. операцию (fill_positions)
+                let material_qty = material_requirement(material.quantity, quantity, waste);
+                if level == 0 && waste != Decimal::ONE {
+                    result.adjustments.waste.insert(material_id.to_string(), waste);
+                }
+
+                let stock_info = self.client.get_product_stock_info(material_id, store_id).await?;
+                let (stock, reserve) = stock_info
+                    .as_ref()
+                    .map(|info| (info.stock, info.reserve))
+                    .unwrap_or_default();
+                let available = stock_info
+                    .map(|info| self.stock_mode.effective(info.stock, info.reserve, info.in_transit))
+                    .unwrap_or_default();
+
+                let material_name = material.product.name.clone()
+                    .unwrap_or_else(|| "unknown".to_string());
+
+                debug!(
+                    "Material {} stock: {}, needed: {} (level {})",
+                    material_name, available, material_qty, level
+                );
+
+                let mut missing = (material_qty - available).max(Decimal::ZERO);
+                let mut produced_by = None;
+                let mut nested = None;
+
+                let visited = rollup
+                    .as_ref()
+                    .filter(|_| missing > Decimal::ZERO && level < self.settings.bom_max_depth);
+                if let Some(visited) = visited
+                    && let Some(sub_plan) = self.find_material_plan(material_id).await?
+                {
+                    if visited.contains(&sub_plan.id) {
+                        warn!("Tech card cycle detected at {}, stopping rollup", sub_plan.name);
+                    } else {
+                        let mut sub_visited = visited.clone();
+                        sub_visited.push(sub_plan.id.clone());
+                        let sub_check = self
+                            .check_materials_level(
+                                &sub_plan,
+                                round_quantity(missing),
+                                store_id,
+                                level + 1,
+                                Some(material_name.clone()),
+                                Some(sub_visited),
+                            )
+                            .await?;
+                        produced_by = Some(sub_plan.name.clone());
+                        nested = Some(sub_check);
+                    }
+                }
+
+                match nested {
+                    // Полуфабрикат можно произвести: нехватка переносится на его материалы
+                    Some(sub_check) => {
+                        result.missing.extend(sub_check.missing);
+                        result.materials.push(MaterialRequirement {
+                            id: material_id.to_string(),
+                            name: material_name,
+                            required: material_qty,
+                            stock,
+                            reserve,
+                            available,
+                            missing,
+                            level,
+                            parent: parent.clone(),
+                            produced_by,
+                            substituted_by: None,
+                        });
+                        result.materials.extend(sub_check.materials);
+                    }
+                    None => {
+                        // Заменитель покрывает нехватку материала основной тех. карты
+                        let mut substituted_by = None;
+                        if missing > Decimal::ZERO && level == 0 && !self.substitutes.is_empty() {
+                            let claimed = &result.adjustments.substitutions;
+                            if let Some(substitution) = self
+                                .find_substitute(material_id, &material_name, missing, store_id, claimed)
+                                .await?
+                            {
+                                info!("{}", substitution.describe());
+                                missing = (missing - substitution.quantity).max(Decimal::ZERO);
+                                substituted_by = Some(substitution.substitute.clone());
+                                result.adjustments.substitutions.push(substitution);
+                            }
+                        }
+
+                        if missing > Decimal::ZERO {
+                            result.missing.push(MaterialShortage {
+                                name: material_name.clone(),
+                                quantity: missing,
+                                level,
+                                parent: parent.clone(),
+                            });
+                        }
+
+                        result.materials.push(MaterialRequirement {
+                            id: material_id.to_string(),
+                            name: material_name,
+                            required: material_qty,
+                            stock,
+                            reserve,
+                            available,
+                            missing,
+                            level,
+                            parent: parent.clone(),
+                            produced_by,
+                            substituted_by,
+                        });
+                    }
+                }
+            }
+
+            Ok(result)
         })
     }
 
-    /// Найти название тех. карты в атрибутах товара
-    fn find_tech_card_name(&self, product: &Product) -> Result<String> {
-        let attributes = match &product.attributes {
-            Some(attrs) => attrs,
-            None => return Ok(String::new()),
+    /// Коэффициент расхода материала с учётом потерь. Поле WASTE_FIELD_NAME читается
+    /// один раз на PLAN_CACHE_TTL_SECS вместе с тех. картами, а не на каждый материал.
+    async fn waste_factor(&self, material_id: &str) -> Result<Decimal> {
+        let Some(ref field) = self.settings.waste_field_name else {
+            return Ok(self.waste_factor_by_id(material_id));
         };
+        if self.overrides.get(material_id).waste_percent.is_some() {
+            return Ok(self.waste_factor_by_id(material_id));
+        }
 
-        for attr in attributes {
-            if attr.name == self.settings.tech_card_field_name {
-                if let Some(value) = attr.as_string() {
-                    return Ok(value);
-                }
+        let percent = match self.plan_cache.waste_percent(material_id) {
+            Some(percent) => percent,
+            None => {
+                let product = self.client.get_product(material_id).await?;
+                let percent = field_waste_percent(&product, field);
+                self.plan_cache.insert_waste_percent(material_id, percent);
+                percent
             }
+        };
+        Ok(waste_multiplier(percent.unwrap_or(self.settings.waste_percent)))
+    }
+
+    /// Потери из настроек товара, иначе WASTE_PERCENT
+    fn waste_factor_by_id(&self, material_id: &str) -> Decimal {
+        let percent = self
+            .overrides
+            .get(material_id)
+            .waste_percent
+            .unwrap_or(self.settings.waste_percent);
+        waste_multiplier(percent)
+    }
+
+    /// Потери из настроек товара, поля WASTE_FIELD_NAME, иначе WASTE_PERCENT
+    fn waste_factor_of(&self, product: &Product) -> Decimal {
+        let Some(ref field) = self.settings.waste_field_name else {
+            return self.waste_factor_by_id(&product.id);
+        };
+        if self.overrides.get(&product.id).waste_percent.is_some() {
+            return self.waste_factor_by_id(&product.id);
         }
 
-        Ok(String::new())
+        let percent = field_waste_percent(product, field);
+        self.plan_cache.insert_waste_percent(&product.id, percent);
+        waste_multiplier(percent.unwrap_or(self.settings.waste_percent))
     }
 
-    /// Проверить доступность материалов
-    async fn check_materials_availability(
+    /// Заменитель недостающего материала по MATERIAL_SUBSTITUTES_FILE: первый из правил,
+    /// которого есть на складе. Покрывает нехватку целиком или частично. Заменитель,
+    /// уже занятый заменами других материалов (`claimed`), учитывается за вычетом занятого.
+    async fn find_substitute(
         &self,
-        processing_plan: &ProcessingPlan,
-        quantity: f64,
+        material_id: &str,
+        material_name: &str,
+        missing: Decimal,
         store_id: &str,
-    ) -> Result<MaterialsCheckResult> {
-        let materials_expanded = match &processing_plan.materials {
-            Some(m) => m,
-            None => return Ok(MaterialsCheckResult::available()),
+        claimed: &[MaterialSubstitution],
+    ) -> Result<Option<MaterialSubstitution>> {
+        for rule in self.substitutes.for_material(material_id, material_name) {
+            let stock_info = self.client.get_product_stock_info(&rule.substitute, store_id).await?;
+            let Some(info) = stock_info else {
+                continue;
+            };
+            let reserved: Decimal = claimed
+                .iter()
+                .filter(|s| s.substitute_id == rule.substitute)
+                .map(|s| s.substitute_quantity)
+                .sum();
+            let available = self.stock_mode.effective(info.stock, info.reserve, info.in_transit) - reserved;
+            let covered = round_quantity(missing.min(available / decimal(rule.ratio)));
+            if covered <= Decimal::ZERO {
+                continue;
+            }
+
+            return Ok(Some(MaterialSubstitution {
+                material_id: material_id.to_string(),
+                material: material_name.to_string(),
+                substitute_id: rule.substitute.clone(),
+                substitute: info.name,
+                quantity: covered,
+                substitute_quantity: round_quantity(covered * decimal(rule.ratio)),
+                substitute_meta: Some(info.meta),
+            }));
+        }
+
+        Ok(None)
+    }
+
+    /// Тех. карта полуфабриката: по карточке материала и цепочке PLAN_LOOKUP_MODE
+    async fn find_material_plan(&self, material_id: &str) -> Result<Option<ProcessingPlan>> {
+        let Some(ref attribute) = self.tech_card_attribute_cache else {
+            debug!("Tech card field not resolved yet, skipping rollup");
+            return Ok(None);
+        };
+
+        let product = self.client.get_product(material_id).await?;
+        let tech_card_name = self.find_tech_card_name(&product, &attribute.id);
+
+        self.find_plan(&product, &tech_card_name).await
+    }
+
+    /// Пополнить остаток перемещением со склада-источника (не больше, чем там доступно)
+    pub(crate) async fn replenish_by_move(
+        &mut self,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError> {
+        let ReplenishRequest { order, position, info, store, .. } = request;
+        let source = staged!(self, PositionStage::Stock, self.get_source_store())?;
+        let source_id = source
+            .id
+            .as_ref()
+            .ok_or_else(|| anyhow!("Source store ID missing"))
+            .stage(PositionStage::Stock)?;
+        let source_available = staged!(
+            self,
+            PositionStage::Stock,
+            self.client.get_product_stock(&info.id, source_id)
+        )?;
+        let move_quantity = info.quantity.min(round_quantity(source_available.max(Decimal::ZERO)));
+
+        if move_quantity <= Decimal::ZERO {
+            warn!("No stock of {} on source store to move", info.name);
+            self.notifier
+                .notify(Notification::new(
+                    NotificationEvent::Shortage,
+                    format!("Нет остатка для перемещения '{}'", info.name),
+                    format!(
+                        "Заказ {}: на складе '{}' нет товара для перемещения",
+                        order.name,
+                        source.name.clone().unwrap_or_default()
+                    ),
+                ))
+                .await;
+            return Ok(ProcessingResult::failed(
+                "Нет остатка на складе-источнике для перемещения",
+                "Нет остатка на складе-источнике",
+            )
+            .for_order(order)
+            .with_product(info));
+        }
+
+        let organization = staged!(self, PositionStage::Create, self.get_organization())?;
+        let request = CreateMoveRequest {
+            organization: EntityRefSmall {
+                meta: organization.meta.clone(),
+            },
+            source_store: EntityRefSmall {
+                meta: source.meta.clone(),
+            },
+            target_store: EntityRefSmall {
+                meta: store.meta.clone(),
+            },
+            applicable: true,
+            description: Some(document_description(order)),
+            positions: vec![DocumentPosition {
+                quantity: move_quantity,
+                assortment: EntityRefSmall {
+                    meta: position.assortment.meta.clone(),
+                },
+            }],
         };
+        let created = staged!(write self, PositionStage::Create, self.client.create_move(&request))?;
+        self.stock_cache.invalidate(&info.id);
+
+        info!("Created move {} for {} x{}", created.name, info.name, move_quantity);
+        self.notifier
+            .notify(Notification::new(
+                NotificationEvent::Success,
+                format!("Создано перемещение {}", created.name),
+                format!(
+                    "Перемещение {} шт. '{}' для заказа {}",
+                    move_quantity, info.name, order.name
+                ),
+            ))
+            .await;
 
-        let materials = match &materials_expanded.rows {
-            Some(r) => r,
-            None => return Ok(MaterialsCheckResult::available()),
+        Ok(ProcessingResult::produced(
+            format!(
+                "Создано перемещение {} шт. '{}' со склада '{}'",
+                move_quantity,
+                info.name,
+                source.name.clone().unwrap_or_default()
+            ),
+            &created.id,
+            &created.name,
+        )
+        .with_document_type("move")
+        .for_order(order)
+        .with_product(info))
+    }
+
+    /// Пополнить остаток заказом поставщику (PURCHASE_SUPPLIER_NAME)
+    pub(crate) async fn purchase(
+        &mut self,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError> {
+        let ReplenishRequest { order, position, info, store, .. } = request;
+
+        let supplier = staged!(self, PositionStage::Create, self.get_supplier())?;
+        let organization = staged!(self, PositionStage::Create, self.get_organization())?;
+        let request = CreatePurchaseOrderRequest {
+            organization: EntityRefSmall {
+                meta: organization.meta.clone(),
+            },
+            agent: EntityRefSmall {
+                meta: supplier.meta.clone(),
+            },
+            store: EntityRefSmall {
+                meta: store.meta.clone(),
+            },
+            description: Some(document_description(order)),
+            positions: vec![DocumentPosition {
+                quantity: info.quantity,
+                assortment: EntityRefSmall {
+                    meta: position.assortment.meta.clone(),
+                },
+            }],
         };
+        let created = staged!(
+            write self,
+            PositionStage::Create,
+            self.client.create_purchase_order(&request)
+        )?;
+        // Заказ поставщику меняет ожидание товара
+        self.stock_cache.invalidate(&info.id);
 
-        let mut missing: Vec<(String, f64)> = Vec::new();
+        info!("Created purchase order {} for {} x{}", created.name, info.name, info.quantity);
+        self.notifier
+            .notify(Notification::new(
+                NotificationEvent::Success,
+                format!("Создан заказ поставщику {}", created.name),
+                format!(
+                    "Закупка {} шт. '{}' для заказа {}",
+                    info.quantity, info.name, order.name
+                ),
+            ))
+            .await;
 
-        for material in materials {
-            let material_qty = material.quantity * quantity;
+        Ok(ProcessingResult::produced(
+            format!(
+                "Создан заказ поставщику на {} шт. '{}'",
+                info.quantity, info.name
+            ),
+            &created.id,
+            &created.name,
+        )
+        .with_document_type("purchaseorder")
+        .for_order(order)
+        .with_product(info))
+    }
 
-            let material_id = material.product.meta.href
-                .rsplit('/')
-                .next()
-                .unwrap_or("");
+    /// Только уведомить о низком остатке, ничего не создавая
+    pub(crate) async fn notify_low_stock(
+        &mut self,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError> {
+        let ReplenishRequest { order, info, demand, .. } = request;
+        let threshold = self.threshold_for(&info.id);
 
-            let stock = self.client.get_product_stock(material_id, store_id).await?;
+        let title = if demand.ignores_threshold() {
+            format!("Заявка на производство '{}'", info.name)
+        } else {
+            format!("Остаток '{}' ниже порога", info.name)
+        };
+        self.notifier
+            .notify(Notification::new(
+                NotificationEvent::Shortage,
+                title,
+                format!(
+                    "Заказ {}: нужно {} шт., остаток {} (порог {})",
+                    order.name, info.quantity, info.stock_before, threshold
+                ),
+            ))
+            .await;
 
-            let material_name = material.product.name.clone()
-                .unwrap_or_else(|| "unknown".to_string());
+        Ok(ProcessingResult::skipped(
+            SkipReason::NotifyOnly,
+            format!(
+                "Нужно {} шт. (остаток {}, порог {}), отправлено уведомление",
+                info.quantity, info.stock_before, threshold
+            ),
+        )
+        .for_order(order)
+        .with_product(info))
+    }
 
-            debug!(
-                "Material {} stock: {}, needed: {}",
-                material_name, stock, material_qty
-            );
+    /// Только уведомления по товару: пробный режим NOTIFY_ONLY или флаг NOTIFY_ONLY_FIELD_NAME
+    fn is_notify_only(&self, product: &Product) -> bool {
+        if self.settings.notify_only {
+            return true;
+        }
+        let Some(ref field) = self.settings.notify_only_field_name else {
+            return false;
+        };
 
-            if stock < material_qty {
-                missing.push((material_name, material_qty - stock));
+        product
+            .attributes
+            .iter()
+            .flatten()
+            .any(|attr| &attr.name == field && attr.is_set())
+    }
+
+    /// Пересчёт единиц продажи в единицы производства: из поля UNIT_FACTOR_FIELD_NAME,
+    /// иначе по упаковке товара с единицей PRODUCTION_PACK_UOM
+    async fn unit_conversion(&mut self, product: &Product) -> Result<Option<UnitConversion>> {
+        let invalid = |problem: String| {
+            anyhow!("Некорректный пересчёт единиц для '{}': {}", product.name, problem)
+        };
+
+        if let Some(ref field) = self.settings.unit_factor_field_name {
+            let conversion = UnitConversion::from_attribute(product, field).map_err(invalid)?;
+            if conversion.is_some() {
+                return Ok(conversion);
             }
         }
 
-        if missing.is_empty() {
-            Ok(MaterialsCheckResult::available())
-        } else {
-            Ok(MaterialsCheckResult::missing(missing))
+        if self.settings.production_pack_uom.is_none() || product.packs.is_none() {
+            return Ok(None);
         }
+        let uom = self.production_uom().await?;
+        UnitConversion::from_pack(product, &uom).map_err(invalid)
     }
 
-    /// Создать тех. операцию
-    async fn create_processing_operation(
+    /// Получить кэшированную единицу измерения упаковок производства (PRODUCTION_PACK_UOM)
+    async fn production_uom(&mut self) -> Result<EntityRef> {
+        if let Some(ref uom) = self.production_uom_cache {
+            return Ok(uom.clone());
+        }
+
+        let name = self
+            .settings
+            .production_pack_uom
+            .clone()
+            .ok_or_else(|| anyhow!("PRODUCTION_PACK_UOM is not set"))?;
+        let uom = self
+            .client
+            .find_uom_by_name(&name)
+            .await?
+            .ok_or_else(|| anyhow!("Unit of measure '{}' not found", name))?;
+
+        info!("Found production unit of measure: {:?}", uom.name);
+        self.production_uom_cache = Some(uom.clone());
+        Ok(uom)
+    }
+
+    /// Отмечен ли товар для оприходования вместо производства (ENTER_FALLBACK_FIELD_NAME)
+    fn is_enter_fallback(&self, product: &Product) -> bool {
+        let Some(ref field) = self.settings.enter_fallback_field_name else {
+            return false;
+        };
+
+        product
+            .attributes
+            .iter()
+            .flatten()
+            .any(|attr| &attr.name == field && attr.is_set())
+    }
+
+    /// Создать проведённое оприходование на отслеживаемый склад
+    async fn create_enter_operation(
+        &self,
+        assortment: &Meta,
+        store: &EntityRef,
+        organization: &EntityRef,
+        quantity: Decimal,
+        order: &CustomerOrder,
+    ) -> Result<Enter> {
+        let request = CreateEnterRequest {
+            organization: EntityRefSmall {
+                meta: organization.meta.clone(),
+            },
+            store: EntityRefSmall {
+                meta: store.meta.clone(),
+            },
+            applicable: true,
+            description: Some(document_description(order)),
+            positions: vec![DocumentPosition {
+                quantity,
+                assortment: EntityRefSmall {
+                    meta: assortment.clone(),
+                },
+            }],
+        };
+
+        self.client.create_enter(&request).await
+    }
+
+    /// Собрать запрос на создание тех. операции для заказа
+    fn build_processing_request(
         &self,
         processing_plan: &ProcessingPlan,
         store: &EntityRef,
         organization: &EntityRef,
-        quantity: f64,
+        quantity: Decimal,
         order: &CustomerOrder,
-        _product_name: &str,
-    ) -> Result<Processing> {
-        let request = CreateProcessingRequest {
+        cost: Option<&ProductionCost>,
+    ) -> CreateProcessingRequest {
+        let mut request = self.base_processing_request(
+            processing_plan,
+            store,
+            organization,
+            quantity,
+            document_description(order),
+            cost,
+        );
+        request.project = self.processing_project(order).map(|p| EntityRefSmall { meta: p.meta });
+        request.sales_channel = self
+            .settings
+            .copy_order_project
+            .then(|| order.sales_channel.clone())
+            .flatten()
+            .map(|c| EntityRefSmall { meta: c.meta });
+        request
+    }
+
+    /// Запрос на создание тех. операции без привязки к заказу (проект — PRODUCTION_PROJECT)
+    fn base_processing_request(
+        &self,
+        processing_plan: &ProcessingPlan,
+        store: &EntityRef,
+        organization: &EntityRef,
+        quantity: Decimal,
+        description: String,
+        cost: Option<&ProductionCost>,
+    ) -> CreateProcessingRequest {
+        let description = match (cost, &self.overhead) {
+            (Some(cost), Some(overhead)) if cost.overhead > Decimal::ZERO => {
+                format!("{}. {}", description, overhead.describe(cost.overhead))
+            }
+            _ => description,
+        };
+
+        CreateProcessingRequest {
             processing_plan: ProcessingPlanRef {
                 meta: processing_plan.meta.clone(),
             },
@@ -439,35 +3963,395 @@ impl OrderProcessor {
             },
             quantity,
             name: None,
-            description: Some(format!(
-                "Автоматически создано для заказа {} от {}",
-                order.name, order.moment
-            )),
-            processing_sum: 0.0,
-        };
+            description: Some(description),
+            processing_sum: cost.map(|c| c.total).unwrap_or_default(),
+            project: self.project_cache.clone().map(|p| EntityRefSmall { meta: p.meta }),
+            sales_channel: None,
+            moment: self.schedule.as_ref().and_then(|s| s.moment(chrono::Utc::now())),
+            products: None,
+            materials: None,
+        }
+    }
+
+    /// Проект тех. операции: проект заказа (COPY_ORDER_PROJECT), иначе PRODUCTION_PROJECT
+    fn processing_project(&self, order: &CustomerOrder) -> Option<EntityRef> {
+        self.settings
+            .copy_order_project
+            .then(|| order.project.clone())
+            .flatten()
+            .or_else(|| self.project_cache.clone())
+    }
+
+    /// Создать тех. операцию
+    async fn create_processing_operation(
+        &self,
+        processing_plan: &ProcessingPlan,
+        store: &EntityRef,
+        organization: &EntityRef,
+        quantity: Decimal,
+        order: &CustomerOrder,
+        adjustments: &MaterialAdjustments,
+    ) -> Result<Processing> {
+        let cost = self.processing_cost(processing_plan, quantity).await;
+        let mut request = self.build_processing_request(
+            processing_plan,
+            store,
+            organization,
+            quantity,
+            order,
+            cost.as_ref(),
+        );
+        fill_positions(&mut request, processing_plan, quantity, adjustments);
+        request.name = self.next_processing_name().await;
 
         self.client.create_processing(&request).await
     }
+
+    /// Номер тех. операции по NAME_TEMPLATE на дату документа; `None` — нумерует МойСклад
+    async fn next_processing_name(&self) -> Option<String> {
+        self.names
+            .next(account_now(self.settings.moysklad_utc_offset_hours).date_naive())
+            .await
+    }
+
+    /// Сумма тех. операции: себестоимость по закупочным ценам материалов, если включено
+    /// PROCESSING_COST_FROM_MATERIALS, и накладные расходы. Ошибка расчёта не мешает производству:
+    /// в сумму попадают только фиксированные накладные.
+    async fn processing_cost(&self, processing_plan: &ProcessingPlan, quantity: Decimal) -> Option<ProductionCost> {
+        let from_materials = self.settings.processing_cost_from_materials;
+        if !from_materials && self.overhead.is_none() {
+            return None;
+        }
+
+        let needs_materials =
+            from_materials || self.overhead.as_ref().is_some_and(Overhead::needs_materials_cost);
+        let mut cost = if needs_materials {
+            match self.materials_cost(processing_plan, quantity).await {
+                Ok(cost) => {
+                    debug!(
+                        "Production cost for {} x{}: {} ({})",
+                        processing_plan.name,
+                        quantity,
+                        cost.total,
+                        cost.materials
+                            .iter()
+                            .map(|m| format!("{}: {}", m.name, m.cost))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    cost
+                }
+                Err(e) => {
+                    warn!("Failed to compute production cost for {}: {:#}", processing_plan.name, e);
+                    // Без накладных в сумме нечего указывать
+                    self.overhead.as_ref().map(|_| ProductionCost::default())?
+                }
+            }
+        } else {
+            ProductionCost::default()
+        };
+
+        let materials_total = cost.total;
+        if !from_materials {
+            cost.total = Decimal::ZERO;
+        }
+        if let Some(overhead) = &self.overhead {
+            cost.overhead = overhead.sum(quantity, materials_total);
+            cost.total += cost.overhead;
+        }
+        Some(cost)
+    }
+
+    /// Стоимость материалов тех. карты на количество продукта
+    async fn materials_cost(&self, processing_plan: &ProcessingPlan, quantity: Decimal) -> Result<ProductionCost> {
+        let rows = processing_plan
+            .materials
+            .as_ref()
+            .and_then(|m| m.rows.as_ref())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let mut materials = Vec::with_capacity(rows.len());
+        for material in rows {
+            let material_id = material.product.meta.href.rsplit('/').next().unwrap_or("");
+            let product = self.client.get_product(material_id).await?;
+            let waste = self.waste_factor_of(&product);
+            let unit_price = match product.buy_price {
+                Some(price) => price.value,
+                None => {
+                    warn!("Material {} has no buy price, counted as 0", product.name);
+                    Decimal::ZERO
+                }
+            };
+            let material_quantity = material_requirement(material.quantity, quantity, waste);
+
+            materials.push(MaterialCost {
+                id: material_id.to_string(),
+                name: product.name,
+                quantity: material_quantity,
+                unit_price,
+                cost: round_kopecks(unit_price * material_quantity),
+            });
+        }
+
+        Ok(ProductionCost {
+            total: materials.iter().map(|m| m.cost).sum(),
+            materials,
+            overhead: Decimal::ZERO,
+        })
+    }
+}
+
+/// Тех. карта, выбранная select_plan
+struct PlanSelection {
+    plan: ProcessingPlan,
+    /// Причина выбора, если тех. карт несколько
+    reason: Option<String>,
+    /// Проверка материалов выбранной тех. карты, если она выполнялась при выборе
+    check: Option<MaterialsCheckResult>,
 }
 
 /// Результат проверки материалов
+#[derive(Default)]
 struct MaterialsCheckResult {
-    available: bool,
-    missing: Vec<(String, f64)>,
+    materials: Vec<MaterialRequirement>,
+    missing: Vec<MaterialShortage>,
+    adjustments: MaterialAdjustments,
+}
+
+/// Поправки к составу тех. операции по результату проверки материалов
+#[derive(Default)]
+struct MaterialAdjustments {
+    /// Замены недостающих материалов основной тех. карты
+    substitutions: Vec<MaterialSubstitution>,
+    /// Коэффициент расхода с учётом потерь по ID материала (без потерь — нет в списке)
+    waste: HashMap<String, Decimal>,
 }
 
 impl MaterialsCheckResult {
-    fn available() -> Self {
-        Self {
-            available: true,
-            missing: Vec::new(),
+    fn available(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Наибольшее целое количество, на которое хватает материалов основной тех. карты
+    /// вместе с их заменителями
+    fn max_producible(&self, quantity: Decimal) -> Decimal {
+        if quantity <= Decimal::ZERO {
+            return Decimal::ZERO;
         }
+
+        self.materials
+            .iter()
+            .filter(|m| m.level == 0 && m.required > Decimal::ZERO)
+            .map(|m| {
+                let substituted: Decimal = self
+                    .adjustments
+                    .substitutions
+                    .iter()
+                    .filter(|s| s.material_id == m.id)
+                    .map(|s| s.quantity)
+                    .sum();
+                let available = m.available.max(Decimal::ZERO) + substituted;
+                (available * quantity / m.required).floor()
+            })
+            .fold(quantity, Decimal::min)
     }
+}
 
-    fn missing(missing: Vec<(String, f64)>) -> Self {
-        Self {
-            available: false,
-            missing,
+/// Проверить количество к пополнению: положительное после округления
+/// до QUANTITY_DECIMALS знаков и не больше `max` (0 — без ограничения)
+fn validate_quantity(quantity: Decimal, max: Decimal) -> Result<Decimal, String> {
+    let rounded = round_quantity(quantity);
+    if rounded <= Decimal::ZERO {
+        return Err(format!("количество должно быть положительным ({})", quantity));
+    }
+    if max > Decimal::ZERO && rounded > max {
+        return Err(format!("количество {} больше MAX_AUTO_QUANTITY ({})", rounded, max));
+    }
+    Ok(rounded)
+}
+
+/// Явные продукты и материалы тех. операции: расход материалов включает потери, недостающие
+/// материалы заменены, замены перечислены в описании. Без замен и потерь состав берётся
+/// МойСклад из тех. карты.
+fn fill_positions(
+    request: &mut CreateProcessingRequest,
+    processing_plan: &ProcessingPlan,
+    quantity: Decimal,
+    adjustments: &MaterialAdjustments,
+) {
+    let substitutions = &adjustments.substitutions;
+    if substitutions.is_empty() && adjustments.waste.is_empty() {
+        return;
+    }
+    let products = processing_plan.products.as_ref().and_then(|p| p.rows.as_ref());
+    let materials = processing_plan.materials.as_ref().and_then(|m| m.rows.as_ref());
+    let (Some(products), Some(materials)) = (products, materials) else {
+        return;
+    };
+
+    let position = |meta: &Meta, q: Decimal| DocumentPosition {
+        quantity: round_quantity(q),
+        assortment: EntityRefSmall { meta: meta.clone() },
+    };
+
+    request.products = Some(
+        products
+            .iter()
+            .map(|row| position(&row.assortment.meta, row.quantity * quantity))
+            .collect(),
+    );
+
+    let mut positions = Vec::with_capacity(materials.len() + substitutions.len());
+    for row in materials {
+        let material_id = row.product.meta.href.rsplit('/').next().unwrap_or("");
+        let waste = adjustments.waste.get(material_id).copied().unwrap_or(Decimal::ONE);
+        let mut required = material_requirement(row.quantity, quantity, waste);
+
+        if let Some(substitution) = substitutions.iter().find(|s| s.material_id == material_id) {
+            required -= substitution.quantity;
+            if let Some(ref meta) = substitution.substitute_meta {
+                positions.push(position(meta, substitution.substitute_quantity));
+            }
         }
+        if required > Decimal::ZERO {
+            positions.push(position(&row.assortment.meta, required));
+        }
+    }
+    request.materials = Some(positions);
+
+    if substitutions.is_empty() {
+        return;
+    }
+    let notes = substitutions.iter().map(|s| s.describe()).collect::<Vec<_>>().join("; ");
+    request.description = Some(match request.description.take() {
+        Some(description) => format!("{}. Замены материалов: {}", description, notes),
+        None => format!("Замены материалов: {}", notes),
+    });
+}
+
+/// Расход материала на `quantity` продукта с учётом потерь, округлённый до знаков
+/// МойСклад. Одна формула для проверки остатков, себестоимости и тех. операции.
+fn material_requirement(material_quantity: Decimal, quantity: Decimal, waste: Decimal) -> Decimal {
+    round_quantity(material_quantity * quantity * waste)
+}
+
+/// Коэффициент расхода при потерях `percent` процентов
+fn waste_multiplier(percent: f64) -> Decimal {
+    Decimal::ONE + decimal(percent) / Decimal::ONE_HUNDRED
+}
+
+/// Названия тех. карт через запятую
+fn plan_names(plans: &[ProcessingPlan]) -> String {
+    plans.iter().map(|plan| plan.name.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+/// Непустое значение поля
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Подходит ли контрагент или канал продаж под фильтр из названий и ID (пустой — любой)
+fn matches_filter(filter: &[String], entity: Option<&EntityRef>) -> bool {
+    if filter.is_empty() {
+        return true;
+    }
+    let Some(entity) = entity else {
+        return false;
+    };
+
+    let id = entity.id.as_deref().or_else(|| entity.meta.href.rsplit('/').next());
+    filter.iter().any(|item| {
+        Some(item.as_str()) == id
+            || entity.name.as_deref().is_some_and(|name| name.to_lowercase() == item.to_lowercase())
+    })
+}
+
+/// Название или ID сущности для сообщений
+fn entity_label(entity: Option<&EntityRef>) -> String {
+    match entity {
+        Some(entity) => format!(
+            "'{}'",
+            entity.name.as_deref().or(entity.id.as_deref()).unwrap_or(&entity.meta.href)
+        ),
+        None => "не указан".to_string(),
+    }
+}
+
+/// Название документа пополнения для сообщений
+fn document_label(entity_type: &str) -> &'static str {
+    match entity_type {
+        "move" => "перемещение",
+        "enter" => "оприходование",
+        "purchaseorder" => "заказ поставщику",
+        _ => "тех. операция",
+    }
+}
+
+/// ID заказа из события webhook
+fn event_order_id(event: &WebhookEvent) -> Option<String> {
+    event
+        .entity
+        .as_ref()
+        .map(|order| order.id.clone())
+        .or_else(|| event.content.as_ref().and_then(|c| c.id.clone()))
+}
+
+/// Продано товаров со склада за последние `days` дней (по времени аккаунта), по ID
+async fn recent_sales(
+    client: &MoyskladClient,
+    store: &EntityRef,
+    days: u32,
+    offset_hours: i32,
+) -> Result<HashMap<String, f64>> {
+    let to = account_now(offset_hours).naive_local();
+    let from = to - chrono::Duration::days(days as i64);
+
+    let mut sales: HashMap<String, f64> = HashMap::new();
+    for row in client
+        .get_sales_by_product(
+            &store.meta.href,
+            &from.format("%Y-%m-%d %H:%M:%S").to_string(),
+            &to.format("%Y-%m-%d %H:%M:%S").to_string(),
+        )
+        .await?
+    {
+        *sales.entry(row.product_id().to_string()).or_default() += row.net_quantity();
+    }
+    Ok(sales)
+}
+
+/// Текущее время аккаунта по MOYSKLAD_UTC_OFFSET
+fn account_now(offset_hours: i32) -> chrono::DateTime<chrono::FixedOffset> {
+    let offset = chrono::FixedOffset::east_opt(offset_hours * 3600)
+        .unwrap_or_else(|| chrono::FixedOffset::east_opt(0).expect("zero offset is valid"));
+    chrono::Utc::now().with_timezone(&offset)
+}
+
+/// Процент потерь из поля товара, если он заполнен и меньше 100
+fn field_waste_percent(product: &Product, field: &str) -> Option<f64> {
+    product
+        .attributes
+        .iter()
+        .flatten()
+        .find(|attr| attr.name == field)
+        .and_then(Attribute::as_number)
+        .filter(|percent| (0.0..100.0).contains(percent))
+}
+
+/// Описание создаваемого документа со ссылкой на документ-основание
+fn document_description(order: &CustomerOrder) -> String {
+    if order.meta.entity_type.as_deref() == Some(PRODUCTION_PLAN_TYPE) {
+        return format!("Создано по производственному плану ({})", order.name);
     }
+    format!("Автоматически создано для заказа {} от {}", order.name, order.moment)
+}
+
+/// Дата МойСклад (время аккаунта без пояса) в UTC
+fn moysklad_moment_utc(moment: &str, offset_hours: i32) -> Option<chrono::DateTime<chrono::Utc>> {
+    let offset = chrono::FixedOffset::east_opt(offset_hours * 3600)?;
+    parse_moment(moment)?
+        .and_local_timezone(offset)
+        .single()
+        .map(|moment| moment.with_timezone(&chrono::Utc))
 }
@@ -0,0 +1,75 @@
+//! Повтор сохранённых webhook событий: файл CLI `replay` и тело `/admin/replay`
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::api::{Playback, RecordedResponse};
+use crate::models::WebhookEvent;
+
+/// Событие для повторной обработки: параметры исходного webhook, необязательно
+/// с документом на момент события и записанными ответами API
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplayEvent {
+    pub id: String,
+    #[serde(rename = "type", default = "default_entity_type")]
+    pub entity_type: String,
+    #[serde(rename = "accountId", default)]
+    pub account_id: Option<String>,
+    #[serde(default)]
+    pub action: Option<String>,
+    /// Документ на момент события: подменяет чтение документа из МойСклад
+    #[serde(default)]
+    pub entity: Option<Value>,
+    /// Записанные ответы API (`api_calls` прошлого повтора или `responses` фикстур).
+    /// Без них ответы читаются из МойСклад, с ними запрос без записи — ошибка.
+    #[serde(default)]
+    pub responses: Option<Vec<RecordedResponse>>,
+}
+
+fn default_entity_type() -> String {
+    "customerorder".to_string()
+}
+
+impl ReplayEvent {
+    /// Действие исходного webhook в нижнем регистре, по умолчанию `update`
+    pub fn action(&self) -> String {
+        self.action.as_deref().unwrap_or("update").to_lowercase()
+    }
+
+    /// Событие в том виде, в каком его обрабатывает процессор
+    pub fn webhook(&self) -> WebhookEvent {
+        WebhookEvent::entity_action(&self.entity_type, &self.id, &self.action())
+    }
+
+    /// Ответы для пробного повтора: записанные и документ события
+    pub fn playback(&self) -> Playback {
+        let mut responses = self.responses.clone().unwrap_or_default();
+        if let Some(ref entity) = self.entity {
+            responses.insert(
+                0,
+                RecordedResponse {
+                    endpoint: format!("/entity/{}/{}", self.entity_type.to_lowercase(), self.id),
+                    body: entity.clone(),
+                },
+            );
+        }
+        Playback::new(responses, self.responses.is_none())
+    }
+}
+
+/// Файл CLI `replay`: одно событие или массив
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ReplayFile {
+    One(ReplayEvent),
+    Many(Vec<ReplayEvent>),
+}
+
+impl ReplayFile {
+    pub fn into_events(self) -> Vec<ReplayEvent> {
+        match self {
+            Self::One(event) => vec![event],
+            Self::Many(events) => events,
+        }
+    }
+}
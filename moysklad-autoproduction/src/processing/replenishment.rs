@@ -0,0 +1,55 @@
+//! Способ пополнения остатка
+
+use crate::models::Product;
+
+/// Чем пополнять остаток товара ниже порога
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplenishmentKind {
+    /// Тех. операция по тех. карте
+    Produce,
+    /// Перемещение с центрального склада
+    Move,
+    /// Заказ поставщику
+    Purchase,
+    /// Только уведомление о низком остатке
+    NotifyOnly,
+}
+
+impl ReplenishmentKind {
+    /// Разобрать способ пополнения из строки
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "produce" | "производство" => Some(Self::Produce),
+            "move" | "перемещение" => Some(Self::Move),
+            "purchase" | "закупка" => Some(Self::Purchase),
+            "notify" | "notify_only" | "уведомление" => Some(Self::NotifyOnly),
+            _ => None,
+        }
+    }
+
+    /// Строковое представление
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Produce => "produce",
+            Self::Move => "move",
+            Self::Purchase => "purchase",
+            Self::NotifyOnly => "notify_only",
+        }
+    }
+
+    /// Способ пополнения товара: из его поля (если задано и заполнено), иначе по умолчанию
+    pub fn for_product(product: &Product, field_name: Option<&str>, default: Self) -> Self {
+        let Some(field_name) = field_name else {
+            return default;
+        };
+
+        product
+            .attributes
+            .iter()
+            .flatten()
+            .find(|attr| attr.name == field_name)
+            .and_then(|attr| attr.as_string())
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or(default)
+    }
+}
@@ -0,0 +1,199 @@
+//! Рабочий календарь: вне рабочего времени производство откладывается до начала рабочего окна
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveTime, Utc};
+
+use crate::config::Settings;
+
+/// Рабочие дни, часы и праздники. Время — часовой пояс аккаунта (MOYSKLAD_UTC_OFFSET).
+#[derive(Debug, Clone)]
+pub struct WorkSchedule {
+    /// Рабочие дни недели, 1 — понедельник
+    days: Vec<u32>,
+    start: NaiveTime,
+    end: NaiveTime,
+    holidays: Vec<NaiveDate>,
+    offset: FixedOffset,
+    /// Ставить документам, созданным вне рабочего времени, дату начала следующего окна
+    set_moment: bool,
+}
+
+impl WorkSchedule {
+    /// Календарь из WORK_HOURS, WORK_DAYS и HOLIDAYS; без WORK_HOURS ограничений нет
+    pub fn from_settings(settings: &Settings) -> Result<Option<Self>> {
+        let Some(ref hours) = settings.work_hours else {
+            return Ok(None);
+        };
+
+        let (start, end) = hours
+            .split_once('-')
+            .ok_or_else(|| anyhow!("WORK_HOURS must be HH:MM-HH:MM, got '{}'", hours))?;
+        let start = parse_time(start)?;
+        let end = parse_time(end)?;
+        if start >= end {
+            bail!("WORK_HOURS must end after it starts, got '{}'", hours);
+        }
+
+        let mut days = Vec::new();
+        for item in &settings.work_days {
+            days.extend(parse_days(item)?);
+        }
+        if days.is_empty() {
+            days = (1..=5).collect();
+        }
+
+        let holidays = settings
+            .holidays
+            .iter()
+            .map(|date| {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .with_context(|| format!("HOLIDAYS must be YYYY-MM-DD dates, got '{}'", date))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let offset = FixedOffset::east_opt(settings.moysklad_utc_offset_hours * 3600)
+            .ok_or_else(|| anyhow!("Invalid MOYSKLAD_UTC_OFFSET"))?;
+
+        Ok(Some(Self {
+            days,
+            start,
+            end,
+            holidays,
+            offset,
+            set_moment: settings.work_schedule_moment,
+        }))
+    }
+
+    fn is_working_day(&self, date: NaiveDate) -> bool {
+        self.days.contains(&date.weekday().number_from_monday()) && !self.holidays.contains(&date)
+    }
+
+    /// Идёт ли рабочее время
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.offset).naive_local();
+        self.is_working_day(local.date()) && local.time() >= self.start && local.time() < self.end
+    }
+
+    /// Начало ближайшего рабочего окна (`now`, если оно уже идёт)
+    pub fn next_open(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.is_open(now) {
+            return Some(now);
+        }
+
+        let local = now.with_timezone(&self.offset).naive_local();
+        let mut date = local.date();
+        if local.time() >= self.start {
+            date = date.succ_opt()?;
+        }
+
+        // Год вперёд: дальше рабочего дня быть не может, если календарь задан разумно
+        for _ in 0..366 {
+            if self.is_working_day(date) {
+                return date
+                    .and_time(self.start)
+                    .and_local_timezone(self.offset)
+                    .single()
+                    .map(|start| start.with_timezone(&Utc));
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    /// Дата документа в формате МойСклад для создаваемого вне рабочего времени
+    /// (WORK_SCHEDULE_MOMENT); в рабочее время — не задаётся. Вне рабочего времени
+    /// документы создаёт только ручной запуск: webhook откладываются до окна.
+    pub fn moment(&self, now: DateTime<Utc>) -> Option<String> {
+        if !self.set_moment || self.is_open(now) {
+            return None;
+        }
+        self.next_open(now).map(|start| {
+            start.with_timezone(&self.offset).format("%Y-%m-%d %H:%M:%S").to_string()
+        })
+    }
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .with_context(|| format!("Invalid time '{}', expected HH:MM", value.trim()))
+}
+
+/// День недели (`6`) или диапазон (`1-5`), 1 — понедельник
+fn parse_days(item: &str) -> Result<Vec<u32>> {
+    let parse = |day: &str| -> Result<u32> {
+        day.trim()
+            .parse()
+            .ok()
+            .filter(|day| (1..=7).contains(day))
+            .ok_or_else(|| anyhow!("WORK_DAYS must be days 1-7, got '{}'", item))
+    };
+
+    match item.split_once('-') {
+        Some((from, to)) => {
+            let (from, to) = (parse(from)?, parse(to)?);
+            if from > to {
+                bail!("WORK_DAYS range must be ascending, got '{}'", item);
+            }
+            Ok((from..=to).collect())
+        }
+        None => Ok(vec![parse(item)?]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Пн–Пт 09:00–18:00 по Москве, 2026-01-01 (четверг) — праздник
+    fn schedule() -> WorkSchedule {
+        let settings = Settings {
+            moysklad_utc_offset_hours: 3,
+            work_hours: Some("09:00-18:00".to_string()),
+            work_days: vec!["1-5".to_string()],
+            holidays: vec!["2026-01-01".to_string()],
+            work_schedule_moment: true,
+            ..Settings::default()
+        };
+        WorkSchedule::from_settings(&settings).unwrap().unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_days_accepts_days_and_ranges() {
+        assert_eq!(parse_days("1-5").unwrap(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(parse_days(" 6 ").unwrap(), vec![6]);
+        assert_eq!(parse_days("7-7").unwrap(), vec![7]);
+        for invalid in ["0", "8", "5-1", "пн", "1-", ""] {
+            assert!(parse_days(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn next_open_skips_evenings_weekends_and_holidays() {
+        let schedule = schedule();
+
+        // Пятница 10:00 — окно уже идёт
+        let open = utc("2026-01-02T07:00:00Z");
+        assert_eq!(schedule.next_open(open), Some(open));
+        // Пятница 08:00 — окно начнётся в тот же день
+        assert_eq!(schedule.next_open(utc("2026-01-02T05:00:00Z")), Some(utc("2026-01-02T06:00:00Z")));
+        // Пятница 19:00 — следующее окно в понедельник
+        assert_eq!(schedule.next_open(utc("2026-01-02T16:00:00Z")), Some(utc("2026-01-05T06:00:00Z")));
+        // Среда 22:00 UTC — уже праздничный четверг по времени аккаунта
+        assert_eq!(schedule.next_open(utc("2025-12-31T22:00:00Z")), Some(utc("2026-01-02T06:00:00Z")));
+    }
+
+    #[test]
+    fn moment_is_set_only_outside_the_window() {
+        let schedule = schedule();
+
+        assert_eq!(schedule.moment(utc("2026-01-02T07:00:00Z")), None);
+        assert_eq!(
+            schedule.moment(utc("2026-01-03T12:00:00Z")).as_deref(),
+            Some("2026-01-05 09:00:00")
+        );
+    }
+}
@@ -0,0 +1,37 @@
+//! Счётчики пропущенных позиций по причинам
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::models::SkipReason;
+
+/// Количество пропусков по причинам с момента запуска
+#[derive(Default)]
+pub struct SkipStats {
+    counts: Mutex<BTreeMap<SkipReason, u64>>,
+}
+
+impl SkipStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Учесть пропуск
+    pub fn record(&self, reason: SkipReason) {
+        *self
+            .counts
+            .lock()
+            .expect("skip stats lock poisoned")
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    /// Текущие значения по всем причинам
+    pub fn snapshot(&self) -> Vec<(SkipReason, u64)> {
+        let counts = self.counts.lock().expect("skip stats lock poisoned");
+        SkipReason::ALL
+            .iter()
+            .map(|reason| (*reason, counts.get(reason).copied().unwrap_or(0)))
+            .collect()
+    }
+}
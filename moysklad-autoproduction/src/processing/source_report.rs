@@ -0,0 +1,95 @@
+//! Итог обработки в исходном документе МойСклад (SOURCE_REPORT)
+
+use sha2::{Digest, Sha256};
+
+use crate::models::{ProcessingResult, SkipReason};
+
+/// Начало имени файла с итогами (SOURCE_REPORT=file)
+const REPORT_FILE_PREFIX: &str = "autoproduction-";
+
+/// Заголовок блока итогов в описании документа; блок заменяется при повторной обработке
+const REPORT_MARKER: &str = "--- Автопроизводство ---";
+
+/// Куда записывать итог обработки
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceReportMode {
+    Off,
+    /// Блок в комментарии (описании) документа
+    Description,
+    /// Текстовый файл, прикреплённый к документу
+    File,
+}
+
+impl SourceReportMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "off" | "" => Some(Self::Off),
+            "description" => Some(Self::Description),
+            "file" => Some(Self::File),
+            _ => None,
+        }
+    }
+}
+
+/// Итог по позициям: что произведено и почему позиции пропущены. Количество — в единицах
+/// товара, после пересчёта — с единицей производства. `None`, если новых итогов нет:
+/// повторная обработка без изменений (`duplicate`) документ не трогает, иначе запись
+/// в документ вызывала бы новый webhook.
+pub fn report_text(results: &[ProcessingResult]) -> Option<String> {
+    let lines: Vec<String> = results
+        .iter()
+        .filter(|result| result.skip_reason != Some(SkipReason::Duplicate))
+        .filter_map(|result| {
+            let product = result.product.as_ref()?;
+            let mark = if result.processing_id.is_some() {
+                "✓"
+            } else if result.success {
+                "—"
+            } else {
+                "✗"
+            };
+            let detail = match (&result.error, result.success) {
+                (Some(error), false) => error,
+                _ => &result.message,
+            };
+            Some(match product.unit {
+                Some(ref unit) => format!("{} {} ({} {}): {}", mark, product.name, product.quantity, unit, detail),
+                None => format!("{} {} ({}): {}", mark, product.name, product.quantity, detail),
+            })
+        })
+        .collect();
+
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Описание документа с блоком итогов вместо прежнего
+pub fn with_report(description: Option<&str>, report: &str) -> String {
+    let own = description
+        .map(|text| text.split(REPORT_MARKER).next().unwrap_or("").trim_end())
+        .unwrap_or("");
+
+    if own.is_empty() {
+        format!("{}\n{}", REPORT_MARKER, report)
+    } else {
+        format!("{}\n\n{}\n{}", own, REPORT_MARKER, report)
+    }
+}
+
+/// Отпечаток итогов в имени файла: по нему повторный итог не прикрепляется
+fn report_digest(report: &str) -> String {
+    hex::encode(&Sha256::digest(report.as_bytes())[..4])
+}
+
+/// Имя файла итогов: время и отпечаток текста
+pub fn report_filename(timestamp: &str, report: &str) -> String {
+    format!("{}{}-{}.txt", REPORT_FILE_PREFIX, timestamp, report_digest(report))
+}
+
+/// Последний прикреплённый файл итогов содержит тот же текст
+pub fn is_last_report(filenames: &[String], report: &str) -> bool {
+    filenames
+        .iter()
+        .filter(|name| name.starts_with(REPORT_FILE_PREFIX))
+        .max()
+        .is_some_and(|name| name.ends_with(&format!("-{}.txt", report_digest(report))))
+}
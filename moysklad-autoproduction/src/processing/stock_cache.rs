@@ -0,0 +1,138 @@
+//! Кэш остатков отслеживаемого склада с фоновым обновлением (STOCK_CACHE_REFRESH_SECS)
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::models::{Decimal, StockRow};
+use crate::tenants::Tenant;
+
+#[derive(Default)]
+struct Snapshot {
+    store_href: String,
+    /// Остаток, резерв и ожидание по ID товара
+    levels: HashMap<String, (Decimal, Decimal, Decimal)>,
+    refreshed_at: Option<DateTime<Utc>>,
+    /// Товары, по которым сервис создал документы после обновления,
+    /// с номером изменения
+    stale: HashMap<String, u64>,
+}
+
+/// Остатки склада из отчёта, загружаемого целиком в фоне. Кэш не используется,
+/// если он старше двух интервалов обновления (обновление не удаётся).
+pub struct StockCache {
+    max_age: chrono::Duration,
+    snapshot: RwLock<Snapshot>,
+    /// Номер последнего изменения остатков (`invalidate`)
+    version: AtomicU64,
+}
+
+impl StockCache {
+    pub fn new(refresh_interval: Duration) -> Self {
+        Self {
+            max_age: chrono::Duration::from_std(refresh_interval * 2)
+                .unwrap_or(chrono::Duration::MAX),
+            snapshot: RwLock::new(Snapshot::default()),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    /// Остаток, резерв и ожидание товара на складе; `None` — нужно спросить МойСклад.
+    /// По кэшу проверяется порог; остаток ниже порога перед созданием документа
+    /// перечитывается из МойСклад.
+    pub fn get(&self, product_id: &str, store_href: &str) -> Option<(Decimal, Decimal, Decimal)> {
+        let snapshot = self.snapshot.read().expect("stock cache lock poisoned");
+        let fresh = snapshot
+            .refreshed_at
+            .is_some_and(|at| Utc::now() - at <= self.max_age);
+        if !fresh || snapshot.store_href != store_href || snapshot.stale.contains_key(product_id) {
+            return None;
+        }
+
+        // Товара нет в отчёте — на складе его нет
+        Some(snapshot.levels.get(product_id).copied().unwrap_or_default())
+    }
+
+    /// Начало загрузки отчёта: номер изменения для `replace`
+    pub fn begin_refresh(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    /// Заменить остатки отчётом, загрузка которого началась на изменении `started`.
+    /// Товары, изменённые во время загрузки, остаются устаревшими: в отчёте может
+    /// быть остаток до созданного документа.
+    pub fn replace(&self, store_href: &str, rows: Vec<StockRow>, started: u64) {
+        let levels = rows
+            .into_iter()
+            .map(|row| {
+                let id = row.meta.href.rsplit('/').next().unwrap_or("").to_string();
+                (id, (row.stock, row.reserve, row.in_transit))
+            })
+            .collect();
+
+        let mut snapshot = self.snapshot.write().expect("stock cache lock poisoned");
+        let stale = std::mem::take(&mut snapshot.stale)
+            .into_iter()
+            .filter(|(_, version)| *version > started)
+            .collect();
+        *snapshot = Snapshot {
+            store_href: store_href.to_string(),
+            levels,
+            refreshed_at: Some(Utc::now()),
+            stale,
+        };
+    }
+
+    /// Остаток товара изменился (создан документ): до следующего обновления — из МойСклад
+    pub fn invalidate(&self, product_id: &str) {
+        let mut snapshot = self.snapshot.write().expect("stock cache lock poisoned");
+        let version = self.version.fetch_add(1, Ordering::SeqCst) + 1;
+        snapshot.stale.insert(product_id.to_string(), version);
+    }
+
+    /// Возраст кэша, сек
+    pub fn age_secs(&self) -> Option<u64> {
+        self.snapshot
+            .read()
+            .expect("stock cache lock poisoned")
+            .refreshed_at
+            .map(|at| (Utc::now() - at).num_seconds().max(0) as u64)
+    }
+
+    /// Товаров в кэше
+    pub fn count(&self) -> usize {
+        self.snapshot.read().expect("stock cache lock poisoned").levels.len()
+    }
+}
+
+/// Обновлять кэш остатков каждые `interval`. Отчёт загружается без блокировки
+/// процессора, чтобы обработка webhook-ов не ждала.
+pub fn spawn_stock_cache_refresher(tenant: Arc<Tenant>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            if tenant.is_removed() {
+                break;
+            }
+
+            let source = tenant.processor.lock().await.stock_cache_source().await;
+            match source {
+                Ok((client, store_href, cache)) => {
+                    let started = cache.begin_refresh();
+                    match client.get_store_stock(&store_href).await {
+                        Ok(rows) => {
+                            debug!("[{}] Stock cache refreshed: {} products", tenant.name, rows.len());
+                            cache.replace(&store_href, rows, started);
+                        }
+                        Err(e) => warn!("[{}] Failed to refresh stock cache: {:#}", tenant.name, e),
+                    }
+                }
+                Err(e) => warn!("[{}] Stock cache store not resolved: {:#}", tenant.name, e),
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
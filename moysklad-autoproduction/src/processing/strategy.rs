@@ -0,0 +1,159 @@
+//! Стратегии пополнения остатка: что делать, когда остаток ниже порога
+
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use super::error::PositionError;
+use super::processor::OrderProcessor;
+use super::replenishment::ReplenishmentKind;
+use super::unit_conversion::UnitConversion;
+use crate::models::{CustomerOrder, CustomerOrderPosition, EntityRef, ProcessingResult, Product, ProductInfo};
+
+/// Откуда взялась потребность в пополнении
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Demand {
+    /// Документ продажи: пополнение, если остаток ниже порога
+    Threshold,
+    /// Внутренний заказ: производится заказанное количество без порога, паузы и исключений
+    Explicit,
+    /// Строка производственного плана: количество плана без порога,
+    /// с паузой PRODUCTION_COOLDOWN_SECS и исключениями товара
+    Plan,
+}
+
+impl Demand {
+    /// Количество задано явно, порог остатка не проверяется
+    pub fn ignores_threshold(self) -> bool {
+        self != Self::Threshold
+    }
+}
+
+/// Позиция заказа, остаток которой нужно пополнить
+pub struct ReplenishRequest<'a> {
+    pub order: &'a CustomerOrder,
+    pub position: &'a CustomerOrderPosition,
+    pub product: &'a Product,
+    /// Товар, нужное количество и остаток до пополнения
+    pub info: ProductInfo,
+    /// Отслеживаемый склад
+    pub store: &'a EntityRef,
+    /// Откуда взялась потребность: от неё зависят порог, пауза и проверка дубликатов
+    pub demand: Demand,
+    /// Пересчёт из единиц продажи в единицы производства (только для производства)
+    pub conversion: Option<UnitConversion>,
+}
+
+/// Стратегия пополнения остатка
+#[async_trait]
+pub trait ReplenishmentStrategy: Send + Sync {
+    /// Способ пополнения, который реализует стратегия
+    fn kind(&self) -> ReplenishmentKind;
+
+    /// Пополнить остаток по позиции заказа
+    async fn replenish(
+        &self,
+        processor: &mut OrderProcessor,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError>;
+}
+
+/// Производство по тех. карте
+pub struct ProduceStrategy;
+
+#[async_trait]
+impl ReplenishmentStrategy for ProduceStrategy {
+    fn kind(&self) -> ReplenishmentKind {
+        ReplenishmentKind::Produce
+    }
+
+    async fn replenish(
+        &self,
+        processor: &mut OrderProcessor,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError> {
+        processor.produce(request).await
+    }
+}
+
+/// Перемещение с центрального склада
+pub struct MoveStrategy;
+
+#[async_trait]
+impl ReplenishmentStrategy for MoveStrategy {
+    fn kind(&self) -> ReplenishmentKind {
+        ReplenishmentKind::Move
+    }
+
+    async fn replenish(
+        &self,
+        processor: &mut OrderProcessor,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError> {
+        processor.replenish_by_move(request).await
+    }
+}
+
+/// Заказ поставщику
+pub struct PurchaseStrategy;
+
+#[async_trait]
+impl ReplenishmentStrategy for PurchaseStrategy {
+    fn kind(&self) -> ReplenishmentKind {
+        ReplenishmentKind::Purchase
+    }
+
+    async fn replenish(
+        &self,
+        processor: &mut OrderProcessor,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError> {
+        processor.purchase(request).await
+    }
+}
+
+/// Только уведомление
+pub struct NotifyOnlyStrategy;
+
+#[async_trait]
+impl ReplenishmentStrategy for NotifyOnlyStrategy {
+    fn kind(&self) -> ReplenishmentKind {
+        ReplenishmentKind::NotifyOnly
+    }
+
+    async fn replenish(
+        &self,
+        processor: &mut OrderProcessor,
+        request: ReplenishRequest<'_>,
+    ) -> Result<ProcessingResult, PositionError> {
+        processor.notify_low_stock(request).await
+    }
+}
+
+/// Набор стратегий, выбираемых по способу пополнения
+pub struct StrategySet {
+    strategies: Vec<Arc<dyn ReplenishmentStrategy>>,
+}
+
+impl StrategySet {
+    /// Встроенные стратегии
+    pub fn standard() -> Self {
+        Self {
+            strategies: vec![
+                Arc::new(ProduceStrategy),
+                Arc::new(MoveStrategy),
+                Arc::new(PurchaseStrategy),
+                Arc::new(NotifyOnlyStrategy),
+            ],
+        }
+    }
+
+    /// Стратегия для способа пополнения (производство, если не зарегистрирована)
+    pub fn get(&self, kind: ReplenishmentKind) -> Arc<dyn ReplenishmentStrategy> {
+        self.strategies
+            .iter()
+            .find(|s| s.kind() == kind)
+            .or_else(|| self.strategies.iter().find(|s| s.kind() == ReplenishmentKind::Produce))
+            .cloned()
+            .unwrap_or_else(|| Arc::new(ProduceStrategy))
+    }
+}
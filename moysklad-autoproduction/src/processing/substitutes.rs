@@ -0,0 +1,71 @@
+//! Замены материалов: при нехватке материала используется другой в заданной пропорции
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Правило замены
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubstituteRule {
+    /// ID или название материала из тех. карты
+    pub material: String,
+    /// ID материала-заменителя
+    pub substitute: String,
+    /// Сколько заменителя расходуется на единицу материала
+    #[serde(default = "default_ratio")]
+    pub ratio: f64,
+}
+
+fn default_ratio() -> f64 {
+    1.0
+}
+
+/// Таблица замен материалов; для одного материала правила проверяются по порядку
+#[derive(Debug, Clone, Default)]
+pub struct MaterialSubstitutes {
+    rules: Vec<SubstituteRule>,
+}
+
+impl MaterialSubstitutes {
+    /// Загрузить таблицу из JSON файла (`[{"material": ..., "substitute": ..., "ratio": ...}]`)
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read material substitutes {}", path.display()))?;
+        let mut rules: Vec<SubstituteRule> = serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse material substitutes {}", path.display()))?;
+
+        rules.retain(|rule| {
+            let valid = rule.ratio.is_finite() && rule.ratio > 0.0;
+            if !valid {
+                warn!(
+                    "Ignoring substitute {} for {}: ratio must be positive",
+                    rule.substitute, rule.material
+                );
+            }
+            valid
+        });
+
+        info!("Loaded {} material substitution rules", rules.len());
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Правила для материала по ID или названию (без учёта регистра)
+    pub fn for_material<'a>(
+        &'a self,
+        id: &'a str,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a SubstituteRule> {
+        self.rules.iter().filter(move |rule| {
+            rule.material == id || rule.material.to_lowercase() == name.to_lowercase()
+        })
+    }
+}
@@ -0,0 +1,78 @@
+//! Позиции документов, по которым поставлена задача МойСклад (TASK_ASSIGNEE)
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Сколько дней помнить поставленную задачу
+const RETENTION_DAYS: i64 = 90;
+
+/// Персистентный реестр поставленных задач: после перезапуска, повтора или перепроверки
+/// нехватки задача по той же позиции не ставится снова
+pub struct CreatedTasks {
+    path: Option<PathBuf>,
+    /// Время постановки по ключу «документ:товар:причина»
+    entries: Mutex<BTreeMap<String, DateTime<Utc>>>,
+}
+
+impl CreatedTasks {
+    /// Открыть реестр, восстановив сохранённые записи
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read created tasks {}", path.display()))?;
+            entries = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse created tasks {}", path.display()))?;
+            info!("Restored {} created tasks", entries.len());
+        }
+
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Задача по ключу уже поставлена
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries
+            .lock()
+            .expect("created tasks lock poisoned")
+            .contains_key(key)
+    }
+
+    /// Запомнить поставленную задачу; записи старше RETENTION_DAYS удаляются
+    pub fn insert(&self, key: String) {
+        let mut entries = self.entries.lock().expect("created tasks lock poisoned");
+        let now = Utc::now();
+        entries.retain(|_, at| now - *at < chrono::Duration::days(RETENTION_DAYS));
+        entries.insert(key, now);
+
+        self.persist(&entries);
+    }
+
+    fn persist(&self, entries: &BTreeMap<String, DateTime<Utc>>) {
+        let Some(ref path) = self.path else {
+            return;
+        };
+
+        let result = serde_json::to_string_pretty(entries)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| {
+                let tmp = path.with_extension("tmp");
+                std::fs::write(&tmp, data)?;
+                std::fs::rename(&tmp, path)?;
+                Ok(())
+            });
+
+        if let Err(e) = result {
+            warn!("Failed to persist created tasks: {:#}", e);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Поиск ссылки на тех. карту в карточке товара
+
+use crate::models::Product;
+
+/// Запасной источник названия тех. карты (если поле с тех. картой пустое)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TechCardSource {
+    /// Строка описания товара, начинающаяся с префикса (`Техкарта: ...`)
+    Description,
+    /// Внешний код товара
+    ExternalCode,
+    /// Соглашение об именовании: название тех. карты совпадает с артикулом
+    Article,
+}
+
+impl TechCardSource {
+    /// Разобрать источник из строки
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "description" => Some(Self::Description),
+            "external_code" | "externalcode" => Some(Self::ExternalCode),
+            "article" => Some(Self::Article),
+            _ => None,
+        }
+    }
+
+    /// Найти название тех. карты в товаре
+    pub fn find(&self, product: &Product, description_prefix: &str) -> Option<String> {
+        let value = match self {
+            Self::Description => product
+                .description
+                .as_deref()
+                .and_then(|d| from_description(d, description_prefix)),
+            Self::ExternalCode => product.external_code.clone(),
+            Self::Article => product.article.clone(),
+        };
+
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// Значение после префикса в первой подходящей строке описания
+fn from_description(description: &str, prefix: &str) -> Option<String> {
+    if prefix.is_empty() {
+        return None;
+    }
+
+    description
+        .lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix(prefix))
+        .map(|value| value.trim().to_string())
+}
+
+/// Разобрать список источников, пропуская неизвестные
+pub fn parse_sources(sources: &[String]) -> (Vec<TechCardSource>, Vec<String>) {
+    let mut parsed = Vec::new();
+    let mut unknown = Vec::new();
+
+    for source in sources {
+        match TechCardSource::parse(source) {
+            Some(s) => parsed.push(s),
+            None => unknown.push(source.clone()),
+        }
+    }
+
+    (parsed, unknown)
+}
+
+/// Способ поиска тех. карты товара (PLAN_LOOKUP_MODE)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanLookup {
+    /// По названию из поля с тех. картой (и запасных источников)
+    Attribute,
+    /// Артикул товара совпадает с внешним кодом тех. карты
+    Article,
+    /// Код товара совпадает с кодом тех. карты
+    Code,
+}
+
+impl PlanLookup {
+    /// Разобрать способ из строки
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "attribute" | "name" => Some(Self::Attribute),
+            "article" => Some(Self::Article),
+            "code" => Some(Self::Code),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Attribute => "attribute",
+            Self::Article => "article",
+            Self::Code => "code",
+        }
+    }
+}
+
+/// Разобрать цепочку способов поиска, пропуская неизвестные.
+/// Пустая цепочка означает поиск по полю с тех. картой.
+pub fn parse_lookups(modes: &[String]) -> (Vec<PlanLookup>, Vec<String>) {
+    let mut parsed = Vec::new();
+    let mut unknown = Vec::new();
+
+    for mode in modes {
+        match PlanLookup::parse(mode) {
+            Some(m) if !parsed.contains(&m) => parsed.push(m),
+            Some(_) => {}
+            None => unknown.push(mode.clone()),
+        }
+    }
+
+    if parsed.is_empty() {
+        parsed.push(PlanLookup::Attribute);
+    }
+
+    (parsed, unknown)
+}
+
+/// Названия тех. карт из поля товара: несколько — через запятую
+pub fn split_names(tech_card_name: &str) -> Vec<&str> {
+    tech_card_name
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Версия тех. карты `<base> v<N>`: 0 — сама `base`, `None` — другая тех. карта
+pub fn plan_version(plan_name: &str, base: &str) -> Option<u32> {
+    let plan_name = plan_name.trim();
+    if plan_name == base {
+        return Some(0);
+    }
+
+    let suffix = plan_name.strip_prefix(base)?.trim_start();
+    let number = suffix.strip_prefix('v').or_else(|| suffix.strip_prefix('V'))?;
+    number.parse().ok()
+}
@@ -0,0 +1,92 @@
+//! Пересчёт количества из единиц продажи в единицы производства (упаковки и штуки)
+
+use crate::models::{decimal, Decimal, EntityRef, Product};
+
+/// Откуда взят коэффициент пересчёта
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversionSource {
+    /// Поле товара UNIT_FACTOR_FIELD_NAME
+    Attribute(String),
+    /// Упаковка товара с единицей PRODUCTION_PACK_UOM
+    Pack(String),
+}
+
+/// Коэффициент: единиц производства на единицу продажи
+#[derive(Debug, Clone)]
+pub struct UnitConversion {
+    pub factor: Decimal,
+    pub source: ConversionSource,
+}
+
+impl UnitConversion {
+    /// Коэффициент из числового поля товара; `Err` — поле заполнено некорректно
+    pub fn from_attribute(product: &Product, field: &str) -> Result<Option<Self>, String> {
+        let Some(attr) = product.attributes.iter().flatten().find(|a| a.name == field) else {
+            return Ok(None);
+        };
+        if attr.value.is_none() {
+            return Ok(None);
+        }
+
+        let factor = attr
+            .as_number()
+            .ok_or_else(|| format!("поле '{}' не содержит число", field))?;
+        Self::new(decimal(factor), ConversionSource::Attribute(field.to_string())).map(Some)
+    }
+
+    /// Производство ведётся в упаковках единицы `uom`: одна упаковка на `quantity` единиц товара
+    pub fn from_pack(product: &Product, uom: &EntityRef) -> Result<Option<Self>, String> {
+        let Some(pack) = product
+            .packs
+            .iter()
+            .flatten()
+            .find(|p| p.uom.as_ref().is_some_and(|u| u.meta.href == uom.meta.href))
+        else {
+            return Ok(None);
+        };
+
+        let name = uom.name.clone().unwrap_or_default();
+        if pack.quantity <= Decimal::ZERO {
+            return Err(format!("в упаковке '{}' указано количество {}", name, pack.quantity));
+        }
+        Self::new(Decimal::ONE / pack.quantity, ConversionSource::Pack(name)).map(Some)
+    }
+
+    fn new(factor: Decimal, source: ConversionSource) -> Result<Self, String> {
+        if factor <= Decimal::ZERO {
+            return Err(format!("коэффициент единиц должен быть больше нуля, получено {}", factor));
+        }
+        Ok(Self { factor, source })
+    }
+
+    /// Количество производства, округлённое вверх до целых единиц
+    pub fn apply(&self, quantity: Decimal) -> Decimal {
+        // Погрешность деления не должна добавлять лишнюю единицу
+        (quantity * self.factor).round_dp(6).ceil()
+    }
+
+    /// Единица производства для отчётов
+    pub fn unit(&self) -> String {
+        match &self.source {
+            ConversionSource::Attribute(_) => "ед. производства".to_string(),
+            ConversionSource::Pack(uom) => uom.clone(),
+        }
+    }
+
+    /// Описание пересчёта для сообщений и уведомлений
+    pub fn describe(&self, quantity: Decimal, converted: Decimal) -> String {
+        match &self.source {
+            ConversionSource::Attribute(field) => format!(
+                "{} ед. продажи × {} = {} ед. производства (поле '{}')",
+                quantity, self.factor, converted, field
+            ),
+            ConversionSource::Pack(uom) => format!(
+                "{} шт. = {} {} (упаковка по {} шт.)",
+                quantity,
+                converted,
+                uom,
+                (Decimal::ONE / self.factor).round_dp(6).normalize()
+            ),
+        }
+    }
+}
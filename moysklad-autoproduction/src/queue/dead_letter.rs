@@ -0,0 +1,120 @@
+//! Заказы, которые не удалось обработать за RETRY_MAX_ATTEMPTS попыток или из-за ошибки,
+//! которую повтор не исправит
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::info;
+
+use super::retry::{RetryEntry, RetryError};
+use crate::config::persist_json;
+
+/// Заказ, снятый с повторов, с историей ошибок
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub order_id: String,
+    /// Тип документа, если это не заказ покупателя
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<String>,
+    /// Действие webhook, если это не изменение (`update`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    pub attempts: u32,
+    pub enqueued_at: DateTime<Utc>,
+    pub dead_at: DateTime<Utc>,
+    #[serde(default)]
+    pub errors: Vec<RetryError>,
+}
+
+impl DeadLetter {
+    pub fn entity_type(&self) -> &str {
+        self.entity_type.as_deref().unwrap_or("customerorder")
+    }
+
+    pub fn action(&self) -> &str {
+        self.action.as_deref().unwrap_or("update")
+    }
+}
+
+/// Персистентное хранилище dead-letter; заказы остаются в нём до ручного повтора или удаления
+pub struct DeadLetterStore {
+    path: Option<PathBuf>,
+    entries: Mutex<BTreeMap<String, DeadLetter>>,
+}
+
+impl DeadLetterStore {
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read dead letters {}", path.display()))?;
+            let list: Vec<DeadLetter> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse dead letters {}", path.display()))?;
+            for entry in list {
+                entries.insert(entry.order_id.clone(), entry);
+            }
+            info!("Restored {} dead letters", entries.len());
+        }
+
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Перенести заказ из очереди повторов
+    pub fn add(&self, entry: RetryEntry) -> DeadLetter {
+        let letter = DeadLetter {
+            order_id: entry.order_id,
+            entity_type: entry.entity_type,
+            action: entry.action,
+            attempts: entry.attempts,
+            enqueued_at: entry.enqueued_at,
+            dead_at: Utc::now(),
+            errors: entry.errors,
+        };
+
+        let mut entries = self.entries.lock().expect("dead letters lock poisoned");
+        entries.insert(letter.order_id.clone(), letter.clone());
+        self.persist(&entries);
+        letter
+    }
+
+    /// Убрать заказ (для повтора или окончательно)
+    pub fn remove(&self, order_id: &str) -> Option<DeadLetter> {
+        let mut entries = self.entries.lock().expect("dead letters lock poisoned");
+        let removed = entries.remove(order_id);
+        if removed.is_some() {
+            self.persist(&entries);
+        }
+        removed
+    }
+
+    /// Все записи, последние — первыми
+    pub fn list(&self) -> Vec<DeadLetter> {
+        let mut list: Vec<DeadLetter> = self
+            .entries
+            .lock()
+            .expect("dead letters lock poisoned")
+            .values()
+            .cloned()
+            .collect();
+        list.sort_by_key(|entry| std::cmp::Reverse(entry.dead_at));
+        list
+    }
+
+    pub fn depth(&self) -> usize {
+        self.entries.lock().expect("dead letters lock poisoned").len()
+    }
+
+    fn persist(&self, entries: &BTreeMap<String, DeadLetter>) {
+        let list: Vec<&DeadLetter> = entries.values().collect();
+        persist_json(self.path.as_deref(), &list, "dead letters");
+    }
+}
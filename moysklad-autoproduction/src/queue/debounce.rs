@@ -0,0 +1,112 @@
+//! Склейка повторных webhook по одному документу
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::notifications::DeferredForward;
+
+/// Последнее событие по документу в окне склейки
+#[derive(Debug, Clone)]
+struct Pending {
+    entity_type: String,
+    action: String,
+    last_seen: Instant,
+    events: u32,
+    forward: Option<DeferredForward>,
+}
+
+/// Итог окна: тип и действие последнего события, число склеенных событий
+#[derive(Debug, Clone)]
+pub struct DebouncedEvent {
+    pub entity_type: String,
+    pub action: String,
+    pub events: u32,
+    /// Пересылка последнего webhook окна, если документ окажется чужим
+    pub forward: Option<DeferredForward>,
+}
+
+/// Окно склейки webhook (WEBHOOK_DEBOUNCE_SECS).
+/// МойСклад может прислать несколько событий изменения документа подряд: события,
+/// пришедшие с паузой меньше окна, обрабатываются один раз — после паузы, по последнему действию.
+/// Документ при этом читается заново, так что обработка видит его последнее состояние.
+pub struct WebhookDebouncer {
+    window: Duration,
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl WebhookDebouncer {
+    /// Нулевое окно отключает склейку
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.window.is_zero()
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Зарегистрировать событие по документу.
+    /// `true` — открыто новое окно, и вызывающий ждёт его в `settle`;
+    /// `false` — событие склеено с уже ожидающим.
+    pub fn push(&self, entity_type: &str, id: &str, action: &str, forward: Option<DeferredForward>) -> bool {
+        let mut pending = self.pending.lock().expect("debounce lock poisoned");
+
+        match pending.get_mut(id) {
+            Some(entry) => {
+                entry.entity_type = entity_type.to_string();
+                entry.action = action.to_string();
+                entry.last_seen = Instant::now();
+                entry.events += 1;
+                entry.forward = forward;
+                false
+            }
+            None => {
+                pending.insert(
+                    id.to_string(),
+                    Pending {
+                        entity_type: entity_type.to_string(),
+                        action: action.to_string(),
+                        last_seen: Instant::now(),
+                        events: 1,
+                        forward,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Дождаться паузы в событиях по документу и забрать итог окна
+    pub async fn settle(&self, id: &str) -> Option<DebouncedEvent> {
+        loop {
+            let wait = {
+                let mut pending = self.pending.lock().expect("debounce lock poisoned");
+                let elapsed = pending.get(id)?.last_seen.elapsed();
+                if elapsed >= self.window {
+                    let entry = pending.remove(id)?;
+                    return Some(DebouncedEvent {
+                        entity_type: entry.entity_type,
+                        action: entry.action,
+                        events: entry.events,
+                        forward: entry.forward,
+                    });
+                }
+                self.window - elapsed
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Документов, ожидающих окончания окна
+    pub fn depth(&self) -> usize {
+        self.pending.lock().expect("debounce lock poisoned").len()
+    }
+}
@@ -0,0 +1,52 @@
+//! Ограничение глубины очереди входящих webhook
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Счётчик webhook, ожидающих или выполняющих обработку.
+/// При переполнении новые события отклоняются, и МойСклад повторяет их позже.
+pub struct IntakeLimiter {
+    max_depth: usize,
+    depth: AtomicUsize,
+}
+
+/// Место в очереди; освобождается при удалении
+pub struct IntakePermit<'a> {
+    limiter: &'a IntakeLimiter,
+}
+
+impl IntakeLimiter {
+    /// `max_depth` = 0 — без ограничения
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+            depth: AtomicUsize::new(0),
+        }
+    }
+
+    /// Занять место в очереди, если она не заполнена
+    pub fn try_acquire(&self) -> Option<IntakePermit<'_>> {
+        let acquired = self
+            .depth
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |depth| {
+                (self.max_depth == 0 || depth < self.max_depth).then_some(depth + 1)
+            })
+            .is_ok();
+
+        acquired.then_some(IntakePermit { limiter: self })
+    }
+
+    /// Текущая глубина очереди
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Acquire)
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+}
+
+impl Drop for IntakePermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.depth.fetch_sub(1, Ordering::AcqRel);
+    }
+}
@@ -0,0 +1,19 @@
+pub mod dead_letter;
+pub mod debounce;
+pub mod intake;
+pub mod pending;
+pub mod priority;
+pub mod retry;
+pub mod shortage;
+pub mod stream;
+pub mod worker;
+
+pub use dead_letter::*;
+pub use debounce::*;
+pub use intake::*;
+pub use pending::*;
+pub use priority::*;
+pub use retry::*;
+pub use shortage::*;
+pub use stream::*;
+pub use worker::*;
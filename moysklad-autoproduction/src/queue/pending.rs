@@ -0,0 +1,273 @@
+//! Очередь документов, ожидающих обработки, с приоритетами (PRIORITY_RULES)
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use tracing::info;
+
+use super::priority::PriorityRules;
+use crate::config::persist_json;
+use crate::notifications::ForwardedWebhook;
+
+/// Документ в очереди
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingEntry {
+    pub id: String,
+    pub entity_type: String,
+    pub action: String,
+    /// Приоритет; считается, когда в очереди больше одного документа
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Плановая дата отгрузки: при равном приоритете раньше обрабатывается ближайшая
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shipment: Option<DateTime<Utc>>,
+    pub enqueued_at: DateTime<Utc>,
+    /// Поднят в начало очереди оператором
+    pub promoted: bool,
+    /// Исходный webhook для FORWARD_URL, если документ окажется чужим
+    #[serde(skip)]
+    pub forward: Option<ForwardedWebhook>,
+    #[serde(skip)]
+    seq: u64,
+}
+
+/// Запись файла очереди: вместе с webhook для пересылки. Без `flatten`: вложенные
+/// числа не разбираются через него при arbitrary_precision.
+#[derive(Serialize)]
+struct StoredEntry {
+    entry: PendingEntry,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forward: Option<ForwardedWebhook>,
+}
+
+impl<'de> Deserialize<'de> for StoredEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let mut fields = Map::deserialize(deserializer)?;
+        let forward = match fields.remove("forward") {
+            Some(Value::Null) | None => None,
+            Some(forward) => Some(serde_json::from_value(forward).map_err(D::Error::custom)?),
+        };
+        // Файлы прежних версий хранили поля документа на верхнем уровне
+        let entry = fields.remove("entry").unwrap_or(Value::Object(fields));
+        let entry = serde_json::from_value(entry).map_err(D::Error::custom)?;
+        Ok(Self { entry, forward })
+    }
+}
+
+impl PendingEntry {
+    /// Ключ порядка: поднятые оператором (последний — первым), больший приоритет,
+    /// ближайшая отгрузка, затем по времени поступления
+    fn order_key(&self) -> (bool, i64, i32, i64, u64) {
+        (
+            !self.promoted,
+            if self.promoted { -(self.seq as i64) } else { 0 },
+            -self.priority.unwrap_or(0),
+            self.shipment.map_or(i64::MAX, |s| s.timestamp()),
+            self.seq,
+        )
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    items: Vec<PendingEntry>,
+    next_seq: u64,
+}
+
+impl Entries {
+    fn next_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+}
+
+/// Очередь обработки документов. Повторные события по документу в очереди
+/// не добавляют новую запись, а обновляют действие. Очередь сохраняется в файл
+/// и переживает перезапуск; глубина ограничена WEBHOOK_QUEUE_DEPTH.
+pub struct PendingQueue {
+    rules: PriorityRules,
+    /// 0 — без ограничения
+    max_depth: usize,
+    path: Option<PathBuf>,
+    entries: Mutex<Entries>,
+    notify: Notify,
+}
+
+impl PendingQueue {
+    /// Открыть очередь, восстановив сохранённые документы
+    pub fn open(rules: PriorityRules, max_depth: usize, path: Option<PathBuf>) -> Result<Self> {
+        let mut entries = Entries::default();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read pending queue {}", path.display()))?;
+            let list: Vec<StoredEntry> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse pending queue {}", path.display()))?;
+            for stored in list {
+                let seq = entries.next_seq();
+                entries.items.push(PendingEntry { forward: stored.forward, seq, ..stored.entry });
+            }
+            if !entries.items.is_empty() {
+                info!("Restored {} documents from pending queue", entries.items.len());
+            }
+        }
+
+        let queue = Self {
+            rules,
+            max_depth,
+            path,
+            entries: Mutex::new(entries),
+            notify: Notify::new(),
+        };
+        if queue.depth() > 0 {
+            queue.notify.notify_one();
+        }
+        Ok(queue)
+    }
+
+    /// Очередь используется, если заданы правила приоритета
+    pub fn enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> &PriorityRules {
+        &self.rules
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Поставить документ в очередь; возвращает число документов в очереди.
+    /// `None` — очередь заполнена, новый документ не принят.
+    pub fn push(
+        &self,
+        entity_type: &str,
+        id: &str,
+        action: &str,
+        forward: Option<ForwardedWebhook>,
+    ) -> Option<usize> {
+        let mut entries = self.entries.lock().expect("pending queue lock poisoned");
+
+        if let Some(entry) = entries.items.iter_mut().find(|e| e.id == id) {
+            entry.action = action.to_string();
+            entry.forward = forward;
+        } else if self.max_depth > 0 && entries.items.len() >= self.max_depth {
+            return None;
+        } else {
+            let seq = entries.next_seq();
+            entries.items.push(PendingEntry {
+                id: id.to_string(),
+                entity_type: entity_type.to_string(),
+                action: action.to_string(),
+                priority: None,
+                shipment: None,
+                enqueued_at: Utc::now(),
+                promoted: false,
+                forward,
+                seq,
+            });
+        }
+
+        let depth = entries.items.len();
+        self.persist(&entries);
+        drop(entries);
+        self.notify.notify_one();
+        Some(depth)
+    }
+
+    /// Документы без рассчитанного приоритета, если очередь накопилась (больше одного)
+    pub fn unscored(&self) -> Vec<PendingEntry> {
+        let entries = self.entries.lock().expect("pending queue lock poisoned");
+        if entries.items.len() < 2 {
+            return Vec::new();
+        }
+        entries.items.iter().filter(|e| e.priority.is_none()).cloned().collect()
+    }
+
+    /// Записать приоритет документа
+    pub fn set_priority(&self, id: &str, priority: i32, shipment: Option<DateTime<Utc>>) {
+        let mut entries = self.entries.lock().expect("pending queue lock poisoned");
+        if let Some(entry) = entries.items.iter_mut().find(|e| e.id == id) {
+            entry.priority = Some(priority);
+            entry.shipment = shipment;
+            self.persist(&entries);
+        }
+    }
+
+    /// Взять самый срочный документ
+    pub fn pop(&self) -> Option<PendingEntry> {
+        let mut entries = self.entries.lock().expect("pending queue lock poisoned");
+        let index = entries
+            .items
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, e)| e.order_key())
+            .map(|(index, _)| index)?;
+        let entry = entries.items.remove(index);
+        self.persist(&entries);
+        Some(entry)
+    }
+
+    /// Удалить документ из очереди
+    pub fn remove(&self, id: &str) -> Option<PendingEntry> {
+        let mut entries = self.entries.lock().expect("pending queue lock poisoned");
+        let index = entries.items.iter().position(|e| e.id == id)?;
+        let entry = entries.items.remove(index);
+        self.persist(&entries);
+        Some(entry)
+    }
+
+    /// Поднять документ в начало очереди
+    pub fn promote(&self, id: &str) -> bool {
+        let mut entries = self.entries.lock().expect("pending queue lock poisoned");
+        let seq = entries.next_seq();
+        let Some(entry) = entries.items.iter_mut().find(|e| e.id == id) else {
+            return false;
+        };
+        entry.promoted = true;
+        entry.seq = seq;
+        self.persist(&entries);
+        true
+    }
+
+    /// Дождаться новых документов
+    pub async fn wait(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Документы в порядке обработки
+    pub fn list(&self) -> Vec<PendingEntry> {
+        let mut list = self.entries.lock().expect("pending queue lock poisoned").items.clone();
+        list.sort_by_key(PendingEntry::order_key);
+        list
+    }
+
+    pub fn depth(&self) -> usize {
+        self.entries.lock().expect("pending queue lock poisoned").items.len()
+    }
+
+    /// Сохранить очередь в порядке поступления
+    fn persist(&self, entries: &Entries) {
+        let Some(ref path) = self.path else {
+            return;
+        };
+
+        let mut list: Vec<&PendingEntry> = entries.items.iter().collect();
+        list.sort_by_key(|e| e.seq);
+        let stored: Vec<StoredEntry> = list
+            .into_iter()
+            .map(|entry| StoredEntry { entry: entry.clone(), forward: entry.forward.clone() })
+            .collect();
+
+        persist_json(Some(path), &stored, "pending queue");
+    }
+}
@@ -0,0 +1,105 @@
+//! Правила приоритета документов в очереди обработки
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::models::EntityRef;
+
+/// Условие правила
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    /// Контрагент (название или ID)
+    Agent(String),
+    /// Канал продаж (название или ID)
+    Channel(String),
+    /// Плановая отгрузка не позже чем через столько часов (в том числе просроченная)
+    ShipmentWithin(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PriorityRule {
+    condition: Condition,
+    priority: i32,
+}
+
+/// Данные документа, по которым считается приоритет
+#[derive(Debug, Clone, Default)]
+pub struct PriorityInput {
+    pub agent: Option<EntityRef>,
+    pub sales_channel: Option<EntityRef>,
+    /// Плановая дата отгрузки
+    pub shipment: Option<DateTime<Utc>>,
+}
+
+/// Правила PRIORITY_RULES: `agent:Ozon=10`, `channel:WB FBS=20`, `shipment:24=30`.
+/// Приоритет документа — наибольший из подошедших правил, без правил — 0.
+#[derive(Debug, Clone, Default)]
+pub struct PriorityRules {
+    rules: Vec<PriorityRule>,
+}
+
+impl PriorityRules {
+    pub fn parse(items: &[String]) -> Result<Self, String> {
+        let mut rules = Vec::with_capacity(items.len());
+
+        for item in items {
+            let invalid = || {
+                format!(
+                    "Invalid priority rule '{}', expected agent:<name>=N, channel:<name>=N \
+                     or shipment:<hours>=N",
+                    item
+                )
+            };
+            let (condition, priority) = item.rsplit_once('=').ok_or_else(invalid)?;
+            let priority: i32 = priority.trim().parse().map_err(|_| invalid())?;
+            let (kind, value) = condition.split_once(':').ok_or_else(invalid)?;
+            let value = value.trim();
+            if value.is_empty() {
+                return Err(invalid());
+            }
+
+            let condition = match kind.trim().to_lowercase().as_str() {
+                "agent" => Condition::Agent(value.to_lowercase()),
+                "channel" => Condition::Channel(value.to_lowercase()),
+                "shipment" => {
+                    let hours: u32 = value.parse().map_err(|_| invalid())?;
+                    Condition::ShipmentWithin(Duration::hours(hours as i64))
+                }
+                _ => return Err(invalid()),
+            };
+            rules.push(PriorityRule { condition, priority });
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Приоритет документа
+    pub fn priority(&self, input: &PriorityInput, now: DateTime<Utc>) -> i32 {
+        self.rules
+            .iter()
+            .filter(|rule| match rule.condition {
+                Condition::Agent(ref name) => matches_entity(name, input.agent.as_ref()),
+                Condition::Channel(ref name) => matches_entity(name, input.sales_channel.as_ref()),
+                Condition::ShipmentWithin(within) => {
+                    input.shipment.is_some_and(|shipment| shipment - now <= within)
+                }
+            })
+            .map(|rule| rule.priority)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Название (без учёта регистра) или ID сущности
+fn matches_entity(value: &str, entity: Option<&EntityRef>) -> bool {
+    let Some(entity) = entity else {
+        return false;
+    };
+
+    let id = entity.id.as_deref().or_else(|| entity.meta.href.rsplit('/').next());
+    entity.name.as_deref().is_some_and(|name| name.to_lowercase() == value)
+        || id.is_some_and(|id| id.to_lowercase() == value)
+}
@@ -0,0 +1,262 @@
+//! Очередь повторной обработки заказов при недоступности МойСклад
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::info;
+
+use crate::config::persist_json;
+
+/// Заказ, ожидающий повторной обработки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryEntry {
+    pub order_id: String,
+    /// Тип документа, если это не заказ покупателя
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<String>,
+    /// Действие webhook, если это не изменение (`update`): удаление повторяется удалением
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    pub attempts: u32,
+    pub enqueued_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Ошибки последних попыток
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<RetryError>,
+}
+
+/// Ошибка попытки обработки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryError {
+    pub at: DateTime<Utc>,
+    pub message: String,
+}
+
+/// Сколько последних ошибок хранится в записи
+const ERROR_HISTORY: usize = 20;
+
+impl RetryEntry {
+    /// Действие, с которым документ обрабатывается повторно
+    pub fn action(&self) -> &str {
+        self.action.as_deref().unwrap_or("update")
+    }
+
+    fn record_error(&mut self, at: DateTime<Utc>, message: &str) {
+        self.last_error = Some(message.to_string());
+        self.errors.push(RetryError { at, message: message.to_string() });
+        if self.errors.len() > ERROR_HISTORY {
+            self.errors.remove(0);
+        }
+    }
+}
+
+/// Персистентная очередь повторов с экспоненциальной задержкой
+pub struct RetryQueue {
+    path: Option<PathBuf>,
+    base_delay: Duration,
+    max_delay: Duration,
+    /// После стольких неудачных попыток заказ уходит в dead-letter (0 — без ограничения)
+    max_attempts: u32,
+    entries: Mutex<BTreeMap<String, RetryEntry>>,
+}
+
+impl RetryQueue {
+    /// Открыть очередь, восстановив сохранённые записи
+    pub fn open(
+        path: Option<PathBuf>,
+        base_delay_secs: u64,
+        max_delay_secs: u64,
+        max_attempts: u32,
+    ) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read retry queue {}", path.display()))?;
+            let list: Vec<RetryEntry> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse retry queue {}", path.display()))?;
+            for entry in list {
+                entries.insert(entry.order_id.clone(), entry);
+            }
+            info!("Restored {} orders from retry queue", entries.len());
+        }
+
+        Ok(Self {
+            path,
+            base_delay: Duration::seconds(base_delay_secs as i64),
+            max_delay: Duration::seconds(max_delay_secs as i64),
+            max_attempts,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Задержка перед попыткой номер `attempts + 1`
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2i32.saturating_pow(attempts.min(20));
+        (self.base_delay * factor).min(self.max_delay)
+    }
+
+    /// Поставить заказ в очередь (или обновить существующую запись).
+    /// Повтор выполняется с действием последнего события `action`.
+    pub fn enqueue(&self, entity_type: &str, order_id: &str, action: &str, error: &str) {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().expect("retry queue lock poisoned");
+
+        let entry = entries
+            .entry(order_id.to_string())
+            .or_insert_with(|| RetryEntry {
+                order_id: order_id.to_string(),
+                entity_type: (entity_type != "customerorder").then(|| entity_type.to_string()),
+                action: (action != "update").then(|| action.to_string()),
+                attempts: 0,
+                enqueued_at: now,
+                next_attempt_at: now,
+                last_error: None,
+                errors: Vec::new(),
+            });
+        entry.action = (action != "update").then(|| action.to_string());
+        entry.record_error(now, error);
+        entry.next_attempt_at = now + self.backoff(entry.attempts);
+
+        info!(
+            "Order {} queued for retry at {} (attempt {})",
+            order_id,
+            entry.next_attempt_at,
+            entry.attempts + 1
+        );
+
+        self.persist(&entries);
+    }
+
+    /// Отложить обработку до начала рабочего окна; счётчик попыток не меняется
+    pub fn defer(&self, entity_type: &str, order_id: &str, action: &str, until: DateTime<Utc>) {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().expect("retry queue lock poisoned");
+
+        let entry = entries
+            .entry(order_id.to_string())
+            .or_insert_with(|| RetryEntry {
+                order_id: order_id.to_string(),
+                entity_type: (entity_type != "customerorder").then(|| entity_type.to_string()),
+                action: (action != "update").then(|| action.to_string()),
+                attempts: 0,
+                enqueued_at: now,
+                next_attempt_at: until,
+                last_error: None,
+                errors: Vec::new(),
+            });
+        entry.next_attempt_at = entry.next_attempt_at.max(until);
+
+        info!("Order {} deferred until {} (off hours)", order_id, entry.next_attempt_at);
+
+        self.persist(&entries);
+    }
+
+    /// Отметить неудачную попытку: увеличить счётчик и отложить.
+    /// После RETRY_MAX_ATTEMPTS попыток заказ убирается из очереди и возвращается.
+    pub fn record_failure(&self, order_id: &str, error: &str) -> Option<RetryEntry> {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().expect("retry queue lock poisoned");
+
+        let exhausted = match entries.get_mut(order_id) {
+            Some(entry) => {
+                entry.attempts += 1;
+                entry.record_error(now, error);
+                entry.next_attempt_at = now + self.backoff(entry.attempts);
+                self.max_attempts > 0 && entry.attempts >= self.max_attempts
+            }
+            None => false,
+        };
+        let dead = if exhausted { entries.remove(order_id) } else { None };
+
+        self.persist(&entries);
+        dead
+    }
+
+    /// Снять заказ с повторов после ошибки, которую повтор не исправит. Запись с этой
+    /// ошибкой возвращается для dead-letter, даже если заказа в очереди не было.
+    pub fn give_up(&self, entity_type: &str, order_id: &str, action: &str, error: &str) -> RetryEntry {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().expect("retry queue lock poisoned");
+
+        let queued = entries.remove(order_id);
+        if queued.is_some() {
+            self.persist(&entries);
+        }
+        let mut entry = queued.unwrap_or_else(|| RetryEntry {
+            order_id: order_id.to_string(),
+            entity_type: (entity_type != "customerorder").then(|| entity_type.to_string()),
+            action: (action != "update").then(|| action.to_string()),
+            attempts: 0,
+            enqueued_at: now,
+            next_attempt_at: now,
+            last_error: None,
+            errors: Vec::new(),
+        });
+        entry.attempts += 1;
+        entry.record_error(now, error);
+        entry
+    }
+
+    /// Повторить заказ при следующем проходе, не дожидаясь задержки
+    pub fn promote(&self, order_id: &str) -> bool {
+        let mut entries = self.entries.lock().expect("retry queue lock poisoned");
+        let Some(entry) = entries.get_mut(order_id) else {
+            return false;
+        };
+        entry.next_attempt_at = Utc::now();
+
+        self.persist(&entries);
+        true
+    }
+
+    /// Удалить заказ из очереди
+    pub fn remove(&self, order_id: &str) -> Option<RetryEntry> {
+        let mut entries = self.entries.lock().expect("retry queue lock poisoned");
+        let removed = entries.remove(order_id);
+        if removed.is_some() {
+            self.persist(&entries);
+        }
+        removed
+    }
+
+    /// Заказы, время повтора которых наступило
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<RetryEntry> {
+        self.entries
+            .lock()
+            .expect("retry queue lock poisoned")
+            .values()
+            .filter(|e| e.next_attempt_at <= now)
+            .cloned()
+            .collect()
+    }
+
+    /// Все записи очереди
+    pub fn list(&self) -> Vec<RetryEntry> {
+        let mut list: Vec<RetryEntry> = self
+            .entries
+            .lock()
+            .expect("retry queue lock poisoned")
+            .values()
+            .cloned()
+            .collect();
+        list.sort_by_key(|e| e.next_attempt_at);
+        list
+    }
+
+    pub fn depth(&self) -> usize {
+        self.entries.lock().expect("retry queue lock poisoned").len()
+    }
+
+    fn persist(&self, entries: &BTreeMap<String, RetryEntry>) {
+        let list: Vec<&RetryEntry> = entries.values().collect();
+        persist_json(self.path.as_deref(), &list, "retry queue");
+    }
+}
@@ -0,0 +1,163 @@
+//! Позиции, ожидающие поступления материалов
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::info;
+
+use crate::config::persist_json;
+use crate::models::{Decimal, MaterialShortage};
+
+/// Позиция, не произведённая из-за нехватки материалов
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortageEntry {
+    pub order_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_name: Option<String>,
+    /// Тип документа, если это не заказ покупателя
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<String>,
+    pub product_id: String,
+    pub product_name: String,
+    pub quantity: Decimal,
+    /// Недостающие материалы по последней проверке
+    pub missing: Vec<MaterialShortage>,
+    pub queued_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked_at: Option<DateTime<Utc>>,
+    /// Сколько раз позиция перепроверялась
+    #[serde(default)]
+    pub checks: u32,
+}
+
+impl ShortageEntry {
+    fn key(&self) -> String {
+        entry_key(&self.order_id, &self.product_id)
+    }
+}
+
+fn entry_key(order_id: &str, product_id: &str) -> String {
+    format!("{}/{}", order_id, product_id)
+}
+
+/// Персистентная очередь позиций, ожидающих материалов
+pub struct ShortageQueue {
+    path: Option<PathBuf>,
+    entries: Mutex<BTreeMap<String, ShortageEntry>>,
+}
+
+impl ShortageQueue {
+    /// Открыть очередь, восстановив сохранённые записи
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read shortage queue {}", path.display()))?;
+            let list: Vec<ShortageEntry> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse shortage queue {}", path.display()))?;
+            for entry in list {
+                entries.insert(entry.key(), entry);
+            }
+            info!("Restored {} positions waiting for materials", entries.len());
+        }
+
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Поставить позицию в очередь; для уже ожидающей позиции учесть повторную проверку
+    pub fn add(&self, mut entry: ShortageEntry) {
+        let mut entries = self.entries.lock().expect("shortage queue lock poisoned");
+
+        let key = entry.key();
+        if let Some(existing) = entries.get(&key) {
+            entry.queued_at = existing.queued_at;
+            entry.checks = existing.checks + 1;
+            entry.checked_at = Some(Utc::now());
+        } else {
+            info!(
+                "Position '{}' of order {} is waiting for materials",
+                entry.product_name,
+                entry.order_name.as_deref().unwrap_or(&entry.order_id)
+            );
+        }
+        entries.insert(key, entry);
+
+        self.persist(&entries);
+    }
+
+    /// Позиция уже ожидает материалов
+    pub fn contains(&self, order_id: &str, product_id: &str) -> bool {
+        self.entries
+            .lock()
+            .expect("shortage queue lock poisoned")
+            .contains_key(&entry_key(order_id, product_id))
+    }
+
+    /// Убрать позицию из очереди
+    pub fn remove(&self, order_id: &str, product_id: &str) -> Option<ShortageEntry> {
+        let mut entries = self.entries.lock().expect("shortage queue lock poisoned");
+        let removed = entries.remove(&entry_key(order_id, product_id));
+        if removed.is_some() {
+            self.persist(&entries);
+        }
+        removed
+    }
+
+    /// Убрать все позиции заказа (заказ удалён или распроведён)
+    pub fn remove_order(&self, order_id: &str) -> usize {
+        let mut entries = self.entries.lock().expect("shortage queue lock poisoned");
+        let before = entries.len();
+        entries.retain(|_, e| e.order_id != order_id);
+        let removed = before - entries.len();
+        if removed > 0 {
+            self.persist(&entries);
+        }
+        removed
+    }
+
+    /// Заказы с ожидающими позициями: ID заказа и тип документа
+    pub fn orders(&self) -> Vec<(String, String)> {
+        let orders: BTreeMap<String, String> = self
+            .entries
+            .lock()
+            .expect("shortage queue lock poisoned")
+            .values()
+            .map(|e| {
+                let entity_type = e.entity_type.clone().unwrap_or_else(|| "customerorder".to_string());
+                (e.order_id.clone(), entity_type)
+            })
+            .collect();
+        orders.into_iter().collect()
+    }
+
+    /// Все записи очереди, давние первыми
+    pub fn list(&self) -> Vec<ShortageEntry> {
+        let mut list: Vec<ShortageEntry> = self
+            .entries
+            .lock()
+            .expect("shortage queue lock poisoned")
+            .values()
+            .cloned()
+            .collect();
+        list.sort_by_key(|e| e.queued_at);
+        list
+    }
+
+    pub fn depth(&self) -> usize {
+        self.entries.lock().expect("shortage queue lock poisoned").len()
+    }
+
+    fn persist(&self, entries: &BTreeMap<String, ShortageEntry>) {
+        let list: Vec<&ShortageEntry> = entries.values().collect();
+        persist_json(self.path.as_deref(), &list, "shortage queue");
+    }
+}
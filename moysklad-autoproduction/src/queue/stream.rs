@@ -0,0 +1,327 @@
+//! Распределённая очередь webhook на Redis Streams
+//!
+//! Приём webhook кладёт событие в поток, а каждый экземпляр сервиса читает его
+//! через общую группу потребителей: одно событие обрабатывает только один экземпляр.
+
+use anyhow::{Context, Result};
+use redis::aio::ConnectionManager;
+use redis::streams::{
+    StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamMaxlen, StreamReadOptions, StreamReadReply,
+};
+use redis::AsyncCommands;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use crate::api::is_transient_error;
+use crate::api::redact::error_message;
+use crate::config::Settings;
+use crate::models::WebhookEvent;
+use crate::notifications::{DeferredForward, ForwardedWebhook, WebhookForwarder};
+use crate::tenants::TenantRegistry;
+
+use super::worker::give_up;
+
+/// Сколько событий читать за раз
+const READ_BATCH: usize = 10;
+
+/// Сколько ждать новых событий в одном запросе, мс
+const READ_BLOCK_MS: usize = 5000;
+
+/// Событие из потока
+#[derive(Debug, Clone)]
+pub struct QueuedEvent {
+    pub id: String,
+    pub tenant: String,
+    pub entity_type: String,
+    pub order_id: String,
+    pub action: String,
+    /// Исходный webhook для FORWARD_URL, если документ окажется чужим
+    pub forward: Option<ForwardedWebhook>,
+}
+
+impl QueuedEvent {
+    fn from_stream(entry: &StreamId) -> Option<Self> {
+        Some(Self {
+            id: entry.id.clone(),
+            tenant: entry.get("tenant")?,
+            entity_type: entry.get("entity_type").unwrap_or_else(|| "customerorder".to_string()),
+            order_id: entry.get("order_id")?,
+            action: entry.get("action").unwrap_or_else(|| "update".to_string()),
+            forward: entry.get("forward_query").map(|query| ForwardedWebhook {
+                query,
+                content_type: entry.get("forward_content_type"),
+                body: entry.get::<String>("forward_body").unwrap_or_default().into_bytes(),
+            }),
+        })
+    }
+}
+
+/// Поток событий в Redis с группой потребителей
+pub struct EventStream {
+    conn: ConnectionManager,
+    stream: String,
+    group: String,
+    consumer: String,
+    maxlen: usize,
+    claim_idle: Duration,
+}
+
+impl EventStream {
+    /// Подключиться к Redis и создать группу потребителей, если её ещё нет
+    pub async fn connect(settings: &Settings) -> Result<Self> {
+        let url = settings.redis_url.as_deref().context("REDIS_URL is not set")?;
+        let client = redis::Client::open(url).context("Invalid REDIS_URL")?;
+        let mut conn = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to Redis")?;
+
+        let created: redis::RedisResult<()> = conn
+            .xgroup_create_mkstream(&settings.redis_stream, &settings.redis_consumer_group, "0")
+            .await;
+        match created {
+            Ok(()) => info!(
+                "Created consumer group '{}' on stream '{}'",
+                settings.redis_consumer_group, settings.redis_stream
+            ),
+            Err(e) if e.code() == Some("BUSYGROUP") => {}
+            Err(e) => return Err(e).context("Failed to create Redis consumer group"),
+        }
+
+        Ok(Self {
+            conn,
+            stream: settings.redis_stream.clone(),
+            group: settings.redis_consumer_group.clone(),
+            consumer: settings.worker_name.clone(),
+            maxlen: settings.redis_stream_maxlen,
+            claim_idle: Duration::from_secs(settings.redis_claim_idle_secs),
+        })
+    }
+
+    /// Поставить событие документа в очередь; возвращает ID записи в потоке.
+    /// `forward` — исходный webhook для пересылки, если документ окажется чужим.
+    pub async fn publish(
+        &self,
+        tenant: &str,
+        entity_type: &str,
+        order_id: &str,
+        action: &str,
+        forward: Option<&ForwardedWebhook>,
+    ) -> Result<String> {
+        let mut fields = vec![
+            ("tenant", tenant.to_string()),
+            ("entity_type", entity_type.to_string()),
+            ("order_id", order_id.to_string()),
+            ("action", action.to_string()),
+        ];
+        if let Some(webhook) = forward {
+            fields.push(("forward_query", webhook.query.clone()));
+            if let Some(ref content_type) = webhook.content_type {
+                fields.push(("forward_content_type", content_type.clone()));
+            }
+            fields.push(("forward_body", String::from_utf8_lossy(&webhook.body).into_owned()));
+        }
+
+        let mut conn = self.conn.clone();
+        let id: String = conn
+            .xadd_maxlen(&self.stream, StreamMaxlen::Approx(self.maxlen), "*", &fields)
+            .await
+            .context("Failed to publish event to Redis")?;
+        Ok(id)
+    }
+
+    /// Прочитать события: `pending` — свои неподтверждённые (после перезапуска), иначе новые
+    async fn read(&self, pending: bool) -> Result<Vec<QueuedEvent>> {
+        let mut conn = self.conn.clone();
+        let mut options = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .count(READ_BATCH);
+        if !pending {
+            options = options.block(READ_BLOCK_MS);
+        }
+
+        let start = if pending { "0" } else { ">" };
+        let reply: Option<StreamReadReply> = conn
+            .xread_options(&[&self.stream], &[start], &options)
+            .await
+            .context("Failed to read events from Redis")?;
+
+        Ok(reply
+            .into_iter()
+            .flat_map(|r| r.keys)
+            .flat_map(|key| key.ids)
+            .filter_map(|entry| {
+                let event = QueuedEvent::from_stream(&entry);
+                if event.is_none() {
+                    warn!("Skipping malformed stream entry {}", entry.id);
+                }
+                event
+            })
+            .collect())
+    }
+
+    /// Забрать себе события, которые другие экземпляры не подтвердили за REDIS_CLAIM_IDLE_SECS
+    async fn claim_stale(&self) -> Result<Vec<QueuedEvent>> {
+        let mut conn = self.conn.clone();
+        let mut start = "0-0".to_string();
+        let mut events = Vec::new();
+
+        loop {
+            let reply: StreamAutoClaimReply = conn
+                .xautoclaim_options(
+                    &self.stream,
+                    &self.group,
+                    &self.consumer,
+                    self.claim_idle.as_millis() as u64,
+                    &start,
+                    StreamAutoClaimOptions::default().count(READ_BATCH),
+                )
+                .await
+                .context("Failed to claim stale events in Redis")?;
+
+            for entry in &reply.claimed {
+                match QueuedEvent::from_stream(entry) {
+                    Some(event) => events.push(event),
+                    None => warn!("Skipping malformed stream entry {}", entry.id),
+                }
+            }
+            if reply.next_stream_id == "0-0" {
+                return Ok(events);
+            }
+            start = reply.next_stream_id;
+        }
+    }
+
+    /// Подтвердить обработку события
+    async fn ack(&self, id: &str) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let _: i64 = conn
+            .xack(&self.stream, &self.group, &[id])
+            .await
+            .context("Failed to acknowledge event in Redis")?;
+        Ok(())
+    }
+}
+
+/// Запустить потребителя потока: события обрабатываются процессором нужного тенанта.
+/// Сначала дочитываются собственные неподтверждённые события, оставшиеся после перезапуска;
+/// раз в REDIS_CLAIM_IDLE_SECS забираются зависшие события других экземпляров.
+/// С PRIORITY_RULES события встают в очередь приоритетов экземпляра.
+/// Чужие документы пересылаются в FORWARD_URL.
+pub fn spawn_stream_consumer(
+    stream: Arc<EventStream>,
+    tenants: Arc<TenantRegistry>,
+    forwarder: Option<Arc<WebhookForwarder>>,
+) {
+    tokio::spawn(async move {
+        info!("Consuming webhooks from Redis stream as '{}'", stream.consumer);
+        let mut pending = true;
+        let mut last_claim = Instant::now();
+
+        loop {
+            let events = if last_claim.elapsed() >= stream.claim_idle {
+                last_claim = Instant::now();
+                match stream.claim_stale().await {
+                    Ok(events) if !events.is_empty() => {
+                        warn!("Claimed {} events not acknowledged by other workers", events.len());
+                        Ok(events)
+                    }
+                    Ok(_) => stream.read(pending).await,
+                    Err(e) => Err(e),
+                }
+            } else {
+                stream.read(pending).await
+            };
+
+            let events = match events {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("{:#}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            };
+
+            if pending && events.is_empty() {
+                pending = false;
+                continue;
+            }
+
+            for event in events {
+                process_event(&tenants, forwarder.as_ref(), &event).await;
+
+                // Ошибки обработки уже учтены (очередь повторов, dead-letter); событие не переигрывается
+                if let Err(e) = stream.ack(&event.id).await {
+                    error!("{:#}", e);
+                }
+            }
+        }
+    });
+}
+
+async fn process_event(
+    tenants: &TenantRegistry,
+    forwarder: Option<&Arc<WebhookForwarder>>,
+    event: &QueuedEvent,
+) {
+    let Some(tenant) = tenants.by_name(&event.tenant) else {
+        warn!("Dropping event {} for unknown tenant '{}'", event.id, event.tenant);
+        return;
+    };
+
+    // С приоритетами событие встаёт в очередь экземпляра (она сохраняется в файл);
+    // если очередь заполнена, документ обрабатывается сразу
+    if tenant.pending.enabled() {
+        let queued = tenant
+            .pending
+            .push(&event.entity_type, &event.order_id, &event.action, event.forward.clone());
+        match queued {
+            Some(depth) => {
+                debug!("Queued order {} by priority ({} waiting)", event.order_id, depth);
+                return;
+            }
+            None => warn!("Pending queue full, processing {} right away", event.order_id),
+        }
+    }
+
+    let webhook = WebhookEvent::entity_action(&event.entity_type, &event.order_id, &event.action);
+    let mut processor = tenant.processor.lock().await;
+
+    match processor
+        .process_webhook(&webhook)
+        .instrument(info_span!("tenant", name = %tenant.name))
+        .await
+    {
+        Ok(results) => {
+            info!(
+                "Processed queued order {}: {} of {} positions successful",
+                event.order_id,
+                results.iter().filter(|r| r.success).count(),
+                results.len()
+            );
+            if let Some(failed) = results.iter().find(|r| r.retryable()) {
+                let message = failed.error.as_deref().unwrap_or(&failed.message);
+                tenant
+                    .retry_queue
+                    .enqueue(&event.entity_type, &event.order_id, &webhook.action, message);
+            }
+
+            if let (Some(forwarder), Some(webhook)) = (forwarder, &event.forward) {
+                let forward = DeferredForward::new(forwarder.clone(), webhook.clone());
+                if forward.forward_if_foreign(&results) {
+                    info!("Queued order {} is not ours, forwarding", event.order_id);
+                }
+            }
+        }
+        Err(e) if is_transient_error(&e) => {
+            warn!("Moysklad unavailable while processing order {}, queued for retry: {}", event.order_id, e);
+            tenant
+                .retry_queue
+                .enqueue(&event.entity_type, &event.order_id, &webhook.action, &error_message(&e));
+        }
+        Err(e) => {
+            error!("Error processing queued order {}: {:#}", event.order_id, e);
+            give_up(&tenant, &webhook, &error_message(&e)).await;
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Фоновая повторная обработка заказов из очередей
+
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use crate::api::is_transient_error;
+use crate::api::redact::error_message;
+use crate::models::{SkipReason, WebhookEvent};
+use crate::notifications::{DeferredForward, Notification, NotificationEvent, WebhookForwarder};
+use crate::processing::OrderProcessor;
+use crate::tenants::Tenant;
+
+use super::retry::RetryEntry;
+
+/// Запустить фоновый обработчик очереди повторов.
+/// Перед обработкой проверяется доступность МойСклад; пока API недоступен,
+/// очередь не трогается.
+pub fn spawn_retry_worker(tenant: Arc<Tenant>, poll_interval: Duration) {
+    let span = info_span!("tenant", name = %tenant.name);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(poll_interval);
+
+        loop {
+            interval.tick().await;
+
+            if tenant.is_removed() {
+                break;
+            }
+
+            let due = tenant.retry_queue.due(Utc::now());
+            if due.is_empty() {
+                continue;
+            }
+
+            let mut processor = tenant.processor.lock().await;
+
+            if let Err(e) = processor.probe_api().await {
+                debug!("Moysklad API still unavailable: {:#}", e);
+                continue;
+            }
+
+            info!("Moysklad API is available, retrying {} queued orders", due.len());
+
+            for entry in due {
+                let event = WebhookEvent::entity_action(
+                    entry.entity_type.as_deref().unwrap_or("customerorder"),
+                    &entry.order_id,
+                    entry.action(),
+                );
+
+                match processor.process_webhook(&event).await {
+                    Ok(results) => {
+                        // Вне рабочего времени заказ уже перенесён на начало рабочего окна
+                        if results.iter().any(|r| r.skip_reason == Some(SkipReason::OffHours)) {
+                            continue;
+                        }
+                        // Позиции с временной ошибкой остаются в очереди до следующей попытки
+                        if let Some(failed) = results.iter().find(|r| r.retryable()) {
+                            let message = failed.error.as_deref().unwrap_or(&failed.message);
+                            warn!(
+                                "Retry of order {} left positions unprocessed: {}",
+                                entry.order_id, message
+                            );
+                            record_retry_failure(&tenant, &entry.order_id, message).await;
+                            continue;
+                        }
+                        info!(
+                            "Retried order {} after {} attempts: {} positions processed",
+                            entry.order_id,
+                            entry.attempts + 1,
+                            results.len()
+                        );
+                        tenant.retry_queue.remove(&entry.order_id);
+                    }
+                    Err(e) if is_transient_error(&e) => {
+                        warn!("Retry of order {} failed: {:#}", entry.order_id, e);
+                        record_retry_failure(&tenant, &entry.order_id, &error_message(&e)).await;
+                    }
+                    Err(e) => {
+                        error!("Retry of order {} failed permanently: {:#}", entry.order_id, e);
+                        give_up(&tenant, &event, &error_message(&e)).await;
+                    }
+                }
+            }
+        }
+    }.instrument(span));
+}
+
+/// Неудачная попытка повтора; после RETRY_MAX_ATTEMPTS заказ переносится в dead-letter
+async fn record_retry_failure(tenant: &Tenant, order_id: &str, error: &str) {
+    let Some(entry) = tenant.retry_queue.record_failure(order_id, error) else {
+        return;
+    };
+    move_to_dead_letters(tenant, entry, error).await;
+}
+
+/// Ошибка, которую повтор не исправит (не сбой МойСклад): документ сразу переносится
+/// в dead-letter, а не остаётся в логе или очереди повторов
+pub(super) async fn give_up(tenant: &Tenant, event: &WebhookEvent, error: &str) {
+    let Some(order_id) = event.content.as_ref().and_then(|c| c.id.as_deref()) else {
+        return;
+    };
+    let entry = tenant.retry_queue.give_up(&event.entity_type, order_id, &event.action, error);
+    move_to_dead_letters(tenant, entry, error).await;
+}
+
+/// Перенести документ в dead-letter и уведомить (`dead_letter`)
+async fn move_to_dead_letters(tenant: &Tenant, entry: RetryEntry, error: &str) {
+    let letter = tenant.dead_letters.add(entry);
+    let order_id = &letter.order_id;
+    error!("Order {} moved to dead letters after {} attempts", order_id, letter.attempts);
+    tenant
+        .notifier
+        .notify(Notification::new(
+            NotificationEvent::DeadLetter,
+            format!("Заказ {} снят с повторов", order_id),
+            format!(
+                "{} неудачных попыток, последняя ошибка: {}. \
+                 Повторить: POST /admin/dead-letters/{}/requeue, удалить: DELETE /admin/dead-letters/{}",
+                letter.attempts, error, order_id, order_id
+            ),
+        ))
+        .await;
+}
+
+/// Запустить периодическую перепроверку позиций, ожидающих материалов
+pub fn spawn_shortage_worker(tenant: Arc<Tenant>, recheck_interval: Duration) {
+    let span = info_span!("tenant", name = %tenant.name);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(recheck_interval);
+        // Первый тик срабатывает сразу; перепроверка — только через интервал после старта
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            if tenant.is_removed() {
+                break;
+            }
+
+            if tenant.shortages.depth() == 0 {
+                continue;
+            }
+
+            let mut processor = tenant.processor.lock().await;
+
+            if !processor.is_working_time() {
+                debug!("Off hours, shortage recheck postponed");
+                continue;
+            }
+
+            if let Err(e) = processor.probe_api().await {
+                debug!("Moysklad API unavailable, shortage recheck postponed: {:#}", e);
+                continue;
+            }
+
+            if let Err(e) = processor.recheck_shortages().await {
+                warn!("Shortage recheck failed: {:#}", e);
+            }
+        }
+    }.instrument(span));
+}
+
+/// Обработать документ, когда webhook по нему перестанут приходить (WEBHOOK_DEBOUNCE_SECS).
+/// Вызывается для первого события окна; следующие склеиваются в `tenant.debounce`.
+pub fn spawn_debounced_processing(tenant: Arc<Tenant>, id: String) {
+    let span = info_span!("tenant", name = %tenant.name);
+
+    tokio::spawn(async move {
+        let Some(debounced) = tenant.debounce.settle(&id).await else {
+            return;
+        };
+        if tenant.is_removed() {
+            return;
+        }
+
+        if debounced.events > 1 {
+            info!(
+                "Coalesced {} webhooks for {} into one run (action={})",
+                debounced.events, id, debounced.action
+            );
+        }
+
+        // С приоритетами документ встаёт в общую очередь, если в ней есть место
+        if tenant.pending.enabled() {
+            let forward = debounced.forward.clone().map(DeferredForward::into_webhook);
+            if tenant.pending.push(&debounced.entity_type, &id, &debounced.action, forward).is_some() {
+                return;
+            }
+            warn!("Pending queue full, processing {} right away", id);
+        }
+
+        let event = WebhookEvent::entity_action(&debounced.entity_type, &id, &debounced.action);
+        let mut processor = tenant.processor.lock().await;
+        process_queued(&tenant, &mut processor, &event, debounced.forward).await;
+    }.instrument(span));
+}
+
+/// Запустить обработку очереди документов по приоритету (PRIORITY_RULES).
+/// Пока очередь не накопилась, документы обрабатываются по мере поступления;
+/// когда ждут несколько, для них считается приоритет и первым берётся самый срочный.
+pub fn spawn_pending_worker(tenant: Arc<Tenant>, forwarder: Option<Arc<WebhookForwarder>>) {
+    let span = info_span!("tenant", name = %tenant.name);
+
+    tokio::spawn(async move {
+        loop {
+            tenant.pending.wait().await;
+
+            if tenant.is_removed() {
+                break;
+            }
+
+            loop {
+                // Документы читаются без блокировки процессора: обработка не ждёт расчёта приоритета
+                let rules = tenant.pending.rules();
+                let now = Utc::now();
+                for entry in tenant.pending.unscored() {
+                    let input =
+                        OrderProcessor::priority_input(&tenant.processor, &entry.entity_type, &entry.id)
+                            .await;
+                    match input {
+                        Ok(input) => {
+                            let priority = rules.priority(&input, now);
+                            tenant.pending.set_priority(&entry.id, priority, input.shipment);
+                        }
+                        Err(e) => {
+                            warn!("Failed to get priority of {}: {:#}", entry.id, e);
+                            tenant.pending.set_priority(&entry.id, 0, None);
+                        }
+                    }
+                }
+
+                let Some(entry) = tenant.pending.pop() else {
+                    break;
+                };
+                if let Some(priority) = entry.priority {
+                    debug!(
+                        "Processing {} (priority {}, {} more waiting)",
+                        entry.id,
+                        priority,
+                        tenant.pending.depth()
+                    );
+                }
+
+                let event =
+                    WebhookEvent::entity_action(&entry.entity_type, &entry.id, &entry.action);
+                let forward = forwarder
+                    .clone()
+                    .zip(entry.forward)
+                    .map(|(forwarder, webhook)| DeferredForward::new(forwarder, webhook));
+                let mut processor = tenant.processor.lock().await;
+                process_queued(&tenant, &mut processor, &event, forward).await;
+            }
+        }
+    }.instrument(span));
+}
+
+/// Обработать документ из фоновой очереди; при временной ошибке — в очередь повторов,
+/// чужой документ — переслать в FORWARD_URL
+async fn process_queued(
+    tenant: &Tenant,
+    processor: &mut OrderProcessor,
+    event: &WebhookEvent,
+    forward: Option<DeferredForward>,
+) {
+    let entity_type = &event.entity_type;
+    let Some(id) = event.content.as_ref().and_then(|c| c.id.as_deref()) else {
+        return;
+    };
+
+    match processor.process_webhook(event).await {
+        Ok(results) => {
+            if let Some(failed) = results.iter().find(|r| r.retryable()) {
+                let message = failed.error.as_deref().unwrap_or(&failed.message);
+                warn!("Order {} has positions failed on transient errors, queued for retry", id);
+                tenant.retry_queue.enqueue(entity_type, id, &event.action, message);
+            }
+            info!("Processed {} {}: {} positions", entity_type, id, results.len());
+
+            if forward.is_some_and(|forward| forward.forward_if_foreign(&results)) {
+                info!("{} {} is not ours, forwarding", entity_type, id);
+            }
+        }
+        Err(e) if is_transient_error(&e) => {
+            warn!("Moysklad unavailable while processing {}, queued for retry: {}", id, e);
+            tenant.retry_queue.enqueue(entity_type, id, &event.action, &error_message(&e));
+        }
+        Err(e) => {
+            error!("Error processing {} {}: {:#}", entity_type, id, e);
+            give_up(tenant, event, &error_message(&e)).await;
+        }
+    }
+}
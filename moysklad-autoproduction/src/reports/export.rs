@@ -0,0 +1,116 @@
+//! Выгрузка истории обработки в CSV/XLSX
+
+use anyhow::{Context, Result};
+use rust_xlsxwriter::Workbook;
+
+use crate::history::HistoryRecord;
+
+/// Формат выгрузки
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "xlsx" => Some(Self::Xlsx),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+        }
+    }
+}
+
+const HEADERS: [&str; 11] = [
+    "Дата",
+    "Заказ",
+    "ID заказа",
+    "Товар",
+    "ID товара",
+    "Количество",
+    "Успешно",
+    "Тех. операция",
+    "ID тех. операции",
+    "Сообщение",
+    "Ошибка",
+];
+
+fn row(record: &HistoryRecord) -> [String; 11] {
+    [
+        record.timestamp.to_rfc3339(),
+        record.order_name.clone().unwrap_or_default(),
+        record.order_id.clone().unwrap_or_default(),
+        record.product_name.clone().unwrap_or_default(),
+        record.product_id.clone().unwrap_or_default(),
+        record.quantity.to_string(),
+        if record.success { "да" } else { "нет" }.to_string(),
+        record.processing_name.clone().unwrap_or_default(),
+        record.processing_id.clone().unwrap_or_default(),
+        record.message.clone(),
+        record.error.clone().unwrap_or_default(),
+    ]
+}
+
+/// Выгрузить записи в выбранном формате
+pub fn export_history(records: &[HistoryRecord], format: ExportFormat) -> Result<Vec<u8>> {
+    match format {
+        ExportFormat::Csv => export_csv(records),
+        ExportFormat::Xlsx => export_xlsx(records),
+    }
+}
+
+fn export_csv(records: &[HistoryRecord]) -> Result<Vec<u8>> {
+    // BOM, чтобы Excel корректно открывал кириллицу
+    let mut buffer = "\u{feff}".as_bytes().to_vec();
+
+    {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(b';')
+            .from_writer(&mut buffer);
+
+        writer.write_record(HEADERS)?;
+        for record in records {
+            writer.write_record(row(record))?;
+        }
+        writer.flush().context("Failed to write CSV")?;
+    }
+
+    Ok(buffer)
+}
+
+fn export_xlsx(records: &[HistoryRecord]) -> Result<Vec<u8>> {
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet();
+
+    for (col, header) in HEADERS.iter().enumerate() {
+        worksheet.write_string(0, col as u16, *header)?;
+    }
+
+    for (i, record) in records.iter().enumerate() {
+        let row_idx = (i + 1) as u32;
+        for (col, value) in row(record).iter().enumerate() {
+            if col == 5 {
+                worksheet.write_number(row_idx, col as u16, record.quantity)?;
+            } else {
+                worksheet.write_string(row_idx, col as u16, value)?;
+            }
+        }
+    }
+
+    workbook.save_to_buffer().context("Failed to build XLSX")
+}
@@ -0,0 +1,136 @@
+//! Прогноз остатков по открытым заказам
+
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+
+use crate::models::{parse_moment, CustomerOrder, Decimal, StoreStockInfo};
+
+/// Остаток на конкретный день
+#[derive(Debug, Clone, Serialize)]
+pub struct ForecastDay {
+    pub date: NaiveDate,
+    /// Количество к отгрузке в этот день
+    pub outgoing: Decimal,
+    /// Прогнозный остаток на конец дня
+    pub projected_stock: Decimal,
+    pub below_threshold: bool,
+}
+
+/// Открытый заказ, влияющий на прогноз
+#[derive(Debug, Clone, Serialize)]
+pub struct ForecastOrder {
+    pub order_id: String,
+    pub order_name: String,
+    pub quantity: Decimal,
+    pub due_date: NaiveDate,
+}
+
+/// Прогноз остатков товара
+#[derive(Debug, Clone, Serialize)]
+pub struct StockForecast {
+    pub product_id: String,
+    pub stock: Decimal,
+    pub reserve: Decimal,
+    pub in_transit: Decimal,
+    pub threshold: Decimal,
+    pub open_orders: Vec<ForecastOrder>,
+    pub days: Vec<ForecastDay>,
+    /// Первый день, когда остаток опустится ниже порога
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_day_below_threshold: Option<NaiveDate>,
+}
+
+impl StockForecast {
+    /// Построить прогноз: к текущему остатку добавляется ожидание,
+    /// неотгруженные количества открытых заказов списываются в день
+    /// планируемой отгрузки (просроченные и без даты — сегодня)
+    pub fn build(
+        product_id: &str,
+        stock_info: Option<&StoreStockInfo>,
+        orders: &[CustomerOrder],
+        store_id: &str,
+        threshold: Decimal,
+        today: NaiveDate,
+        horizon_days: u32,
+    ) -> Self {
+        let (stock, reserve, in_transit) = stock_info
+            .map(|s| (s.stock, s.reserve, s.in_transit))
+            .unwrap_or_default();
+
+        let mut open_orders = Vec::new();
+        for order in orders {
+            let order_store_id = order.store.as_ref().and_then(|s| s.id.as_deref());
+            if order_store_id.is_some_and(|id| id != store_id) {
+                continue;
+            }
+
+            let Some(ref positions) = order.positions else {
+                continue;
+            };
+
+            let quantity: Decimal = positions
+                .rows
+                .iter()
+                .filter(|p| p.assortment.meta.href.rsplit('/').next() == Some(product_id))
+                .map(|p| (p.quantity - p.shipped).max(Decimal::ZERO))
+                .sum();
+
+            if quantity <= Decimal::ZERO {
+                continue;
+            }
+
+            let due_date = order
+                .delivery_planned_moment
+                .as_deref()
+                .and_then(parse_moment)
+                .map(|dt| dt.date())
+                .unwrap_or(today)
+                .max(today);
+
+            open_orders.push(ForecastOrder {
+                order_id: order.id.clone(),
+                order_name: order.name.clone(),
+                quantity,
+                due_date,
+            });
+        }
+        open_orders.sort_by_key(|o| o.due_date);
+
+        let mut projected = stock + in_transit;
+        let mut days = Vec::with_capacity(horizon_days as usize + 1);
+        let mut first_day_below_threshold = None;
+
+        for offset in 0..=horizon_days {
+            let date = today + Duration::days(offset as i64);
+            let outgoing: Decimal = open_orders
+                .iter()
+                .filter(|o| o.due_date == date)
+                .map(|o| o.quantity)
+                .sum();
+
+            projected -= outgoing;
+            let below_threshold = projected < threshold;
+            if below_threshold && first_day_below_threshold.is_none() {
+                first_day_below_threshold = Some(date);
+            }
+
+            days.push(ForecastDay {
+                date,
+                outgoing,
+                projected_stock: projected,
+                below_threshold,
+            });
+        }
+
+        Self {
+            product_id: product_id.to_string(),
+            stock,
+            reserve,
+            in_transit,
+            threshold,
+            open_orders,
+            days,
+            first_day_below_threshold,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Проверка связи товаров склада с тех. картами
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Проблема настройки товара
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingIssueKind {
+    /// Тех. карта не указана ни в поле, ни в других источниках
+    MissingTechCard,
+    /// Указанной тех. карты нет в МойСклад
+    PlanNotFound,
+    /// В тех. карте нет материалов
+    EmptyMaterials,
+    /// Тех. карту не удалось проверить (ошибка API)
+    LookupFailed,
+}
+
+/// Товар с проблемой настройки
+#[derive(Debug, Clone, Serialize)]
+pub struct MappingIssue {
+    pub product_id: String,
+    pub product: String,
+    pub kind: MappingIssueKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tech_card: Option<String>,
+    pub message: String,
+}
+
+/// Отчёт о товарах склада, для которых производство не сработает
+#[derive(Debug, Clone, Serialize)]
+pub struct MappingHealthReport {
+    pub generated_at: DateTime<Utc>,
+    pub products_checked: usize,
+    /// Товаров без проблем
+    pub healthy: usize,
+    pub missing_tech_card: usize,
+    pub plan_not_found: usize,
+    pub empty_materials: usize,
+    pub lookup_failed: usize,
+    pub issues: Vec<MappingIssue>,
+}
+
+impl MappingHealthReport {
+    pub fn build(products_checked: usize, mut issues: Vec<MappingIssue>, now: DateTime<Utc>) -> Self {
+        issues.sort_by(|a, b| a.product.cmp(&b.product));
+
+        let count = |kind| issues.iter().filter(|issue| issue.kind == kind).count();
+        let mut affected: Vec<&str> = issues.iter().map(|issue| issue.product_id.as_str()).collect();
+        affected.sort_unstable();
+        affected.dedup();
+
+        Self {
+            generated_at: now,
+            products_checked,
+            healthy: products_checked.saturating_sub(affected.len()),
+            missing_tech_card: count(MappingIssueKind::MissingTechCard),
+            plan_not_found: count(MappingIssueKind::PlanNotFound),
+            empty_materials: count(MappingIssueKind::EmptyMaterials),
+            lookup_failed: count(MappingIssueKind::LookupFailed),
+            issues,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Прогноз потребности в материалах по скорости продаж и порогам
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Ожидаемое производство товара на горизонт прогноза
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductDemand {
+    pub product_id: String,
+    pub name: String,
+    /// Средние продажи в день за SALES_VELOCITY_DAYS
+    pub daily_sales: f64,
+    /// Остаток для сравнения с порогом (STOCK_MODE)
+    pub stock: f64,
+    pub threshold: f64,
+    /// Сколько придётся произвести: продажи за горизонт плюс порог сверх остатка
+    pub production: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tech_card: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ProductDemand {
+    pub fn new(
+        product_id: &str,
+        name: &str,
+        daily_sales: f64,
+        stock: f64,
+        threshold: f64,
+        horizon_days: u32,
+    ) -> Self {
+        let production = (daily_sales * horizon_days as f64 + threshold - stock).max(0.0);
+
+        Self {
+            product_id: product_id.to_string(),
+            name: name.to_string(),
+            daily_sales,
+            stock,
+            threshold,
+            production,
+            tech_card: None,
+            error: None,
+        }
+    }
+}
+
+/// Расход материала на производство одного товара
+#[derive(Debug, Clone)]
+pub struct MaterialUsage {
+    pub material_id: String,
+    pub material: String,
+    pub product: String,
+    pub quantity: f64,
+}
+
+/// Потребность в материале на горизонт прогноза
+#[derive(Debug, Clone, Serialize)]
+pub struct MaterialDemand {
+    pub material_id: String,
+    pub name: String,
+    pub required: f64,
+    pub available: f64,
+    /// Средний расход в день
+    pub daily_usage: f64,
+    /// На сколько дней хватит остатка
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days_left: Option<f64>,
+    /// Материал закончится в пределах горизонта
+    pub runs_out: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_out_date: Option<NaiveDate>,
+    /// Товары, на которые расходуется материал
+    pub products: Vec<String>,
+}
+
+/// Прогноз потребности в материалах
+#[derive(Debug, Clone, Serialize)]
+pub struct MaterialsDemandReport {
+    pub generated_at: DateTime<Utc>,
+    pub horizon_days: u32,
+    pub velocity_days: u32,
+    /// Материалов, которые закончатся в пределах горизонта
+    pub running_out: usize,
+    /// Сначала материалы, которые закончатся раньше
+    pub materials: Vec<MaterialDemand>,
+    pub products: Vec<ProductDemand>,
+}
+
+impl MaterialsDemandReport {
+    /// Свести расход материалов по товарам и сравнить с остатками
+    /// (`stock` — доступный остаток материала по ID)
+    pub fn build(
+        horizon_days: u32,
+        velocity_days: u32,
+        products: Vec<ProductDemand>,
+        usage: &[MaterialUsage],
+        stock: &HashMap<String, f64>,
+        now: DateTime<Utc>,
+        today: NaiveDate,
+    ) -> Self {
+        let mut materials: Vec<MaterialDemand> = Vec::new();
+        for item in usage {
+            let index = match materials.iter().position(|m| m.material_id == item.material_id) {
+                Some(index) => index,
+                None => {
+                    materials.push(MaterialDemand {
+                        material_id: item.material_id.clone(),
+                        name: item.material.clone(),
+                        required: 0.0,
+                        available: stock.get(&item.material_id).copied().unwrap_or(0.0),
+                        daily_usage: 0.0,
+                        days_left: None,
+                        runs_out: false,
+                        run_out_date: None,
+                        products: Vec::new(),
+                    });
+                    materials.len() - 1
+                }
+            };
+
+            let material = &mut materials[index];
+            material.required += item.quantity;
+            if !material.products.contains(&item.product) {
+                material.products.push(item.product.clone());
+            }
+        }
+
+        for material in &mut materials {
+            material.daily_usage = material.required / horizon_days.max(1) as f64;
+            if material.daily_usage > 0.0 {
+                let days_left = material.available.max(0.0) / material.daily_usage;
+                material.days_left = Some(days_left);
+                material.runs_out = material.required > material.available;
+                if material.runs_out {
+                    material.run_out_date = Some(today + Duration::days(days_left.floor() as i64));
+                }
+            }
+        }
+        materials.sort_by(|a, b| {
+            let (a_left, b_left) = (a.days_left.unwrap_or(f64::MAX), b.days_left.unwrap_or(f64::MAX));
+            b.runs_out
+                .cmp(&a.runs_out)
+                .then_with(|| a_left.total_cmp(&b_left))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        Self {
+            generated_at: now,
+            horizon_days,
+            velocity_days,
+            running_out: materials.iter().filter(|m| m.runs_out).count(),
+            materials,
+            products,
+        }
+    }
+}
@@ -0,0 +1,17 @@
+pub mod export;
+pub mod forecast;
+pub mod mapping_health;
+pub mod materials_demand;
+pub mod query;
+pub mod scheduler;
+pub mod sla;
+pub mod summary;
+
+pub use export::*;
+pub use forecast::*;
+pub use mapping_health::*;
+pub use materials_demand::*;
+pub use query::*;
+pub use scheduler::*;
+pub use sla::*;
+pub use summary::*;
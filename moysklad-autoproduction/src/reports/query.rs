@@ -0,0 +1,199 @@
+//! Выборки и агрегаты по истории обработки для BI-инструментов
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::history::HistoryRecord;
+use crate::models::SkipReason;
+
+/// Записей в ответе без группировки по умолчанию
+pub const DEFAULT_QUERY_LIMIT: usize = 1000;
+
+/// Условия отбора записей истории
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Только эти товары (по ID)
+    pub product_ids: Vec<String>,
+    /// Подстрока названия товара, без учёта регистра
+    pub product_name: Option<String>,
+    pub order_id: Option<String>,
+    /// Только пропуски с этими причинами
+    pub reasons: Vec<SkipReason>,
+    pub success: Option<bool>,
+    /// Только записи, по которым создана тех. операция
+    pub produced: Option<bool>,
+    pub revoked: Option<bool>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, record: &HistoryRecord) -> bool {
+        if self.from.is_some_and(|from| record.timestamp < from) {
+            return false;
+        }
+        if self.to.is_some_and(|to| record.timestamp >= to) {
+            return false;
+        }
+        if !self.product_ids.is_empty()
+            && !record
+                .product_id
+                .as_ref()
+                .is_some_and(|id| self.product_ids.contains(id))
+        {
+            return false;
+        }
+        if let Some(name) = &self.product_name {
+            let name = name.to_lowercase();
+            if !record
+                .product_name
+                .as_ref()
+                .is_some_and(|n| n.to_lowercase().contains(&name))
+            {
+                return false;
+            }
+        }
+        if self.order_id.is_some() && record.order_id != self.order_id {
+            return false;
+        }
+        if !self.reasons.is_empty()
+            && !record.skip_reason.is_some_and(|r| self.reasons.contains(&r))
+        {
+            return false;
+        }
+        if self.success.is_some_and(|s| record.success != s) {
+            return false;
+        }
+        if self.produced.is_some_and(|p| record.processing_id.is_some() != p) {
+            return false;
+        }
+        if self.revoked.is_some_and(|r| record.revoked != r) {
+            return false;
+        }
+        true
+    }
+}
+
+/// Поле группировки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupKey {
+    /// День (UTC)
+    Day,
+    Product,
+    /// Причина пропуска
+    Reason,
+    /// Успех / ошибка
+    Status,
+}
+
+/// Ключ группы: заполнены только поля, по которым группировали
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct GroupValues {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<SkipReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
+
+/// Агрегат по группе записей
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryGroup {
+    #[serde(flatten)]
+    pub key: GroupValues,
+    pub records: usize,
+    pub failures: usize,
+    pub skipped: usize,
+    /// Создано тех. операций
+    pub productions: usize,
+    /// Произведено (сумма по созданным тех. операциям)
+    pub produced_quantity: f64,
+}
+
+/// Результат запроса к истории
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum HistoryQueryResult {
+    Records {
+        total: usize,
+        records: Vec<HistoryRecord>,
+    },
+    Groups {
+        total: usize,
+        groups: Vec<HistoryGroup>,
+    },
+}
+
+/// Отобрать записи и, если задана группировка, свернуть их в агрегаты.
+/// Без группировки возвращаются последние `limit` записей.
+pub fn query_history(
+    records: Vec<HistoryRecord>,
+    filter: &HistoryFilter,
+    group_by: &[GroupKey],
+    limit: usize,
+) -> HistoryQueryResult {
+    let matched: Vec<_> = records.into_iter().filter(|r| filter.matches(r)).collect();
+
+    if group_by.is_empty() {
+        let total = matched.len();
+        let skip = total.saturating_sub(limit);
+        return HistoryQueryResult::Records {
+            total,
+            records: matched.into_iter().skip(skip).collect(),
+        };
+    }
+
+    let mut groups: BTreeMap<GroupValues, HistoryGroup> = BTreeMap::new();
+    for record in &matched {
+        let key = group_values(record, group_by);
+        let group = groups.entry(key.clone()).or_insert_with(|| HistoryGroup {
+            key,
+            records: 0,
+            failures: 0,
+            skipped: 0,
+            productions: 0,
+            produced_quantity: 0.0,
+        });
+
+        group.records += 1;
+        if !record.success {
+            group.failures += 1;
+        }
+        if record.skip_reason.is_some() {
+            group.skipped += 1;
+        }
+        if record.processing_id.is_some() && !record.revoked {
+            group.productions += 1;
+            group.produced_quantity += record.quantity;
+        }
+    }
+
+    let total = groups.len();
+    HistoryQueryResult::Groups {
+        total,
+        groups: groups.into_values().take(limit).collect(),
+    }
+}
+
+fn group_values(record: &HistoryRecord, group_by: &[GroupKey]) -> GroupValues {
+    let mut key = GroupValues::default();
+    for field in group_by {
+        match field {
+            GroupKey::Day => key.day = Some(record.timestamp.date_naive()),
+            GroupKey::Product => {
+                key.product_id = record.product_id.clone();
+                key.product_name = record.product_name.clone();
+            }
+            GroupKey::Reason => key.reason = record.skip_reason,
+            GroupKey::Status => key.success = Some(record.success),
+        }
+    }
+    key
+}
@@ -0,0 +1,68 @@
+//! Плановая отправка сводных отчётов
+
+use chrono::{Datelike, Duration, Local, NaiveTime, TimeZone, Utc, Weekday};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use super::summary::{ReportPeriod, SummaryReport};
+use crate::notifications::{Notification, NotificationEvent, NotificationRouter};
+use crate::tenants::{Tenant, DEFAULT_TENANT};
+
+/// Запустить фоновую отправку сводки: ежедневно в `hour` часов
+/// (для недельной сводки — по понедельникам)
+pub fn spawn_summary_scheduler(
+    tenant: Arc<Tenant>,
+    notifier: Arc<NotificationRouter>,
+    period: ReportPeriod,
+    hour: u32,
+) {
+    tokio::spawn(async move {
+        info!("Summary reports for {} scheduled: {:?} at {:02}:00", tenant.name, period, hour);
+
+        loop {
+            let wait = until_next_run(period, hour);
+            tokio::time::sleep(wait).await;
+
+            if tenant.is_removed() {
+                break;
+            }
+
+            let now = Utc::now();
+            let records = tenant.history.records_between(now - period.duration(), now);
+            let report = SummaryReport::build(&records, period, now);
+
+            let title = if tenant.name == DEFAULT_TENANT {
+                report.title()
+            } else {
+                format!("[{}] {}", tenant.name, report.title())
+            };
+
+            notifier
+                .notify(Notification::new(NotificationEvent::Summary, title, report.to_text()))
+                .await;
+        }
+    });
+}
+
+/// Время до следующего запуска
+pub(crate) fn until_next_run(period: ReportPeriod, hour: u32) -> std::time::Duration {
+    let now = Local::now();
+    let time = NaiveTime::from_hms_opt(hour.min(23), 0, 0).expect("valid hour");
+
+    let mut date = now.date_naive();
+    loop {
+        let is_run_day = period == ReportPeriod::Day || date.weekday() == Weekday::Mon;
+        if is_run_day
+            && let Some(candidate) = Local.from_local_datetime(&date.and_time(time)).earliest()
+            && candidate > now
+        {
+            return (candidate - now).to_std().unwrap_or_default();
+        }
+        date += Duration::days(1);
+
+        if date > now.date_naive() + Duration::days(8) {
+            warn!("Failed to compute next summary time, retrying in an hour");
+            return std::time::Duration::from_secs(3600);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Время от изменения документа в МойСклад до создания тех. операции по дням
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::history::HistoryRecord;
+
+/// Показатели за день (UTC)
+#[derive(Debug, Clone, Serialize)]
+pub struct SlaDay {
+    pub date: NaiveDate,
+    pub productions: usize,
+    pub p50_secs: f64,
+    pub p95_secs: f64,
+    pub max_secs: f64,
+    /// Тех. операций, созданных позже SLA_LIMIT_SECS
+    pub over_limit: usize,
+}
+
+/// Отчёт по SLA за несколько дней
+#[derive(Debug, Clone, Serialize)]
+pub struct SlaReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit_secs: Option<u64>,
+    pub days: Vec<SlaDay>,
+}
+
+impl SlaReport {
+    /// Собрать отчёт по записям истории за `days` дней, заканчивающихся в `to`
+    pub fn build(
+        records: &[HistoryRecord],
+        days: u32,
+        limit_secs: Option<u64>,
+        to: DateTime<Utc>,
+    ) -> Self {
+        let from = to - Duration::days(days as i64);
+
+        let mut by_day: BTreeMap<NaiveDate, Vec<f64>> = BTreeMap::new();
+        for record in records.iter().filter(|r| r.timestamp >= from && r.timestamp < to) {
+            if let Some(secs) = record.sla_secs {
+                by_day.entry(record.timestamp.date_naive()).or_default().push(secs);
+            }
+        }
+
+        let days = by_day
+            .into_iter()
+            .map(|(date, mut secs)| {
+                secs.sort_by(f64::total_cmp);
+                SlaDay {
+                    date,
+                    productions: secs.len(),
+                    p50_secs: percentile(&secs, 50.0),
+                    p95_secs: percentile(&secs, 95.0),
+                    max_secs: secs.last().copied().unwrap_or(0.0),
+                    over_limit: limit_secs
+                        .map(|limit| secs.iter().filter(|s| **s > limit as f64).count())
+                        .unwrap_or(0),
+                }
+            })
+            .collect();
+
+        Self {
+            from,
+            to,
+            limit_secs,
+            days,
+        }
+    }
+}
+
+/// Процентиль по ближайшему рангу; `sorted` отсортирован по возрастанию
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
@@ -0,0 +1,228 @@
+//! Сводный отчёт по производству
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::history::HistoryRecord;
+use crate::models::{Decimal, SkipReason};
+
+/// Период отчёта
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportPeriod {
+    Day,
+    Week,
+}
+
+impl ReportPeriod {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "day" | "daily" => Some(Self::Day),
+            "week" | "weekly" => Some(Self::Week),
+            _ => None,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            Self::Day => Duration::days(1),
+            Self::Week => Duration::weeks(1),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Self::Day => "за сутки",
+            Self::Week => "за неделю",
+        }
+    }
+}
+
+/// Произведённое количество по товару
+#[derive(Debug, Clone, Serialize)]
+pub struct ProductQuantity {
+    pub product_id: String,
+    pub product_name: String,
+    pub productions: usize,
+    pub quantity: f64,
+}
+
+/// Количество ошибок по причине
+#[derive(Debug, Clone, Serialize)]
+pub struct ReasonCount {
+    pub reason: String,
+    pub count: usize,
+}
+
+/// Суммарная нехватка материала
+#[derive(Debug, Clone, Serialize)]
+pub struct MaterialShortageSummary {
+    pub name: String,
+    pub occurrences: usize,
+    pub missing_quantity: Decimal,
+}
+
+/// Сводный отчёт
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryReport {
+    pub period: ReportPeriod,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub positions_processed: usize,
+    pub productions_created: usize,
+    pub failures: usize,
+    pub quantities_by_product: Vec<ProductQuantity>,
+    /// Товары, по которым в режиме NOTIFY_ONLY отправлены уведомления вместо документов
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alerts_by_product: Vec<ProductQuantity>,
+    pub failures_by_reason: Vec<ReasonCount>,
+    pub material_shortages: Vec<MaterialShortageSummary>,
+}
+
+impl SummaryReport {
+    /// Собрать отчёт по записям истории за период, заканчивающийся в `to`
+    pub fn build(records: &[HistoryRecord], period: ReportPeriod, to: DateTime<Utc>) -> Self {
+        let from = to - period.duration();
+
+        let mut by_product: HashMap<String, ProductQuantity> = HashMap::new();
+        let mut alerts: HashMap<String, ProductQuantity> = HashMap::new();
+        let mut by_reason: HashMap<String, usize> = HashMap::new();
+        let mut shortages: HashMap<String, MaterialShortageSummary> = HashMap::new();
+        let mut positions_processed = 0;
+        let mut productions_created = 0;
+        let mut failures = 0;
+
+        for record in records.iter().filter(|r| r.timestamp >= from && r.timestamp < to) {
+            positions_processed += 1;
+
+            if record.processing_id.is_some() {
+                productions_created += 1;
+                let product_id = record.product_id.clone().unwrap_or_default();
+                let entry = by_product
+                    .entry(product_id.clone())
+                    .or_insert_with(|| ProductQuantity {
+                        product_id,
+                        product_name: record.product_name.clone().unwrap_or_default(),
+                        productions: 0,
+                        quantity: 0.0,
+                    });
+                entry.productions += 1;
+                entry.quantity += record.quantity;
+            }
+
+            if record.skip_reason == Some(SkipReason::NotifyOnly) {
+                let product_id = record.product_id.clone().unwrap_or_default();
+                let entry = alerts
+                    .entry(product_id.clone())
+                    .or_insert_with(|| ProductQuantity {
+                        product_id,
+                        product_name: record.product_name.clone().unwrap_or_default(),
+                        productions: 0,
+                        quantity: 0.0,
+                    });
+                entry.productions += 1;
+                entry.quantity += record.quantity;
+            }
+
+            if !record.success {
+                failures += 1;
+                let reason = record.error.clone().unwrap_or_else(|| record.message.clone());
+                *by_reason.entry(reason).or_insert(0) += 1;
+            }
+
+            for material in &record.missing_materials {
+                let entry = shortages
+                    .entry(material.name.clone())
+                    .or_insert_with(|| MaterialShortageSummary {
+                        name: material.name.clone(),
+                        occurrences: 0,
+                        missing_quantity: Decimal::ZERO,
+                    });
+                entry.occurrences += 1;
+                entry.missing_quantity += material.quantity;
+            }
+        }
+
+        let mut quantities_by_product: Vec<_> = by_product.into_values().collect();
+        quantities_by_product.sort_by(|a, b| b.quantity.total_cmp(&a.quantity));
+
+        let mut alerts_by_product: Vec<_> = alerts.into_values().collect();
+        alerts_by_product.sort_by(|a, b| b.quantity.total_cmp(&a.quantity));
+
+        let mut failures_by_reason: Vec<_> = by_reason
+            .into_iter()
+            .map(|(reason, count)| ReasonCount { reason, count })
+            .collect();
+        failures_by_reason.sort_by_key(|r| std::cmp::Reverse(r.count));
+
+        let mut material_shortages: Vec<_> = shortages.into_values().collect();
+        material_shortages.sort_by_key(|s| std::cmp::Reverse(s.occurrences));
+
+        Self {
+            period,
+            from,
+            to,
+            positions_processed,
+            productions_created,
+            failures,
+            quantities_by_product,
+            alerts_by_product,
+            failures_by_reason,
+            material_shortages,
+        }
+    }
+
+    /// Текстовое представление для уведомлений
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "Период: {} — {}",
+                self.from.format("%d.%m.%Y %H:%M"),
+                self.to.format("%d.%m.%Y %H:%M")
+            ),
+            format!("Обработано позиций: {}", self.positions_processed),
+            format!("Создано тех. операций: {}", self.productions_created),
+            format!("Ошибок: {}", self.failures),
+        ];
+
+        if !self.quantities_by_product.is_empty() {
+            lines.push(String::new());
+            lines.push("Произведено:".to_string());
+            for p in &self.quantities_by_product {
+                lines.push(format!("  {} — {} шт. ({} оп.)", p.product_name, p.quantity, p.productions));
+            }
+        }
+
+        if !self.alerts_by_product.is_empty() {
+            lines.push(String::new());
+            lines.push("Нужно пополнить (только уведомления):".to_string());
+            for p in &self.alerts_by_product {
+                lines.push(format!("  {} — {} шт. ({} увед.)", p.product_name, p.quantity, p.productions));
+            }
+        }
+
+        if !self.failures_by_reason.is_empty() {
+            lines.push(String::new());
+            lines.push("Ошибки:".to_string());
+            for r in &self.failures_by_reason {
+                lines.push(format!("  {} — {}", r.reason, r.count));
+            }
+        }
+
+        if !self.material_shortages.is_empty() {
+            lines.push(String::new());
+            lines.push("Нехватка материалов:".to_string());
+            for m in &self.material_shortages {
+                lines.push(format!("  {} — {} раз, всего не хватало {}", m.name, m.occurrences, m.missing_quantity));
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    /// Заголовок для уведомлений
+    pub fn title(&self) -> String {
+        format!("Сводка автопроизводства {}", self.period.title())
+    }
+}
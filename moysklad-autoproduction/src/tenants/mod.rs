@@ -0,0 +1,7 @@
+pub mod registry;
+pub mod tokens;
+pub mod workers;
+
+pub use registry::*;
+pub use tokens::*;
+pub use workers::*;
@@ -0,0 +1,577 @@
+//! Несколько аккаунтов МойСклад (тенантов) в одном сервисе
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use super::TokenStore;
+use crate::api::{ApiUsage, CircuitBreaker};
+use crate::config::{reveal, SecretsKey, Settings};
+use crate::history::{AuditLog, HistoryStore, JobStore};
+use crate::notifications::NotificationRouter;
+use crate::processing::{
+    CreatedTasks, FolderTechCards, Locks, MaterialSubstitutes, NameSequence, OrderProcessor,
+    PlanCache, ProcessedOrders, ProcessorStores, ProductOverrides, SkipStats, WorkSchedule,
+};
+use crate::queue::{
+    DeadLetterStore, IntakeLimiter, PendingQueue, PriorityRules, RetryQueue, ShortageQueue,
+    WebhookDebouncer,
+};
+use crate::vendor::{VendorAccount, VendorAccounts};
+
+/// Имя тенанта, настроенного через переменные окружения
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Описание тенанта в файле TENANTS_FILE
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TenantConfig {
+    pub name: String,
+    /// accountId аккаунта МойСклад, по которому маршрутизируются webhook
+    #[serde(default)]
+    pub account_id: Option<String>,
+    /// Токен как есть или зашифрованный для имени тенанта (`enc:v2:...`)
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub login: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Адрес API, например песочницы (иначе MOYSKLAD_API_URL)
+    #[serde(default)]
+    pub api_url: Option<String>,
+    #[serde(default)]
+    pub store_name: Option<String>,
+    #[serde(default)]
+    pub store_id: Option<String>,
+    #[serde(default)]
+    pub organization_id: Option<String>,
+    #[serde(default)]
+    pub tech_card_field_name: Option<String>,
+    #[serde(default)]
+    pub min_stock_threshold: Option<f64>,
+}
+
+impl TenantConfig {
+    /// Настройки тенанта: общие настройки с переопределёнными полями
+    pub fn apply(&self, base: &Settings) -> Settings {
+        let mut settings = base.clone();
+
+        if self.token.is_some() || self.login.is_some() {
+            settings.moysklad_token = self.token.clone().unwrap_or_default();
+            settings.moysklad_login = self.login.clone();
+            settings.moysklad_password = self.password.clone();
+        }
+        if let Some(ref api_url) = self.api_url {
+            settings.moysklad_api_url = api_url.trim_end_matches('/').to_string();
+        }
+        if let Some(ref store_name) = self.store_name {
+            settings.store_name = store_name.clone();
+        }
+        // ID склада и организации принадлежат одному аккаунту и не наследуются
+        settings.store_id = self.store_id.clone();
+        settings.organization_id = self.organization_id.clone();
+        if let Some(ref field) = self.tech_card_field_name {
+            settings.tech_card_field_name = field.clone();
+        }
+        if let Some(threshold) = self.min_stock_threshold {
+            settings.min_stock_threshold = threshold;
+        }
+
+        settings.history_file = base.history_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.audit_file = base.audit_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.processed_orders_file = base.processed_orders_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.product_overrides_file = base.product_overrides_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.name_sequence_file = base.name_sequence_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.retry_queue_file = base.retry_queue_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.dead_letter_file = base.dead_letter_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.pending_queue_file = base.pending_queue_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.shortage_queue_file = base.shortage_queue_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.tasks_file = base.tasks_file.as_deref().map(|p| tenant_path(p, &self.name));
+        settings.drift_baseline_file = base.drift_baseline_file.as_deref().map(|p| tenant_path(p, &self.name));
+
+        settings
+    }
+}
+
+/// Путь к файлу тенанта: `history.jsonl` → `history-<tenant>.jsonl`
+fn tenant_path(path: &str, tenant: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("data");
+    let file_name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}-{}.{}", stem, tenant, ext),
+        None => format!("{}-{}", stem, tenant),
+    };
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+/// Тенант: собственный процессор, кэши, история и очередь повторов
+pub struct Tenant {
+    pub name: String,
+    pub account_id: Option<String>,
+    pub settings: Settings,
+    pub history: Arc<HistoryStore>,
+    pub audit: Arc<AuditLog>,
+    pub retry_queue: Arc<RetryQueue>,
+    /// Заказы, снятые с повторов после RETRY_MAX_ATTEMPTS попыток
+    pub dead_letters: DeadLetterStore,
+    pub notifier: Arc<NotificationRouter>,
+    /// Позиции, ожидающие поступления материалов
+    pub shortages: Arc<ShortageQueue>,
+    pub circuit_breaker: Arc<CircuitBreaker>,
+    pub api_usage: Arc<ApiUsage>,
+    pub skip_stats: Arc<SkipStats>,
+    pub overrides: Arc<ProductOverrides>,
+    /// Найденные тех. карты; сбрасываются webhook processingplan без блокировки процессора
+    pub plan_cache: Arc<PlanCache>,
+    /// Webhook, ожидающие процессор
+    pub intake: IntakeLimiter,
+    /// Повторные webhook, ожидающие окончания окна склейки
+    pub debounce: WebhookDebouncer,
+    /// Документы, ожидающие обработки в порядке приоритета (PRIORITY_RULES)
+    pub pending: PendingQueue,
+    /// Результаты ручной обработки для постраничного чтения
+    pub jobs: JobStore,
+    pub processor: Mutex<OrderProcessor>,
+    /// Тенант удалён из реестра (решение удалено из аккаунта)
+    removed: AtomicBool,
+}
+
+impl Tenant {
+    pub fn new(
+        name: &str,
+        account_id: Option<String>,
+        settings: Settings,
+        notifier: Arc<NotificationRouter>,
+    ) -> Result<Self> {
+        let history = Arc::new(
+            HistoryStore::open(settings.history_file.as_deref().map(PathBuf::from))
+                .with_context(|| format!("Failed to open history for tenant {}", name))?,
+        );
+
+        let audit = Arc::new(
+            AuditLog::open(settings.audit_file.as_deref().map(PathBuf::from))
+                .with_context(|| format!("Failed to open audit log for tenant {}", name))?,
+        );
+
+        let retry_queue = Arc::new(
+            RetryQueue::open(
+                settings.retry_queue_file.as_deref().map(PathBuf::from),
+                settings.retry_base_delay_secs,
+                settings.retry_max_delay_secs,
+                settings.retry_max_attempts,
+            )
+            .with_context(|| format!("Failed to open retry queue for tenant {}", name))?,
+        );
+
+        let dead_letters =
+            DeadLetterStore::open(settings.dead_letter_file.as_deref().map(PathBuf::from))
+                .with_context(|| format!("Failed to open dead letters for tenant {}", name))?;
+
+        let shortages = Arc::new(
+            ShortageQueue::open(settings.shortage_queue_file.as_deref().map(PathBuf::from))
+                .with_context(|| format!("Failed to open shortage queue for tenant {}", name))?,
+        );
+
+        let processed = Arc::new(
+            ProcessedOrders::open(settings.processed_orders_file.as_deref().map(PathBuf::from))
+                .with_context(|| format!("Failed to open processed orders for tenant {}", name))?,
+        );
+
+        let tasks = Arc::new(
+            CreatedTasks::open(settings.tasks_file.as_deref().map(PathBuf::from))
+                .with_context(|| format!("Failed to open created tasks for tenant {}", name))?,
+        );
+
+        let overrides = Arc::new(
+            ProductOverrides::open(settings.product_overrides_file.as_deref().map(PathBuf::from))
+                .with_context(|| format!("Failed to open product overrides for tenant {}", name))?,
+        );
+
+        // С общей очередью событий заказы обрабатывают несколько реплик: счётчик в файле дал бы повторы
+        let names = match settings.redis_url {
+            Some(ref url) if settings.event_queue == "redis" => {
+                NameSequence::redis(settings.name_template.clone(), url, name)
+            }
+            _ => NameSequence::open(
+                settings.name_template.clone(),
+                settings.name_sequence_file.as_deref().map(PathBuf::from),
+            ),
+        };
+        let names =
+            Arc::new(names.with_context(|| format!("Failed to open name sequence for tenant {}", name))?);
+
+        let folder_tech_cards =
+            FolderTechCards::load(settings.folder_tech_card_file.as_deref().map(Path::new))
+                .with_context(|| format!("Failed to load folder mapping for tenant {}", name))?;
+
+        let substitutes =
+            MaterialSubstitutes::load(settings.material_substitutes_file.as_deref().map(Path::new))
+                .with_context(|| format!("Failed to load material substitutes for tenant {}", name))?;
+
+        let schedule = WorkSchedule::from_settings(&settings)
+            .with_context(|| format!("Failed to configure work schedule for tenant {}", name))?;
+
+        let locks = Locks::from_settings(&settings, name)
+            .with_context(|| format!("Failed to configure locks for tenant {}", name))?;
+
+        let processor = OrderProcessor::new(
+            settings.clone(),
+            notifier.clone(),
+            ProcessorStores {
+                history: history.clone(),
+                audit: audit.clone(),
+                processed,
+                overrides: overrides.clone(),
+                shortages: shortages.clone(),
+                retry_queue: retry_queue.clone(),
+                names,
+                tasks,
+            },
+            folder_tech_cards,
+            substitutes,
+            schedule,
+            locks,
+        );
+        let circuit_breaker = processor.circuit_breaker();
+        let api_usage = processor.api_usage();
+        let skip_stats = processor.skip_stats();
+        let plan_cache = processor.plan_cache();
+        let intake = IntakeLimiter::new(settings.webhook_queue_depth);
+        let debounce = WebhookDebouncer::new(Duration::from_secs(settings.webhook_debounce_secs));
+        let priority_rules = PriorityRules::parse(&settings.priority_rules)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Failed to parse priority rules for tenant {}", name))?;
+        let pending = PendingQueue::open(
+            priority_rules,
+            settings.webhook_queue_depth,
+            settings.pending_queue_file.as_deref().map(PathBuf::from),
+        )
+        .with_context(|| format!("Failed to open pending queue for tenant {}", name))?;
+        let jobs = JobStore::new(settings.job_results_max);
+
+        Ok(Self {
+            name: name.to_string(),
+            account_id,
+            settings,
+            history,
+            audit,
+            retry_queue,
+            dead_letters,
+            notifier,
+            shortages,
+            circuit_breaker,
+            api_usage,
+            skip_stats,
+            overrides,
+            plan_cache,
+            intake,
+            debounce,
+            pending,
+            jobs,
+            processor: Mutex::new(processor),
+            removed: AtomicBool::new(false),
+        })
+    }
+
+    /// Тенант удалён из реестра: его фоновые задачи завершаются
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Relaxed)
+    }
+
+    fn mark_removed(&self) {
+        self.removed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Реестр тенантов. Первый тенант используется по умолчанию.
+/// Аккаунты, установившие решение из маркетплейса, подключаются и отключаются на ходу.
+pub struct TenantRegistry {
+    tenants: RwLock<Vec<Arc<Tenant>>>,
+    settings: Settings,
+    notifier: Arc<NotificationRouter>,
+    vendor_accounts: Option<Arc<VendorAccounts>>,
+    /// Токены, заменённые через /admin/token (есть, если задан мастер-ключ)
+    tokens: Option<TokenStore>,
+}
+
+impl TenantRegistry {
+    /// Создать тенантов из настроек окружения, файла TENANTS_FILE и установок решения
+    pub fn load(settings: &Settings, notifier: Arc<NotificationRouter>) -> Result<Self> {
+        let secrets = SecretsKey::from_settings(settings)?.map(Arc::new);
+        let tokens = match secrets {
+            Some(ref key) => Some(
+                TokenStore::open(settings.tokens_file.as_deref().map(PathBuf::from), key.clone())
+                    .context("Failed to open tokens")?,
+            ),
+            None => None,
+        };
+        let settings = &reveal_credentials(settings, secrets.as_deref())?;
+        let mut tenants = Vec::new();
+
+        if !settings.moysklad_token.is_empty() || settings.moysklad_login.is_some() {
+            let mut default = settings.clone();
+            if let Some(token) = stored_token(tokens.as_ref(), DEFAULT_TENANT)? {
+                default.moysklad_token = token;
+            }
+            tenants.push(Arc::new(Tenant::new(
+                DEFAULT_TENANT,
+                settings.moysklad_account_id.clone(),
+                default,
+                notifier.clone(),
+            )?));
+        }
+
+        if let Some(ref path) = settings.tenants_file {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read tenants file {}", path))?;
+            let configs: Vec<TenantConfig> = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse tenants file {}", path))?;
+
+            for mut config in configs {
+                if tenants.iter().any(|t: &Arc<Tenant>| t.name == config.name) {
+                    return Err(anyhow!("Duplicate tenant name '{}'", config.name));
+                }
+                let name = config.name.clone();
+                let reveal_field = |value: &Option<String>| {
+                    value.as_deref().map(|v| reveal(secrets.as_deref(), v, &name)).transpose()
+                };
+                config.token = match stored_token(tokens.as_ref(), &config.name)? {
+                    Some(token) => Some(token),
+                    None => reveal_field(&config.token)
+                        .with_context(|| format!("Invalid token of tenant '{}'", config.name))?,
+                };
+                config.password = reveal_field(&config.password)
+                    .with_context(|| format!("Invalid password of tenant '{}'", config.name))?;
+                if config.token.is_none() && config.login.is_none() {
+                    return Err(anyhow!("Tenant '{}' has no token or login", config.name));
+                }
+
+                info!(
+                    "Tenant '{}': store '{}'",
+                    config.name,
+                    config.store_name.as_deref().unwrap_or(&settings.store_name)
+                );
+
+                tenants.push(Arc::new(Tenant::new(
+                    &config.name,
+                    config.account_id.clone(),
+                    config.apply(settings),
+                    notifier.clone(),
+                )?));
+            }
+        }
+
+        let vendor_accounts = if settings.vendor_enabled() {
+            if secrets.is_none() {
+                warn!("SECRETS_KEY is not set: solution tokens are stored unencrypted");
+            }
+            let accounts = VendorAccounts::open(
+                settings.vendor_accounts_file.as_deref().map(PathBuf::from),
+                secrets.clone(),
+            )
+            .context("Failed to open vendor accounts")?;
+            for account in accounts.active() {
+                let config = vendor_tenant_config(&tenants, &account);
+                info!("Tenant '{}': installed from the Moysklad marketplace", config.name);
+                tenants.push(Arc::new(Tenant::new(
+                    &config.name,
+                    config.account_id.clone(),
+                    config.apply(settings),
+                    notifier.clone(),
+                )?));
+            }
+            Some(Arc::new(accounts))
+        } else {
+            None
+        };
+
+        // Решение маркетплейса может стартовать без аккаунтов: они появятся при установке
+        if tenants.is_empty() && vendor_accounts.is_none() {
+            return Err(anyhow!("No Moysklad accounts configured"));
+        }
+
+        Ok(Self {
+            tenants: RwLock::new(tenants),
+            settings: settings.clone(),
+            notifier,
+            vendor_accounts,
+            tokens,
+        })
+    }
+
+    /// Все тенанты
+    pub fn all(&self) -> Vec<Arc<Tenant>> {
+        self.tenants.read().expect("tenant registry lock poisoned").clone()
+    }
+
+    /// Тенант по умолчанию (нет, пока ни один аккаунт не подключён)
+    pub fn default_tenant(&self) -> Option<Arc<Tenant>> {
+        self.tenants.read().expect("tenant registry lock poisoned").first().cloned()
+    }
+
+    /// Найти тенанта по accountId аккаунта МойСклад
+    pub fn by_account(&self, account_id: &str) -> Option<Arc<Tenant>> {
+        self.tenants
+            .read()
+            .expect("tenant registry lock poisoned")
+            .iter()
+            .find(|t| t.account_id.as_deref() == Some(account_id))
+            .cloned()
+    }
+
+    /// Найти тенанта по имени
+    pub fn by_name(&self, name: &str) -> Option<Arc<Tenant>> {
+        self.tenants
+            .read()
+            .expect("tenant registry lock poisoned")
+            .iter()
+            .find(|t| t.name == name)
+            .cloned()
+    }
+
+    /// Найти тенанта по имени или accountId; без ключа — тенант по умолчанию
+    pub fn resolve(&self, key: Option<&str>) -> Option<Arc<Tenant>> {
+        match key {
+            None => self.default_tenant(),
+            Some(key) => self.by_name(key).or_else(|| self.by_account(key)),
+        }
+    }
+
+    /// Установки решения из маркетплейса (если задан VENDOR_SECRET_KEY)
+    pub fn vendor_accounts(&self) -> Option<&Arc<VendorAccounts>> {
+        self.vendor_accounts.as_ref()
+    }
+
+    /// Подключить аккаунт, установивший решение: сохранить установку и создать тенанта.
+    /// При переустановке или возобновлении тенант пересоздаётся с новым токеном.
+    pub fn install(&self, account: VendorAccount) -> Result<Arc<Tenant>> {
+        let accounts = self
+            .vendor_accounts
+            .as_ref()
+            .ok_or_else(|| anyhow!("Vendor API is not configured"))?;
+
+        // Тенант создаётся (с чтением его файлов) без блокировки реестра
+        let config = {
+            let tenants = self.tenants.read().expect("tenant registry lock poisoned");
+            let others: Vec<Arc<Tenant>> = tenants
+                .iter()
+                .filter(|t| t.account_id.as_deref() != Some(account.account_id.as_str()))
+                .cloned()
+                .collect();
+            vendor_tenant_config(&others, &account)
+        };
+        let tenant = Arc::new(Tenant::new(
+            &config.name,
+            config.account_id.clone(),
+            config.apply(&self.settings),
+            self.notifier.clone(),
+        )?);
+        accounts.upsert(account);
+
+        let replaced = {
+            let mut tenants = self.tenants.write().expect("tenant registry lock poisoned");
+            let replaced = tenants
+                .iter()
+                .position(|t| t.account_id == tenant.account_id)
+                .map(|index| tenants.remove(index));
+            tenants.push(tenant.clone());
+            replaced
+        };
+        if let Some(replaced) = replaced {
+            replaced.mark_removed();
+        }
+
+        info!("Tenant '{}' installed from the Moysklad marketplace", tenant.name);
+        Ok(tenant)
+    }
+
+    /// Отключить аккаунт, удаливший или приостановивший решение.
+    /// Возвращает `false`, если установка не найдена.
+    pub fn uninstall(&self, account_id: &str, suspend: bool) -> bool {
+        let Some(ref accounts) = self.vendor_accounts else {
+            return false;
+        };
+        let known = if suspend {
+            accounts.suspend(account_id)
+        } else {
+            accounts.remove(account_id).is_some()
+        };
+
+        let mut tenants = self.tenants.write().expect("tenant registry lock poisoned");
+        if let Some(index) = tenants
+            .iter()
+            .position(|t| t.account_id.as_deref() == Some(account_id))
+        {
+            let tenant = tenants.remove(index);
+            tenant.mark_removed();
+            warn!(
+                "Tenant '{}' {} in the Moysklad marketplace",
+                tenant.name,
+                if suspend { "suspended" } else { "uninstalled" }
+            );
+        }
+
+        known
+    }
+
+    /// Заменить токен тенанта без перезапуска. Токен установки решения сохраняется
+    /// в VENDOR_ACCOUNTS_FILE, остальных тенантов — в TOKENS_FILE, в обоих случаях
+    /// зашифрованным мастер-ключом.
+    pub async fn rotate_token(&self, tenant: &Tenant, token: &str) -> Result<()> {
+        let vendor = self
+            .vendor_accounts
+            .as_ref()
+            .zip(tenant.account_id.as_deref())
+            .and_then(|(accounts, account_id)| accounts.get(account_id).map(|a| (accounts, a)));
+
+        match vendor {
+            Some((accounts, mut account)) => {
+                account.access_token = token.to_string();
+                account.updated_at = Utc::now();
+                accounts.upsert(account);
+            }
+            None => self
+                .tokens
+                .as_ref()
+                .ok_or_else(|| anyhow!("Token rotation requires SECRETS_KEY or SECRETS_KEY_FILE"))?
+                .set(&tenant.name, token)?,
+        }
+
+        tenant.processor.lock().await.set_token(token.to_string()).await;
+        info!("[{}] Moysklad token rotated", tenant.name);
+        Ok(())
+    }
+}
+
+/// Настройки с расшифрованными MOYSKLAD_TOKEN и MOYSKLAD_PASSWORD
+fn reveal_credentials(settings: &Settings, secrets: Option<&SecretsKey>) -> Result<Settings> {
+    let mut settings = settings.clone();
+    settings.moysklad_token = reveal(secrets, &settings.moysklad_token, DEFAULT_TENANT).context("Invalid MOYSKLAD_TOKEN")?;
+    settings.moysklad_password = settings
+        .moysklad_password
+        .as_deref()
+        .map(|p| reveal(secrets, p, DEFAULT_TENANT))
+        .transpose()
+        .context("Invalid MOYSKLAD_PASSWORD")?;
+    Ok(settings)
+}
+
+/// Токен тенанта, заменённый через /admin/token
+fn stored_token(tokens: Option<&TokenStore>, tenant: &str) -> Result<Option<String>> {
+    tokens.map_or(Ok(None), |tokens| tokens.get(tenant))
+}
+
+/// Настройки тенанта установки; имя заменяется на accountId, если оно уже занято
+fn vendor_tenant_config(tenants: &[Arc<Tenant>], account: &VendorAccount) -> TenantConfig {
+    let mut config = account.tenant_config();
+    if tenants.iter().any(|t| t.name == config.name) {
+        config.name = account.account_id.clone();
+    }
+    config
+}
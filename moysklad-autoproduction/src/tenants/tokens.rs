@@ -0,0 +1,80 @@
+//! Токены тенантов, заменённые без перезапуска (хранятся зашифрованными)
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+use crate::config::{persist_json, SecretsKey};
+
+/// Заменённый токен тенанта
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredToken {
+    /// Токен в виде `enc:v2:...`, зашифрованный для имени тенанта
+    pub token: String,
+    pub rotated_at: DateTime<Utc>,
+}
+
+/// Персистентные токены тенантов, приоритетнее токенов из окружения и TENANTS_FILE.
+/// В памяти и на диске токены хранятся зашифрованными.
+pub struct TokenStore {
+    path: Option<PathBuf>,
+    key: Arc<SecretsKey>,
+    entries: Mutex<BTreeMap<String, StoredToken>>,
+}
+
+impl TokenStore {
+    /// Открыть хранилище, восстановив сохранённые токены
+    pub fn open(path: Option<PathBuf>, key: Arc<SecretsKey>) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+
+        if let Some(ref path) = path
+            && path.exists()
+        {
+            let data = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read tokens {}", path.display()))?;
+            entries = serde_json::from_str(&data)
+                .with_context(|| format!("Failed to parse tokens {}", path.display()))?;
+            info!("Restored {} rotated tokens", entries.len());
+        }
+
+        Ok(Self {
+            path,
+            key,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Расшифрованный токен тенанта, если он заменялся
+    pub fn get(&self, tenant: &str) -> Result<Option<String>> {
+        let entries = self.entries.lock().expect("token store lock poisoned");
+        entries
+            .get(tenant)
+            .map(|stored| {
+                self.key
+                    .decrypt(&stored.token, tenant)
+                    .with_context(|| format!("Failed to decrypt token of tenant {}", tenant))
+            })
+            .transpose()
+    }
+
+    /// Сохранить новый токен тенанта
+    pub fn set(&self, tenant: &str, token: &str) -> Result<()> {
+        let stored = StoredToken {
+            token: self.key.encrypt(token, tenant)?,
+            rotated_at: Utc::now(),
+        };
+
+        let mut entries = self.entries.lock().expect("token store lock poisoned");
+        entries.insert(tenant.to_string(), stored);
+        self.persist(&entries);
+        Ok(())
+    }
+
+    fn persist(&self, entries: &BTreeMap<String, StoredToken>) {
+        persist_json(self.path.as_deref(), entries, "tokens");
+    }
+}
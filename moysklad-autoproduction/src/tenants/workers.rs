@@ -0,0 +1,77 @@
+//! Фоновые задачи тенанта
+
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use super::registry::Tenant;
+use crate::notifications::{NotificationRouter, WebhookForwarder};
+use crate::processing;
+use crate::queue;
+use crate::reports::{self, ReportPeriod};
+
+/// Запустить фоновые задачи тенанта: прогрев кэшей, очередь повторов,
+/// перепроверку нехватки материалов, кэш остатков, пересчёт порогов, проверку настроек
+/// в МойСклад и плановые сводки.
+/// Задачи завершаются, когда тенант удалён из реестра.
+pub fn spawn_tenant_tasks(
+    tenant: &Arc<Tenant>,
+    notifier: Arc<NotificationRouter>,
+    forwarder: Option<Arc<WebhookForwarder>>,
+) {
+    let settings = &tenant.settings;
+
+    // Прогреваем кэши при старте: первый webhook не ждёт поиска склада и тех. карт
+    let startup_tenant = tenant.clone();
+    tokio::spawn(async move {
+        match processing::OrderProcessor::warm_up(&startup_tenant.processor).await {
+            Ok(plans) => info!(
+                "[{}] Caches warmed up, {} tech cards prefetched",
+                startup_tenant.name, plans
+            ),
+            Err(e) => warn!("[{}] Cache warm-up failed: {:#}", startup_tenant.name, e),
+        }
+    });
+
+    // Повторная обработка заказов из очереди
+    queue::spawn_retry_worker(
+        tenant.clone(),
+        Duration::from_secs(settings.retry_poll_interval_secs.max(1)),
+    );
+
+    // Обработка документов по приоритету
+    if tenant.pending.enabled() {
+        queue::spawn_pending_worker(tenant.clone(), forwarder);
+    }
+
+    // Перепроверка позиций, ожидающих материалов (кроме того, по каждой приёмке)
+    if settings.shortage_recheck_interval_secs > 0 {
+        queue::spawn_shortage_worker(
+            tenant.clone(),
+            Duration::from_secs(settings.shortage_recheck_interval_secs),
+        );
+    }
+
+    // Фоновое обновление кэша остатков
+    if settings.stock_cache_refresh_secs > 0 {
+        processing::spawn_stock_cache_refresher(
+            tenant.clone(),
+            Duration::from_secs(settings.stock_cache_refresh_secs),
+        );
+    }
+
+    // Пороги по скорости продаж
+    if settings.dynamic_thresholds {
+        processing::spawn_threshold_refresher(tenant.clone(), settings.dynamic_threshold_hour);
+    }
+
+    // Проверка настроек в МойСклад
+    if settings.drift_check {
+        processing::spawn_drift_checker(tenant.clone(), settings.drift_check_hour);
+    }
+
+    // Плановая отправка сводок
+    if let Some(period) = settings.summary_schedule.as_deref().and_then(ReportPeriod::parse) {
+        reports::spawn_summary_scheduler(tenant.clone(), notifier, period, settings.summary_hour);
+    }
+}